@@ -0,0 +1,385 @@
+// Append-only, hash-chained security audit log stored in sled
+//
+// Entries can't be edited without breaking the chain, so they name users by
+// id only: erasing a user (see `users::erase_user`) leaves no email behind.
+use actix_web::{web, HttpRequest};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+
+use crate::config::AppConfig;
+use crate::db::Database;
+
+pub const AUDIT_TREE: &str = "audit_log";
+
+/// Hash the first entry chains from
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Appends read the chain head and write the next entry; serialize them so
+/// two concurrent requests can't both chain from the same predecessor.
+static APPEND_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+    Denied,
+}
+
+/// What happened, before it is sequenced and chained
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: AuditOutcome,
+    pub details: Option<serde_json::Value>,
+}
+
+impl AuditEvent {
+    pub fn new(action: &str, outcome: AuditOutcome) -> Self {
+        Self {
+            actor: None,
+            action: action.to_string(),
+            target: None,
+            ip: None,
+            user_agent: None,
+            outcome,
+            details: None,
+        }
+    }
+
+    /// Fill in client IP and user agent from the request
+    pub fn from_request(req: &HttpRequest, action: &str, outcome: AuditOutcome) -> Self {
+        let mut event = Self::new(action, outcome);
+        event.ip = client_ip(req);
+        event.user_agent = req
+            .headers()
+            .get("user-agent")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.chars().take(512).collect());
+        event
+    }
+
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+/// The client address: the TCP peer, or, when the peer is one of
+/// `TRUSTED_PROXIES`, the nearest untrusted hop in `X-Forwarded-For`. Each
+/// proxy appends the address it received from, so the list is read from the
+/// right; entries left of the first untrusted hop are whatever the client
+/// sent and are never used.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    let Some(cfg) = req.app_data::<web::Data<AppConfig>>() else { return Some(peer.to_string()) };
+    let trusted = &cfg.security.trusted_proxies;
+    if !trusted.contains(&peer) {
+        return Some(peer.to_string());
+    }
+    let hops: Vec<&str> = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    for hop in hops.iter().rev() {
+        match forwarded_ip(hop) {
+            Some(ip) if trusted.contains(&ip) => continue,
+            Some(ip) => return Some(ip.to_string()),
+            // A trusted proxy wouldn't write this; don't guess past it
+            None => break,
+        }
+    }
+    Some(peer.to_string())
+}
+
+/// One `X-Forwarded-For` entry: an address, optionally with a port
+fn forwarded_ip(hop: &str) -> Option<IpAddr> {
+    hop.parse().ok().or_else(|| hop.parse::<SocketAddr>().ok().map(|a| a.ip()))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub ts: String,
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: AuditOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// SHA-256 over the previous hash and every field except `hash` itself
+    fn compute_hash(&self) -> String {
+        let body = serde_json::json!({
+            "seq": self.seq,
+            "ts": self.ts,
+            "actor": self.actor,
+            "action": self.action,
+            "target": self.target,
+            "ip": self.ip,
+            "user_agent": self.user_agent,
+            "outcome": self.outcome,
+            "details": self.details,
+        });
+        let mut hasher = Sha256::new();
+        hasher.update(self.prev_hash.as_bytes());
+        hasher.update(body.to_string().as_bytes());
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Append an event to the chain
+pub fn append(db: &Database, event: AuditEvent) -> Result<AuditEntry> {
+    let tree = db.db.open_tree(AUDIT_TREE)?;
+    let _guard = APPEND_LOCK.lock().map_err(|_| anyhow!("audit lock poisoned"))?;
+
+    let (seq, prev_hash) = match tree.last()? {
+        Some((_k, v)) => {
+            let last: AuditEntry = serde_json::from_slice(&v)?;
+            (last.seq + 1, last.hash)
+        }
+        None => (1, GENESIS_HASH.to_string()),
+    };
+    let mut entry = AuditEntry {
+        seq,
+        ts: Utc::now().to_rfc3339(),
+        actor: event.actor,
+        action: event.action,
+        target: event.target,
+        ip: event.ip,
+        user_agent: event.user_agent,
+        outcome: event.outcome,
+        details: event.details,
+        prev_hash,
+        hash: String::new(),
+    };
+    entry.hash = entry.compute_hash();
+
+    // Big-endian sequence keys keep the tree in append order
+    tree.insert(seq.to_be_bytes(), serde_json::to_vec(&entry)?)?;
+    // Flushed per the durability policy (`audit_log=always` for per-entry fsync)
+    db.persist(&[AUDIT_TREE])?;
+    Ok(entry)
+}
+
+fn count_auth_event(event: &AuditEvent) {
+    if let Some(name) = event.action.strip_prefix("auth.") {
+        let outcome = match event.outcome {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+            AuditOutcome::Denied => "denied",
+        };
+        crate::metrics::AUTH_EVENTS.inc(&[name, outcome]);
+    }
+}
+
+/// Record an event, logging instead of failing the request if the write
+/// fails. The write runs on the blocking pool, off the async workers.
+pub async fn record(db: &Database, event: AuditEvent) {
+    count_auth_event(&event);
+    let action = event.action.clone();
+    if let Err(e) = db.run(move |db| append(&db, event)).await {
+        log::error!("Failed to write audit entry '{}': {}", action, e);
+    }
+}
+
+/// `record` for callers outside the async runtime (CLI commands)
+pub fn record_blocking(db: &Database, event: AuditEvent) {
+    count_auth_event(&event);
+    let action = event.action.clone();
+    if let Err(e) = append(db, event) {
+        log::error!("Failed to write audit entry '{}': {}", action, e);
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    /// RFC 3339 lower bound (inclusive)
+    pub since: Option<String>,
+    /// RFC 3339 upper bound (exclusive)
+    pub until: Option<String>,
+    /// Only entries with a sequence number greater than this
+    pub after_seq: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    fn matches(&self, e: &AuditEntry, since: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) -> bool {
+        if self.actor.as_ref().is_some_and(|a| e.actor.as_ref() != Some(a)) {
+            return false;
+        }
+        // `action` matches exactly or as a dotted prefix (`user` matches `user.roles_updated`)
+        if let Some(a) = &self.action {
+            if e.action != *a && !e.action.starts_with(&format!("{}.", a)) {
+                return false;
+            }
+        }
+        if self.target.as_ref().is_some_and(|t| e.target.as_ref() != Some(t)) {
+            return false;
+        }
+        if self.outcome.is_some_and(|o| e.outcome != o) {
+            return false;
+        }
+        if since.is_some() || until.is_some() {
+            let ts = match DateTime::parse_from_rfc3339(&e.ts) {
+                Ok(ts) => ts.with_timezone(&Utc),
+                Err(_) => return false,
+            };
+            if since.is_some_and(|s| ts < s) || until.is_some_and(|u| ts >= u) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn parse_bound(value: &Option<String>) -> Result<Option<DateTime<Utc>>> {
+    value
+        .as_deref()
+        .map(|s| {
+            DateTime::parse_from_rfc3339(s)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|e| anyhow!("invalid timestamp '{}': {}", s, e))
+        })
+        .transpose()
+}
+
+/// Entries matching `filter`, oldest first
+pub fn query(db: &Database, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+    let tree = db.db.open_tree(AUDIT_TREE)?;
+    let since = parse_bound(&filter.since)?;
+    let until = parse_bound(&filter.until)?;
+    let start = filter.after_seq.map(|s| s + 1).unwrap_or(0);
+    let limit = filter.limit.unwrap_or(usize::MAX);
+
+    let mut out = Vec::new();
+    for item in tree.range(start.to_be_bytes()..) {
+        let (_k, v) = item?;
+        let entry: AuditEntry = serde_json::from_slice(&v)?;
+        if filter.matches(&entry, since, until) {
+            out.push(entry);
+            if out.len() >= limit {
+                break;
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainReport {
+    pub entries: u64,
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_invalid_seq: Option<u64>,
+}
+
+/// Walk the whole chain and report the first entry whose hash or link is broken
+pub fn verify_chain(db: &Database) -> Result<ChainReport> {
+    let tree = db.db.open_tree(AUDIT_TREE)?;
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut entries = 0u64;
+
+    for (expected_seq, item) in (1u64..).zip(tree.iter()) {
+        let (_k, v) = item?;
+        entries += 1;
+        let entry: AuditEntry = match serde_json::from_slice(&v) {
+            Ok(e) => e,
+            Err(_) => {
+                return Ok(ChainReport { entries, valid: false, first_invalid_seq: Some(expected_seq) });
+            }
+        };
+        if entry.seq != expected_seq || entry.prev_hash != prev_hash || entry.compute_hash() != entry.hash {
+            return Ok(ChainReport { entries, valid: false, first_invalid_seq: Some(expected_seq) });
+        }
+        prev_hash = entry.hash;
+    }
+    Ok(ChainReport { entries, valid: true, first_invalid_seq: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn chain_detects_tampering() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        append(&db, AuditEvent::new("auth.login", AuditOutcome::Success).actor("u1")).unwrap();
+        append(&db, AuditEvent::new("auth.login", AuditOutcome::Failure).target("u3")).unwrap();
+        append(&db, AuditEvent::new("user.roles_updated", AuditOutcome::Success).actor("u1").target("u2")).unwrap();
+
+        assert!(verify_chain(&db).unwrap().valid);
+
+        let filter = AuditFilter { action: Some("auth".into()), ..Default::default() };
+        assert_eq!(query(&db, &filter).unwrap().len(), 2);
+        let filter = AuditFilter { outcome: Some(AuditOutcome::Failure), ..Default::default() };
+        assert_eq!(query(&db, &filter).unwrap()[0].seq, 2);
+
+        // Rewrite the actor of entry 2 without fixing the hash
+        let tree = db.db.open_tree(AUDIT_TREE).unwrap();
+        let mut entry: AuditEntry = serde_json::from_slice(&tree.get(2u64.to_be_bytes()).unwrap().unwrap()).unwrap();
+        entry.actor = Some("someone-else".into());
+        tree.insert(2u64.to_be_bytes(), serde_json::to_vec(&entry).unwrap()).unwrap();
+
+        let report = verify_chain(&db).unwrap();
+        assert!(!report.valid);
+        assert_eq!(report.first_invalid_seq, Some(2));
+    }
+
+    #[test]
+    fn forwarded_addresses_need_a_trusted_peer() {
+        use actix_web::test::TestRequest;
+        let request = |cfg: &AppConfig, forwarded: &str| {
+            TestRequest::default()
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .insert_header(("x-forwarded-for", forwarded))
+                .app_data(web::Data::new(cfg.clone()))
+                .to_http_request()
+        };
+        let mut cfg = crate::config::load_config_from_file("/nonexistent/.env");
+        assert_eq!(client_ip(&request(&cfg, "203.0.113.9")).as_deref(), Some("10.0.0.1"));
+        cfg.security.trusted_proxies = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        assert_eq!(client_ip(&request(&cfg, "203.0.113.9")).as_deref(), Some("203.0.113.9"));
+
+        // The client wrote the left-most entry itself; the proxies appended the rest
+        let spoofed = "198.51.100.66, 203.0.113.9:5123, 10.0.0.2";
+        assert_eq!(client_ip(&request(&cfg, spoofed)).as_deref(), Some("203.0.113.9"));
+        // Nothing untrusted or unreadable before a trusted hop: the peer it is
+        assert_eq!(client_ip(&request(&cfg, "10.0.0.2")).as_deref(), Some("10.0.0.1"));
+        assert_eq!(client_ip(&request(&cfg, "198.51.100.66, garbage, 10.0.0.2")).as_deref(), Some("10.0.0.1"));
+    }
+}
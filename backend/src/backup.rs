@@ -1,5 +1,5 @@
 use anyhow::Result;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use log::{error, info, warn};
 use std::{
     path::{Path, PathBuf},
@@ -8,11 +8,15 @@ use std::{
 };
 use tokio::sync::Mutex;
 
+use crate::metrics;
+
 pub struct BackupManager {
     db_path: PathBuf,
     backup_dir: PathBuf,
     name_template: String,
     lock: Arc<Mutex<()>>,
+    started_at: DateTime<Utc>,
+    last_success: std::sync::Mutex<Option<DateTime<Utc>>>,
 }
 
 impl BackupManager {
@@ -26,6 +30,34 @@ impl BackupManager {
             backup_dir: backup_dir.as_ref().to_path_buf(),
             name_template: name_template.to_string(),
             lock: Arc::new(Mutex::new(())),
+            started_at: Utc::now(),
+            last_success: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// When the last backup succeeded, as far as this process knows
+    pub fn last_success(&self) -> Option<DateTime<Utc>> {
+        self.last_success.lock().ok().and_then(|t| *t)
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    pub(crate) fn mark_success(&self, at: DateTime<Utc>) {
+        if let Ok(mut last) = self.last_success.lock() {
+            *last = Some(at);
+        }
+        metrics::BACKUP_LAST_SUCCESS.set(at.timestamp() as f64);
+    }
+
+    /// Pick up the most recent backup left by a previous run, so readiness
+    /// doesn't report a stale backup just because the process restarted
+    pub async fn load_last_success(&self) {
+        if let Ok(Some(path)) = self.get_latest_backup().await {
+            if let Ok(modified) = tokio::fs::metadata(&path).await.and_then(|m| m.modified()) {
+                self.mark_success(modified.into());
+            }
         }
     }
 
@@ -47,26 +79,32 @@ impl BackupManager {
                 let mut last_err: Option<anyhow::Error> = None;
                 while attempts < 3 {
                     attempts += 1;
-                    match tokio::time::timeout(Duration::from_secs(180), self.do_backup(retention))
-                        .await
-                    {
+                    let started = std::time::Instant::now();
+                    let result = tokio::time::timeout(Duration::from_secs(180), self.do_backup(retention)).await;
+                    let elapsed = started.elapsed().as_secs_f64();
+                    match result {
                         Ok(Ok(())) => {
                             info!("Sled backup completed (attempt {} of 3)", attempts);
+                            metrics::BACKUP_DURATION.observe(&["success"], elapsed);
+                            self.mark_success(Utc::now());
                             last_err = None;
                             break;
                         }
                         Ok(Err(e)) => {
                             warn!("Sled backup error on attempt {}: {}", attempts, e);
+                            metrics::BACKUP_DURATION.observe(&["error"], elapsed);
                             last_err = Some(e);
                         }
                         Err(_) => {
                             warn!("Sled backup timed out on attempt {}", attempts);
+                            metrics::BACKUP_DURATION.observe(&["timeout"], elapsed);
                             last_err = Some(anyhow::anyhow!("timeout"));
                         }
                     }
                 }
                 if let Some(e) = last_err {
                     error!("Sled backup failed after 3 attempts: {}", e);
+                    metrics::BACKUP_FAILURES.inc(&[]);
                 }
             } else {
                 warn!("Backup skipped: another backup in progress for >5 minutes");
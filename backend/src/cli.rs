@@ -15,17 +15,25 @@ pub struct Cli {
     #[arg(short, long, default_value = ".env")]
     pub config: String,
 
-    /// Database connection string override
-    #[arg(long, env = "DATABASE_URL")]
+    /// TOML configuration file, below env and CLI settings (default: config.toml when present)
+    #[arg(long, env = "CONFIG_FILE")]
+    pub config_file: Option<String>,
+
+    /// Override a setting by env name or TOML key, e.g. `--set PORT=9000` (repeatable)
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+
+    /// Database connection string override (otherwise DATABASE_URL from the config)
+    #[arg(long)]
     pub database_url: Option<String>,
 
     /// Server port override
-    #[arg(short, long, env = "PORT")]
+    #[arg(short, long)]
     pub port: Option<u16>,
 
     /// Server host override
-    #[arg(long, env = "HOST", default_value = "127.0.0.1")]
-    pub host: String,
+    #[arg(long)]
+    pub host: Option<String>,
 
     // Compatibility flags (map to subcommands)
     #[arg(long = "dbtest", hide = true)]
@@ -71,6 +79,81 @@ pub enum Commands {
         #[command(subcommand)]
         action: UserCommands,
     },
+    /// Token signing key management (asymmetric token modes)
+    Keys {
+        #[command(subcommand)]
+        action: KeyCommands,
+    },
+    /// Encryption at rest: data key rotation and status
+    Encryption {
+        #[command(subcommand)]
+        action: EncryptionCommands,
+    },
+    /// Debug CORS rules
+    Cors {
+        #[command(subcommand)]
+        action: CorsCommands,
+    },
+    /// Inspect the effective configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CorsCommands {
+    /// Show which rule matches and what the preflight and response would carry
+    Test {
+        /// e.g. https://app.example.com
+        origin: String,
+        /// Method the browser would use, e.g. POST
+        method: String,
+        /// Request path, for path-scoped rules
+        #[arg(long, default_value = "/api/")]
+        path: String,
+        /// Request header the browser would ask for (repeatable)
+        #[arg(long = "header")]
+        headers: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommands {
+    /// Validate the configuration and report every problem found
+    Check,
+    /// Print the effective configuration as TOML, noting where each value came from
+    Print {
+        /// Hide passwords, keys and tokens
+        #[arg(long)]
+        redacted: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum EncryptionCommands {
+    /// Show data keys and how many records each collection has per key
+    Status,
+    /// Generate a new active data key and re-encrypt every record with it.
+    /// Old keys stay in the ring so existing backups can still be restored.
+    Rotate,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum KeyCommands {
+    /// List keys in the token key ring
+    List,
+    /// Generate a new active signing key; the previous one keeps verifying for the overlap
+    Rotate {
+        /// How long tokens signed with the previous key stay valid (e.g. 30m, 12h)
+        #[arg(long, default_value = "24h")]
+        overlap: String,
+    },
+    /// Stop accepting tokens signed with a key immediately
+    Retire {
+        #[arg(long)]
+        kid: String,
+    },
 }
 
 #[derive(Subcommand, Clone)]
@@ -139,6 +222,44 @@ pub enum DbCommands {
 #[derive(Subcommand, Debug, Clone)]
 pub enum UserCommands {
     AddAdmin(AddAdminArgs),
+    /// Create a regular user
+    Create(CreateUserArgs),
+    /// Block login, refresh and API keys for a user and end their sessions
+    Disable(UserSelectArgs),
+    /// Re-enable a disabled user
+    Enable(UserSelectArgs),
+    /// Require a new password at next login; prints the one-time reset token
+    ResetPassword(UserSelectArgs),
+    /// Delete a user, or scrub their personal data with --anonymize
+    Delete(DeleteUserArgs),
+    /// Print a short-lived access token acting as the user
+    Impersonate(UserSelectArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CreateUserArgs {
+    #[arg(long)]
+    pub email: String,
+    #[arg(long)]
+    pub password: String,
+    /// Global roles (repeatable); defaults to `user`
+    #[arg(long = "role")]
+    pub roles: Vec<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct UserSelectArgs {
+    #[arg(long)]
+    pub email: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DeleteUserArgs {
+    #[arg(long)]
+    pub email: String,
+    /// Keep the record but remove personal data (GDPR erasure)
+    #[arg(long)]
+    pub anonymize: bool,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -232,7 +353,8 @@ mod tests {
         let cli = Cli::parse_from(&["description_backend"]);
         assert!(!cli.verbose);
         assert_eq!(cli.config, ".env");
-        assert_eq!(cli.host, "127.0.0.1");
+        // Unset so the configured HOST applies
+        assert_eq!(cli.host, None);
         assert!(cli.is_server_mode());
     }
 
@@ -260,4 +382,62 @@ mod tests {
             _ => panic!("Expected db seed command"),
         }
     }
+
+    #[test]
+    fn test_user_delete_command() {
+        let cli = Cli::parse_from(["description_backend", "user", "delete", "--email", "a@b.dev", "--anonymize"]);
+        match cli.command {
+            Some(Commands::User {
+                action: UserCommands::Delete(args),
+            }) => {
+                assert_eq!(args.email, "a@b.dev");
+                assert!(args.anonymize);
+            }
+            _ => panic!("Expected user delete command"),
+        }
+    }
+
+    #[test]
+    fn test_keys_rotate_command() {
+        let cli = Cli::parse_from(["description_backend", "keys", "rotate", "--overlap", "2h"]);
+        match cli.command {
+            Some(Commands::Keys {
+                action: KeyCommands::Rotate { overlap },
+            }) => assert_eq!(overlap, "2h"),
+            _ => panic!("Expected keys rotate command"),
+        }
+    }
+
+    #[test]
+    fn test_encryption_rotate_command() {
+        let cli = Cli::parse_from(["description_backend", "encryption", "rotate"]);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Encryption { action: EncryptionCommands::Rotate })
+        ));
+    }
+
+    #[test]
+    fn test_cors_test_command() {
+        let cli = Cli::parse_from(["description_backend", "cors", "test", "https://a.dev", "PUT", "--header", "x-api-key"]);
+        match cli.command {
+            Some(Commands::Cors {
+                action: CorsCommands::Test { origin, method, path, headers },
+            }) => {
+                assert_eq!((origin.as_str(), method.as_str(), path.as_str()), ("https://a.dev", "PUT", "/api/"));
+                assert_eq!(headers, ["x-api-key"]);
+            }
+            _ => panic!("Expected cors test command"),
+        }
+    }
+
+    #[test]
+    fn test_config_print_command() {
+        let cli = Cli::parse_from(["description_backend", "--set", "PORT=9000", "config", "print", "--redacted"]);
+        assert_eq!(cli.overrides, ["PORT=9000"]);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Config { action: ConfigCommands::Print { redacted: true } })
+        ));
+    }
 }
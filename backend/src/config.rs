@@ -1,6 +1,11 @@
 use anyhow::{anyhow, Result};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use crate::durability::DurabilityPolicy;
+use crate::encryption::DataKeyRing;
+use crate::config_source::{self, ConfigLayers, ConfigSource};
+use actix_web::cookie::SameSite;
+use crate::keyring::KeyRing;
 use std::{
     collections::{HashMap, HashSet},
     fs,
@@ -15,15 +20,14 @@ pub struct PgConnConfig {
     pub targets: Option<HashSet<String>>, // table names
 }
 
-pub fn is_origin_allowed(rules: &[CorsRule], origin: &str) -> bool {
-    for rule in rules {
-        if origin_matches(&rule.origin, origin) {
-            return rule.action == CorsAction::Allow;
-        }
-    }
-    false
+/// The first rule matching both the origin and the request path
+pub fn find_cors_rule<'a>(rules: &'a [CorsRule], origin: &str, path: &str) -> Option<&'a CorsRule> {
+    rules.iter().find(|rule| {
+        origin_matches(&rule.origin, origin) && (rule.paths.is_empty() || rule.paths.iter().any(|p| origin_matches(p, path)))
+    })
 }
 
+/// `*` wildcard match, used for origins and rule paths
 fn origin_matches(pattern: &str, origin: &str) -> bool {
     // Convert wildcard pattern to anchored regex
     let mut re_pat = String::new();
@@ -57,18 +61,67 @@ pub struct DatabaseConfig {
     pub idle_timeout: u64,
     pub max_lifetime: u64,
     pub ssl_mode: String,
+    /// `DATABASE_URL`, used instead of the fields above when set
+    pub url: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct CorsRule {
     pub origin: String,
     pub action: CorsAction,
+    /// Uppercase methods, or `ALL`
     pub methods: Vec<String>,
+    /// Lowercase request headers, or `ALL`
     pub headers: Vec<String>,
+    /// Let the browser send cookies and `Authorization` (`credentials=false` turns it off)
+    pub credentials: bool,
+    /// Seconds a browser may cache the preflight answer
+    pub max_age: u32,
+    /// Response headers scripts may read, besides `x-request-id`
+    pub expose_headers: Vec<String>,
+    /// Path patterns (`*` wildcard) the rule is limited to; empty for every path
+    pub paths: Vec<String>,
+    /// Line in the rules file, for `cors test`
+    pub line: usize,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl CorsRule {
+    pub fn allows_method(&self, method: &str) -> bool {
+        self.methods.iter().any(|m| m == "ALL" || m.eq_ignore_ascii_case(method))
+    }
+
+    pub fn allows_header(&self, header: &str) -> bool {
+        self.headers.iter().any(|h| h == "ALL" || h.eq_ignore_ascii_case(header))
+    }
+}
+
+/// The rule in the rules file syntax
+impl std::fmt::Display for CorsRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match self.action {
+            CorsAction::Allow => "ALLOW",
+            CorsAction::Deny => "DENY",
+        };
+        write!(f, "{} {} {} {}", self.origin, action, self.methods.join(","), self.headers.join(","))?;
+        if !self.credentials {
+            f.write_str(" credentials=false")?;
+        }
+        if self.max_age != DEFAULT_CORS_MAX_AGE {
+            write!(f, " max-age={}", self.max_age)?;
+        }
+        if !self.expose_headers.is_empty() {
+            write!(f, " expose={}", self.expose_headers.join(","))?;
+        }
+        if !self.paths.is_empty() {
+            write!(f, " path={}", self.paths.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub enum CorsAction {
+    #[default]
     Allow,
     Deny,
 }
@@ -82,11 +135,118 @@ pub struct AppConfig {
     pub backup_name_template: String,
     pub backup_interval: Option<Duration>,
     pub backup_retention: usize,
+    /// `/readyz` reports backups as degraded once the last success is older
+    /// than this (defaults to twice the backup interval)
+    pub backup_max_age: Option<Duration>,
+    /// Free space below which `/readyz` reports a data or backup volume as degraded
+    pub health_min_free_disk_mb: u64,
     pub pg_conns: Vec<PgConnConfig>,
     pub cors_rules: Vec<CorsRule>,
     pub logging: LoggingConfig,
     pub security: SecurityConfig,
     pub database_sync_on: bool,
+    pub durability: DurabilityPolicy,
+    /// Change-feed events kept before the oldest are pruned
+    pub event_log_max_events: usize,
+    /// Revisions kept per entity record before the oldest are trimmed
+    pub history_max_revisions: usize,
+    /// Directory of entity JSON Schemas served by the generic CRUD router
+    pub entities_dir: String,
+    pub encryption: EncryptionConfig,
+    /// Entity collections whose deletes go to the trash
+    pub soft_delete_collections: Vec<String>,
+    /// How long trashed records are kept before the purge job removes them
+    pub trash_retention: Duration,
+    pub trash_purge_interval: Duration,
+    /// Reload the configuration when the .env or CORS file changes
+    pub config_watch: bool,
+    pub headers: HeadersConfig,
+    pub cookies: CookieConfig,
+}
+
+/// Attributes of the session cookies set on login, refresh and org switch
+#[derive(Debug, Clone, PartialEq)]
+pub struct CookieConfig {
+    /// Only send the cookies over HTTPS; turn off for plain-HTTP development
+    pub secure: bool,
+    /// `Domain` attribute; `None` scopes the cookies to the exact host
+    pub domain: Option<String>,
+    pub same_site: SameSite,
+}
+
+/// Policy used when `CSP_POLICY` is not set
+pub const DEFAULT_CSP: &str = "default-src 'self'; script-src 'self' 'wasm-unsafe-eval' 'nonce-{nonce}'; \
+     style-src 'self' 'unsafe-inline'; img-src 'self' data:; font-src 'self' data:; connect-src 'self' https:; \
+     frame-ancestors 'none'; base-uri 'self'";
+
+/// Response headers set by `middleware::security`
+#[derive(Debug, Clone)]
+pub struct HeadersConfig {
+    /// Content-Security-Policy; `{nonce}` becomes a fresh nonce per response,
+    /// which the SPA fallback also adds to index.html's script and style tags.
+    /// Empty sends no policy.
+    pub csp: String,
+    /// Send `Content-Security-Policy-Report-Only` to try a policy out first
+    pub csp_report_only: bool,
+    /// Appended as `report-uri`; empty for none
+    pub csp_report_uri: String,
+    /// HSTS is only sent when set; enable it once the site is HTTPS-only
+    pub hsts_max_age: Option<Duration>,
+    pub hsts_include_subdomains: bool,
+    pub hsts_preload: bool,
+    /// Empty omits the header
+    pub permissions_policy: String,
+    /// `X-Frame-Options` value (`DENY` or `SAMEORIGIN`); `None` omits it
+    pub frame_options: Option<String>,
+}
+
+impl HeadersConfig {
+    /// The policy for one response
+    pub fn csp_header(&self, nonce: &str) -> Option<String> {
+        if self.csp.trim().is_empty() {
+            return None;
+        }
+        let mut policy = self.csp.trim().trim_end_matches(';').replace("{nonce}", nonce);
+        if !self.csp_report_uri.is_empty() && !policy.contains("report-uri") {
+            policy.push_str("; report-uri ");
+            policy.push_str(&self.csp_report_uri);
+        }
+        Some(policy)
+    }
+
+    pub fn hsts_header(&self) -> Option<String> {
+        let max_age = self.hsts_max_age?;
+        let mut value = format!("max-age={}", max_age.as_secs());
+        if self.hsts_include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if self.hsts_preload {
+            value.push_str("; preload");
+        }
+        Some(value)
+    }
+}
+
+/// Encryption at rest (see `encryption.rs`)
+#[derive(Clone)]
+pub struct EncryptionConfig {
+    /// Collections stored encrypted; `*` for all of them
+    pub collections: Vec<String>,
+    /// Hex data key from `DB_ENCRYPTION_KEY`, used when the key ring is empty
+    pub key_hex: String,
+    pub keyring_path: String,
+    pub keyring: DataKeyRing,
+}
+
+impl std::fmt::Debug for EncryptionConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptionConfig")
+            .field("collections", &self.collections)
+            .field("key_hex", &"<redacted>")
+            .field("keyring_path", &self.keyring_path)
+            .field("keyring", &self.keyring)
+            .finish()
+    }
 }
 
 #[allow(dead_code)]
@@ -94,12 +254,28 @@ pub struct AppConfig {
 pub enum TokenMode {
     JwtHmac,
     PasetoV4Local,
+    /// Ed25519-signed PASETO v4.public, verifiable with the published key ring
+    PasetoV4Public,
+    /// Ed25519-signed JWT (`alg: EdDSA`), verifiable with the published key ring
+    JwtEdDsa,
+}
+
+impl TokenMode {
+    /// Modes that sign with the key ring instead of a shared secret
+    pub fn is_asymmetric(&self) -> bool {
+        matches!(self, TokenMode::PasetoV4Public | TokenMode::JwtEdDsa)
+    }
 }
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct SecurityConfig {
     pub access_token: String,
+    /// Accept `ACCESS_TOKEN` as a full admin credential (off unless explicitly enabled)
+    pub access_token_admin_bypass: bool,
+    /// Peers whose forwarding headers are believed for the client address;
+    /// empty trusts none
+    pub trusted_proxies: Vec<std::net::IpAddr>,
     pub rate_limit_enabled: bool,
     pub rate_limit_rpm: u32,
     pub auth_token_expiry_hours: u64,
@@ -108,9 +284,13 @@ pub struct SecurityConfig {
     pub token_ttl_seconds: u64,
     pub paseto_v4_local_key_hex: String,
     pub token_mode: TokenMode,
+    pub token_keyring_path: String,
+    pub keyring: KeyRing,
     pub debug_mode: bool,
     pub health_check_enabled: bool,
     pub metrics_enabled: bool,
+    /// Bearer token Prometheus must present on `/metrics`; empty keeps it closed
+    pub metrics_token: String,
 }
 
 #[derive(Debug, Clone)]
@@ -122,12 +302,76 @@ pub struct ServerConfig {
 
 #[derive(Debug, Clone)]
 pub struct LoggingConfig {
+    /// `EnvFilter` directives, e.g. `info` or `info,actix_web=warn`
     pub level: String,
+    pub format: LogFormat,
     pub file_enabled: bool,
     pub file_path: Option<String>,
+    /// Start a new file once the current one would exceed this many bytes
+    pub file_max_size: Option<u64>,
+    pub file_rotation: LogRotation,
+    /// Rotated files kept next to the active one
+    pub file_retention: usize,
+    /// OTLP/HTTP collector to export spans to (needs the `otlp` feature)
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, for log shippers
+    Json,
 }
 
-fn parse_duration(s: &str) -> Result<Duration> {
+/// Time-based rotation of the log file (on top of `file_max_size`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl LoggingConfig {
+    /// Read before the rest of the config so logging is set up in time to
+    /// report config problems
+    pub fn from_source(src: &ConfigSource) -> Self {
+        Self {
+            level: src.string("LOG_LEVEL"),
+            format: match src.choice("LOG_FORMAT", &["text", "json"]).as_str() {
+                "json" => LogFormat::Json,
+                _ => LogFormat::Text,
+            },
+            file_enabled: src.bool("LOG_FILE_ENABLED"),
+            file_path: src.opt("LOG_FILE_PATH"),
+            // 0 means no size limit
+            file_max_size: src.size("LOG_FILE_MAX_SIZE").filter(|&n| n > 0),
+            file_rotation: match src.choice("LOG_FILE_ROTATION", &["never", "hourly", "daily"]).as_str() {
+                "never" => LogRotation::Never,
+                "hourly" => LogRotation::Hourly,
+                _ => LogRotation::Daily,
+            },
+            file_retention: src.parse("LOG_FILE_RETENTION"),
+            otlp_endpoint: src.opt("OTEL_EXPORTER_OTLP_ENDPOINT"),
+        }
+    }
+}
+
+/// Byte sizes like `512`, `64KB`, `100MB` or `1GB` (binary multiples)
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim().to_lowercase();
+    let (digits, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let n: u64 = digits.parse()?;
+    let multiplier: u64 = match unit.trim() {
+        "" | "b" => 1,
+        "k" | "kb" => 1024,
+        "m" | "mb" => 1024 * 1024,
+        "g" | "gb" => 1024 * 1024 * 1024,
+        _ => return Err(anyhow!("Invalid size format: {}", s)),
+    };
+    n.checked_mul(multiplier).ok_or_else(|| anyhow!("Size too large: {}", s))
+}
+
+pub fn parse_duration(s: &str) -> Result<Duration> {
     let s = s.trim().to_lowercase();
     if s.ends_with("ms") {
         let n: u64 = s[..s.len() - 2].parse()?;
@@ -145,186 +389,305 @@ fn parse_duration(s: &str) -> Result<Duration> {
         let n: u64 = s[..s.len() - 1].parse()?;
         return Ok(Duration::from_secs(n * 3600));
     }
+    if s.ends_with('d') {
+        let n: u64 = s[..s.len() - 1].parse()?;
+        return Ok(Duration::from_secs(n * 24 * 3600));
+    }
     Err(anyhow!("Invalid duration format: {}", s))
 }
 
+/// Problems that make a configuration unusable, beyond unparsable values
+fn problems(cfg: &AppConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    if cfg.server.port == 0 {
+        problems.push("PORT must not be 0".to_string());
+    }
+    for rule in &cfg.cors_rules {
+        if rule.origin.is_empty() || rule.origin.chars().any(char::is_whitespace) {
+            problems.push(format!("invalid CORS origin pattern '{}'", rule.origin));
+        }
+    }
+    if cfg.backup_interval.is_some_and(|i| i.is_zero()) {
+        problems.push("PERIODIC_BACKUP_DB must be positive (leave it unset to disable backups)".to_string());
+    }
+    if cfg.trash_purge_interval.is_zero() {
+        problems.push("TRASH_PURGE_INTERVAL must be positive".to_string());
+    }
+    if cfg.database.min_connections > cfg.database.max_connections {
+        problems.push("DB_MIN_CONNECTIONS must not exceed DB_MAX_CONNECTIONS".to_string());
+    }
+    let sec = &cfg.security;
+    if sec.rate_limit_enabled && sec.rate_limit_rpm == 0 {
+        problems.push("API_RATE_LIMIT_REQUESTS_PER_MINUTE must be positive when rate limiting is enabled".to_string());
+    }
+    if sec.token_ttl_seconds == 0 {
+        problems.push("token TTL must be positive".to_string());
+    }
+    // Browsers drop `SameSite=None` cookies that aren't `Secure`
+    if cfg.cookies.same_site == SameSite::None && !cfg.cookies.secure {
+        problems.push("COOKIE_SAMESITE=none requires COOKIE_SECURE=true".to_string());
+    }
+    let headers = &cfg.headers;
+    for (name, value) in [
+        ("CSP_POLICY", headers.csp_header("nonce").unwrap_or_default()),
+        ("PERMISSIONS_POLICY", headers.permissions_policy.clone()),
+    ] {
+        if actix_web::http::header::HeaderValue::from_str(&value).is_err() {
+            problems.push(format!("{} is not a valid header value", name));
+        }
+    }
+    if headers.hsts_preload && (!headers.hsts_include_subdomains || headers.hsts_max_age.is_none_or(|a| a.as_secs() < 31_536_000)) {
+        problems.push("HSTS_PRELOAD needs HSTS_INCLUDE_SUBDOMAINS and an HSTS_MAX_AGE of at least 365d".to_string());
+    }
+    if sec.token_mode == TokenMode::PasetoV4Local {
+        let key = sec.paseto_v4_local_key_hex.trim();
+        if key.len() != 64 || hex::decode(key).is_err() {
+            problems.push("PASETO_V4_LOCAL_KEY_HEX must be 64 hex characters in PASETO local mode".to_string());
+        }
+    }
+    problems
+}
+
+/// Reject configurations the server can't run with. Used before a reloaded
+/// configuration replaces the live one.
+pub fn validate(cfg: &AppConfig) -> Result<()> {
+    let mut problems = problems(cfg);
+    // At startup a missing key is generated instead (see main)
+    let sec = &cfg.security;
+    if sec.token_mode.is_asymmetric() && sec.keyring.active().is_none() {
+        problems.push(format!("no active signing key in {}", sec.token_keyring_path));
+    }
+    config_source::check(problems)
+}
+
+/// Load the configuration, exiting the process when it can't be used
+pub fn load_config(layers: &ConfigLayers) -> AppConfig {
+    try_load_config(layers).unwrap_or_else(|e| {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// `load_config` with only a .env file (plus the environment and `config.toml`)
 pub fn load_config_from_file(config_path: &str) -> AppConfig {
-    // Load .env file if it exists
-    let abs_config_path = Path::new(config_path)
-        .canonicalize()
-        .unwrap_or_else(|_| PathBuf::from(config_path));
+    load_config(&ConfigLayers::env_file(config_path))
+}
+
+/// Names set in the real process environment at startup. On reload these
+/// keep precedence over the .env file, exactly as they do at startup.
+static PROCESS_ENV: once_cell::sync::OnceCell<std::collections::HashSet<String>> = once_cell::sync::OnceCell::new();
+
+/// Remember the process environment; call before anything loads the .env file
+pub fn snapshot_process_env() {
+    let _ = PROCESS_ENV.set(std::env::vars_os().filter_map(|(k, _)| k.into_string().ok()).collect());
+}
 
-    tracing::info!("About to load .env file from: {}", abs_config_path.display());
+/// Whether `key` came from the process environment rather than the .env
+/// file (always true before `snapshot_process_env`)
+pub fn is_process_env(key: &str) -> bool {
+    PROCESS_ENV.get().is_none_or(|env| env.contains(key))
+}
 
+/// The environment as a reload sees it: the real process environment, with
+/// the .env file as it is now in place of the copy loaded at startup
+/// (variables removed from the file fall back to their defaults). Nothing is
+/// written to the process environment, which handlers may read concurrently.
+pub fn reload_env(config_path: &str) -> Result<std::collections::BTreeMap<String, String>> {
+    let mut env: std::collections::BTreeMap<String, String> = std::env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+        .filter(|(k, _)| is_process_env(k))
+        .collect();
     if Path::new(config_path).exists() {
-        match dotenvy::from_filename(config_path) {
-            Ok(_) => tracing::info!("✓ Loaded .env file from: {}", abs_config_path.display()),
-            Err(e) => tracing::warn!("Failed to load .env file from {}: {}", abs_config_path.display(), e),
+        for item in dotenvy::from_filename_iter(config_path)? {
+            let (key, value) = item?;
+            env.entry(key).or_insert(value);
         }
-    } else {
-        tracing::warn!(".env file not found at: {} (using defaults)", abs_config_path.display());
     }
+    Ok(env)
+}
+
+/// Build the configuration again for a reload, from `reload_env` instead of
+/// the process environment
+pub fn reload_config(layers: &ConfigLayers) -> Result<AppConfig> {
+    from_source(&ConfigSource::from_layers(layers, reload_env(&layers.env_file)?))
+}
 
+/// Resolve all configuration layers and build the configuration, reporting
+/// every invalid value at once
+pub fn try_load_config(layers: &ConfigLayers) -> Result<AppConfig> {
+    let src = ConfigSource::load(layers);
+    match src.toml_file() {
+        Some(path) => tracing::info!("Configuration: {} + environment ({})", path.display(), layers.env_file),
+        None => tracing::info!("Configuration: environment ({})", layers.env_file),
+    }
+    from_source(&src)
+}
+
+pub fn from_source(src: &ConfigSource) -> Result<AppConfig> {
     // Server configuration
     let server = ServerConfig {
-        host: std::env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
-        port: std::env::var("PORT")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(8081),
-        name: std::env::var("SERVER_NAME").unwrap_or_else(|_| "description_backend".to_string()),
+        host: src.string("HOST"),
+        port: src.parse("PORT"),
+        name: src.string("SERVER_NAME"),
     };
 
     // Database configuration
     let database = DatabaseConfig {
-        host: std::env::var("DB_HOST").unwrap_or_else(|_| "localhost".to_string()),
-        port: std::env::var("DB_PORT")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(5432),
-        database: std::env::var("DB_NAME").unwrap_or_else(|_| "quoteflow".to_string()),
-        username: std::env::var("DB_USER").unwrap_or_else(|_| "postgres".to_string()),
-        password: std::env::var("DB_PASSWORD").unwrap_or_else(|_| "".to_string()),
-        max_connections: std::env::var("DB_MAX_CONNECTIONS")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(10),
-        min_connections: std::env::var("DB_MIN_CONNECTIONS")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(1),
-        connection_timeout: std::env::var("DB_CONNECTION_TIMEOUT")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(30),
-        idle_timeout: std::env::var("DB_IDLE_TIMEOUT")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(600),
-        max_lifetime: std::env::var("DB_MAX_LIFETIME")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(3600),
-        ssl_mode: std::env::var("DB_SSL_MODE").unwrap_or_else(|_| "prefer".to_string()),
+        host: src.string("DB_HOST"),
+        port: src.parse("DB_PORT"),
+        database: src.opt("DB_NAME").unwrap_or_else(|| "quoteflow".to_string()),
+        username: src.string("DB_USER"),
+        password: src.string("DB_PASSWORD"),
+        max_connections: src.parse("DB_MAX_CONNECTIONS"),
+        min_connections: src.parse("DB_MIN_CONNECTIONS"),
+        connection_timeout: src.parse("DB_CONNECTION_TIMEOUT"),
+        idle_timeout: src.parse("DB_IDLE_TIMEOUT"),
+        max_lifetime: src.parse("DB_MAX_LIFETIME"),
+        ssl_mode: src.choice("DB_SSL_MODE", &["disable", "allow", "prefer", "require", "verify-ca", "verify-full"]),
+        url: src.opt("DATABASE_URL").filter(|u| !u.is_empty()),
     };
 
     // Logging configuration
-    let logging = LoggingConfig {
-        level: std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()),
-        file_enabled: std::env::var("LOG_FILE_ENABLED")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(false),
-        file_path: std::env::var("LOG_FILE_PATH").ok(),
-    };
+    let logging = LoggingConfig::from_source(src);
 
-    // Sled configuration (new vars with fallback to legacy)
-    let db_name = std::env::var("DB_NAME").unwrap_or_else(|_| "quoteflow_data".to_string());
+    // Sled configuration
+    let db_name = src.opt("DB_NAME").unwrap_or_else(|| "quoteflow_data".to_string());
     // Use a directory by default to avoid backup errors
-    let db_path = std::env::var("DB_PATH").unwrap_or_else(|_| "data".to_string());
+    let db_path = src.string("DB_PATH");
     let sled_path = if db_path.ends_with('/') {
         format!("{}{}", db_path, db_name)
     } else {
         format!("{}/{}", db_path, db_name)
     };
 
-    let backup_dir =
-        std::env::var("PERIODIC_BACKUP_PATH").unwrap_or_else(|_| "backups".to_string());
-    let backup_name_template = std::env::var("PERIODIC_BACKUP_NAME")
-        .unwrap_or_else(|_| "quoteflow_data_backup_{{timestamp}}".to_string());
-    let backup_interval = std::env::var("PERIODIC_BACKUP_DB")
-        .ok()
-        .and_then(|v| parse_duration(&v).ok());
+    let backup_dir = src.string("PERIODIC_BACKUP_PATH");
+    let backup_name_template = src.string("PERIODIC_BACKUP_NAME");
+    let backup_interval = src.duration("PERIODIC_BACKUP_DB");
     let backup_retention: usize = 10;
-
-    // Parse legacy PostgreSQL connections
-    let mut pg_conns: Vec<PgConnConfig> = Vec::new();
-    let re = Regex::new(r"^DATABASE_(\d+)_(CONNECTION_PG_STRING|TARGETS)$").unwrap();
-    use std::collections::BTreeMap;
-    let mut buckets: BTreeMap<String, (Option<String>, Option<String>)> = BTreeMap::new();
-
-    for (k, v) in std::env::vars() {
-        if let Some(caps) = re.captures(&k) {
-            let idx = caps.get(1).unwrap().as_str().to_string();
-            let kind = caps.get(2).unwrap().as_str();
-            let entry = buckets.entry(idx).or_default();
-            match kind {
-                "CONNECTION_PG_STRING" => entry.0 = Some(v),
-                "TARGETS" => entry.1 = Some(v),
-                _ => {}
-            }
-        }
-    }
-
-    for (_idx, (conn_opt, targets_opt)) in buckets.into_iter() {
-        if let Some(conn_string) = conn_opt {
-            let targets = targets_opt.map(|s| {
-                s.split_whitespace()
-                    .map(|t| t.trim().to_string())
-                    .filter(|t| !t.is_empty())
-                    .collect::<HashSet<_>>()
-            });
-            pg_conns.push(PgConnConfig {
-                conn_string,
-                targets,
-            });
-        }
+    if let Some(legacy) = src.opt("BACKUP_PATH").filter(|p| !p.is_empty()) {
+        tracing::warn!(
+            "BACKUP_PATH ({}) is no longer used; backups go to PERIODIC_BACKUP_PATH as PERIODIC_BACKUP_NAME",
+            legacy
+        );
     }
+    let backup_max_age = src.duration("HEALTH_BACKUP_MAX_AGE").or(backup_interval.map(|i| i * 2));
+    let health_min_free_disk_mb = src.parse("HEALTH_MIN_FREE_DISK_MB");
+
+    // PostgreSQL replicas
+    let pg_conns = src
+        .replicas()
+        .into_iter()
+        .map(|(conn_string, targets)| PgConnConfig {
+            conn_string,
+            targets: targets.map(|s| s.split_whitespace().map(str::to_string).collect::<HashSet<_>>()),
+        })
+        .collect();
 
     // Load CORS rules from .env_cors file
-    let cors_rules = load_cors_rules(".env_cors");
+    let (cors_rules, cors_errors) = load_cors_rules(CORS_RULES_FILE);
+    cors_errors.into_iter().for_each(|e| src.error(e));
 
     // Security configuration
-    let token_mode_env = std::env::var("TOKEN_JWT_HMAC_OR_PURE_PASSETO_NOTJWT")
-        .unwrap_or_else(|_| "jwt_hmac".into());
-    let token_mode = if token_mode_env.eq_ignore_ascii_case("pure_passeto_not_jwt") {
-        TokenMode::PasetoV4Local
-    } else {
-        TokenMode::JwtHmac
+    let token_mode = match src
+        .choice(
+            "TOKEN_JWT_HMAC_OR_PURE_PASSETO_NOTJWT",
+            &["jwt_hmac", "pure_passeto_not_jwt", "pure_passeto_public", "jwt_eddsa"],
+        )
+        .as_str()
+    {
+        "pure_passeto_not_jwt" => TokenMode::PasetoV4Local,
+        "pure_passeto_public" => TokenMode::PasetoV4Public,
+        "jwt_eddsa" => TokenMode::JwtEdDsa,
+        _ => TokenMode::JwtHmac,
     };
 
+    // A missing ring loads empty and is bootstrapped at startup; one that
+    // can't be read must stop us, since bootstrap or `keys` would save over it
+    let token_keyring_path = src.string("TOKEN_KEYRING_PATH");
+    let mut keyring_errors = Vec::new();
+    let keyring = KeyRing::load(&token_keyring_path).unwrap_or_else(|e| {
+        keyring_errors.push(format!("Failed to load token key ring from {}: {:#}", token_keyring_path, e));
+        KeyRing::default()
+    });
+
+    let trusted_proxies = src
+        .list("TRUSTED_PROXIES")
+        .iter()
+        .filter_map(|ip| match ip.parse() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                src.error(format!("TRUSTED_PROXIES: '{}' is not an IP address", ip));
+                None
+            }
+        })
+        .collect();
+
     let security = SecurityConfig {
-        access_token: std::env::var("ACCESS_TOKEN").unwrap_or_default(),
-        rate_limit_enabled: std::env::var("API_RATE_LIMIT_ENABLED")
-            .unwrap_or_else(|_| "false".into())
-            .parse()
-            .unwrap_or(false),
-        rate_limit_rpm: std::env::var("API_RATE_LIMIT_REQUESTS_PER_MINUTE")
-            .unwrap_or_else(|_| "100".into())
-            .parse()
-            .unwrap_or(100),
-        auth_token_expiry_hours: std::env::var("AUTH_TOKEN_EXPIRY_HOURS")
-            .unwrap_or_else(|_| "24".into())
-            .parse()
-            .unwrap_or(24),
-        token_iss: std::env::var("TOKEN_ISS").unwrap_or_else(|_| "description_backend".into()),
-        token_aud: std::env::var("TOKEN_AUD").unwrap_or_else(|_| "quoteflowfrontend".into()),
-        token_ttl_seconds: std::env::var("TOKEN_TTL_SECONDS")
-            .unwrap_or_else(|_| "43200".into())
-            .parse()
-            .unwrap_or(43200),
-        paseto_v4_local_key_hex: std::env::var("PASETO_V4_LOCAL_KEY_HEX").unwrap_or_default(),
+        access_token: src.string("ACCESS_TOKEN"),
+        access_token_admin_bypass: src.bool("ACCESS_TOKEN_ADMIN_BYPASS"),
+        trusted_proxies,
+        rate_limit_enabled: src.bool("API_RATE_LIMIT_ENABLED"),
+        rate_limit_rpm: src.parse("API_RATE_LIMIT_REQUESTS_PER_MINUTE"),
+        auth_token_expiry_hours: src.parse("AUTH_TOKEN_EXPIRY_HOURS"),
+        token_iss: src.string("TOKEN_ISS"),
+        token_aud: src.string("TOKEN_AUD"),
+        token_ttl_seconds: src.parse("TOKEN_TTL_SECONDS"),
+        paseto_v4_local_key_hex: src.string("PASETO_V4_LOCAL_KEY_HEX"),
         token_mode,
-        debug_mode: std::env::var("DEBUG_MODE")
-            .unwrap_or_else(|_| "false".into())
-            .parse()
-            .unwrap_or(false),
-        health_check_enabled: std::env::var("HEALTH_CHECK_ENABLED")
-            .unwrap_or_else(|_| "true".into())
-            .parse()
-            .unwrap_or(true),
-        metrics_enabled: std::env::var("METRICS_ENABLED")
-            .unwrap_or_else(|_| "false".into())
-            .parse()
-            .unwrap_or(false),
+        token_keyring_path,
+        keyring,
+        debug_mode: src.bool("DEBUG_MODE"),
+        health_check_enabled: src.bool("HEALTH_CHECK_ENABLED"),
+        metrics_enabled: src.bool("METRICS_ENABLED"),
+        metrics_token: src.string("METRICS_TOKEN"),
+    };
+
+    let cookies = CookieConfig {
+        secure: src.bool("COOKIE_SECURE"),
+        domain: src.opt("COOKIE_DOMAIN").filter(|d| !d.is_empty()),
+        same_site: match src.choice("COOKIE_SAMESITE", &["strict", "lax", "none"]).as_str() {
+            "lax" => SameSite::Lax,
+            "none" => SameSite::None,
+            _ => SameSite::Strict,
+        },
     };
 
     // Database sync on/off
-    let database_sync_on = std::env::var("DATABASE_SYNC_ON_OFF")
-        .unwrap_or_else(|_| "on".into())
-        .eq_ignore_ascii_case("on");
+    let database_sync_on = matches!(src.choice("DATABASE_SYNC_ON_OFF", &["on", "off", "true", "false"]).as_str(), "on" | "true");
+
+    // When sled writes are flushed; critical writes can still demand a flush
+    let durability = DurabilityPolicy::parse(&src.string("DB_DURABILITY"), &src.string("DB_DURABILITY_OVERRIDES"))
+        .unwrap_or_else(|e| {
+            src.error(format!("DB_DURABILITY: {}", e));
+            DurabilityPolicy::default()
+        });
+
+    let event_log_max_events = src.parse("EVENT_LOG_MAX_EVENTS");
+    let history_max_revisions: usize = src.parse("HISTORY_MAX_REVISIONS");
+    if history_max_revisions == 0 {
+        src.error("HISTORY_MAX_REVISIONS must be at least 1".to_string());
+    }
 
-    AppConfig {
+    let entities_dir = src.string("ENTITIES_DIR");
+
+    let soft_delete_collections = src.list("SOFT_DELETE_COLLECTIONS");
+    let trash_retention = src.duration("TRASH_RETENTION").unwrap_or(Duration::from_secs(30 * 24 * 3600));
+    let trash_purge_interval = src.duration("TRASH_PURGE_INTERVAL").unwrap_or(Duration::from_secs(3600));
+
+    // Starting with a fresh ring here would also leave existing records unreadable
+    let data_keyring_path = src.string("DB_ENCRYPTION_KEYRING_PATH");
+    let data_keyring = DataKeyRing::load(&data_keyring_path).unwrap_or_else(|e| {
+        keyring_errors.push(format!("Failed to load data key ring from {}: {}", data_keyring_path, e));
+        DataKeyRing::default()
+    });
+    let encryption = EncryptionConfig {
+        collections: src.list("DB_ENCRYPTED_COLLECTIONS"),
+        key_hex: src.string("DB_ENCRYPTION_KEY"),
+        keyring: data_keyring,
+        keyring_path: data_keyring_path,
+    };
+
+    let cfg = AppConfig {
         server,
         database,
         sled_path,
@@ -332,12 +695,43 @@ pub fn load_config_from_file(config_path: &str) -> AppConfig {
         backup_name_template,
         backup_interval,
         backup_retention,
+        backup_max_age,
+        health_min_free_disk_mb,
         pg_conns,
         cors_rules,
         logging,
         security,
         database_sync_on,
-    }
+        durability,
+        event_log_max_events,
+        history_max_revisions,
+        entities_dir,
+        encryption,
+        soft_delete_collections,
+        trash_retention,
+        trash_purge_interval,
+        config_watch: src.bool("CONFIG_WATCH"),
+        headers: HeadersConfig {
+            csp: src.string("CSP_POLICY"),
+            csp_report_only: src.bool("CSP_REPORT_ONLY"),
+            csp_report_uri: src.string("CSP_REPORT_URI"),
+            hsts_max_age: src.duration("HSTS_MAX_AGE"),
+            hsts_include_subdomains: src.bool("HSTS_INCLUDE_SUBDOMAINS"),
+            hsts_preload: src.bool("HSTS_PRELOAD"),
+            permissions_policy: src.string("PERMISSIONS_POLICY"),
+            frame_options: match src.choice("FRAME_OPTIONS", &["deny", "sameorigin", "off"]).as_str() {
+                "off" => None,
+                other => Some(other.to_uppercase()),
+            },
+        },
+        cookies,
+    };
+
+    let mut all = src.take_errors();
+    all.extend(keyring_errors);
+    all.extend(problems(&cfg));
+    config_source::check(all)?;
+    Ok(cfg)
 }
 
 pub fn build_table_routes(
@@ -422,9 +816,13 @@ pub fn build_table_routes(
     routes
 }
 
-pub fn load_cors_rules(path: &str) -> Vec<CorsRule> {
-    let mut rules = Vec::new();
+/// Allowed/denied origins, one rule per line (see `parse_cors_rules`)
+pub const CORS_RULES_FILE: &str = ".env_cors";
+const DEFAULT_CORS_MAX_AGE: u32 = 3600;
 
+/// Rules from the CORS file, plus the problems found in it. A missing file
+/// means no cross-origin access.
+pub fn load_cors_rules(path: &str) -> (Vec<CorsRule>, Vec<String>) {
     let abs_cors_path = Path::new(path)
         .canonicalize()
         .unwrap_or_else(|_| PathBuf::from(path));
@@ -433,70 +831,95 @@ pub fn load_cors_rules(path: &str) -> Vec<CorsRule> {
 
     if !Path::new(path).exists() {
         tracing::warn!(".env_cors file not found at: {} (CORS will be disabled)", abs_cors_path.display());
-        return rules;
+        return (Vec::new(), Vec::new());
     }
 
-    let content = match fs::read_to_string(path) {
-        Ok(s) => {
+    match fs::read_to_string(path) {
+        Ok(content) => {
             tracing::info!("✓ Loaded .env_cors file from: {}", abs_cors_path.display());
-            s
-        },
-        Err(e) => {
-            tracing::error!("Failed to read .env_cors file from {}: {}", abs_cors_path.display(), e);
-            return rules;
+            let (rules, errors) = parse_cors_rules(&content);
+            (rules, errors.into_iter().map(|e| format!("{}: {}", path, e)).collect())
         }
+        Err(e) => (Vec::new(), vec![format!("Failed to read {}: {}", abs_cors_path.display(), e)]),
+    }
+}
+
+/// One rule per line: `origin [ACTION] [METHODS] [HEADERS] [option=value ...]`
+///
+/// ```text
+/// http://localhost:3000
+/// http://site ALLOW GET,POST
+/// https://x ALLOW ALL content-type,authorization expose=etag max-age=600
+/// https://partner.example.com ALLOW GET ALL path=/api/public/* credentials=false
+/// ```
+///
+/// Options: `credentials=true|false`, `max-age=<seconds>`,
+/// `expose=<headers>` and `path=<patterns>` (comma-separated, `*` wildcard).
+pub fn parse_cors_rules(content: &str) -> (Vec<CorsRule>, Vec<String>) {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    let split_list = |s: &str, upper: bool| -> Vec<String> {
+        s.split(',')
+            .map(|s| if upper { s.trim().to_uppercase() } else { s.trim().to_lowercase() })
+            .filter(|s| !s.is_empty())
+            .collect()
     };
 
-    for raw in content.lines() {
+    for (i, raw) in content.lines().enumerate() {
         let line = raw.trim();
         if line.is_empty() || line.starts_with('#') {
             continue;
         }
-        // Tokens: origin [ACTION] [METHODS] [HEADERS]
-        // Examples:
-        // http://localhost:3000
-        // http://site ALLOW GET,POST
-        // https://x ALLOW ALL content-type,authorization
-        let mut parts = line.split_whitespace();
-        let origin = match parts.next() {
-            Some(o) => o.to_string(),
-            None => continue,
-        };
-        let action_str = parts.next().unwrap_or("ALLOW");
-        let action = match action_str.to_uppercase().as_str() {
-            "DENY" => CorsAction::Deny,
-            _ => CorsAction::Allow,
-        };
-        let methods = parts.next().unwrap_or("ALL").to_string();
-        let headers = parts.next().unwrap_or("ALL").to_string();
-
-        let methods_vec: Vec<String> = if methods.eq_ignore_ascii_case("ALL") {
-            vec!["ALL".to_string()]
-        } else {
-            methods
-                .split(',')
-                .map(|s| s.trim().to_uppercase())
-                .filter(|s| !s.is_empty())
-                .collect()
-        };
-        let headers_vec: Vec<String> = if headers.eq_ignore_ascii_case("ALL") {
-            vec!["ALL".to_string()]
-        } else {
-            headers
-                .split(',')
-                .map(|s| s.trim().to_lowercase())
-                .filter(|s| !s.is_empty())
-                .collect()
+        let mut rule = CorsRule {
+            credentials: true,
+            max_age: DEFAULT_CORS_MAX_AGE,
+            line: i + 1,
+            ..CorsRule::default()
         };
+        let mut positional = Vec::new();
+        for token in line.split_whitespace() {
+            let Some((name, value)) = token.split_once('=') else {
+                positional.push(token);
+                continue;
+            };
+            match name.to_lowercase().as_str() {
+                "credentials" => match value.parse() {
+                    Ok(b) => rule.credentials = b,
+                    Err(_) => errors.push(format!("line {}: credentials must be true or false", i + 1)),
+                },
+                "max-age" => match value.parse() {
+                    Ok(n) => rule.max_age = n,
+                    Err(_) => errors.push(format!("line {}: max-age must be a number of seconds", i + 1)),
+                },
+                "expose" => rule.expose_headers = split_list(value, false),
+                "path" => rule.paths = value.split(',').map(str::trim).filter(|p| !p.is_empty()).map(str::to_string).collect(),
+                _ => errors.push(format!("line {}: unknown option '{}'", i + 1, name)),
+            }
+        }
 
-        rules.push(CorsRule {
-            origin,
-            action,
-            methods: methods_vec,
-            headers: headers_vec,
-        });
+        let mut positional = positional.into_iter();
+        rule.origin = positional.next().unwrap_or_default().to_string();
+        rule.action = match positional.next().map(str::to_uppercase).as_deref() {
+            None | Some("ALLOW") => CorsAction::Allow,
+            Some("DENY") => CorsAction::Deny,
+            Some(other) => {
+                errors.push(format!("line {}: unknown action '{}' (ALLOW or DENY)", i + 1, other));
+                continue;
+            }
+        };
+        let methods = positional.next().unwrap_or("ALL");
+        let headers = positional.next().unwrap_or("ALL");
+        if let Some(extra) = positional.next() {
+            errors.push(format!("line {}: unexpected '{}'", i + 1, extra));
+        }
+        rule.methods = if methods.eq_ignore_ascii_case("ALL") { vec!["ALL".to_string()] } else { split_list(methods, true) };
+        rule.headers = if headers.eq_ignore_ascii_case("ALL") { vec!["ALL".to_string()] } else { split_list(headers, false) };
+        if rule.origin == "*" && rule.credentials && rule.action == CorsAction::Allow {
+            tracing::warn!("CORS rule on line {} lets any origin make credentialed requests", i + 1);
+        }
+        rules.push(rule);
     }
-    rules
+    (rules, errors)
 }
 
 pub fn database_url_from_env_or_config(explicit: Option<&str>, cfg: &AppConfig) -> String {
@@ -505,10 +928,8 @@ pub fn database_url_from_env_or_config(explicit: Option<&str>, cfg: &AppConfig)
             return url.to_string();
         }
     }
-    if let Ok(url) = std::env::var("DATABASE_URL") {
-        if !url.is_empty() {
-            return url;
-        }
+    if let Some(url) = &cfg.database.url {
+        return url.clone();
     }
     // Build from DatabaseConfig
     let sslmode = cfg.database.ssl_mode.clone();
@@ -0,0 +1,710 @@
+// Layered configuration sources
+//
+// Every setting has an env name (`PORT`) and a TOML key (`server.port`).
+// Values are resolved in layers, later ones winning:
+//
+//   built-in defaults -> TOML file -> environment (incl. .env) -> CLI
+//
+// The TOML file is `--config-file`, `CONFIG_FILE`, or `config.toml` when
+// present. CLI overrides are `--set KEY=VALUE` (env name or TOML key) plus
+// the `--host`/`--port` shortcuts. Nothing here falls back silently: bad
+// values and unknown keys are collected so `config::try_load_config` can
+// report all of them at once.
+use anyhow::Result;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config::{self, parse_duration, parse_size};
+
+/// Used when neither `--config-file` nor `CONFIG_FILE` is given
+pub const DEFAULT_TOML_FILE: &str = "config.toml";
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Str,
+    Int,
+    Bool,
+    /// Comma-separated in env, an array in TOML
+    List,
+}
+
+pub struct Key {
+    pub env: &'static str,
+    /// `section.name` in the TOML file
+    pub path: &'static str,
+    pub kind: Kind,
+    /// Empty means unset
+    pub default: &'static str,
+    pub secret: bool,
+}
+
+const fn key(env: &'static str, path: &'static str, kind: Kind, default: &'static str) -> Key {
+    Key { env, path, kind, default, secret: false }
+}
+
+const fn secret(env: &'static str, path: &'static str) -> Key {
+    Key { env, path, kind: Kind::Str, default: "", secret: true }
+}
+
+use Kind::{Bool, Int, List, Str};
+
+/// Every setting the backend reads, in the order `config print` shows them
+pub const KEYS: &[Key] = &[
+    key("HOST", "server.host", Str, "127.0.0.1"),
+    key("PORT", "server.port", Int, "8081"),
+    key("SERVER_NAME", "server.name", Str, "description_backend"),
+    key("DB_HOST", "database.host", Str, "localhost"),
+    key("DB_PORT", "database.port", Int, "5432"),
+    // Postgres database name, and the sled directory name under `storage.path`
+    key("DB_NAME", "database.name", Str, ""),
+    key("DB_USER", "database.user", Str, "postgres"),
+    secret("DB_PASSWORD", "database.password"),
+    key("DB_MAX_CONNECTIONS", "database.max_connections", Int, "10"),
+    key("DB_MIN_CONNECTIONS", "database.min_connections", Int, "1"),
+    key("DB_CONNECTION_TIMEOUT", "database.connection_timeout", Int, "30"),
+    key("DB_IDLE_TIMEOUT", "database.idle_timeout", Int, "600"),
+    key("DB_MAX_LIFETIME", "database.max_lifetime", Int, "3600"),
+    key("DB_SSL_MODE", "database.ssl_mode", Str, "prefer"),
+    secret("DATABASE_URL", "database.url"),
+    key("DATABASE_SYNC_ON_OFF", "database.sync", Str, "on"),
+    key("DB_PATH", "storage.path", Str, "data"),
+    key("DB_DURABILITY", "storage.durability", Str, "always"),
+    key("DB_DURABILITY_OVERRIDES", "storage.durability_overrides", List, ""),
+    key("EVENT_LOG_MAX_EVENTS", "storage.event_log_max_events", Int, "100000"),
+    key("HISTORY_MAX_REVISIONS", "storage.history_max_revisions", Int, "100"),
+    key("ENTITIES_DIR", "storage.entities_dir", Str, "../src/entities"),
+    key("SOFT_DELETE_COLLECTIONS", "storage.soft_delete_collections", List, "quotes,invoices,customers"),
+    key("TRASH_RETENTION", "storage.trash_retention", Str, "720h"),
+    key("TRASH_PURGE_INTERVAL", "storage.trash_purge_interval", Str, "1h"),
+    key("DB_ENCRYPTED_COLLECTIONS", "encryption.collections", List, ""),
+    secret("DB_ENCRYPTION_KEY", "encryption.key"),
+    key("DB_ENCRYPTION_KEYRING_PATH", "encryption.keyring_path", Str, "keys/data_keyring.json"),
+    key("PERIODIC_BACKUP_DB", "backup.interval", Str, ""),
+    key("PERIODIC_BACKUP_PATH", "backup.path", Str, "backups"),
+    key("PERIODIC_BACKUP_NAME", "backup.name", Str, "quoteflow_data_backup_{{timestamp}}"),
+    key("BACKUP_PATH", "backup.legacy_path", Str, ""),
+    key("TOKEN_JWT_HMAC_OR_PURE_PASSETO_NOTJWT", "tokens.mode", Str, "jwt_hmac"),
+    key("TOKEN_ISS", "tokens.issuer", Str, "description_backend"),
+    key("TOKEN_AUD", "tokens.audience", Str, "quoteflowfrontend"),
+    key("TOKEN_TTL_SECONDS", "tokens.ttl_seconds", Int, "43200"),
+    secret("PASETO_V4_LOCAL_KEY_HEX", "tokens.paseto_local_key"),
+    key("TOKEN_KEYRING_PATH", "tokens.keyring_path", Str, "keys/token_keyring.json"),
+    secret("ACCESS_TOKEN", "security.access_token"),
+    key("ACCESS_TOKEN_ADMIN_BYPASS", "security.access_token_admin_bypass", Bool, "false"),
+    key("TRUSTED_PROXIES", "security.trusted_proxies", List, ""),
+    key("API_RATE_LIMIT_ENABLED", "security.rate_limit_enabled", Bool, "false"),
+    key("API_RATE_LIMIT_REQUESTS_PER_MINUTE", "security.rate_limit_rpm", Int, "100"),
+    key("AUTH_TOKEN_EXPIRY_HOURS", "security.auth_token_expiry_hours", Int, "24"),
+    key("DEBUG_MODE", "security.debug_mode", Bool, "false"),
+    key("COOKIE_SECURE", "cookies.secure", Bool, "true"),
+    key("COOKIE_DOMAIN", "cookies.domain", Str, ""),
+    key("COOKIE_SAMESITE", "cookies.same_site", Str, "strict"),
+    key("HEALTH_CHECK_ENABLED", "health.enabled", Bool, "true"),
+    key("HEALTH_BACKUP_MAX_AGE", "health.backup_max_age", Str, ""),
+    key("HEALTH_MIN_FREE_DISK_MB", "health.min_free_disk_mb", Int, "512"),
+    key("METRICS_ENABLED", "metrics.enabled", Bool, "false"),
+    secret("METRICS_TOKEN", "metrics.token"),
+    key("LOG_LEVEL", "logging.level", Str, "info"),
+    key("LOG_FORMAT", "logging.format", Str, "text"),
+    key("LOG_FILE_ENABLED", "logging.file_enabled", Bool, "false"),
+    key("LOG_FILE_PATH", "logging.file_path", Str, ""),
+    key("LOG_FILE_MAX_SIZE", "logging.file_max_size", Str, "100MB"),
+    key("LOG_FILE_ROTATION", "logging.file_rotation", Str, "daily"),
+    key("LOG_FILE_RETENTION", "logging.file_retention", Int, "7"),
+    key("OTEL_EXPORTER_OTLP_ENDPOINT", "logging.otlp_endpoint", Str, ""),
+    key("CONFIG_WATCH", "config.watch", Bool, "false"),
+    key("CSP_POLICY", "headers.csp", Str, config::DEFAULT_CSP),
+    key("CSP_REPORT_ONLY", "headers.csp_report_only", Bool, "false"),
+    key("CSP_REPORT_URI", "headers.csp_report_uri", Str, "/csp-report"),
+    key("HSTS_MAX_AGE", "headers.hsts_max_age", Str, ""),
+    key("HSTS_INCLUDE_SUBDOMAINS", "headers.hsts_include_subdomains", Bool, "false"),
+    key("HSTS_PRELOAD", "headers.hsts_preload", Bool, "false"),
+    key("PERMISSIONS_POLICY", "headers.permissions_policy", Str, "geolocation=(), microphone=(), camera=()"),
+    key("FRAME_OPTIONS", "headers.frame_options", Str, "deny"),
+];
+
+/// Older env names still honoured when the current one is unset
+const ENV_ALIASES: &[(&str, &str)] = &[("LOG_LEVEL", "RUST_LOG")];
+
+/// Replication targets: `DATABASE_<n>_CONNECTION_PG_STRING` and
+/// `DATABASE_<n>_TARGETS` in env, `[[replication]]` tables in TOML
+const REPLICA_PREFIX: &str = "DATABASE_";
+const REPLICA_CONN: &str = "_CONNECTION_PG_STRING";
+const REPLICA_TARGETS: &str = "_TARGETS";
+
+fn lookup(name: &str) -> Option<&'static Key> {
+    KEYS.iter().find(|k| k.env == name || k.path == name)
+}
+
+/// The replica index of a `DATABASE_<n>_*` env name
+fn replica_key(name: &str) -> Option<(u32, bool)> {
+    let rest = name.strip_prefix(REPLICA_PREFIX)?;
+    let (idx, is_conn) = if let Some(idx) = rest.strip_suffix(REPLICA_CONN) {
+        (idx, true)
+    } else {
+        (rest.strip_suffix(REPLICA_TARGETS)?, false)
+    };
+    if idx.is_empty() || !idx.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((idx.parse().ok()?, is_conn))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    File,
+    DotEnv,
+    Env,
+    Cli,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Origin::Default => "default",
+            Origin::File => "config file",
+            Origin::DotEnv => ".env",
+            Origin::Env => "env",
+            Origin::Cli => "cli",
+        })
+    }
+}
+
+/// Where the configuration comes from
+#[derive(Debug, Clone, Default)]
+pub struct ConfigLayers {
+    /// The .env file, loaded into the environment
+    pub env_file: String,
+    /// Explicit TOML file; must exist when given
+    pub toml_file: Option<String>,
+    /// `KEY=VALUE` pairs from the command line
+    pub overrides: Vec<String>,
+}
+
+impl ConfigLayers {
+    pub fn env_file(path: &str) -> Self {
+        Self { env_file: path.to_string(), ..Self::default() }
+    }
+
+    pub fn from_cli(cli: &crate::cli::Cli) -> Self {
+        let mut overrides = Vec::new();
+        if let Some(host) = &cli.host {
+            overrides.push(format!("HOST={}", host));
+        }
+        if let Some(port) = cli.port {
+            overrides.push(format!("PORT={}", port));
+        }
+        overrides.extend(cli.overrides.iter().cloned());
+        Self { env_file: cli.config.clone(), toml_file: cli.config_file.clone(), overrides }
+    }
+
+    /// The TOML file to read, if any
+    pub fn toml_path(&self) -> Option<PathBuf> {
+        if let Some(path) = &self.toml_file {
+            return Some(PathBuf::from(path));
+        }
+        let default = Path::new(DEFAULT_TOML_FILE);
+        default.exists().then(|| default.to_path_buf())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Value {
+    raw: String,
+    origin: Origin,
+}
+
+/// Resolved values by env name, plus the problems found so far
+pub struct ConfigSource {
+    values: BTreeMap<String, Value>,
+    toml_path: Option<PathBuf>,
+    errors: RefCell<Vec<String>>,
+}
+
+impl ConfigSource {
+    /// Resolve all layers against the process environment, loading the .env file first
+    pub fn load(layers: &ConfigLayers) -> Self {
+        let env_file = Path::new(&layers.env_file);
+        let abs = env_file.canonicalize().unwrap_or_else(|_| env_file.to_path_buf());
+        if env_file.exists() {
+            match dotenvy::from_filename(env_file) {
+                Ok(_) => tracing::debug!("Loaded .env file from: {}", abs.display()),
+                Err(e) => tracing::warn!("Failed to load .env file from {}: {}", abs.display(), e),
+            }
+        } else {
+            tracing::debug!(".env file not found at: {} (using defaults)", abs.display());
+        }
+        let env = std::env::vars_os().filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)));
+        Self::from_layers(layers, env)
+    }
+
+    /// Resolve all layers with `env` standing in for the environment
+    pub fn from_layers(layers: &ConfigLayers, env: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut src = Self { values: BTreeMap::new(), toml_path: None, errors: RefCell::new(Vec::new()) };
+        for k in KEYS.iter().filter(|k| !k.default.is_empty()) {
+            src.set(k.env, k.default.to_string(), Origin::Default);
+        }
+
+        let env: BTreeMap<String, String> = env.into_iter().collect();
+        let toml_path = match &layers.toml_file {
+            Some(path) => Some(PathBuf::from(path)),
+            None => match env.get("CONFIG_FILE").filter(|p| !p.trim().is_empty()) {
+                Some(path) => Some(PathBuf::from(path)),
+                None => layers.toml_path(),
+            },
+        };
+        if let Some(path) = toml_path {
+            match std::fs::read_to_string(&path) {
+                Ok(text) => src.merge_toml(&path, &text),
+                Err(e) => src.error(format!("{}: {}", path.display(), e)),
+            }
+            src.toml_path = Some(path);
+        }
+
+        // Empty env values count as unset, so a copied .env.example with
+        // blank entries doesn't wipe out the TOML file
+        for (name, raw) in &env {
+            if raw.trim().is_empty() {
+                continue;
+            }
+            if lookup(name).is_some_and(|k| k.env == name) || replica_key(name).is_some() {
+                let origin = if config::is_process_env(name) { Origin::Env } else { Origin::DotEnv };
+                src.set(name, raw.clone(), origin);
+            }
+        }
+        for (name, alias) in ENV_ALIASES {
+            if let (false, Some(raw)) = (env.contains_key(*name), env.get(*alias).filter(|v| !v.trim().is_empty())) {
+                let origin = if config::is_process_env(alias) { Origin::Env } else { Origin::DotEnv };
+                src.set(name, raw.clone(), origin);
+            }
+        }
+
+        for entry in &layers.overrides {
+            match entry.split_once('=') {
+                Some((name, raw)) => match lookup(name.trim()) {
+                    Some(k) => src.set(k.env, raw.to_string(), Origin::Cli),
+                    None if replica_key(name.trim()).is_some() => src.set(name.trim(), raw.to_string(), Origin::Cli),
+                    None => src.error(format!("--set {}: unknown setting '{}'", entry, name.trim())),
+                },
+                None => src.error(format!("--set {}: expected KEY=VALUE", entry)),
+            }
+        }
+        src
+    }
+
+    fn set(&mut self, name: &str, raw: String, origin: Origin) {
+        self.values.insert(name.to_string(), Value { raw, origin });
+    }
+
+    /// Record a problem found while building the configuration
+    pub fn error(&self, message: String) {
+        self.errors.borrow_mut().push(message);
+    }
+
+    /// Problems found while resolving or reading values
+    pub fn take_errors(&self) -> Vec<String> {
+        std::mem::take(&mut *self.errors.borrow_mut())
+    }
+
+    pub fn toml_file(&self) -> Option<&Path> {
+        self.toml_path.as_deref()
+    }
+
+    fn merge_toml(&mut self, path: &Path, text: &str) {
+        let file = path.display();
+        let table: toml::Table = match text.parse() {
+            Ok(t) => t,
+            Err(e) => return self.error(format!("{}: {}", file, e.message())),
+        };
+        for (section, value) in table {
+            if section == "replication" {
+                self.merge_replicas(path, &value);
+                continue;
+            }
+            let Some(entries) = value.as_table() else {
+                self.error(format!("{}: '{}' must be a [section]", file, section));
+                continue;
+            };
+            for (name, value) in entries {
+                let full = format!("{}.{}", section, name);
+                let Some(k) = KEYS.iter().find(|k| k.path == full) else {
+                    self.error(format!("{}: unknown key '{}'", file, full));
+                    continue;
+                };
+                match toml_scalar(value, k.kind == List) {
+                    Some(raw) => self.set(k.env, raw, Origin::File),
+                    None => self.error(format!("{}: '{}' has an unsupported type", file, full)),
+                }
+            }
+        }
+    }
+
+    fn merge_replicas(&mut self, path: &Path, value: &toml::Value) {
+        let file = path.display();
+        let Some(replicas) = value.as_array() else {
+            return self.error(format!("{}: 'replication' must be an array of tables ([[replication]])", file));
+        };
+        for (i, replica) in replicas.iter().enumerate() {
+            let n = i + 1;
+            let Some(entries) = replica.as_table() else {
+                self.error(format!("{}: replication #{} must be a table", file, n));
+                continue;
+            };
+            for (name, value) in entries {
+                let (suffix, raw) = match name.as_str() {
+                    "conn_string" => (REPLICA_CONN, value.as_str().map(str::to_string)),
+                    // Table names, whitespace-separated like DATABASE_<n>_TARGETS
+                    "targets" => (
+                        REPLICA_TARGETS,
+                        toml_scalar(value, true).map(|s| s.split(',').collect::<Vec<_>>().join(" ")),
+                    ),
+                    _ => {
+                        self.error(format!("{}: unknown key 'replication.{}'", file, name));
+                        continue;
+                    }
+                };
+                match raw {
+                    Some(raw) => self.set(&format!("{}{}{}", REPLICA_PREFIX, n, suffix), raw, Origin::File),
+                    None => self.error(format!("{}: replication #{}: '{}' has an unsupported type", file, n, name)),
+                }
+            }
+        }
+    }
+
+    fn raw(&self, name: &str) -> Option<&Value> {
+        debug_assert!(lookup(name).is_some() || replica_key(name).is_some(), "unregistered setting {}", name);
+        self.values.get(name).filter(|v| !v.raw.trim().is_empty())
+    }
+
+    fn invalid(&self, name: &str, value: &Value, problem: impl fmt::Display) {
+        let shown = if lookup(name).is_some_and(|k| k.secret) { REDACTED } else { value.raw.as_str() };
+        self.error(format!("{} ({}): '{}' {}", name, value.origin, shown, problem));
+    }
+
+    /// The value, or an empty string when unset
+    pub fn string(&self, name: &str) -> String {
+        self.opt(name).unwrap_or_default()
+    }
+
+    pub fn opt(&self, name: &str) -> Option<String> {
+        self.raw(name).map(|v| v.raw.trim().to_string())
+    }
+
+    /// Parse the value; a bad one is recorded and replaced by the default
+    pub fn parse<T>(&self, name: &str) -> T
+    where
+        T: FromStr + Default,
+        T::Err: fmt::Display,
+    {
+        let fallback = || lookup(name).and_then(|k| k.default.parse().ok()).unwrap_or_default();
+        match self.raw(name) {
+            Some(v) => v.raw.trim().parse().unwrap_or_else(|e| {
+                self.invalid(name, v, format!("is invalid: {}", e));
+                fallback()
+            }),
+            None => fallback(),
+        }
+    }
+
+    pub fn bool(&self, name: &str) -> bool {
+        let fallback = lookup(name).is_some_and(|k| k.default == "true");
+        match self.raw(name) {
+            Some(v) => match v.raw.trim().to_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => true,
+                "false" | "0" | "no" | "off" => false,
+                _ => {
+                    self.invalid(name, v, "is not a boolean (true/false)");
+                    fallback
+                }
+            },
+            None => fallback,
+        }
+    }
+
+    /// One of `allowed` (case-insensitive), lowercased
+    pub fn choice(&self, name: &str, allowed: &[&str]) -> String {
+        let fallback = || lookup(name).map(|k| k.default.to_string()).unwrap_or_default();
+        match self.raw(name) {
+            Some(v) => {
+                let value = v.raw.trim().to_lowercase();
+                if allowed.contains(&value.as_str()) {
+                    value
+                } else {
+                    self.invalid(name, v, format!("is not one of {}", allowed.join(", ")));
+                    fallback()
+                }
+            }
+            None => fallback(),
+        }
+    }
+
+    /// A duration like `30s` or `12h`; `None` when unset
+    pub fn duration(&self, name: &str) -> Option<Duration> {
+        let v = self.raw(name)?;
+        match parse_duration(&v.raw) {
+            Ok(d) => Some(d),
+            Err(e) => {
+                self.invalid(name, v, format!("is invalid: {}", e));
+                lookup(name).and_then(|k| parse_duration(k.default).ok())
+            }
+        }
+    }
+
+    /// A byte size like `100MB`; `None` when unset
+    pub fn size(&self, name: &str) -> Option<u64> {
+        let v = self.raw(name)?;
+        match parse_size(&v.raw) {
+            Ok(n) => Some(n),
+            Err(e) => {
+                self.invalid(name, v, format!("is invalid: {}", e));
+                lookup(name).and_then(|k| parse_size(k.default).ok())
+            }
+        }
+    }
+
+    /// Comma-separated items, trimmed, without empty ones
+    pub fn list(&self, name: &str) -> Vec<String> {
+        self.string(name).split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+    }
+
+    /// `(conn_string, targets)` per configured replica, in index order
+    pub fn replicas(&self) -> Vec<(String, Option<String>)> {
+        let mut by_index: BTreeMap<u32, (Option<String>, Option<String>)> = BTreeMap::new();
+        for (name, value) in &self.values {
+            if let Some((idx, is_conn)) = replica_key(name) {
+                let entry = by_index.entry(idx).or_default();
+                if is_conn {
+                    entry.0 = Some(value.raw.clone());
+                } else {
+                    entry.1 = Some(value.raw.clone());
+                }
+            }
+        }
+        let mut replicas = Vec::new();
+        for (idx, (conn, targets)) in by_index {
+            match conn {
+                Some(conn) => replicas.push((conn, targets)),
+                None => self.error(format!("{}{}{} is set without {}{}{}", REPLICA_PREFIX, idx, REPLICA_TARGETS, REPLICA_PREFIX, idx, REPLICA_CONN)),
+            }
+        }
+        replicas
+    }
+
+    /// The resolved settings as TOML, each annotated with its env name and
+    /// the layer it came from
+    pub fn render(&self, redact: bool) -> String {
+        let mut out = String::new();
+        let mut section = "";
+        for k in KEYS {
+            let (sect, name) = k.path.split_once('.').unwrap_or(("", k.path));
+            if sect != section {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(&format!("[{}]\n", sect));
+                section = sect;
+            }
+            match self.values.get(k.env).filter(|v| !v.raw.trim().is_empty()) {
+                Some(v) => {
+                    let shown = if redact && k.secret { toml_string(REDACTED) } else { toml_value(k.kind, &v.raw) };
+                    out.push_str(&format!("{} = {}  # {} ({})\n", name, shown, k.env, v.origin));
+                }
+                None => out.push_str(&format!("# {} is not set  # {}\n", name, k.env)),
+            }
+        }
+
+        let mut replicas: BTreeMap<u32, Vec<(bool, &Value)>> = BTreeMap::new();
+        for (name, value) in &self.values {
+            if let Some((idx, is_conn)) = replica_key(name) {
+                replicas.entry(idx).or_default().push((is_conn, value));
+            }
+        }
+        for (idx, mut entries) in replicas {
+            entries.sort_by_key(|(is_conn, _)| !is_conn);
+            out.push_str("\n[[replication]]\n");
+            for (is_conn, v) in entries {
+                if is_conn {
+                    let shown = if redact { REDACTED } else { v.raw.as_str() };
+                    out.push_str(&format!(
+                        "conn_string = {}  # {}{}{} ({})\n",
+                        toml_string(shown), REPLICA_PREFIX, idx, REPLICA_CONN, v.origin
+                    ));
+                } else {
+                    let targets: Vec<_> = v.raw.split_whitespace().map(toml_string).collect();
+                    out.push_str(&format!(
+                        "targets = [{}]  # {}{}{} ({})\n",
+                        targets.join(", "), REPLICA_PREFIX, idx, REPLICA_TARGETS, v.origin
+                    ));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// TOML scalars as the env-style string they stand for
+fn toml_scalar(value: &toml::Value, list: bool) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(n) => Some(n.to_string()),
+        toml::Value::Float(n) => Some(n.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Array(items) if list => {
+            let items: Option<Vec<_>> = items.iter().map(|i| i.as_str()).collect();
+            items.map(|i| i.join(","))
+        }
+        _ => None,
+    }
+}
+
+fn toml_string(s: &str) -> String {
+    toml::Value::String(s.to_string()).to_string()
+}
+
+/// A raw value in TOML syntax; values of the wrong type stay quoted
+fn toml_value(kind: Kind, raw: &str) -> String {
+    let raw = raw.trim();
+    match kind {
+        Kind::Int if raw.parse::<i64>().is_ok() => raw.to_string(),
+        Kind::Bool if raw == "true" || raw == "false" => raw.to_string(),
+        Kind::List => {
+            let items: Vec<_> = raw.split(',').map(str::trim).filter(|s| !s.is_empty()).map(toml_string).collect();
+            format!("[{}]", items.join(", "))
+        }
+        _ => toml_string(raw),
+    }
+}
+
+/// Report collected problems as one error
+pub fn check(problems: Vec<String>) -> Result<()> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let mut message = format!("invalid configuration ({} problem(s)):", problems.len());
+    for p in &problems {
+        message.push_str("\n  - ");
+        message.push_str(p);
+    }
+    Err(anyhow::anyhow!(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn toml_file(dir: &tempfile::TempDir, text: &str) -> String {
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, text).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let dir = tempfile::tempdir().unwrap();
+        let layers = ConfigLayers {
+            env_file: "/nonexistent/.env".into(),
+            toml_file: Some(toml_file(
+                &dir,
+                "[server]\nport = 9000\nname = \"from-file\"\nhost = \"0.0.0.0\"\n\
+                 [storage]\nsoft_delete_collections = [\"quotes\", \"orders\"]\n\
+                 [[replication]]\nconn_string = \"postgres://replica\"\ntargets = [\"users\", \"quotes\"]\n",
+            )),
+            overrides: vec!["server.host=10.0.0.1".into()],
+        };
+        let src = ConfigSource::from_layers(&layers, env(&[("PORT", "9100"), ("RUST_LOG", "debug"), ("UNRELATED", "x")]));
+
+        assert_eq!(src.parse::<u16>("PORT"), 9100);
+        assert_eq!(src.string("SERVER_NAME"), "from-file");
+        assert_eq!(src.string("HOST"), "10.0.0.1");
+        assert_eq!(src.string("DB_HOST"), "localhost");
+        assert_eq!(src.string("LOG_LEVEL"), "debug");
+        assert_eq!(src.list("SOFT_DELETE_COLLECTIONS"), ["quotes", "orders"]);
+        assert_eq!(src.replicas(), [("postgres://replica".to_string(), Some("users quotes".to_string()))]);
+        assert!(src.take_errors().is_empty());
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let dir = tempfile::tempdir().unwrap();
+        let layers = ConfigLayers {
+            env_file: "/nonexistent/.env".into(),
+            toml_file: Some(toml_file(&dir, "[server]\nprot = 1\n[metrics]\nenabled = \"sometimes\"\n")),
+            overrides: vec!["NOPE=1".into()],
+        };
+        let src = ConfigSource::from_layers(&layers, env(&[("PORT", "80a"), ("PERIODIC_BACKUP_DB", "soon")]));
+        assert_eq!(src.parse::<u16>("PORT"), 8081);
+        assert_eq!(src.duration("PERIODIC_BACKUP_DB"), None);
+        assert!(!src.bool("METRICS_ENABLED"));
+
+        let errors = src.take_errors().join("\n");
+        for needle in ["'server.prot'", "--set NOPE=1", "PORT (env): '80a'", "PERIODIC_BACKUP_DB (env): 'soon'", "METRICS_ENABLED (config file)"] {
+            assert!(errors.contains(needle), "missing {} in:\n{}", needle, errors);
+        }
+    }
+
+    #[test]
+    fn unreadable_key_ring_is_fatal() {
+        let dir = tempfile::tempdir().unwrap();
+        let ring = dir.path().join("ring.json");
+        std::fs::write(&ring, "{ truncated").unwrap();
+        let layers = ConfigLayers::env_file("/nonexistent/.env");
+        let src = ConfigSource::from_layers(&layers, env(&[("TOKEN_KEYRING_PATH", ring.to_str().unwrap())]));
+        let err = crate::config::from_source(&src).unwrap_err().to_string();
+        assert!(err.contains("Failed to load token key ring"), "{}", err);
+
+        // Missing is fine: startup bootstraps a new ring
+        let src = ConfigSource::from_layers(&layers, env(&[("TOKEN_KEYRING_PATH", dir.path().join("new.json").to_str().unwrap())]));
+        assert!(crate::config::from_source(&src).is_ok());
+    }
+
+    #[test]
+    fn cookies_and_database_url_come_from_any_layer() {
+        let dir = tempfile::tempdir().unwrap();
+        let layers = ConfigLayers {
+            env_file: "/nonexistent/.env".into(),
+            toml_file: Some(toml_file(
+                &dir,
+                "[cookies]\nsecure = false\ndomain = \"example.com\"\nsame_site = \"lax\"\n\
+                 [database]\nurl = \"postgres://from-file/db\"\n",
+            )),
+            overrides: vec![],
+        };
+        let cfg = crate::config::from_source(&ConfigSource::from_layers(&layers, env(&[]))).unwrap();
+        assert!(!cfg.cookies.secure);
+        assert_eq!(cfg.cookies.domain.as_deref(), Some("example.com"));
+        assert_eq!(cfg.cookies.same_site, actix_web::cookie::SameSite::Lax);
+        assert_eq!(crate::config::database_url_from_env_or_config(None, &cfg), "postgres://from-file/db");
+
+        let src = ConfigSource::from_layers(&layers, env(&[("COOKIE_SAMESITE", "None")]));
+        let err = crate::config::from_source(&src).unwrap_err().to_string();
+        assert!(err.contains("COOKIE_SAMESITE=none requires COOKIE_SECURE=true"), "{}", err);
+    }
+
+    #[test]
+    fn render_redacts_secrets() {
+        let layers = ConfigLayers::env_file("/nonexistent/.env");
+        let src = ConfigSource::from_layers(
+            &layers,
+            env(&[("DB_PASSWORD", "hunter2"), ("DATABASE_1_CONNECTION_PG_STRING", "postgres://u:pw@db/x"), ("PORT", "8088")]),
+        );
+        let shown = src.render(false);
+        assert!(shown.contains("password = \"hunter2\"  # DB_PASSWORD (env)"), "{}", shown);
+        assert!(shown.contains("port = 8088  # PORT (env)"), "{}", shown);
+
+        let redacted = src.render(true);
+        assert!(!redacted.contains("hunter2") && !redacted.contains("pw@db"), "{}", redacted);
+        assert!(redacted.contains("[[replication]]\nconn_string = \"<redacted>\""), "{}", redacted);
+        // The output is itself a valid config file
+        assert!(redacted.parse::<toml::Table>().is_ok());
+    }
+}
@@ -1,26 +1,547 @@
-
 // ============================================================================
 // src/db.rs - FIXED UPDATE METHOD
 // ============================================================================
 use anyhow::Result;
 use serde::{de::DeserializeOwned, Serialize};
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional, TransactionalTree};
 use sled::Db;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::fmt;
 use std::sync::Arc;
+use crate::durability::{Durability, DurabilityPolicy, GroupCommitter};
+use crate::encryption::{self, Cipher};
+use crate::events::{self, EVENTS_TREE};
+use crate::history::{self, HISTORY_TREE};
+use crate::metrics;
+use tracing::Instrument;
+use crate::query::{decode_cursor, encode_cursor, ListQuery, Page};
 use crate::replicate::Replicator;
+use crate::trash::{self, TRASH_TREE};
+
+/// Separator between the tenant (organization id) and the record key
+pub const TENANT_SEP: char = ':';
+
+/// Separator between the indexed value and the primary key in non-unique index entries
+const INDEX_SEP: u8 = 0;
+
+/// Tree recording which indexes have been backfilled
+const INDEX_META_TREE: &str = "__index_meta";
+
+/// Secondary index on a JSON field of a collection (`data.email` for nested fields).
+///
+/// Index entries live in a companion tree (`{collection}__idx_{name}`) and are
+/// maintained in the same sled transaction as the record itself. String
+/// values are indexed verbatim (callers normalize, e.g. lowercase emails);
+/// numbers and booleans by their JSON text; missing or null fields are not
+/// indexed.
+#[derive(Debug, Clone, Copy)]
+pub struct IndexDef {
+    pub collection: &'static str,
+    pub name: &'static str,
+    pub field: &'static str,
+    pub unique: bool,
+    /// Records are written through `for_tenant` handles, so values are
+    /// indexed per tenant (taken from the key prefix when rebuilding).
+    /// Global collections such as `memberships` may use `:` in their keys.
+    pub tenant_scoped: bool,
+}
+
+impl IndexDef {
+    fn tree_name(&self) -> String {
+        format!("{}__idx_{}", self.collection, self.name)
+    }
+
+    fn value_of(&self, record: &serde_json::Value) -> Option<String> {
+        let pointer = format!("/{}", self.field.replace('.', "/"));
+        match record.pointer(&pointer)? {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+
+    /// Index tree key for a (scoped) value and the record's raw key
+    fn entry_key(&self, value: &str, primary: &[u8]) -> Vec<u8> {
+        let mut key = value.as_bytes().to_vec();
+        if !self.unique {
+            key.push(INDEX_SEP);
+            key.extend_from_slice(primary);
+        }
+        key
+    }
+}
+
+/// Declared secondary indexes, maintained on every insert, update and delete
+pub const INDEXES: &[IndexDef] = &[
+    IndexDef { collection: "users", name: "email", field: "email", unique: true, tenant_scoped: false },
+    IndexDef { collection: "memberships", name: "user_id", field: "user_id", unique: false, tenant_scoped: false },
+    // API keys are global records, like users and memberships
+    IndexDef { collection: "api_keys", name: "user_id", field: "user_id", unique: false, tenant_scoped: false },
+];
+
+fn indexes_for(collection: &str) -> impl Iterator<Item = &'static IndexDef> + '_ {
+    INDEXES.iter().filter(move |d| d.collection == collection)
+}
+
+fn index_def(collection: &str, index: &str) -> Result<&'static IndexDef> {
+    indexes_for(collection)
+        .find(|d| d.name == index)
+        .ok_or_else(|| anyhow::anyhow!("no index '{}' on collection '{}'", index, collection))
+}
+
+/// A write would give a unique index two records with the same value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniqueViolation {
+    pub collection: String,
+    pub index: String,
+    pub value: String,
+}
+
+impl fmt::Display for UniqueViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{} already has a record with value '{}'", self.collection, self.index, self.value)
+    }
+}
+
+impl std::error::Error for UniqueViolation {}
+
+/// Result type of transaction closures; see [`abort`]
+pub type TxResult<T> = std::result::Result<T, ConflictableTransactionError<anyhow::Error>>;
+
+/// Roll back the surrounding transaction with `err`
+pub fn abort<T>(err: impl Into<anyhow::Error>) -> TxResult<T> {
+    Err(ConflictableTransactionError::Abort(err.into()))
+}
+
+/// Marker abort used by `modify` when its closure rejects the write
+#[derive(Debug)]
+struct Rejected;
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "write rejected")
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// Where a collection's record and index trees sit in a transaction's tree list
+struct TreeLayout {
+    collection: String,
+    main: usize,
+    indexes: Vec<(&'static IndexDef, usize)>,
+}
+
+/// A write staged inside a transaction, replicated once it commits
+struct PendingWrite {
+    collection: String,
+    key: String,
+    current: Option<Vec<u8>>,
+    existed: bool,
+}
+
+/// Handle passed to [`Database::transaction`] closures. Keys are scoped to
+/// the tenant of the `Database` the transaction was started on.
+pub struct Tx<'a> {
+    db: &'a Database,
+    views: &'a [TransactionalTree],
+    layout: &'a [TreeLayout],
+    events: &'a TransactionalTree,
+    history: &'a TransactionalTree,
+    trash: &'a TransactionalTree,
+    pending: RefCell<Vec<PendingWrite>>,
+    /// Sequence of the last change event this transaction appended
+    last_event: Cell<Option<u64>>,
+}
+
+impl Tx<'_> {
+    fn layout_of(&self, collection: &str) -> TxResult<&TreeLayout> {
+        match self.layout.iter().find(|l| l.collection == collection) {
+            Some(l) => Ok(l),
+            None => abort(anyhow::anyhow!("collection '{}' is not part of this transaction", collection)),
+        }
+    }
+
+    fn get_raw(&self, collection: &str, key: &str) -> TxResult<Option<Vec<u8>>> {
+        let layout = self.layout_of(collection)?;
+        let key = self.db.scoped_key(key);
+        match self.views[layout.main].get(key.as_bytes())? {
+            Some(stored) => match self.db.decode(collection, key.as_bytes(), &stored) {
+                Ok(plain) => Ok(Some(plain.into_owned())),
+                Err(e) => abort(e),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Store (`Some`) or remove (`None`) a record, keeping its indexes in step
+    fn put_raw(&self, collection: &str, key: &str, current: Option<Vec<u8>>) -> TxResult<bool> {
+        let layout = self.layout_of(collection)?;
+        let key = self.db.scoped_key(key);
+        let main = &self.views[layout.main];
+        let previous = main.get(key.as_bytes())?;
+        let existed = previous.is_some();
+        let keeps_history = self.db.history.contains(collection);
+
+        if !layout.indexes.is_empty() || keeps_history {
+            let previous = match previous.as_deref().map(|b| self.db.decode(collection, key.as_bytes(), b)).transpose() {
+                Ok(p) => p,
+                Err(e) => return abort(e),
+            };
+            if keeps_history {
+                let entry = history::build(self.history, collection, &key, previous.as_deref(), current.as_deref(), self.db.actor.as_deref())?;
+                if let Some((history_key, bytes)) = entry {
+                    match self.db.encode(collection, &history_key, &bytes) {
+                        Ok(stored) => self.history.insert(history_key.as_slice(), stored.as_ref())?,
+                        Err(e) => return abort(e),
+                    };
+                }
+            }
+            let parse = |bytes: Option<&[u8]>| bytes.and_then(|b| serde_json::from_slice::<serde_json::Value>(b).ok());
+            let old_record = parse(previous.as_deref());
+            let new_record = parse(current.as_deref());
+            let index_key = |def: &IndexDef, v: String| self.db.index_key(def, v);
+            for (def, slot) in &layout.indexes {
+                let idx = &self.views[*slot];
+                let old_value = old_record.as_ref().and_then(|r| def.value_of(r)).map(|v| index_key(def, v));
+                let new_value = new_record.as_ref().and_then(|r| def.value_of(r)).map(|v| index_key(def, v));
+                if old_value == new_value {
+                    continue;
+                }
+                if let Some(value) = &new_value {
+                    let entry = def.entry_key(value, key.as_bytes());
+                    if def.unique {
+                        if let Some(owner) = idx.get(&entry)? {
+                            if owner.as_ref() != key.as_bytes() {
+                                return abort(UniqueViolation {
+                                    collection: def.collection.to_string(),
+                                    index: def.name.to_string(),
+                                    value: value.clone(),
+                                });
+                            }
+                        }
+                    }
+                    idx.insert(entry, key.as_bytes())?;
+                }
+                if let Some(value) = old_value {
+                    idx.remove(def.entry_key(&value, key.as_bytes()))?;
+                }
+            }
+        }
+
+        // Events and replication see the plaintext; only the stored copy is sealed
+        match &current {
+            Some(bytes) => match self.db.encode(collection, key.as_bytes(), bytes) {
+                Ok(stored) => main.insert(key.as_bytes(), stored.as_ref())?,
+                Err(e) => return abort(e),
+            },
+            None => main.remove(key.as_bytes())?,
+        };
+        if let Some(seq) = events::append(self.events, collection, &key, current.as_deref(), existed)? {
+            self.last_event.set(Some(seq));
+        }
+        trash::track(self.trash, collection, &key, current.as_deref(), existed)?;
+        self.pending.borrow_mut().push(PendingWrite { collection: collection.to_string(), key, current, existed });
+        Ok(existed)
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, collection: &str, key: &str) -> TxResult<Option<T>> {
+        match self.get_raw(collection, key)? {
+            Some(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(value) => Ok(Some(value)),
+                Err(e) => abort(e),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn insert<T: Serialize>(&self, collection: &str, key: &str, value: &T) -> TxResult<()> {
+        let bytes = match serde_json::to_vec(value) {
+            Ok(b) => b,
+            Err(e) => return abort(e),
+        };
+        self.put_raw(collection, key, Some(bytes)).map(|_| ())
+    }
+
+    /// Returns whether the record existed
+    pub fn delete(&self, collection: &str, key: &str) -> TxResult<bool> {
+        self.put_raw(collection, key, None)
+    }
+}
+
+/// Writes applied together by [`WriteBatch::commit`]: one transaction, at most one flush
+pub struct WriteBatch<'a> {
+    db: &'a Database,
+    /// `(collection, key, serialized record)`
+    ops: Vec<(String, String, Vec<u8>)>,
+}
+
+impl WriteBatch<'_> {
+    pub fn insert<T: Serialize>(&mut self, collection: &str, key: &str, value: &T) -> Result<&mut Self> {
+        self.ops.push((collection.to_string(), key.to_string(), serde_json::to_vec(value)?));
+        Ok(self)
+    }
+
+    /// Apply every write atomically, in the order they were added
+    pub fn commit(self) -> Result<()> {
+        if self.ops.is_empty() {
+            return Ok(());
+        }
+        let mut collections: Vec<&str> = Vec::new();
+        for (c, _, _) in &self.ops {
+            if !collections.contains(&c.as_str()) {
+                collections.push(c);
+            }
+        }
+        self.db.transaction(&collections, |tx| {
+            for (c, k, bytes) in &self.ops {
+                tx.put_raw(c, k, Some(bytes.clone()))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Serialized record before and after a successful `modify`
+#[derive(Debug, Clone)]
+pub struct Modified {
+    pub previous: Option<Vec<u8>>,
+    pub current: Option<Vec<u8>>,
+}
 
 #[derive(Clone)]
 pub struct Database {
     pub db: Arc<Db>,
     replicator: Option<Arc<Replicator>>,
+    /// Organization id all keys are prefixed with; `None` for global data
+    tenant: Option<String>,
+    durability: Arc<DurabilityPolicy>,
+    group_commit: Option<Arc<GroupCommitter>>,
+    /// Flush every write made through this handle regardless of policy
+    force_durable: bool,
+    /// Latest change-feed sequence, for live subscribers
+    events: Arc<tokio::sync::watch::Sender<u64>>,
+    /// Held while a transaction commits, so change-feed sequence numbers
+    /// become visible in order and a reader never skips a late commit
+    commit_lock: Arc<std::sync::Mutex<()>>,
+    /// Encryption at rest; `None` stores everything as plaintext
+    cipher: Option<Arc<Cipher>>,
+    /// Collections whose writes are kept as revisions (see `history.rs`)
+    history: Arc<HashSet<String>>,
+    /// Revisions kept per record; older ones are trimmed after each write
+    history_limit: usize,
+    /// Recorded as the author of revisions written through this handle
+    actor: Option<String>,
 }
 
 impl Database {
+    /// Open without encryption at rest
+    #[cfg(test)]
     pub fn new(path: &str) -> Result<Self> {
+        Self::open(path, None)
+    }
+
+    /// Open with encryption at rest. Indexes are (re)built with the cipher
+    /// available, since blind indexes depend on it.
+    pub fn open(path: &str, cipher: Option<Arc<Cipher>>) -> Result<Self> {
         let db = sled::open(path)?;
-        Ok(Self {
+        let db = Self {
             db: Arc::new(db),
             replicator: None,
-        })
+            tenant: None,
+            durability: Arc::new(DurabilityPolicy::default()),
+            group_commit: None,
+            force_durable: false,
+            events: Arc::new(tokio::sync::watch::Sender::new(0)),
+            commit_lock: Arc::new(std::sync::Mutex::new(())),
+            cipher,
+            history: Arc::new(HashSet::new()),
+            history_limit: usize::MAX,
+            actor: None,
+        };
+        db.events.send_replace(events::last_seq(&db)?);
+        db.backfill_indexes()?;
+        db.backfill_trash()?;
+        Ok(db)
+    }
+
+    /// Build indexes declared after their collection already had data
+    fn backfill_indexes(&self) -> Result<()> {
+        let meta = self.db.open_tree(INDEX_META_TREE)?;
+        for def in INDEXES {
+            let marker = def.tree_name();
+            // Rebuild when an index switches between plain and blind values;
+            // `v2` redoes indexes built when global keys were read as tenant-scoped,
+            // `v3` those built while `api_keys` was declared tenant-scoped
+            let mode: &[u8] = if self.encrypts(def.collection) { b"v3:blind" } else { b"v3" };
+            if meta.get(&marker)?.is_some_and(|m| m == mode) {
+                continue;
+            }
+            self.rebuild_index(def)?;
+            meta.insert(marker, mode)?;
+        }
+        Ok(())
+    }
+
+    /// Index the trash of data written before the trash index existed
+    fn backfill_trash(&self) -> Result<()> {
+        let meta = self.db.open_tree(INDEX_META_TREE)?;
+        if meta.contains_key(TRASH_TREE)? {
+            return Ok(());
+        }
+        trash::rebuild(self)?;
+        meta.insert(TRASH_TREE, b"v1")?;
+        Ok(())
+    }
+
+    /// Drop and recreate one index from its collection. On duplicate values
+    /// in a unique index the first record (in key order) wins.
+    pub fn rebuild_index(&self, def: &IndexDef) -> Result<()> {
+        let tree = self.db.open_tree(def.collection)?;
+        let idx = self.db.open_tree(def.tree_name())?;
+        idx.clear()?;
+        for item in tree.iter() {
+            let (key, value) = item?;
+            let value = self.decode(def.collection, &key, &value)?;
+            let record: serde_json::Value = match serde_json::from_slice(&value) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let Some(indexed) = def.value_of(&record).map(|v| self.index_value(def, v)) else { continue };
+            let scope = if def.tenant_scoped { Self::scope_of(&key) } else { None };
+            let scoped = scope.map(|t| format!("{}{}{}", t, TENANT_SEP, indexed)).unwrap_or(indexed);
+            let entry = def.entry_key(&scoped, &key);
+            if def.unique && idx.contains_key(&entry)? {
+                log::warn!(
+                    "Duplicate value '{}' for unique index {}.{}; keeping the first record",
+                    scoped, def.collection, def.name
+                );
+                continue;
+            }
+            idx.insert(entry, key.to_vec())?;
+        }
+        Ok(())
+    }
+
+    /// Tenant prefix of a raw record key, if it has one
+    fn scope_of(key: &[u8]) -> Option<String> {
+        let key = std::str::from_utf8(key).ok()?;
+        key.split_once(TENANT_SEP).map(|(t, _)| t.to_string())
+    }
+
+    /// Handle whose reads and writes are confined to one organization.
+    /// Keys are stored as `{org_id}:{key}` in the same trees.
+    pub fn for_tenant(&self, org_id: &str) -> Self {
+        Self { tenant: Some(org_id.to_string()), ..self.clone() }
+    }
+
+    /// Handle whose writes are attributed to `actor` (a `Claims.sub`) in
+    /// revision history
+    pub fn as_actor(&self, actor: &str) -> Self {
+        Self { actor: Some(actor.to_string()), ..self.clone() }
+    }
+
+    /// Handle whose writes are flushed before they return, whatever the
+    /// collection's policy. Use it for writes that must survive a crash.
+    pub fn durable(&self) -> Self {
+        Self { force_durable: true, ..self.clone() }
+    }
+
+    /// `durable()` if the client sent `X-Durability: always`, else a plain clone
+    pub fn for_request(&self, req: &actix_web::HttpRequest) -> Self {
+        if crate::durability::requested(req) {
+            self.durable()
+        } else {
+            self.clone()
+        }
+    }
+
+    pub(crate) fn scoped_key(&self, key: &str) -> String {
+        match &self.tenant {
+            Some(t) => format!("{}{}{}", t, TENANT_SEP, key),
+            None => key.to_string(),
+        }
+    }
+
+    fn encrypts(&self, collection: &str) -> bool {
+        self.cipher.as_ref().is_some_and(|c| c.covers(collection))
+    }
+
+    /// Plaintext of a stored value; sealed values need the cipher even if
+    /// their collection is no longer configured for encryption
+    pub(crate) fn decode<'a>(&self, collection: &str, key: &[u8], stored: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        if !encryption::is_sealed(stored) {
+            return Ok(Cow::Borrowed(stored));
+        }
+        match &self.cipher {
+            Some(cipher) => Ok(Cow::Owned(cipher.open(collection, key, stored)?)),
+            None => Err(anyhow::anyhow!("{} record is encrypted but no data key is configured", collection)),
+        }
+    }
+
+    /// What to store for a serialized record: sealed if its collection is encrypted
+    fn encode<'a>(&self, collection: &str, key: &[u8], plain: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        match &self.cipher {
+            Some(cipher) if cipher.covers(collection) => Ok(Cow::Owned(cipher.seal(collection, key, plain)?)),
+            _ => Ok(Cow::Borrowed(plain)),
+        }
+    }
+
+    /// Index tree key for a field value: blinded, then scoped to this
+    /// handle's tenant if the index is tenant-scoped
+    fn index_key(&self, def: &IndexDef, value: String) -> String {
+        let value = self.index_value(def, value);
+        if def.tenant_scoped { self.scoped_key(&value) } else { value }
+    }
+
+    /// Indexed value as stored in the index tree: blinded for encrypted collections
+    fn index_value(&self, def: &IndexDef, value: String) -> String {
+        match &self.cipher {
+            Some(cipher) if cipher.covers(def.collection) => cipher.blind(&value),
+            _ => value,
+        }
+    }
+
+    /// Rewrite records of `collection` sealed with an old key, not yet
+    /// sealed, or sealed although the collection is no longer encrypted.
+    /// Returns how many were rewritten.
+    pub fn reencrypt(&self, collection: &str) -> Result<usize> {
+        let Some(cipher) = &self.cipher else { return Ok(0) };
+        let tree = self.db.open_tree(collection)?;
+        let mut rewritten = 0;
+        for item in tree.iter() {
+            let (key, stored) = item?;
+            if !cipher.needs_rewrite(collection, &stored) {
+                continue;
+            }
+            let plain = self.decode(collection, &key, &stored)?;
+            let updated = self.encode(collection, &key, &plain)?.into_owned();
+            // A concurrent write already stored it with the current settings
+            if tree.compare_and_swap(&key, Some(&stored), Some(updated))?.is_ok() {
+                rewritten += 1;
+            }
+        }
+        self.db.flush()?;
+        Ok(rewritten)
+    }
+
+    /// Keep revision history for writes to `collections`
+    pub fn with_history(mut self, collections: impl IntoIterator<Item = String>) -> Self {
+        self.history = Arc::new(collections.into_iter().collect());
+        self
+    }
+
+    /// Keep at most `limit` revisions per record
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit;
+        self
+    }
+
+    pub fn replicator(&self) -> Option<&Arc<Replicator>> {
+        self.replicator.as_ref()
     }
 
     pub fn with_replicator(mut self, replicator: Option<Arc<Replicator>>) -> Self {
@@ -28,34 +549,40 @@ impl Database {
         self
     }
 
+    /// Replace the default always-flush policy; starts the group-commit
+    /// flusher if the policy needs one
+    pub fn with_durability(mut self, policy: DurabilityPolicy) -> Self {
+        self.group_commit = GroupCommitter::start(&self.db, &policy);
+        self.durability = Arc::new(policy);
+        self
+    }
+
+    /// Run blocking database work on the blocking thread pool so flushes
+    /// and long scans don't stall the async workers
+    pub async fn run<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(Database) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let db = self.clone();
+        // Keep the request span on the blocking thread so its logs (and any
+        // replication it spawns) stay correlated with the request
+        let span = tracing::Span::current();
+        actix_web::web::block(move || span.in_scope(|| f(db))).await?
+    }
+
     pub fn insert<T: Serialize>(&self, collection: &str, key: &str, value: &T) -> Result<()> {
-        let tree = self.db.open_tree(collection)?;
         let serialized = serde_json::to_vec(value)?;
-        tree.insert(key, serialized.clone())?;
-        self.db.flush()?;
-
-        if let Some(rep) = &self.replicator {
-            let table = collection.to_string();
-            let id = key.to_string();
-            let json_value: serde_json::Value =
-                serde_json::from_slice(&serialized).unwrap_or(serde_json::json!({}));
-            let last_updated = json_value
-                .get("last_updated")
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-            let rep = rep.clone();
-            tokio::spawn(async move {
-                let _ = rep.upsert(&table, &id, &last_updated, &json_value).await;
-            });
-        }
+        self.modify(collection, key, |_| Ok::<_, Infallible>(Some(serialized.clone())))?
+            .unwrap_or_else(|never| match never {});
         Ok(())
     }
 
     pub fn get<T: DeserializeOwned>(&self, collection: &str, key: &str) -> Result<Option<T>> {
         let tree = self.db.open_tree(collection)?;
-        if let Some(data) = tree.get(key)? {
-            let value: T = serde_json::from_slice(&data)?;
+        let key = self.scoped_key(key);
+        if let Some(data) = tree.get(&key)? {
+            let value: T = serde_json::from_slice(&self.decode(collection, key.as_bytes(), &data)?)?;
             Ok(Some(value))
         } else {
             Ok(None)
@@ -63,33 +590,321 @@ impl Database {
     }
 
     pub fn list<T: DeserializeOwned>(&self, collection: &str) -> Result<Vec<T>> {
+        self.list_prefix(collection, "")
+    }
+
+    /// List records whose (tenant-relative) key starts with `prefix`
+    pub fn list_prefix<T: DeserializeOwned>(&self, collection: &str, prefix: &str) -> Result<Vec<T>> {
         let tree = self.db.open_tree(collection)?;
         let mut items = Vec::new();
 
-        for result in tree.iter() {
-            let (_key, value) = result?;
-            let item: T = serde_json::from_slice(&value)?;
+        for result in tree.scan_prefix(self.scoped_key(prefix)) {
+            let (key, value) = result?;
+            let item: T = serde_json::from_slice(&self.decode(collection, &key, &value)?)?;
             items.push(item);
         }
 
         Ok(items)
     }
 
+    /// Like `list_prefix`, but with each record's (tenant-relative) key
+    pub fn list_entries<T: DeserializeOwned>(&self, collection: &str, prefix: &str) -> Result<Vec<(String, T)>> {
+        let tree = self.db.open_tree(collection)?;
+        let scope_len = self.scoped_key("").len();
+        let mut items = Vec::new();
+
+        for result in tree.scan_prefix(self.scoped_key(prefix)) {
+            let (key, value) = result?;
+            let value = serde_json::from_slice(&self.decode(collection, &key, &value)?)?;
+            items.push((String::from_utf8_lossy(&key[scope_len..]).into_owned(), value));
+        }
+
+        Ok(items)
+    }
+
+    /// One page of records matching `q`, scanning only the key range it
+    /// covers. Filters are applied while scanning, so a selective filter
+    /// may read past `limit` records to fill a page.
+    pub fn query<T: DeserializeOwned>(&self, collection: &str, q: &ListQuery) -> Result<Page<T>> {
+        let tree = self.db.open_tree(collection)?;
+        let scope_len = self.scoped_key("").len();
+
+        let prefix = self.scoped_key(&q.prefix).into_bytes();
+        let mut lower = prefix.clone();
+        let mut upper = prefix_successor(&prefix);
+        if let Some(start) = &q.start {
+            lower = lower.max(self.scoped_key(start).into_bytes());
+        }
+        if let Some(end) = &q.end {
+            upper = min_upper(upper, self.scoped_key(end).into_bytes());
+        }
+        if let Some(cursor) = &q.cursor {
+            let after = self.scoped_key(&decode_cursor(cursor)?).into_bytes();
+            if q.reverse {
+                upper = min_upper(upper, after);
+            } else {
+                // Smallest key strictly greater than the cursor
+                let mut next = after;
+                next.push(0);
+                lower = lower.max(next);
+            }
+        }
+        if upper.as_ref().is_some_and(|u| *u <= lower) {
+            return Ok(Page { items: Vec::new(), next_cursor: None, has_more: false });
+        }
+
+        let range = match upper {
+            Some(upper) => tree.range(lower..upper),
+            None => tree.range(lower..),
+        };
+        let entries: Box<dyn Iterator<Item = sled::Result<(sled::IVec, sled::IVec)>>> =
+            if q.reverse { Box::new(range.rev()) } else { Box::new(range) };
+
+        let limit = q.limit();
+        let mut items = Vec::new();
+        let mut has_more = false;
+        for entry in entries {
+            let (key, value) = entry?;
+            let record: serde_json::Value = serde_json::from_slice(&self.decode(collection, &key, &value)?)?;
+            if !q.matches(&record) {
+                continue;
+            }
+            if items.len() == limit {
+                has_more = true;
+                break;
+            }
+            let key = String::from_utf8_lossy(&key[scope_len..]).into_owned();
+            items.push((key, serde_json::from_value(record)?));
+        }
+
+        let next_cursor = if has_more { items.last().map(|(k, _)| encode_cursor(k)) } else { None };
+        Ok(Page { items, next_cursor, has_more })
+    }
+
     pub fn delete(&self, collection: &str, key: &str) -> Result<bool> {
+        let outcome = self
+            .modify(collection, key, |_| Ok::<_, Infallible>(None))?
+            .unwrap_or_else(|never| match never {});
+        Ok(outcome.previous.is_some())
+    }
+
+    /// Atomically read-modify-write one record.
+    ///
+    /// `f` sees the current serialized record (if any) and returns the bytes to
+    /// store, `None` to delete, or `Err` to leave the record untouched. It may
+    /// run more than once if the transaction is retried.
+    pub fn modify<E, F>(&self, collection: &str, key: &str, f: F) -> Result<std::result::Result<Modified, E>>
+    where
+        F: Fn(Option<&[u8]>) -> std::result::Result<Option<Vec<u8>>, E>,
+    {
+        let rejected: RefCell<Option<E>> = RefCell::new(None);
+        let result = self.transaction(&[collection], |tx| {
+            let previous = tx.get_raw(collection, key)?;
+            let current = match f(previous.as_deref()) {
+                Ok(current) => current,
+                Err(e) => {
+                    *rejected.borrow_mut() = Some(e);
+                    return abort(Rejected);
+                }
+            };
+            tx.put_raw(collection, key, current.clone())?;
+            Ok(Modified { previous, current })
+        });
+        match (result, rejected.into_inner()) {
+            (_, Some(e)) => Ok(Err(e)),
+            (Ok(outcome), None) => Ok(Ok(outcome)),
+            (Err(e), None) => Err(e),
+        }
+    }
+
+    /// Run `f` as one atomic transaction over `collections` (and their
+    /// indexes). Either every write lands or none does; the commit is made
+    /// durable per the strictest policy of `collections` and replication
+    /// events are only emitted after commit.
+    ///
+    /// `f` may be re-run on conflict, so it must not have side effects of its
+    /// own. Return `abort(err)` to roll back; `err` comes back out as the error.
+    pub fn transaction<R, F>(&self, collections: &[&str], f: F) -> Result<R>
+    where
+        F: Fn(&Tx<'_>) -> TxResult<R>,
+    {
+        let mut trees = Vec::new();
+        let mut layout = Vec::new();
+        for collection in collections {
+            if layout.iter().any(|l: &TreeLayout| l.collection == *collection) {
+                continue;
+            }
+            let main = trees.len();
+            trees.push(self.db.open_tree(collection)?);
+            let mut indexes = Vec::new();
+            for def in indexes_for(collection) {
+                indexes.push((def, trees.len()));
+                trees.push(self.db.open_tree(def.tree_name())?);
+            }
+            layout.push(TreeLayout { collection: collection.to_string(), main, indexes });
+        }
+        let events_slot = trees.len();
+        trees.push(self.db.open_tree(EVENTS_TREE)?);
+        trees.push(self.db.open_tree(HISTORY_TREE)?);
+        trees.push(self.db.open_tree(TRASH_TREE)?);
+
+        let commit = self.commit_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let result = trees.as_slice().transaction(|views| {
+            let tx = Tx {
+                db: self,
+                views,
+                layout: &layout,
+                events: &views[events_slot],
+                history: &views[events_slot + 1],
+                trash: &views[events_slot + 2],
+                pending: RefCell::new(Vec::new()),
+                last_event: Cell::new(None),
+            };
+            let value = f(&tx)?;
+            Ok((value, tx.pending.into_inner(), tx.last_event.get()))
+        });
+        drop(commit);
+        let (value, pending, last_event) = match result {
+            Ok(ok) => ok,
+            Err(TransactionError::Abort(e)) => return Err(e),
+            Err(TransactionError::Storage(e)) => return Err(e.into()),
+        };
+
+        if !pending.is_empty() {
+            self.persist(collections)?;
+        }
+        if let Some(seq) = last_event {
+            self.events.send_if_modified(|latest| {
+                let newer = seq > *latest;
+                if newer {
+                    *latest = seq;
+                }
+                newer
+            });
+        }
+        for write in pending.iter().filter(|w| self.history.contains(&w.collection)) {
+            // Outside the transaction: sled transactions can't scan a prefix
+            if let Err(e) = history::trim(self, &write.collection, &write.key, self.history_limit) {
+                log::warn!("Failed to trim history of {}/{}: {}", write.collection, write.key, e);
+            }
+        }
+        for write in pending {
+            match write.current {
+                Some(bytes) => self.replicate_upsert(&write.collection, &write.key, &bytes),
+                None if write.existed => self.replicate_delete(&write.collection, &write.key),
+                None => {}
+            }
+        }
+        Ok(value)
+    }
+
+    /// Notified with the latest change-feed sequence after each commit
+    pub fn subscribe_events(&self) -> tokio::sync::watch::Receiver<u64> {
+        self.events.subscribe()
+    }
+
+    /// Flush (now or via the group committer) after writing to `collections`
+    pub(crate) fn persist(&self, collections: &[&str]) -> Result<()> {
+        let durability = if self.force_durable { Durability::Always } else { self.durability.for_collections(collections) };
+        match (durability, &self.group_commit) {
+            (Durability::Os, _) => {}
+            (Durability::GroupCommit(_), Some(committer)) => committer.mark_dirty(),
+            // Group commit without a flusher can only mean it was never started
+            (Durability::Always, _) | (Durability::GroupCommit(_), None) => {
+                self.db.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Collect writes to apply together in one transaction
+    pub fn batch(&self) -> WriteBatch<'_> {
+        WriteBatch { db: self, ops: Vec::new() }
+    }
+
+    fn replicate_upsert(&self, collection: &str, key: &str, serialized: &[u8]) {
+        if let Some(rep) = self.replicator.as_ref().filter(|rep| rep.replicates(collection)) {
+            let table = collection.to_string();
+            let id = key.to_string();
+            let json_value: serde_json::Value =
+                serde_json::from_slice(serialized).unwrap_or(serde_json::json!({}));
+            let last_updated = json_value
+                .get("last_updated")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            let rep = rep.clone();
+            let committed = std::time::Instant::now();
+            // Child of the request span, so failures can be traced to the write
+            let span = tracing::info_span!("replicate", op = "upsert", table = %table);
+            tokio::spawn(async move {
+                match rep.upsert(&table, &id, &last_updated, &json_value).await {
+                    Ok(_) => metrics::REPLICATION_LAG.observe(&["upsert"], committed.elapsed().as_secs_f64()),
+                    Err(e) => {
+                        log::warn!("Replication upsert to {} failed: {}", table, e);
+                        metrics::REPLICATION_ERRORS.inc(&[&table, "upsert"]);
+                    }
+                }
+            }.instrument(span));
+        }
+    }
+
+    fn replicate_delete(&self, collection: &str, key: &str) {
+        if let Some(rep) = self.replicator.as_ref().filter(|rep| rep.replicates(collection)) {
+            let table = collection.to_string();
+            let id = key.to_string();
+            let rep = rep.clone();
+            let committed = std::time::Instant::now();
+            let span = tracing::info_span!("replicate", op = "delete", table = %table);
+            tokio::spawn(async move {
+                match rep.delete(&table, &id).await {
+                    Ok(_) => metrics::REPLICATION_LAG.observe(&["delete"], committed.elapsed().as_secs_f64()),
+                    Err(e) => {
+                        log::warn!("Replication delete from {} failed: {}", table, e);
+                        metrics::REPLICATION_ERRORS.inc(&[&table, "delete"]);
+                    }
+                }
+            }.instrument(span));
+        }
+    }
+
+    /// Records whose indexed field equals `value` (unique or non-unique index)
+    pub fn get_by_index<T: DeserializeOwned>(&self, collection: &str, index: &str, value: &str) -> Result<Vec<T>> {
+        let def = index_def(collection, index)?;
         let tree = self.db.open_tree(collection)?;
-        let existed = tree.remove(key)?.is_some();
-        self.db.flush()?;
-        if existed {
-            if let Some(rep) = &self.replicator {
-                let table = collection.to_string();
-                let id = key.to_string();
-                let rep = rep.clone();
-                tokio::spawn(async move {
-                    let _ = rep.delete(&table, &id).await;
-                });
+        let idx = self.db.open_tree(def.tree_name())?;
+        let scoped = self.index_key(def, value.to_string());
+
+        let primaries: Vec<sled::IVec> = if def.unique {
+            idx.get(scoped.as_bytes())?.into_iter().collect()
+        } else {
+            let mut prefix = scoped.into_bytes();
+            prefix.push(INDEX_SEP);
+            idx.scan_prefix(prefix).values().collect::<std::result::Result<_, _>>()?
+        };
+
+        let mut items = Vec::with_capacity(primaries.len());
+        for primary in primaries {
+            if let Some(data) = tree.get(&primary)? {
+                items.push(serde_json::from_slice(&self.decode(collection, &primary, &data)?)?);
             }
         }
-        Ok(existed)
+        Ok(items)
+    }
+
+    /// Single record by a unique index
+    pub fn find_unique<T: DeserializeOwned>(&self, collection: &str, index: &str, value: &str) -> Result<Option<T>> {
+        let def = index_def(collection, index)?;
+        if !def.unique {
+            return Err(anyhow::anyhow!("index '{}' on '{}' is not unique", index, collection));
+        }
+        Ok(self.get_by_index(collection, index, value)?.into_iter().next())
+    }
+
+    /// Whether the collection has no records (within this handle's tenant)
+    pub fn is_empty(&self, collection: &str) -> Result<bool> {
+        let tree = self.db.open_tree(collection)?;
+        Ok(tree.scan_prefix(self.scoped_key("")).next().is_none())
     }
 
     // FIXED: Returns Result<()> instead of Result<bool>
@@ -101,6 +916,26 @@ impl Database {
     }
 }
 
+/// Smallest key greater than every key starting with `prefix`; `None` when
+/// no such key exists (empty or all-0xff prefix)
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut upper = prefix.to_vec();
+    while let Some(last) = upper.pop() {
+        if last < u8::MAX {
+            upper.push(last + 1);
+            return Some(upper);
+        }
+    }
+    None
+}
+
+fn min_upper(current: Option<Vec<u8>>, bound: Vec<u8>) -> Option<Vec<u8>> {
+    Some(match current {
+        Some(current) => current.min(bound),
+        None => bound,
+    })
+}
+
 #[cfg(test)]
 mod db_tests {
     use super::*;
@@ -150,4 +985,216 @@ mod db_tests {
         let retrieved: Option<TestItem> = db.get("test_items", "1").unwrap();
         assert!(retrieved.is_none());
     }
+
+    #[test]
+    fn test_tenant_scoping_isolates_orgs() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let acme = db.for_tenant("acme");
+        let globex = db.for_tenant("globex");
+
+        let item = TestItem { id: "1".into(), name: "Acme quote".into() };
+        acme.insert("quotes", "1", &item).unwrap();
+
+        assert_eq!(acme.get::<TestItem>("quotes", "1").unwrap(), Some(item));
+        assert!(globex.get::<TestItem>("quotes", "1").unwrap().is_none());
+        assert_eq!(acme.list::<TestItem>("quotes").unwrap().len(), 1);
+        assert!(globex.list::<TestItem>("quotes").unwrap().is_empty());
+        assert!(!globex.delete("quotes", "1").unwrap());
+
+        // The unscoped handle sees the raw prefixed key
+        assert!(db.get::<TestItem>("quotes", "acme:1").unwrap().is_some());
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    struct IndexedUser {
+        id: String,
+        email: String,
+    }
+
+    #[test]
+    fn test_indexes_follow_writes() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let a = IndexedUser { id: "a".into(), email: "a@test.dev".into() };
+        db.insert("users", "a", &a).unwrap();
+
+        assert_eq!(db.find_unique::<IndexedUser>("users", "email", "a@test.dev").unwrap(), Some(a.clone()));
+
+        // Unique violation leaves both the record and the index untouched
+        let b = IndexedUser { id: "b".into(), email: "a@test.dev".into() };
+        let err = db.insert("users", "b", &b).unwrap_err();
+        assert!(err.downcast_ref::<UniqueViolation>().is_some());
+        assert!(db.get::<IndexedUser>("users", "b").unwrap().is_none());
+
+        // Changing the indexed value moves the entry
+        let renamed = IndexedUser { email: "renamed@test.dev".into(), ..a };
+        db.update("users", "a", &renamed).unwrap();
+        assert!(db.find_unique::<IndexedUser>("users", "email", "a@test.dev").unwrap().is_none());
+        db.insert("users", "b", &b).unwrap();
+        assert_eq!(db.find_unique::<IndexedUser>("users", "email", "renamed@test.dev").unwrap(), Some(renamed));
+
+        db.delete("users", "b").unwrap();
+        assert!(db.find_unique::<IndexedUser>("users", "email", "a@test.dev").unwrap().is_none());
+
+        // Non-unique index over global records, as API keys are written
+        #[derive(Serialize, Deserialize)]
+        struct Key { user_id: String }
+        db.insert("api_keys", "k1", &Key { user_id: "u1".into() }).unwrap();
+        db.insert("api_keys", "k2", &Key { user_id: "u1".into() }).unwrap();
+        db.insert("api_keys", "k3", &Key { user_id: "u2".into() }).unwrap();
+        assert_eq!(db.get_by_index::<Key>("api_keys", "user_id", "u1").unwrap().len(), 2);
+        db.delete("api_keys", "k2").unwrap();
+        assert_eq!(db.get_by_index::<Key>("api_keys", "user_id", "u1").unwrap().len(), 1);
+        assert!(db.find_unique::<Key>("api_keys", "user_id", "u1").is_err());
+    }
+
+    #[test]
+    fn test_transaction_is_all_or_nothing() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let quote = TestItem { id: "q1".into(), name: "Quote".into() };
+        let invoice = TestItem { id: "i1".into(), name: "Invoice".into() };
+
+        // Aborting after the first write leaves neither collection touched
+        let err = db
+            .transaction(&["quotes", "invoices"], |tx| {
+                tx.insert("quotes", "q1", &quote)?;
+                abort::<()>(anyhow::anyhow!("invoice numbering failed"))
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invoice numbering failed");
+        assert!(db.get::<TestItem>("quotes", "q1").unwrap().is_none());
+
+        let moved = db
+            .transaction(&["quotes", "invoices"], |tx| {
+                tx.insert("quotes", "q1", &quote)?;
+                let q: TestItem = tx.get("quotes", "q1")?.expect("visible inside the transaction");
+                tx.insert("invoices", "i1", &TestItem { name: format!("{} invoice", q.name), ..invoice.clone() })?;
+                tx.delete("quotes", "q1")
+            })
+            .unwrap();
+        assert!(moved);
+        assert!(db.get::<TestItem>("quotes", "q1").unwrap().is_none());
+        assert_eq!(db.get::<TestItem>("invoices", "i1").unwrap().unwrap().name, "Quote invoice");
+
+        // Collections must be declared up front
+        assert!(db.transaction(&["quotes"], |tx| tx.insert("invoices", "i2", &invoice)).is_err());
+    }
+
+    #[test]
+    fn test_batch_rolls_back_on_unique_violation() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        db.insert("users", "a", &IndexedUser { id: "a".into(), email: "a@test.dev".into() }).unwrap();
+
+        let mut batch = db.batch();
+        batch.insert("users", "b", &IndexedUser { id: "b".into(), email: "b@test.dev".into() }).unwrap();
+        batch.insert("users", "c", &IndexedUser { id: "c".into(), email: "a@test.dev".into() }).unwrap();
+        let err = batch.commit().unwrap_err();
+        assert!(err.downcast_ref::<UniqueViolation>().is_some());
+        assert!(db.get::<IndexedUser>("users", "b").unwrap().is_none());
+        assert!(db.find_unique::<IndexedUser>("users", "email", "b@test.dev").unwrap().is_none());
+
+        let mut batch = db.batch();
+        batch.insert("users", "a", &IndexedUser { id: "a".into(), email: "a2@test.dev".into() }).unwrap();
+        batch.insert("users", "c", &IndexedUser { id: "c".into(), email: "a@test.dev".into() }).unwrap();
+        batch.commit().unwrap();
+        assert_eq!(db.find_unique::<IndexedUser>("users", "email", "a@test.dev").unwrap().unwrap().id, "c");
+    }
+
+    #[test]
+    fn test_query_pages_with_cursor_filters_and_reverse() {
+        use crate::query::Filter;
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let acme = db.for_tenant("acme");
+        for i in 0..7 {
+            let item = TestItem { id: format!("item{}", i), name: if i % 2 == 0 { "even".into() } else { "odd".into() } };
+            acme.insert("items", &item.id, &item).unwrap();
+        }
+        db.for_tenant("other").insert("items", "item9", &TestItem { id: "item9".into(), name: "even".into() }).unwrap();
+
+        let mut q = ListQuery { limit: Some(3), ..Default::default() };
+        let mut seen = Vec::new();
+        loop {
+            let page: Page<TestItem> = acme.query("items", &q).unwrap();
+            seen.extend(page.items.iter().map(|(k, _)| k.clone()));
+            if !page.has_more {
+                assert!(page.next_cursor.is_none());
+                break;
+            }
+            q.cursor = page.next_cursor;
+        }
+        assert_eq!(seen, (0..7).map(|i| format!("item{}", i)).collect::<Vec<_>>());
+
+        let q = ListQuery {
+            filters: vec![Filter::parse("name:eq:even").unwrap()],
+            reverse: true,
+            limit: Some(2),
+            ..Default::default()
+        };
+        let page: Page<TestItem> = acme.query("items", &q).unwrap();
+        assert_eq!(page.items.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), ["item6", "item4"]);
+        assert!(page.has_more);
+        let page: Page<TestItem> = acme.query("items", &ListQuery { cursor: page.next_cursor, ..q }).unwrap();
+        assert_eq!(page.items.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), ["item2", "item0"]);
+        assert!(!page.has_more);
+
+        let q = ListQuery { start: Some("item2".into()), end: Some("item4".into()), ..Default::default() };
+        let page: Page<TestItem> = acme.query("items", &q).unwrap();
+        assert_eq!(page.items.len(), 2);
+    }
+
+    #[test]
+    fn test_group_commit_defers_flush_unless_durable() {
+        use crate::durability::DurabilityPolicy;
+        let dir = tempdir().unwrap();
+        let policy = DurabilityPolicy::parse("group:50ms", "scratch=os").unwrap();
+        let db = Database::new(dir.path().join("test.db").to_str().unwrap()).unwrap().with_durability(policy);
+        let committer = db.group_commit.clone().expect("group commit starts a flusher");
+        let item = TestItem { id: "1".into(), name: "one".into() };
+
+        db.insert("scratch", "1", &item).unwrap();
+        assert!(!committer.pending());
+        db.durable().insert("notes", "1", &item).unwrap();
+        assert!(!committer.pending());
+
+        db.insert("notes", "2", &item).unwrap();
+        assert!(committer.pending());
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(!committer.pending(), "flusher should have picked up the write");
+        assert_eq!(db.get::<TestItem>("notes", "2").unwrap(), Some(item));
+    }
+
+    #[test]
+    fn test_rebuild_index_backfills_existing_records() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        // Written behind the index's back, as data from before the index existed would be
+        let raw = db.db.open_tree("users").unwrap();
+        let u = IndexedUser { id: "x".into(), email: "x@test.dev".into() };
+        raw.insert("x", serde_json::to_vec(&u).unwrap()).unwrap();
+        assert!(db.find_unique::<IndexedUser>("users", "email", "x@test.dev").unwrap().is_none());
+
+        db.rebuild_index(index_def("users", "email").unwrap()).unwrap();
+        assert_eq!(db.find_unique::<IndexedUser>("users", "email", "x@test.dev").unwrap(), Some(u));
+    }
+
+    #[test]
+    fn test_rebuild_index_keeps_global_keys_containing_the_separator() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        #[derive(Serialize, Deserialize)]
+        struct Member { user_id: String }
+        // Memberships are global records keyed `{org}:{user}`
+        db.insert("memberships", "org1:u1", &Member { user_id: "u1".into() }).unwrap();
+        db.insert("api_keys", "qfk:k1", &Member { user_id: "u1".into() }).unwrap();
+
+        for def in INDEXES.iter().filter(|d| d.collection != "users") {
+            db.rebuild_index(def).unwrap();
+        }
+        assert_eq!(db.get_by_index::<Member>("memberships", "user_id", "u1").unwrap().len(), 1);
+        assert_eq!(db.get_by_index::<Member>("api_keys", "user_id", "u1").unwrap().len(), 1);
+    }
 }
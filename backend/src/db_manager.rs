@@ -481,7 +481,7 @@ impl DbManager {
         (ty_full, not_null)
     }
 
-    fn pluralize_snake(name: &str) -> String {
+    pub(crate) fn pluralize_snake(name: &str) -> String {
         let snake = Self::to_snake_case(name);
         if snake.ends_with('y') {
             format!("{}ies", &snake[..snake.len() - 1])
@@ -0,0 +1,188 @@
+// When writes to sled are made durable (fsync'd), per collection
+use actix_web::HttpRequest;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use crate::config::parse_duration;
+
+/// Header a client sends to have its write flushed before the response
+pub const DURABILITY_HEADER: &str = "X-Durability";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// Flush before the write returns
+    Always,
+    /// Flush in the background at most this long after the write
+    GroupCommit(Duration),
+    /// Leave it to sled's own periodic flush and the OS page cache
+    Os,
+}
+
+impl Durability {
+    /// `always`, `os`, or `group:<duration>` (e.g. `group:20ms`)
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim().to_lowercase();
+        match s.as_str() {
+            "always" => Ok(Durability::Always),
+            "os" => Ok(Durability::Os),
+            _ => match s.strip_prefix("group:") {
+                Some(interval) => {
+                    let interval = parse_duration(interval)?;
+                    if interval.is_zero() {
+                        return Err(anyhow!("group commit interval must be greater than zero"));
+                    }
+                    Ok(Durability::GroupCommit(interval))
+                }
+                None => Err(anyhow!("Invalid durability '{}': expected always, os or group:<duration>", s)),
+            },
+        }
+    }
+
+    /// Ordering used when one write touches collections with different
+    /// policies: the strictest one applies
+    fn strictness(&self) -> (u8, Duration) {
+        match self {
+            Durability::Always => (2, Duration::ZERO),
+            Durability::GroupCommit(d) => (1, Duration::MAX - *d),
+            Durability::Os => (0, Duration::ZERO),
+        }
+    }
+}
+
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Durability::Always => write!(f, "always"),
+            Durability::GroupCommit(d) => write!(f, "group:{}ms", d.as_millis()),
+            Durability::Os => write!(f, "os"),
+        }
+    }
+}
+
+/// Global durability plus per-collection overrides
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DurabilityPolicy {
+    pub default: Durability,
+    pub overrides: HashMap<String, Durability>,
+}
+
+impl Default for DurabilityPolicy {
+    fn default() -> Self {
+        Self { default: Durability::Always, overrides: HashMap::new() }
+    }
+}
+
+impl DurabilityPolicy {
+    /// Parse `DB_DURABILITY` and `DB_DURABILITY_OVERRIDES`
+    /// (`collection=policy,collection=policy`)
+    pub fn parse(default: &str, overrides: &str) -> Result<Self> {
+        let mut policy = Self { default: Durability::parse(default)?, overrides: HashMap::new() };
+        for entry in overrides.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (collection, value) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid durability override '{}': expected collection=policy", entry))?;
+            policy.overrides.insert(collection.trim().to_string(), Durability::parse(value)?);
+        }
+        Ok(policy)
+    }
+
+    pub fn for_collection(&self, collection: &str) -> Durability {
+        self.overrides.get(collection).copied().unwrap_or(self.default)
+    }
+
+    /// Strictest policy among the collections a write touched
+    pub fn for_collections(&self, collections: &[&str]) -> Durability {
+        collections
+            .iter()
+            .map(|c| self.for_collection(c))
+            .max_by_key(Durability::strictness)
+            .unwrap_or(self.default)
+    }
+
+    /// Shortest group-commit interval in use, if any collection uses one
+    fn group_interval(&self) -> Option<Duration> {
+        std::iter::once(&self.default)
+            .chain(self.overrides.values())
+            .filter_map(|d| match d {
+                Durability::GroupCommit(i) => Some(*i),
+                _ => None,
+            })
+            .min()
+    }
+}
+
+/// Background flusher for group-commit writes. Writers only set a flag; the
+/// thread flushes once per interval if anything was written, so many writes
+/// share one fsync. It exits when the database is dropped.
+pub struct GroupCommitter {
+    dirty: AtomicBool,
+}
+
+impl GroupCommitter {
+    /// Start a flusher if `policy` uses group commit anywhere
+    pub fn start(db: &Arc<sled::Db>, policy: &DurabilityPolicy) -> Option<Arc<Self>> {
+        let interval = policy.group_interval()?;
+        let committer = Arc::new(Self { dirty: AtomicBool::new(false) });
+        let weak_db: Weak<sled::Db> = Arc::downgrade(db);
+        let weak_self = Arc::downgrade(&committer);
+        std::thread::Builder::new()
+            .name("sled-group-commit".into())
+            .spawn(move || loop {
+                std::thread::sleep(interval);
+                let (Some(db), Some(committer)) = (weak_db.upgrade(), weak_self.upgrade()) else { break };
+                if committer.dirty.swap(false, Ordering::AcqRel) {
+                    if let Err(e) = db.flush() {
+                        log::error!("Group commit flush failed: {}", e);
+                        committer.dirty.store(true, Ordering::Release);
+                    }
+                }
+            })
+            .ok()?;
+        Some(committer)
+    }
+
+    pub fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// Whether writes are waiting for the next flush
+    #[cfg(test)]
+    pub fn pending(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+}
+
+/// Whether the client asked for this request's writes to be flushed before
+/// the response (`X-Durability: always`)
+pub fn requested(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(DURABILITY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().eq_ignore_ascii_case("always"))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_policies_and_picks_the_strictest() {
+        let policy = DurabilityPolicy::parse("group:50ms", "invoices=always, cache=os, events=group:5ms").unwrap();
+        assert_eq!(policy.default, Durability::GroupCommit(Duration::from_millis(50)));
+        assert_eq!(policy.for_collection("invoices"), Durability::Always);
+        assert_eq!(policy.for_collection("notes"), Durability::GroupCommit(Duration::from_millis(50)));
+        assert_eq!(policy.for_collections(&["cache", "notes"]), Durability::GroupCommit(Duration::from_millis(50)));
+        assert_eq!(policy.for_collections(&["notes", "events"]), Durability::GroupCommit(Duration::from_millis(5)));
+        assert_eq!(policy.for_collections(&["cache", "invoices"]), Durability::Always);
+        assert_eq!(policy.group_interval(), Some(Duration::from_millis(5)));
+
+        assert!(Durability::parse("sometimes").is_err());
+        assert!(Durability::parse("group:0ms").is_err());
+        assert!(DurabilityPolicy::parse("os", "invoices").is_err());
+    }
+}
@@ -0,0 +1,433 @@
+// Envelope encryption at rest for selected collections
+//
+// Records of encrypted collections are stored as
+// `QFE1 | key id length | key id | 24-byte nonce | XChaCha20-Poly1305 ciphertext`,
+// with the collection name and raw record key as associated data so a
+// ciphertext can't be moved to another record. Values without the `QFE1`
+// prefix are plaintext (written before encryption was enabled) and are read
+// as is until `encryption rotate` rewrites them.
+//
+// Data keys live in a JSON key ring next to the token key ring. Rotation
+// adds a new active key and keeps the old ones so older backups stay
+// readable. Secondary indexes of encrypted collections hold an HMAC of the
+// indexed value ("blind index") instead of the value itself.
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use orion::hazardous::aead::xchacha20poly1305::{self, Nonce, SecretKey};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::{fmt, fs, path::Path};
+
+use crate::audit::AUDIT_TREE;
+use crate::config::EncryptionConfig;
+use crate::db::Database;
+
+const MAGIC: &[u8; 4] = b"QFE1";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+
+/// Label of plaintext records in [`census`]
+pub const PLAINTEXT: &str = "plaintext";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DataKey {
+    /// First 8 bytes of the key's SHA-256, hex; stored in every envelope
+    pub id: String,
+    pub key_hex: String,
+    pub created_at: String,
+}
+
+impl fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataKey")
+            .field("id", &self.id)
+            .field("key_hex", &"<redacted>")
+            .field("created_at", &self.created_at)
+            .finish()
+    }
+}
+
+impl DataKey {
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; KEY_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        Self { id: key_id(&bytes), key_hex: hex::encode(bytes), created_at: Utc::now().to_rfc3339() }
+    }
+}
+
+fn key_id(key: &[u8]) -> String {
+    hex::encode(&Sha256::digest(key)[..8])
+}
+
+fn decode_key(key_hex: &str) -> Result<[u8; KEY_LEN]> {
+    hex::decode(key_hex.trim())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| anyhow!("data keys must be {} bytes of hex", KEY_LEN))
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DataKeyRing {
+    /// Id of the key new writes are sealed with
+    #[serde(default)]
+    pub active: Option<String>,
+    /// HMAC key for blind indexes; never rotated, so indexes survive key rotation
+    #[serde(default)]
+    pub index_key_hex: String,
+    #[serde(default)]
+    pub keys: Vec<DataKey>,
+}
+
+impl fmt::Debug for DataKeyRing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataKeyRing")
+            .field("active", &self.active)
+            .field("index_key_hex", &"<redacted>")
+            .field("keys", &self.keys)
+            .finish()
+    }
+}
+
+impl DataKeyRing {
+    /// Load the data key ring from a JSON file. A missing file yields an empty ring.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read data key ring {}", path.display()))?;
+        let ring: DataKeyRing = serde_json::from_str(&content)
+            .with_context(|| format!("invalid data key ring {}", path.display()))?;
+        if let Some(active) = &ring.active {
+            if !ring.keys.iter().any(|k| &k.id == active) {
+                return Err(anyhow!("data key ring {} has no key '{}'", path.display(), active));
+            }
+        }
+        Ok(ring)
+    }
+
+    /// Persist the ring atomically with owner-only permissions
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        crate::keyring::write_private_json(path.as_ref(), self)
+    }
+
+    /// Generate a new active key. Previous keys are kept: records and backups
+    /// sealed with them must stay readable.
+    pub fn rotate(&mut self) -> &DataKey {
+        if self.index_key_hex.is_empty() {
+            let mut index_key = [0u8; KEY_LEN];
+            rand::rngs::OsRng.fill_bytes(&mut index_key);
+            self.index_key_hex = hex::encode(index_key);
+        }
+        let key = DataKey::generate();
+        self.active = Some(key.id.clone());
+        self.keys.push(key);
+        self.keys.last().expect("key just pushed")
+    }
+}
+
+/// The configured key ring, or one holding just `DB_ENCRYPTION_KEY` when
+/// the ring is empty (its index key is derived from the data key)
+pub fn effective_ring(cfg: &EncryptionConfig) -> Result<DataKeyRing> {
+    if cfg.keyring.active.is_some() || cfg.key_hex.trim().is_empty() {
+        return Ok(cfg.keyring.clone());
+    }
+    let key = decode_key(&cfg.key_hex).context("invalid DB_ENCRYPTION_KEY")?;
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).expect("HMAC accepts any key length");
+    mac.update(b"blind-index");
+    Ok(DataKeyRing {
+        active: Some(key_id(&key)),
+        index_key_hex: hex::encode(mac.finalize().into_bytes()),
+        keys: vec![DataKey { id: key_id(&key), key_hex: hex::encode(key), created_at: String::new() }],
+    })
+}
+
+/// Which collections get encrypted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Collections {
+    All,
+    Only(Vec<String>),
+}
+
+impl Collections {
+    pub fn parse(names: &[String]) -> Self {
+        if names.iter().any(|n| n == "*") {
+            Collections::All
+        } else {
+            Collections::Only(names.to_vec())
+        }
+    }
+}
+
+/// Trees that are never encrypted: internal bookkeeping, index trees and
+/// the audit log (written and read outside `Database`)
+fn is_internal(tree: &str) -> bool {
+    tree.starts_with("__") || tree.contains("__idx_") || tree == AUDIT_TREE
+}
+
+/// Seals and opens record envelopes
+pub struct Cipher {
+    keys: HashMap<String, [u8; KEY_LEN]>,
+    active: String,
+    index_key: [u8; KEY_LEN],
+    collections: Collections,
+}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cipher")
+            .field("active", &self.active)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .field("collections", &self.collections)
+            .finish()
+    }
+}
+
+impl Cipher {
+    pub fn new(ring: &DataKeyRing, collections: Collections) -> Result<Self> {
+        let mut keys = HashMap::new();
+        for key in &ring.keys {
+            keys.insert(key.id.clone(), decode_key(&key.key_hex)?);
+        }
+        let active = ring.active.clone().ok_or_else(|| anyhow!("data key ring has no active key"))?;
+        if !keys.contains_key(&active) {
+            return Err(anyhow!("active data key '{}' is not in the ring", active));
+        }
+        let index_key = decode_key(&ring.index_key_hex).context("invalid index key")?;
+        Ok(Self { keys, active, index_key, collections })
+    }
+
+    /// Cipher for the configured collections, or `None` when encryption is
+    /// off and no key is configured
+    pub fn from_config(cfg: &EncryptionConfig) -> Result<Option<Self>> {
+        let ring = effective_ring(cfg)?;
+        if ring.active.is_none() {
+            if cfg.collections.is_empty() {
+                return Ok(None);
+            }
+            return Err(anyhow!(
+                "encrypted collections configured but no data key (set DB_ENCRYPTION_KEY or run `encryption rotate`)"
+            ));
+        }
+        Self::new(&ring, Collections::parse(&cfg.collections)).map(Some)
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.active
+    }
+
+    /// Whether new writes to `collection` are encrypted
+    pub fn covers(&self, collection: &str) -> bool {
+        if is_internal(collection) {
+            return false;
+        }
+        match &self.collections {
+            Collections::All => true,
+            Collections::Only(names) => names.iter().any(|n| n == collection),
+        }
+    }
+
+    fn aad(collection: &str, key: &[u8]) -> Vec<u8> {
+        let mut aad = Vec::with_capacity(collection.len() + 1 + key.len());
+        aad.extend_from_slice(collection.as_bytes());
+        aad.push(0);
+        aad.extend_from_slice(key);
+        aad
+    }
+
+    /// Encrypt a record with the active key
+    pub fn seal(&self, collection: &str, key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let secret = SecretKey::from_slice(&self.keys[&self.active]).map_err(|_| anyhow!("invalid data key"))?;
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let nonce_obj = Nonce::from_slice(&nonce).map_err(|_| anyhow!("invalid nonce"))?;
+
+        let header_len = MAGIC.len() + 1 + self.active.len() + NONCE_LEN;
+        let mut out = Vec::with_capacity(header_len + plaintext.len() + TAG_LEN);
+        out.extend_from_slice(MAGIC);
+        out.push(self.active.len() as u8);
+        out.extend_from_slice(self.active.as_bytes());
+        out.extend_from_slice(&nonce);
+        out.resize(header_len + plaintext.len() + TAG_LEN, 0);
+        xchacha20poly1305::seal(&secret, &nonce_obj, plaintext, Some(&Self::aad(collection, key)), &mut out[header_len..])
+            .map_err(|_| anyhow!("encryption failed"))?;
+        Ok(out)
+    }
+
+    /// Decrypt an envelope written by [`Cipher::seal`]
+    pub fn open(&self, collection: &str, key: &[u8], stored: &[u8]) -> Result<Vec<u8>> {
+        let (key_id, rest) = split_envelope(stored).ok_or_else(|| anyhow!("malformed encrypted record"))?;
+        let data_key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| anyhow!("record sealed with unknown data key '{}'", key_id))?;
+        let secret = SecretKey::from_slice(data_key).map_err(|_| anyhow!("invalid data key"))?;
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let nonce = Nonce::from_slice(nonce).map_err(|_| anyhow!("invalid nonce"))?;
+        let mut plaintext = vec![0u8; ciphertext.len() - TAG_LEN];
+        xchacha20poly1305::open(&secret, &nonce, ciphertext, Some(&Self::aad(collection, key)), &mut plaintext)
+            .map_err(|_| anyhow!("{} record failed authentication (wrong key or tampered data)", collection))?;
+        Ok(plaintext)
+    }
+
+    /// Keyed hash of an indexed value, so index trees don't leak it
+    pub fn blind(&self, value: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.index_key).expect("HMAC accepts any key length");
+        mac.update(value.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// Whether a stored value should be rewritten to match the current
+    /// configuration (sealed with the active key, or plaintext if not covered)
+    pub fn needs_rewrite(&self, collection: &str, stored: &[u8]) -> bool {
+        match (self.covers(collection), sealed_key_id(stored)) {
+            (true, Some(id)) => id != self.active,
+            (true, None) => true,
+            (false, sealed) => sealed.is_some(),
+        }
+    }
+}
+
+/// Whether a stored value is an encryption envelope
+pub fn is_sealed(stored: &[u8]) -> bool {
+    stored.starts_with(MAGIC)
+}
+
+/// Id of the data key a stored value was sealed with; `None` for plaintext
+pub fn sealed_key_id(stored: &[u8]) -> Option<&str> {
+    split_envelope(stored).map(|(id, _)| id)
+}
+
+fn split_envelope(stored: &[u8]) -> Option<(&str, &[u8])> {
+    let rest = stored.strip_prefix(MAGIC)?;
+    let (&id_len, rest) = rest.split_first()?;
+    let id_len = id_len as usize;
+    if rest.len() < id_len + NONCE_LEN + TAG_LEN {
+        return None;
+    }
+    let (id, rest) = rest.split_at(id_len);
+    Some((std::str::from_utf8(id).ok()?, rest))
+}
+
+/// Collections holding records (everything but internal trees)
+fn data_trees(db: &Database) -> Vec<String> {
+    db.db
+        .tree_names()
+        .into_iter()
+        .filter_map(|name| String::from_utf8(name.to_vec()).ok())
+        .filter(|name| !is_internal(name))
+        .collect()
+}
+
+/// Rewrite every record that isn't stored the way the current configuration
+/// wants it. Returns how many records were rewritten.
+pub fn reencrypt_all(db: &Database) -> Result<usize> {
+    let mut total = 0;
+    for collection in data_trees(db) {
+        let rewritten = db.reencrypt(&collection)?;
+        if rewritten > 0 {
+            log::info!("Re-encrypted {} record(s) in {}", rewritten, collection);
+        }
+        total += rewritten;
+    }
+    Ok(total)
+}
+
+/// Record counts per collection and data key id ([`PLAINTEXT`] for unencrypted)
+pub fn census(db: &Database) -> Result<BTreeMap<String, BTreeMap<String, usize>>> {
+    let mut counts = BTreeMap::new();
+    for collection in data_trees(db) {
+        let mut per_key: BTreeMap<String, usize> = BTreeMap::new();
+        for value in db.db.open_tree(&collection)?.iter().values() {
+            let value = value?;
+            let label = sealed_key_id(&value).unwrap_or(PLAINTEXT).to_string();
+            *per_key.entry(label).or_default() += 1;
+        }
+        if !per_key.is_empty() {
+            counts.insert(collection, per_key);
+        }
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    fn cipher(ring: &DataKeyRing) -> Option<Arc<Cipher>> {
+        Some(Arc::new(Cipher::new(ring, Collections::parse(&["users".to_string()])).unwrap()))
+    }
+
+    /// Reopen after a drop. sled's background threads can hold the file lock
+    /// for a moment after the last handle goes away.
+    fn reopen(path: &str, cipher: Option<Arc<Cipher>>) -> Result<Database> {
+        for _ in 0..50 {
+            match Database::open(path, cipher.clone()) {
+                Err(e) if e.to_string().contains("could not acquire lock") => {
+                    std::thread::sleep(std::time::Duration::from_millis(20))
+                }
+                result => return result,
+            }
+        }
+        Database::open(path, cipher)
+    }
+
+    #[test]
+    fn encrypts_covered_collections_and_rotates_in_place() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sled");
+        let path = path.to_str().unwrap();
+        let mut ring = DataKeyRing::default();
+        let first = ring.rotate().id.clone();
+
+        let db = Database::open(path, cipher(&ring)).unwrap();
+        db.insert("users", "u1", &json!({"email": "ada@example.test", "name": "Ada"})).unwrap();
+        db.insert("notes", "n1", &json!({"text": "public"})).unwrap();
+
+        // At rest: sealed, no plaintext in the record or its index
+        let raw = db.db.open_tree("users").unwrap().get("u1").unwrap().unwrap();
+        assert_eq!(sealed_key_id(&raw), Some(first.as_str()));
+        assert!(!String::from_utf8_lossy(&raw).contains("ada@example.test"));
+        for entry in db.db.open_tree("users__idx_email").unwrap().iter().keys() {
+            assert!(!String::from_utf8_lossy(&entry.unwrap()).contains("ada"));
+        }
+        assert!(!is_sealed(&db.db.open_tree("notes").unwrap().get("n1").unwrap().unwrap()));
+
+        // Transparent reads
+        let user: Value = db.get("users", "u1").unwrap().unwrap();
+        assert_eq!(user["name"], "Ada");
+        assert_eq!(db.list::<Value>("users").unwrap().len(), 1);
+        let found: Option<Value> = db.find_unique("users", "email", "ada@example.test").unwrap();
+        assert!(found.is_some());
+
+        // Moving a ciphertext to another key fails authentication
+        db.db.open_tree("users").unwrap().insert("u2", raw.clone()).unwrap();
+        assert!(db.get::<Value>("users", "u2").is_err());
+        db.db.open_tree("users").unwrap().remove("u2").unwrap();
+        drop(db);
+
+        // Rotate, reopen, rewrite in place
+        let second = ring.rotate().id.clone();
+        let db = reopen(path, cipher(&ring)).unwrap();
+        assert_eq!(reencrypt_all(&db).unwrap(), 1);
+        assert_eq!(reencrypt_all(&db).unwrap(), 0);
+        let counts = census(&db).unwrap();
+        assert_eq!(counts["users"].keys().collect::<Vec<_>>(), [&second]);
+        assert_eq!(counts["notes"][PLAINTEXT], 1);
+        let user: Value = db.get("users", "u1").unwrap().unwrap();
+        assert_eq!(user["email"], "ada@example.test");
+        drop(db);
+
+        // Without any key, encrypted records can't be read
+        let err = reopen(path, None).err().unwrap();
+        assert!(err.to_string().contains("no data key"), "{}", err);
+    }
+}
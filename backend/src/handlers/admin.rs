@@ -0,0 +1,40 @@
+// Server administration endpoints
+use actix_web::{post, web, HttpRequest, HttpResponse, Result};
+use serde_json::json;
+
+use crate::audit::{self, AuditEvent, AuditOutcome};
+use crate::db::Database;
+use crate::handlers::users::require_admin;
+use crate::reload::{self, LiveConfig};
+use crate::types::ErrorResponse;
+
+/// Re-read the .env, TOML and CORS files and apply them without a restart (admin only).
+/// Responds with what changed; settings marked `applied: false` need a restart.
+#[post("/admin/config/reload")]
+pub async fn reload_config(req: HttpRequest, live: web::Data<LiveConfig>, db: web::Data<Database>) -> Result<HttpResponse> {
+    let claims = require_admin(&req)?;
+    let live = live.into_inner();
+    let result = web::block(move || live.reload()).await.map_err(actix_web::error::ErrorInternalServerError)?;
+    match result {
+        Ok(changes) => {
+            reload::log_changes(&format!("admin {}", claims.sub), &changes);
+            audit::record(
+                &db,
+                AuditEvent::from_request(&req, "config.reloaded", AuditOutcome::Success)
+                    .actor(&claims.sub)
+                    .details(json!({ "changed": changes.iter().map(|c| &c.field).collect::<Vec<_>>() })),
+            ).await;
+            Ok(HttpResponse::Ok().json(json!({ "changes": changes })))
+        }
+        Err(e) => {
+            log::error!("Config reload by {} rejected: {}", claims.sub, e);
+            audit::record(
+                &db,
+                AuditEvent::from_request(&req, "config.reloaded", AuditOutcome::Failure)
+                    .actor(&claims.sub)
+                    .details(json!({ "error": e.to_string() })),
+            ).await;
+            Ok(HttpResponse::UnprocessableEntity().json(ErrorResponse::new("invalid_config", e.to_string())))
+        }
+    }
+}
@@ -0,0 +1,320 @@
+// Personal API keys for machine clients
+use actix_web::{delete, get, post, web, HttpMessage, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::audit::{self, AuditEvent, AuditOutcome};
+use crate::config::AppConfig;
+use crate::db::Database;
+use crate::handlers::orgs::get_membership;
+use crate::models::api_key_types::{
+    ApiKeyInfo, ApiKeyRecord, CreateApiKeyRequest, ALL_SCOPES, SCOPE_ADMIN, SCOPE_READ, SCOPE_WRITE,
+};
+use crate::models::auth_types::{Claims, UserRecord};
+use crate::types::ErrorResponse;
+
+pub const API_KEYS_TREE: &str = "api_keys";
+
+/// Every API key starts with this marker so `guard_api` can tell it apart from session tokens
+pub const API_KEY_MARKER: &str = "qfk_";
+
+const KEY_ALPHABET: [char; 62] = [
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i',
+    'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'A', 'B',
+    'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U',
+    'V', 'W', 'X', 'Y', 'Z',
+];
+
+/// Don't rewrite `last_used_at` more often than this per key
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Split `qfk_<prefix>_<secret>` into its prefix
+fn key_prefix(key: &str) -> Option<&str> {
+    let rest = key.strip_prefix(API_KEY_MARKER)?;
+    let (prefix, secret) = rest.split_once('_')?;
+    if prefix.is_empty() || secret.is_empty() {
+        return None;
+    }
+    Some(prefix)
+}
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_MARKER)
+}
+
+/// Resolve an API key to request claims.
+///
+/// Returns `None` for unknown, revoked or expired keys and for keys whose
+/// owner no longer exists or is disabled. The owner's global `admin` role is only kept when
+/// the key has the `admin` scope; the org is dropped if the membership is gone.
+pub fn authenticate_api_key(db: &Database, cfg: &AppConfig, key: &str) -> Option<(Claims, ApiKeyRecord)> {
+    let prefix = key_prefix(key)?;
+    let mut record: ApiKeyRecord = db.get(API_KEYS_TREE, prefix).ok().flatten()?;
+    if !constant_time_eq(record.key_hash.as_bytes(), hash_key(key).as_bytes()) {
+        return None;
+    }
+    if record.revoked_at.is_some() {
+        return None;
+    }
+    let now = Utc::now();
+    let expires_at = match &record.expires_at {
+        Some(ts) => {
+            let exp = DateTime::parse_from_rfc3339(ts).ok()?.with_timezone(&Utc);
+            if exp <= now {
+                return None;
+            }
+            Some(exp)
+        }
+        None => None,
+    };
+    let user: UserRecord = db.get("users", &record.user_id).ok().flatten()?;
+    if user.disabled || user.anonymized_at.is_some() {
+        return None;
+    }
+
+    let stale = record
+        .last_used_at
+        .as_deref()
+        .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+        .map(|ts| (now - ts.with_timezone(&Utc)).num_seconds() >= LAST_USED_RESOLUTION_SECS)
+        .unwrap_or(true);
+    if stale {
+        record.last_used_at = Some(now.to_rfc3339());
+        if let Err(e) = db.update(API_KEYS_TREE, prefix, &record) {
+            log::warn!("Failed to record API key use for {}: {}", prefix, e);
+        }
+    }
+
+    let roles = user
+        .roles
+        .iter()
+        .filter(|r| r.as_str() != "admin" || record.has_scope(SCOPE_ADMIN))
+        .cloned()
+        .collect();
+    let membership = record
+        .org_id
+        .as_deref()
+        .and_then(|org_id| get_membership(db, org_id, &user.id));
+    let claims = Claims {
+        sub: user.id,
+        email: user.email,
+        roles,
+        iss: cfg.security.token_iss.clone(),
+        aud: cfg.security.token_aud.clone(),
+        iat: now.timestamp(),
+        iat_ms: Some(now.timestamp_millis()),
+        exp: expires_at
+            .unwrap_or_else(|| now + Duration::seconds(cfg.security.token_ttl_seconds as i64))
+            .timestamp(),
+        org_id: membership.as_ref().map(|m| m.org_id.clone()),
+        org_roles: membership.map(|m| m.roles).unwrap_or_default(),
+        impersonator: None,
+    };
+    Some((claims, record))
+}
+
+/// Whether a key's scopes permit the HTTP method (`read` covers safe methods only)
+pub fn scope_allows_method(record: &ApiKeyRecord, method: &actix_web::http::Method) -> bool {
+    use actix_web::http::Method;
+    if record.has_scope(SCOPE_WRITE) || record.has_scope(SCOPE_ADMIN) {
+        return true;
+    }
+    record.has_scope(SCOPE_READ) && matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn caller(req: &HttpRequest) -> Result<Claims> {
+    req.extensions()
+        .get::<Claims>()
+        .cloned()
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing authentication token"))
+}
+
+/// Create an API key for the caller. The plaintext key is only returned here.
+#[post("/keys")]
+pub async fn create_api_key(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    body: web::Json<CreateApiKeyRequest>,
+) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    if claims.impersonator.is_some() {
+        return Ok(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "impersonation_not_allowed",
+            "API keys cannot be created while impersonating",
+        )));
+    }
+    if req.extensions().get::<ApiKeyRecord>().is_some() {
+        return Ok(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "api_key_not_allowed",
+            "API keys cannot create other API keys",
+        )));
+    }
+
+    let name = body.name.trim();
+    if name.is_empty() || name.len() > 64 {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "invalid_name",
+            "Key name must be 1-64 characters",
+        )));
+    }
+    let scopes = if body.scopes.is_empty() { vec![SCOPE_READ.to_string()] } else { body.scopes.clone() };
+    if let Some(bad) = scopes.iter().find(|s| !ALL_SCOPES.contains(&s.as_str())) {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "invalid_scope",
+            format!("Unknown scope '{}'", bad),
+        )));
+    }
+    if scopes.iter().any(|s| s == SCOPE_ADMIN) && !claims.roles.contains(&"admin".to_string()) {
+        return Ok(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "insufficient_permissions",
+            "Only admins can create keys with the admin scope",
+        )));
+    }
+
+    let prefix = nanoid::nanoid!(12, &KEY_ALPHABET);
+    let secret = nanoid::nanoid!(40, &KEY_ALPHABET);
+    let key = format!("{}{}_{}", API_KEY_MARKER, prefix, secret);
+    let now = Utc::now();
+    let record = ApiKeyRecord {
+        prefix: prefix.clone(),
+        user_id: claims.sub.clone(),
+        name: name.to_string(),
+        key_hash: hash_key(&key),
+        scopes,
+        org_id: claims.org_id.clone(),
+        created_at: now.to_rfc3339(),
+        expires_at: body.expires_in_days.map(|d| (now + Duration::days(d as i64)).to_rfc3339()),
+        last_used_at: None,
+        revoked_at: None,
+    };
+    let (key_id, stored) = (prefix.clone(), record.clone());
+    db.for_request(&req)
+        .run(move |db| db.insert(API_KEYS_TREE, &key_id, &stored))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    audit::record(&db, AuditEvent::from_request(&req, "api_key.created", AuditOutcome::Success)
+        .actor(claims.sub.clone())
+        .target(prefix.clone())
+        .details(json!({"scopes": record.scopes, "expires_at": record.expires_at}))).await;
+
+    Ok(HttpResponse::Created().json(json!({
+        "key": key,
+        "info": ApiKeyInfo::from(record),
+    })))
+}
+
+/// List the caller's API keys (metadata only)
+#[get("/keys")]
+pub async fn list_api_keys(db: web::Data<Database>, req: HttpRequest) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    let mut keys: Vec<ApiKeyInfo> = db
+        .get_by_index::<ApiKeyRecord>(API_KEYS_TREE, "user_id", &claims.sub)
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .into_iter()
+        .map(ApiKeyInfo::from)
+        .collect();
+    keys.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(HttpResponse::Ok().json(json!({ "keys": keys })))
+}
+
+/// Revoke one of the caller's keys (admins may revoke any key)
+#[delete("/keys/{prefix}")]
+pub async fn revoke_api_key(
+    path: web::Path<String>,
+    db: web::Data<Database>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    let prefix = path.into_inner();
+    let mut record: ApiKeyRecord = match db.get(API_KEYS_TREE, &prefix) {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse::new("key_not_found", "API key not found")));
+        }
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    };
+    if record.user_id != claims.sub && !claims.roles.contains(&"admin".to_string()) {
+        return Ok(HttpResponse::NotFound().json(ErrorResponse::new("key_not_found", "API key not found")));
+    }
+    if record.revoked_at.is_none() {
+        record.revoked_at = Some(Utc::now().to_rfc3339());
+        // A revocation lost in a crash would bring the key back to life
+        let (key_id, stored) = (prefix.clone(), record.clone());
+        db.run(move |db| db.durable().update(API_KEYS_TREE, &key_id, &stored))
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        audit::record(&db, AuditEvent::from_request(&req, "api_key.revoked", AuditOutcome::Success)
+            .actor(claims.sub.clone())
+            .target(prefix.clone())).await;
+    }
+    Ok(HttpResponse::Ok().json(ApiKeyInfo::from(record)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn store_key(db: &Database, user: &UserRecord, scopes: &[&str], expires_at: Option<String>) -> String {
+        let prefix = nanoid::nanoid!(12, &KEY_ALPHABET);
+        let key = format!("{}{}_{}", API_KEY_MARKER, prefix, nanoid::nanoid!(40, &KEY_ALPHABET));
+        let record = ApiKeyRecord {
+            prefix: prefix.clone(),
+            user_id: user.id.clone(),
+            name: "ci".into(),
+            key_hash: hash_key(&key),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            org_id: None,
+            created_at: Utc::now().to_rfc3339(),
+            expires_at,
+            last_used_at: None,
+            revoked_at: None,
+        };
+        db.insert(API_KEYS_TREE, &prefix, &record).unwrap();
+        key
+    }
+
+    #[test]
+    fn api_key_authentication_rules() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        let cfg = crate::config::load_config_from_file("/nonexistent/.env");
+        let admin = UserRecord::new_admin("admin@test.dev", String::new());
+        db.insert("users", &admin.id, &admin).unwrap();
+
+        // read-only key drops the admin role and tracks last use
+        let key = store_key(&db, &admin, &[SCOPE_READ], None);
+        let (claims, record) = authenticate_api_key(&db, &cfg, &key).expect("valid key");
+        assert_eq!(claims.sub, admin.id);
+        assert!(claims.roles.is_empty());
+        assert!(!scope_allows_method(&record, &actix_web::http::Method::POST));
+        let stored: ApiKeyRecord = db.get(API_KEYS_TREE, &record.prefix).unwrap().unwrap();
+        assert!(stored.last_used_at.is_some());
+
+        // admin scope keeps the role
+        let key = store_key(&db, &admin, &[SCOPE_ADMIN], None);
+        let (claims, _) = authenticate_api_key(&db, &cfg, &key).unwrap();
+        assert_eq!(claims.roles, vec!["admin"]);
+
+        // wrong secret, expired and revoked keys are rejected
+        let mut tampered = key.clone();
+        tampered.push('x');
+        assert!(authenticate_api_key(&db, &cfg, &tampered).is_none());
+        let expired = store_key(&db, &admin, &[SCOPE_READ], Some((Utc::now() - Duration::days(1)).to_rfc3339()));
+        assert!(authenticate_api_key(&db, &cfg, &expired).is_none());
+        let prefix = key_prefix(&key).unwrap().to_string();
+        let mut record: ApiKeyRecord = db.get(API_KEYS_TREE, &prefix).unwrap().unwrap();
+        record.revoked_at = Some(Utc::now().to_rfc3339());
+        db.update(API_KEYS_TREE, &prefix, &record).unwrap();
+        assert!(authenticate_api_key(&db, &cfg, &key).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+// Audit log query and export endpoints (admin only)
+use actix_web::{get, web, HttpMessage, HttpRequest, HttpResponse, Result};
+use serde_json::json;
+
+use crate::audit::{self, AuditFilter};
+use crate::db::Database;
+use crate::models::auth_types::Claims;
+use crate::types::ErrorResponse;
+
+/// Default page size for the JSON query endpoint
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+fn require_admin(req: &HttpRequest) -> Result<Option<HttpResponse>> {
+    let claims = req.extensions().get::<Claims>().cloned()
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing authentication token"))?;
+    if !claims.roles.contains(&"admin".to_string()) {
+        return Ok(Some(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "insufficient_permissions",
+            "Admin role required"
+        ))));
+    }
+    Ok(None)
+}
+
+/// Query audit entries, oldest first.
+/// Filters: `actor`, `action` (exact or dotted prefix), `target`, `outcome`,
+/// `since`/`until` (RFC 3339), `after_seq` for paging and `limit`.
+#[get("/audit")]
+pub async fn query_audit(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    filter: web::Query<AuditFilter>,
+) -> Result<HttpResponse> {
+    if let Some(resp) = require_admin(&req)? {
+        return Ok(resp);
+    }
+    let mut filter = filter.into_inner();
+    let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    filter.limit = Some(limit + 1);
+
+    let mut entries = match audit::query(&db, &filter) {
+        Ok(e) => e,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new("invalid_filter", e.to_string())));
+        }
+    };
+    let has_more = entries.len() > limit;
+    entries.truncate(limit);
+    let next_after_seq = if has_more { entries.last().map(|e| e.seq) } else { None };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "entries": entries,
+        "has_more": has_more,
+        "next_after_seq": next_after_seq,
+    })))
+}
+
+/// Export matching entries as JSON Lines (no limit unless one is given)
+#[get("/audit/export")]
+pub async fn export_audit(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    filter: web::Query<AuditFilter>,
+) -> Result<HttpResponse> {
+    if let Some(resp) = require_admin(&req)? {
+        return Ok(resp);
+    }
+    let entries = match audit::query(&db, &filter) {
+        Ok(e) => e,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse::new("invalid_filter", e.to_string())));
+        }
+    };
+    let mut body = String::new();
+    for entry in &entries {
+        body.push_str(&serde_json::to_string(entry).map_err(actix_web::error::ErrorInternalServerError)?);
+        body.push('\n');
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header(("Content-Disposition", "attachment; filename=\"audit_log.jsonl\""))
+        .body(body))
+}
+
+/// Re-compute the hash chain and report whether it is intact
+#[get("/audit/verify")]
+pub async fn verify_audit(db: web::Data<Database>, req: HttpRequest) -> Result<HttpResponse> {
+    if let Some(resp) = require_admin(&req)? {
+        return Ok(resp);
+    }
+    let report = audit::verify_chain(&db).map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(report))
+}
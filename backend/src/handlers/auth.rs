@@ -6,13 +6,17 @@ use rand_core::OsRng;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
-use pasetors::{local, keys::SymmetricKey, version4::V4, token::UntrustedToken, claims::{Claims as PasetoClaims, ClaimsValidationRules}};
-use time::{OffsetDateTime, Duration as TimeDuration, format_description::well_known::Rfc3339};
+use pasetors::{local, public, Public, keys::SymmetricKey, version4::V4, token::UntrustedToken, footer::Footer, claims::{Claims as PasetoClaims, ClaimsValidationRules}};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
 use chrono::{Utc, Duration};
 use serde_json::json;
 
-use crate::{db::Database, models::auth_types::{UserRecord, RegisterRequest, LoginRequest, Claims}};
+use crate::audit::{self, AuditEvent, AuditOutcome};
+use crate::{db::{Database, UniqueViolation}, models::auth_types::{UserRecord, RegisterRequest, LoginRequest, Claims}};
 use crate::config::{AppConfig, TokenMode};
+use crate::handlers::orgs::{get_membership, resolve_active_org};
+use crate::handlers::api_keys::{authenticate_api_key, is_api_key, scope_allows_method};
+use crate::handlers::users::{complete_password_reset, session_is_current, LifecycleError};
 use crate::handlers::cookies::{set_auth_cookies, clear_auth_cookies, extract_token, ACCESS_COOKIE_NAME, REFRESH_COOKIE_NAME};
 
 
@@ -74,14 +78,39 @@ fn validate_token_hmac(cfg: &AppConfig, token: &str) -> Option<Claims> {
         if claims.exp < now { return None; }
         if claims.iss != cfg.security.token_iss || claims.aud != cfg.security.token_aud { return None; }
         Some(claims)
-    } else if !cfg.security.access_token.is_empty() && cfg.security.access_token == token {
-        // Static access token fallback (admin privileges)
-        Some(Claims { sub: "access".into(), email: "access@local".into(), roles: vec!["admin".into()], iss: cfg.security.token_iss.clone(), aud: cfg.security.token_aud.clone(), iat: Utc::now().timestamp(), exp: (Utc::now() + Duration::hours(cfg.security.auth_token_expiry_hours as i64)).timestamp() })
     } else {
         None
     }
 }
 
+/// Static `ACCESS_TOKEN` as full admin. Only honoured when
+/// `ACCESS_TOKEN_ADMIN_BYPASS=true`, and every use is logged at warn level.
+fn validate_static_access_token(cfg: &AppConfig, token: &str) -> Option<Claims> {
+    if !is_static_access_token(cfg, token) {
+        return None;
+    }
+    tracing::warn!("⚠️ Request authenticated with the global ACCESS_TOKEN admin bypass");
+    Some(Claims { sub: "access".into(), email: "access@local".into(), roles: vec!["admin".into()], iss: cfg.security.token_iss.clone(), aud: cfg.security.token_aud.clone(), iat: Utc::now().timestamp(), iat_ms: None, exp: (Utc::now() + Duration::hours(cfg.security.auth_token_expiry_hours as i64)).timestamp(), org_id: None, org_roles: vec![], impersonator: None })
+}
+
+fn is_static_access_token(cfg: &AppConfig, token: &str) -> bool {
+    cfg.security.access_token_admin_bypass
+        && !cfg.security.access_token.is_empty()
+        && token.len() == cfg.security.access_token.len()
+        && token.bytes().zip(cfg.security.access_token.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Disabled, anonymized or reset-pending accounts can't start sessions
+fn login_blocked(user: &UserRecord) -> Option<HttpResponse> {
+    if user.disabled || user.anonymized_at.is_some() {
+        return Some(HttpResponse::Forbidden().json(json!({"error": "Account disabled", "code": "account_disabled"})));
+    }
+    if user.password_reset.is_some() {
+        return Some(HttpResponse::Forbidden().json(json!({"error": "Password reset required", "code": "password_reset_required"})));
+    }
+    None
+}
+
 #[allow(dead_code)]
 fn extract_bearer_or_query(req: &actix_web::HttpRequest) -> Option<String> {
     if let Some(h) = req.headers().get("authorization") {
@@ -96,7 +125,7 @@ fn extract_bearer_or_query(req: &actix_web::HttpRequest) -> Option<String> {
 
 
 #[allow(dead_code)]
-fn paseto_key(cfg: &AppConfig) -> Option<SymmetricKey<V4>> {
+pub(crate) fn paseto_key(cfg: &AppConfig) -> Option<SymmetricKey<V4>> {
     let hex = cfg.security.paseto_v4_local_key_hex.trim();
     if hex.len() < 64 { return None; }
     let bytes = hex::decode(hex).ok()?;
@@ -111,15 +140,13 @@ fn make_token_paseto(cfg: &AppConfig, claims: &Claims) -> Option<String> {
     pclaims.issuer(&cfg.security.token_iss).ok()?;
     pclaims.audience(&cfg.security.token_aud).ok()?;
     pclaims.subject(&claims.sub).ok()?;
-    let now = OffsetDateTime::now_utc();
-    let iat_str = now.format(&Rfc3339).ok()?;
-    pclaims.issued_at(&iat_str).ok()?;
-    let exp = now + TimeDuration::seconds(cfg.security.token_ttl_seconds as i64);
-    let exp_str = exp.format(&Rfc3339).ok()?;
-    pclaims.expiration(&exp_str).ok()?;
+    // Honour the caller's iat/exp so refresh and impersonation tokens keep their own lifetimes
+    pclaims.issued_at(&rfc3339_from_timestamp(claims.iat)?).ok()?;
+    pclaims.expiration(&rfc3339_from_timestamp(claims.exp)?).ok()?;
     // Additional claims
     pclaims.add_additional("email", serde_json::Value::String(claims.email.clone())).ok()?;
     pclaims.add_additional("roles", serde_json::to_value(&claims.roles).ok()?).ok()?;
+    add_session_claims(&mut pclaims, claims)?;
     local::encrypt(&key, &pclaims, None, None).ok()
 }
 
@@ -137,25 +164,152 @@ fn validate_token_paseto(cfg: &AppConfig, token: &str) -> Option<Claims> {
     // Additional
     let email = pc.get_claim("email").and_then(|v| v.as_str().map(|s| s.to_string())).unwrap_or_default();
     let roles = pc.get_claim("roles").and_then(|v| serde_json::from_value::<Vec<String>>(v.clone()).ok()).unwrap_or_default();
-    Some(Claims { sub, email, roles, iss, aud, iat: 0, exp: 0 })
+    let iat = timestamp_from_rfc3339(pc.get_claim("iat"));
+    let exp = timestamp_from_rfc3339(pc.get_claim("exp"));
+    let (iat_ms, org_id, org_roles, impersonator) = session_claims(pc);
+    Some(Claims { sub, email, roles, iss, aud, iat, iat_ms, exp, org_id, org_roles, impersonator })
+}
+
+/// Millisecond issue time, active organization and impersonation marker
+/// travel as additional claims in PASETO payloads
+fn add_session_claims(pclaims: &mut PasetoClaims, claims: &Claims) -> Option<()> {
+    if let Some(iat_ms) = claims.iat_ms {
+        pclaims.add_additional("iat_ms", iat_ms).ok()?;
+    }
+    if let Some(org_id) = &claims.org_id {
+        pclaims.add_additional("org_id", serde_json::Value::String(org_id.clone())).ok()?;
+        pclaims.add_additional("org_roles", serde_json::to_value(&claims.org_roles).ok()?).ok()?;
+    }
+    if let Some(impersonator) = &claims.impersonator {
+        pclaims.add_additional("impersonator", serde_json::Value::String(impersonator.clone())).ok()?;
+    }
+    Some(())
+}
+
+fn session_claims(pc: &PasetoClaims) -> (Option<i64>, Option<String>, Vec<String>, Option<String>) {
+    let iat_ms = pc.get_claim("iat_ms").and_then(|v| v.as_i64());
+    let org_id = pc.get_claim("org_id").and_then(|v| v.as_str().map(|s| s.to_string()));
+    let org_roles = pc.get_claim("org_roles").and_then(|v| serde_json::from_value::<Vec<String>>(v.clone()).ok()).unwrap_or_default();
+    let impersonator = pc.get_claim("impersonator").and_then(|v| v.as_str().map(|s| s.to_string()));
+    (iat_ms, org_id, org_roles, impersonator)
+}
+
+fn rfc3339_from_timestamp(ts: i64) -> Option<String> {
+    OffsetDateTime::from_unix_timestamp(ts).ok()?.format(&Rfc3339).ok()
+}
+
+fn timestamp_from_rfc3339(value: Option<&serde_json::Value>) -> i64 {
+    value
+        .and_then(|v| v.as_str())
+        .and_then(|s| OffsetDateTime::parse(s, &Rfc3339).ok())
+        .map(|t| t.unix_timestamp())
+        .unwrap_or(0)
+}
+
+/// Sign with the active key of the ring; the `kid` travels in the footer
+fn make_token_paseto_public(cfg: &AppConfig, claims: &Claims) -> Option<String> {
+    let key = cfg.security.keyring.active()?;
+    let secret = key.secret_key()?;
+    let mut pclaims = PasetoClaims::new().ok()?;
+    pclaims.issuer(&cfg.security.token_iss).ok()?;
+    pclaims.audience(&cfg.security.token_aud).ok()?;
+    pclaims.subject(&claims.sub).ok()?;
+    pclaims.issued_at(&rfc3339_from_timestamp(claims.iat)?).ok()?;
+    pclaims.expiration(&rfc3339_from_timestamp(claims.exp)?).ok()?;
+    pclaims.add_additional("email", serde_json::Value::String(claims.email.clone())).ok()?;
+    pclaims.add_additional("roles", serde_json::to_value(&claims.roles).ok()?).ok()?;
+    add_session_claims(&mut pclaims, claims)?;
+    // `kid` is reserved in pasetors' footer API (PASERK ids only), so build the footer JSON directly
+    let mut footer = Footer::new();
+    footer.parse_string(&json!({ "kid": key.kid }).to_string()).ok()?;
+    public::sign(&secret, &pclaims, Some(&footer), None).ok()
+}
+
+/// Verify against whichever non-retired key the footer `kid` names.
+/// With `check_expiry == false` only the signature is checked (used by reconfirm).
+fn verify_token_paseto_public(cfg: &AppConfig, token: &str, check_expiry: bool) -> Option<Claims> {
+    let utok = UntrustedToken::<Public, V4>::try_from(token).ok()?;
+    let footer: serde_json::Value = serde_json::from_slice(utok.untrusted_footer()).ok()?;
+    let kid = footer.get("kid")?.as_str()?;
+    let key = cfg.security.keyring.verifying_key(kid)?.public_key()?;
+    let mut rules = ClaimsValidationRules::new();
+    if !check_expiry {
+        rules.disable_valid_at();
+    }
+    let trusted = public::verify(&key, &utok, &rules, None, None).ok()?;
+    let pc = trusted.payload_claims()?;
+    let iss = pc.get_claim("iss").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let aud = pc.get_claim("aud").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    if iss != cfg.security.token_iss || aud != cfg.security.token_aud { return None; }
+    let sub = pc.get_claim("sub").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let email = pc.get_claim("email").and_then(|v| v.as_str().map(|s| s.to_string())).unwrap_or_default();
+    let roles = pc.get_claim("roles").and_then(|v| serde_json::from_value::<Vec<String>>(v.clone()).ok()).unwrap_or_default();
+    let iat = timestamp_from_rfc3339(pc.get_claim("iat"));
+    let exp = timestamp_from_rfc3339(pc.get_claim("exp"));
+    let (iat_ms, org_id, org_roles, impersonator) = session_claims(pc);
+    Some(Claims { sub, email, roles, iss, aud, iat, iat_ms, exp, org_id, org_roles, impersonator })
+}
+
+fn validate_token_paseto_public(cfg: &AppConfig, token: &str) -> Option<Claims> {
+    verify_token_paseto_public(cfg, token, true)
+}
+
+fn make_token_eddsa(cfg: &AppConfig, claims: &Claims) -> Option<String> {
+    let key = cfg.security.keyring.active()?;
+    let secret = ed25519_compact::SecretKey::from_slice(&hex::decode(key.secret_key_hex.trim()).ok()?).ok()?;
+    let header = json!({"alg":"EdDSA","typ":"JWT","kid":key.kid});
+    let header_b64 = base64url(&serde_json::to_vec(&header).ok()?);
+    let payload_b64 = base64url(&serde_json::to_vec(claims).ok()?);
+    let signing_input = format!("{}.{}", header_b64, payload_b64);
+    let sig = secret.sign(signing_input.as_bytes(), None);
+    Some(format!("{}.{}", signing_input, base64url(sig.as_ref())))
+}
+
+/// Verify an EdDSA JWT signature against the key named by the header `kid`
+fn verify_eddsa(cfg: &AppConfig, token: &str) -> Option<Claims> {
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 { return None; }
+    let (h, p, s) = (parts[0], parts[1], parts[2]);
+    let header: serde_json::Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(h).ok()?).ok()?;
+    if header.get("alg")?.as_str()? != "EdDSA" { return None; }
+    let kid = header.get("kid")?.as_str()?;
+    let key = cfg.security.keyring.verifying_key(kid)?;
+    let public = ed25519_compact::PublicKey::from_slice(&hex::decode(key.public_key_hex.trim()).ok()?).ok()?;
+    let sig = ed25519_compact::Signature::from_slice(&URL_SAFE_NO_PAD.decode(s).ok()?).ok()?;
+    public.verify(format!("{}.{}", h, p).as_bytes(), &sig).ok()?;
+    serde_json::from_slice(&URL_SAFE_NO_PAD.decode(p).ok()?).ok()
+}
+
+fn validate_token_eddsa(cfg: &AppConfig, token: &str) -> Option<Claims> {
+    let claims = verify_eddsa(cfg, token)?;
+    if claims.exp < Utc::now().timestamp() { return None; }
+    if claims.iss != cfg.security.token_iss || claims.aud != cfg.security.token_aud { return None; }
+    Some(claims)
 }
 
 pub fn make_token(cfg: &AppConfig, claims: &Claims) -> Option<String> {
     match cfg.security.token_mode {
         TokenMode::JwtHmac => Some(make_token_hmac(cfg, claims)),
         TokenMode::PasetoV4Local => make_token_paseto(cfg, claims),
+        TokenMode::PasetoV4Public => make_token_paseto_public(cfg, claims),
+        TokenMode::JwtEdDsa => make_token_eddsa(cfg, claims),
     }
 }
 
 pub fn validate_token(cfg: &AppConfig, token: &str) -> Option<Claims> {
+    if let Some(claims) = validate_static_access_token(cfg, token) {
+        return Some(claims);
+    }
     match cfg.security.token_mode {
         TokenMode::JwtHmac => validate_token_hmac(cfg, token),
         TokenMode::PasetoV4Local => validate_token_paseto(cfg, token),
+        TokenMode::PasetoV4Public => validate_token_paseto_public(cfg, token),
+        TokenMode::JwtEdDsa => validate_token_eddsa(cfg, token),
     }
 }
 
 #[post("/register")]
-pub async fn register(db: web::Data<Database>, cfg: web::Data<AppConfig>, body: web::Json<RegisterRequest>) -> Result<HttpResponse> {
+pub async fn register(db: web::Data<Database>, cfg: web::Data<AppConfig>, req: HttpRequest, body: web::Json<RegisterRequest>) -> Result<HttpResponse> {
     use crate::validation as v;
 
     let email = body.email.trim().to_lowercase();
@@ -171,15 +325,26 @@ pub async fn register(db: web::Data<Database>, cfg: web::Data<AppConfig>, body:
     }
 
     // Existing user?
-    let users: Vec<UserRecord> = db.list("users").unwrap_or_default();
-    if users.iter().any(|u| u.email == email) {
+    if db.find_unique::<UserRecord>("users", "email", &email).ok().flatten().is_some() {
         return Ok(HttpResponse::Conflict().json(json!({"error": "Email already registered"})));
     }
 
     let salt = SaltString::generate(&mut OsRng);
     let hash = Argon2::default().hash_password(body.password.as_bytes(), &salt).map_err(|_| actix_web::error::ErrorInternalServerError("hash error"))?.to_string();
-    let user = if users.is_empty() { UserRecord::new_admin(&email, hash) } else { UserRecord::new_user(&email, hash) };
-    db.insert("users", &user.id, &user).map_err(|_| actix_web::error::ErrorInternalServerError("db error"))?;
+    let first_user = db.is_empty("users").unwrap_or(false);
+    let user = if first_user { UserRecord::new_admin(&email, hash) } else { UserRecord::new_user(&email, hash) };
+    let record = user.clone();
+    if let Err(e) = db.for_request(&req).run(move |db| db.insert("users", &record.id, &record)).await {
+        // Lost a race with a concurrent registration for the same email
+        if e.downcast_ref::<UniqueViolation>().is_some() {
+            return Ok(HttpResponse::Conflict().json(json!({"error": "Email already registered"})));
+        }
+        return Err(actix_web::error::ErrorInternalServerError("db error"));
+    }
+    audit::record(&db, AuditEvent::from_request(&req, "auth.register", AuditOutcome::Success)
+        .actor(user.id.clone())
+        .target(user.id.clone())
+        .details(json!({"roles": user.roles}))).await;
 
     let now = Utc::now();
 
@@ -191,7 +356,11 @@ pub async fn register(db: web::Data<Database>, cfg: web::Data<AppConfig>, body:
         iss: cfg.security.token_iss.clone(),
         aud: cfg.security.token_aud.clone(),
         iat: now.timestamp(),
-        exp: (now + Duration::seconds(cfg.security.token_ttl_seconds as i64)).timestamp()
+        iat_ms: Some(now.timestamp_millis()),
+        exp: (now + Duration::seconds(cfg.security.token_ttl_seconds as i64)).timestamp(),
+        org_id: None,
+        org_roles: vec![],
+        impersonator: None,
     };
     let access_token = make_token(&cfg, &access_claims).ok_or_else(|| actix_web::error::ErrorInternalServerError("token error"))?;
 
@@ -203,15 +372,17 @@ pub async fn register(db: web::Data<Database>, cfg: web::Data<AppConfig>, body:
         iss: cfg.security.token_iss.clone(),
         aud: cfg.security.token_aud.clone(),
         iat: now.timestamp(),
+        iat_ms: Some(now.timestamp_millis()),
         exp: (now + Duration::days(7)).timestamp(),
+        org_id: None,
+        org_roles: vec![],
+        impersonator: None,
     };
     let refresh_token = make_token(&cfg, &refresh_claims).ok_or_else(|| actix_web::error::ErrorInternalServerError("token error"))?;
 
     // Set HttpOnly cookies
     let access_ttl = cfg.security.token_ttl_seconds as i64;
     let refresh_ttl = 7 * 24 * 60 * 60; // 7 days in seconds
-    let cookie_secure = std::env::var("COOKIE_SECURE").unwrap_or_else(|_| "true".to_string()) == "true";
-    let cookie_domain = std::env::var("COOKIE_DOMAIN").ok();
 
     let response = HttpResponse::Created().json(json!({
         "user": {
@@ -227,15 +398,14 @@ pub async fn register(db: web::Data<Database>, cfg: web::Data<AppConfig>, body:
         refresh_token,
         access_ttl,
         refresh_ttl,
-        cookie_secure,
-        cookie_domain.as_deref(),
+        &cfg.cookies,
     );
 
     Ok(response)
 }
 
 #[post("/login")]
-pub async fn login(db: web::Data<Database>, cfg: web::Data<AppConfig>, body: web::Json<LoginRequest>) -> Result<HttpResponse> {
+pub async fn login(db: web::Data<Database>, cfg: web::Data<AppConfig>, req: HttpRequest, body: web::Json<LoginRequest>) -> Result<HttpResponse> {
     use crate::validation as v;
 
     let email = body.email.trim().to_lowercase();
@@ -250,11 +420,18 @@ pub async fn login(db: web::Data<Database>, cfg: web::Data<AppConfig>, body: web
         return Ok(HttpResponse::BadRequest().json(json!({"error": "Invalid password"})));
     }
 
-    let users: Vec<UserRecord> = db.list("users").unwrap_or_default();
-    if let Some(u) = users.iter().find(|u| u.email == email) {
+    let user: Option<UserRecord> = db.find_unique("users", "email", &email).unwrap_or_default();
+    if let Some(u) = user.as_ref().filter(|u| u.anonymized_at.is_none()) {
         let parsed = PasswordHash::new(&u.password_hash).map_err(|_| actix_web::error::ErrorInternalServerError("hash read error"))?;
         if Argon2::default().verify_password(body.password.as_bytes(), &parsed).is_ok() {
+            if let Some(resp) = login_blocked(u) {
+                audit::record(&db, AuditEvent::from_request(&req, "auth.login", AuditOutcome::Denied)
+                    .actor(u.id.clone())
+                    .target(u.id.clone())).await;
+                return Ok(resp);
+            }
             let now = Utc::now();
+            let active_org = resolve_active_org(&db, u);
 
             // Create access token (short-lived)
             let access_claims = Claims {
@@ -264,7 +441,11 @@ pub async fn login(db: web::Data<Database>, cfg: web::Data<AppConfig>, body: web
                 iss: cfg.security.token_iss.clone(),
                 aud: cfg.security.token_aud.clone(),
                 iat: now.timestamp(),
-                exp: (now + Duration::seconds(cfg.security.token_ttl_seconds as i64)).timestamp()
+                iat_ms: Some(now.timestamp_millis()),
+                exp: (now + Duration::seconds(cfg.security.token_ttl_seconds as i64)).timestamp(),
+                org_id: active_org.as_ref().map(|m| m.org_id.clone()),
+                org_roles: active_org.as_ref().map(|m| m.roles.clone()).unwrap_or_default(),
+                impersonator: None,
             };
             let access_token = make_token(&cfg, &access_claims).ok_or_else(|| actix_web::error::ErrorInternalServerError("token error"))?;
 
@@ -276,21 +457,24 @@ pub async fn login(db: web::Data<Database>, cfg: web::Data<AppConfig>, body: web
                 iss: cfg.security.token_iss.clone(),
                 aud: cfg.security.token_aud.clone(),
                 iat: now.timestamp(),
+                iat_ms: Some(now.timestamp_millis()),
                 exp: (now + Duration::days(7)).timestamp(),
+                org_id: active_org.as_ref().map(|m| m.org_id.clone()),
+                org_roles: active_org.as_ref().map(|m| m.roles.clone()).unwrap_or_default(),
+                impersonator: None,
             };
             let refresh_token = make_token(&cfg, &refresh_claims).ok_or_else(|| actix_web::error::ErrorInternalServerError("token error"))?;
 
             // Set HttpOnly cookies
             let access_ttl = cfg.security.token_ttl_seconds as i64;
             let refresh_ttl = 7 * 24 * 60 * 60; // 7 days in seconds
-            let cookie_secure = std::env::var("COOKIE_SECURE").unwrap_or_else(|_| "true".to_string()) == "true";
-            let cookie_domain = std::env::var("COOKIE_DOMAIN").ok();
 
             // Return user data as JSON
             let response = HttpResponse::Ok().json(json!({
                 "id": u.id,
                 "email": u.email,
-                "roles": u.roles
+                "roles": u.roles,
+                "org_id": active_org.as_ref().map(|m| m.org_id.clone()),
             }));
 
             let response = set_auth_cookies(
@@ -299,24 +483,67 @@ pub async fn login(db: web::Data<Database>, cfg: web::Data<AppConfig>, body: web
                 refresh_token,
                 access_ttl,
                 refresh_ttl,
-                cookie_secure,
-                cookie_domain.as_deref(),
+                &cfg.cookies,
             );
 
+            audit::record(&db, AuditEvent::from_request(&req, "auth.login", AuditOutcome::Success)
+                .actor(u.id.clone())
+                .target(u.id.clone())).await;
             return Ok(response);
         }
     }
+    // Only ids go into the (immutable) audit log, never the typed email
+    let mut event = AuditEvent::from_request(&req, "auth.login", AuditOutcome::Failure);
+    event.target = user.map(|u| u.id);
+    audit::record(&db, event).await;
     // Return generic error to prevent user enumeration
     Ok(HttpResponse::Unauthorized().json(json!({"error": "Invalid email or password"})))
 }
 
 #[post("/logout")]
-pub async fn logout(_db: web::Data<Database>) -> Result<HttpResponse> {
+pub async fn logout(db: web::Data<Database>, cfg: web::Data<AppConfig>, req: HttpRequest) -> Result<HttpResponse> {
+    let actor = extract_token(&req, ACCESS_COOKIE_NAME).and_then(|t| validate_token(&cfg, &t)).map(|c| c.sub);
+    let mut event = AuditEvent::from_request(&req, "auth.logout", AuditOutcome::Success);
+    event.actor = actor;
+    audit::record(&db, event).await;
     let response = HttpResponse::NoContent().finish();
-    let response = clear_auth_cookies(response);
+    let response = clear_auth_cookies(response, &cfg.cookies);
     Ok(response)
 }
 
+#[derive(serde::Deserialize)]
+pub struct PasswordResetRequest {
+    token: String,
+    new_password: String,
+}
+
+/// Redeem an admin-issued reset token and set a new password
+#[post("/password-reset")]
+pub async fn reset_password(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    body: web::Json<PasswordResetRequest>,
+) -> Result<HttpResponse> {
+    let body = body.into_inner();
+    let outcome = db
+        .run(move |db| Ok(complete_password_reset(&db, &body.token, &body.new_password)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    match outcome {
+        Ok(user) => {
+            audit::record(&db, AuditEvent::from_request(&req, "user.password_reset_completed", AuditOutcome::Success)
+                .actor(user.id.clone())
+                .target(user.id)).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(LifecycleError::Invalid(msg)) => {
+            audit::record(&db, AuditEvent::from_request(&req, "user.password_reset_completed", AuditOutcome::Failure)).await;
+            Ok(HttpResponse::BadRequest().json(json!({"error": msg})))
+        }
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e.to_string())),
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct ReconfirmRequest {
     password: String,
@@ -367,28 +594,50 @@ pub async fn reconfirm(
                 .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing user ID in token"))?
                 .to_string()
         }
+        TokenMode::PasetoV4Public => match verify_token_paseto_public(&cfg, &token, false) {
+            Some(claims) => claims.sub,
+            None => return Ok(HttpResponse::Unauthorized().json(json!({"error": "Invalid token"}))),
+        },
+        TokenMode::JwtEdDsa => match verify_eddsa(&cfg, &token) {
+            // Don't check expiration - we want to allow expired tokens
+            Some(claims) => claims.sub,
+            None => return Ok(HttpResponse::Unauthorized().json(json!({"error": "Invalid token"}))),
+        },
     };
 
     // Find user in database
-    let users: Vec<UserRecord> = db.list("users").unwrap_or_default();
-    let user = users.iter().find(|u| u.id == user_id).ok_or_else(|| {
+    let user: UserRecord = db.get("users", &user_id).ok().flatten().ok_or_else(|| {
         actix_web::error::ErrorUnauthorized("User not found")
     })?;
-
-    // Verify password
-    let parsed = PasswordHash::new(&user.password_hash).map_err(|_| {
-        actix_web::error::ErrorInternalServerError("Password hash read error")
-    })?;
-
-    if Argon2::default()
-        .verify_password(body.password.as_bytes(), &parsed)
-        .is_err()
-    {
+    let user = &user;
+
+    // Verify password first, like login: the account state is only revealed
+    // to someone who knows the password. Anonymized accounts have no hash.
+    let verified = user.anonymized_at.is_none() && {
+        let parsed = PasswordHash::new(&user.password_hash).map_err(|_| {
+            actix_web::error::ErrorInternalServerError("Password hash read error")
+        })?;
+        Argon2::default().verify_password(body.password.as_bytes(), &parsed).is_ok()
+    };
+    if !verified {
+        audit::record(&db, AuditEvent::from_request(&req, "auth.reconfirm", AuditOutcome::Failure)
+            .actor(user.id.clone())
+            .target(user.id.clone())).await;
         return Ok(HttpResponse::Unauthorized().json(json!({"error": "Invalid password"})));
     }
+    if let Some(resp) = login_blocked(user) {
+        audit::record(&db, AuditEvent::from_request(&req, "auth.reconfirm", AuditOutcome::Denied)
+            .actor(user.id.clone())
+            .target(user.id.clone())).await;
+        return Ok(resp);
+    }
+    audit::record(&db, AuditEvent::from_request(&req, "auth.reconfirm", AuditOutcome::Success)
+        .actor(user.id.clone())
+        .target(user.id.clone())).await;
 
     // Password is correct - generate new token
     let now = Utc::now();
+    let active_org = resolve_active_org(&db, user);
     let claims = Claims {
         sub: user.id.clone(),
         email: user.email.clone(),
@@ -396,7 +645,11 @@ pub async fn reconfirm(
         iss: cfg.security.token_iss.clone(),
         aud: cfg.security.token_aud.clone(),
         iat: now.timestamp(),
+        iat_ms: Some(now.timestamp_millis()),
         exp: (now + Duration::seconds(cfg.security.token_ttl_seconds as i64)).timestamp(),
+        org_id: active_org.as_ref().map(|m| m.org_id.clone()),
+        org_roles: active_org.map(|m| m.roles).unwrap_or_default(),
+        impersonator: None,
     };
 
     let new_token = make_token(&cfg, &claims).ok_or_else(|| {
@@ -414,36 +667,73 @@ pub async fn reconfirm(
 }
 
 #[get("/me")]
-pub async fn me(_db: web::Data<Database>, cfg: web::Data<AppConfig>, req: HttpRequest) -> Result<HttpResponse> {
+pub async fn me(db: web::Data<Database>, cfg: web::Data<AppConfig>, req: HttpRequest) -> Result<HttpResponse> {
     // Try to extract token from cookie or Authorization header
     if let Some(tok) = extract_token(&req, ACCESS_COOKIE_NAME) {
         if let Some(claims) = validate_token(&cfg, &tok) {
-            return Ok(HttpResponse::Ok().json(json!({
-                "id": claims.sub,
-                "email": claims.email,
-                "roles": claims.roles
-            })));
+            if is_static_access_token(&cfg, &tok) || session_is_current(&db, &claims) {
+                return Ok(HttpResponse::Ok().json(json!({
+                    "id": claims.sub,
+                    "email": claims.email,
+                    "roles": claims.roles,
+                    "org_id": claims.org_id,
+                    "org_roles": claims.org_roles,
+                    "impersonator": claims.impersonator
+                })));
+            }
         }
     }
     Ok(HttpResponse::Unauthorized().json(json!({"error": "unauthorized"})))
 }
 
 #[post("/refresh")]
-pub async fn refresh(cfg: web::Data<AppConfig>, req: HttpRequest) -> Result<HttpResponse> {
+pub async fn refresh(db: web::Data<Database>, cfg: web::Data<AppConfig>, req: HttpRequest) -> Result<HttpResponse> {
     // Extract refresh token from cookie
     let refresh_token = extract_token(&req, REFRESH_COOKIE_NAME)
         .ok_or_else(|| actix_web::error::ErrorUnauthorized("No refresh token"))?;
 
     // Validate refresh token
-    let claims = validate_token(&cfg, &refresh_token)
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid or expired refresh token"))?;
+    let claims = match validate_token(&cfg, &refresh_token) {
+        Some(c) => c,
+        None => {
+            audit::record(&db, AuditEvent::from_request(&req, "auth.refresh", AuditOutcome::Failure)
+                .details(json!({"reason": "invalid_token"}))).await;
+            return Err(actix_web::error::ErrorUnauthorized("Invalid or expired refresh token"));
+        }
+    };
 
     // Check if token is expired
-    let now = Utc::now().timestamp();
+    let issued = Utc::now();
+    let now = issued.timestamp();
     if claims.exp < now {
+        audit::record(&db, AuditEvent::from_request(&req, "auth.refresh", AuditOutcome::Failure)
+            .actor(claims.sub.clone())
+            .details(json!({"reason": "expired"}))).await;
         return Ok(HttpResponse::Unauthorized().json(json!({"error": "Refresh token expired"})));
     }
 
+    // Disabled users, invalidated sessions and impersonation tokens can't refresh
+    if claims.impersonator.is_some() || !session_is_current(&db, &claims) {
+        audit::record(&db, AuditEvent::from_request(&req, "auth.refresh", AuditOutcome::Denied)
+            .actor(claims.sub.clone())
+            .details(json!({"reason": "session_revoked"}))).await;
+        return Ok(clear_auth_cookies(
+            HttpResponse::Unauthorized().json(json!({"error": "Session revoked"})),
+            &cfg.cookies,
+        ));
+    }
+    audit::record(&db, AuditEvent::from_request(&req, "auth.refresh", AuditOutcome::Success)
+        .actor(claims.sub.clone())).await;
+
+    // Carry the active org over, but re-read the membership so removed
+    // members lose access and role changes take effect on refresh
+    let membership = claims
+        .org_id
+        .as_deref()
+        .and_then(|org_id| get_membership(&db, org_id, &claims.sub));
+    let org_id = membership.as_ref().map(|m| m.org_id.clone());
+    let org_roles = membership.map(|m| m.roles).unwrap_or_default();
+
     // Issue new access token (short-lived)
     let access_claims = Claims {
         sub: claims.sub.clone(),
@@ -452,7 +742,11 @@ pub async fn refresh(cfg: web::Data<AppConfig>, req: HttpRequest) -> Result<Http
         iss: cfg.security.token_iss.clone(),
         aud: cfg.security.token_aud.clone(),
         iat: now,
-        exp: (Utc::now() + Duration::seconds(cfg.security.token_ttl_seconds as i64)).timestamp()
+        iat_ms: Some(issued.timestamp_millis()),
+        exp: (Utc::now() + Duration::seconds(cfg.security.token_ttl_seconds as i64)).timestamp(),
+        org_id: org_id.clone(),
+        org_roles: org_roles.clone(),
+        impersonator: None,
     };
     let new_access_token = make_token(&cfg, &access_claims)
         .ok_or_else(|| actix_web::error::ErrorInternalServerError("Token generation error"))?;
@@ -465,7 +759,11 @@ pub async fn refresh(cfg: web::Data<AppConfig>, req: HttpRequest) -> Result<Http
         iss: cfg.security.token_iss.clone(),
         aud: cfg.security.token_aud.clone(),
         iat: now,
+        iat_ms: Some(issued.timestamp_millis()),
         exp: (Utc::now() + Duration::days(7)).timestamp(),
+        org_id,
+        org_roles,
+        impersonator: None,
     };
     let new_refresh_token = make_token(&cfg, &new_refresh_claims)
         .ok_or_else(|| actix_web::error::ErrorInternalServerError("Token generation error"))?;
@@ -473,8 +771,6 @@ pub async fn refresh(cfg: web::Data<AppConfig>, req: HttpRequest) -> Result<Http
     // Set new cookies
     let access_ttl = cfg.security.token_ttl_seconds as i64;
     let refresh_ttl = 7 * 24 * 60 * 60; // 7 days in seconds
-    let cookie_secure = std::env::var("COOKIE_SECURE").unwrap_or_else(|_| "true".to_string()) == "true";
-    let cookie_domain = std::env::var("COOKIE_DOMAIN").ok();
 
     let response = HttpResponse::NoContent().finish();
     let response = set_auth_cookies(
@@ -483,8 +779,7 @@ pub async fn refresh(cfg: web::Data<AppConfig>, req: HttpRequest) -> Result<Http
         new_refresh_token,
         access_ttl,
         refresh_ttl,
-        cookie_secure,
-        cookie_domain.as_deref(),
+        &cfg.cookies,
     );
 
     Ok(response)
@@ -493,6 +788,7 @@ pub async fn refresh(cfg: web::Data<AppConfig>, req: HttpRequest) -> Result<Http
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::body::BoxBody;
 use actix_web::HttpMessage;
+use crate::metrics;
 
 pub async fn guard_api(
     req: ServiceRequest,
@@ -502,12 +798,49 @@ pub async fn guard_api(
     let cfg = req.app_data::<web::Data<AppConfig>>().cloned();
     if let Some(cfg) = cfg {
         if let Some(tok) = extract_token(req.request(), ACCESS_COOKIE_NAME) {
+            // Personal API keys (`Authorization: Bearer qfk_...`)
+            if is_api_key(&tok) {
+                // The lookup may rewrite `last_used_at`, so it runs off the async workers
+                let found = match req.app_data::<web::Data<Database>>() {
+                    Some(db) => {
+                        let (cfg, tok) = (cfg.clone(), tok.clone());
+                        db.run(move |db| Ok(authenticate_api_key(&db, &cfg, &tok))).await.ok().flatten()
+                    }
+                    None => None,
+                };
+                if let Some((claims, key)) = found {
+                    if !scope_allows_method(&key, req.method()) {
+                        let (req, _pl) = req.into_parts();
+                        let resp = HttpResponse::Forbidden().json(json!({"error": "API key scope does not allow this method"}));
+                        return Ok(ServiceResponse::new(req, resp.map_into_boxed_body()));
+                    }
+                    metrics::AUTH_CHECKS.inc(&["api_key", "success"]);
+                    req.extensions_mut().insert(claims);
+                    req.extensions_mut().insert(key);
+                    return next.call(req).await;
+                }
+                metrics::AUTH_CHECKS.inc(&["api_key", "failure"]);
+                let (req, _pl) = req.into_parts();
+                let resp = HttpResponse::Unauthorized().json("unauthorized");
+                return Ok(ServiceResponse::new(req, resp.map_into_boxed_body()));
+            }
             if let Some(claims) = validate_token(&cfg, &tok) {
-                req.extensions_mut().insert(claims);
-                return next.call(req).await;
+                // Session tokens must still match the user record (disabled
+                // users and invalidated sessions are rejected). Apps without
+                // a registered Database have no user store to check against.
+                let revoked = !is_static_access_token(&cfg, &tok)
+                    && req
+                        .app_data::<web::Data<Database>>()
+                        .is_some_and(|db| !session_is_current(db, &claims));
+                if !revoked {
+                    metrics::AUTH_CHECKS.inc(&["token", "success"]);
+                    req.extensions_mut().insert(claims);
+                    return next.call(req).await;
+                }
             }
         }
     }
+    metrics::AUTH_CHECKS.inc(&["token", "failure"]);
     let (req, _pl) = req.into_parts();
     let resp = HttpResponse::Unauthorized().json("unauthorized");
     Ok(ServiceResponse::new(req, resp.map_into_boxed_body()))
@@ -525,6 +858,8 @@ mod tests {
         AppConfig {
             security: SecurityConfig {
                 access_token: "test_access".into(),
+                access_token_admin_bypass: true,
+                trusted_proxies: vec![],
                 rate_limit_enabled: false,
                 rate_limit_rpm: 100,
                 auth_token_expiry_hours: 24,
@@ -532,10 +867,19 @@ mod tests {
                 token_aud: "test_aud".into(),
                 token_ttl_seconds: 3600,
                 paseto_v4_local_key_hex: "142f46b1b4acb0946e0d9413f29b331db345cf664b9307165eab7531fa32d8bd".into(),
+                token_keyring_path: "keys/test_keyring.json".into(),
+                keyring: if mode.is_asymmetric() {
+                    let mut ring = crate::keyring::KeyRing::default();
+                    ring.rotate(std::time::Duration::from_secs(3600)).unwrap();
+                    ring
+                } else {
+                    crate::keyring::KeyRing::default()
+                },
                 token_mode: mode,
                 debug_mode: false,
                 health_check_enabled: true,
                 metrics_enabled: false,
+                metrics_token: String::new(),
             },
             server: ServerConfig {
                 host: "localhost".into(),
@@ -554,20 +898,57 @@ mod tests {
                 idle_timeout: 600,
                 max_lifetime: 3600,
                 ssl_mode: "prefer".into(),
+                url: None,
             },
             sled_path: "test.db".into(),
             backup_dir: "backups".into(),
             backup_name_template: "backup_{{timestamp}}".into(),
             backup_interval: None,
             backup_retention: 10,
+            backup_max_age: None,
+            health_min_free_disk_mb: 512,
             pg_conns: vec![],
             cors_rules: vec![],
             logging: LoggingConfig {
                 level: "info".into(),
+                format: crate::config::LogFormat::Text,
                 file_enabled: false,
                 file_path: None,
+                file_max_size: None,
+                file_rotation: crate::config::LogRotation::Never,
+                file_retention: 7,
+                otlp_endpoint: None,
             },
             database_sync_on: false,
+            durability: Default::default(),
+            event_log_max_events: 1000,
+            history_max_revisions: 100,
+            entities_dir: "entities".into(),
+            encryption: crate::config::EncryptionConfig {
+                collections: vec![],
+                key_hex: String::new(),
+                keyring_path: String::new(),
+                keyring: Default::default(),
+            },
+            soft_delete_collections: vec![],
+            trash_retention: std::time::Duration::from_secs(3600),
+            trash_purge_interval: std::time::Duration::from_secs(3600),
+            config_watch: false,
+            headers: crate::config::HeadersConfig {
+                csp: crate::config::DEFAULT_CSP.into(),
+                csp_report_only: false,
+                csp_report_uri: String::new(),
+                hsts_max_age: None,
+                hsts_include_subdomains: false,
+                hsts_preload: false,
+                permissions_policy: String::new(),
+                frame_options: Some("DENY".into()),
+            },
+            cookies: crate::config::CookieConfig {
+                secure: true,
+                domain: None,
+                same_site: actix_web::cookie::SameSite::Strict,
+            },
         }
     }
 
@@ -579,7 +960,11 @@ mod tests {
             iss: "test_iss".into(),
             aud: "test_aud".into(),
             iat: chrono::Utc::now().timestamp(),
+            iat_ms: Some(chrono::Utc::now().timestamp_millis()),
             exp: (chrono::Utc::now() + chrono::Duration::hours(1)).timestamp(),
+            org_id: Some("org_1".into()),
+            org_roles: vec!["member".into()],
+            impersonator: None,
         }
     }
 
@@ -592,6 +977,7 @@ mod tests {
         assert_eq!(decoded.sub, claims.sub);
         assert_eq!(decoded.email, claims.email);
         assert_eq!(decoded.roles, claims.roles);
+        assert_eq!(decoded.org_id, claims.org_id);
     }
 
     #[test]
@@ -603,6 +989,47 @@ mod tests {
         assert_eq!(decoded.sub, claims.sub);
         assert_eq!(decoded.email, claims.email);
         assert_eq!(decoded.roles, claims.roles);
+        assert_eq!(decoded.org_id, claims.org_id);
+        assert_eq!(decoded.iat_ms, claims.iat_ms);
+    }
+
+    #[test]
+    async fn test_token_roundtrip_asymmetric() {
+        for mode in [TokenMode::PasetoV4Public, TokenMode::JwtEdDsa] {
+            let cfg = make_test_config(mode);
+            let claims = make_test_claims();
+            let token = make_token(&cfg, &claims).expect("make token");
+            let decoded = validate_token(&cfg, &token).expect("validate token");
+            assert_eq!(decoded.sub, claims.sub);
+            assert_eq!(decoded.email, claims.email);
+            assert_eq!(decoded.roles, claims.roles);
+            assert_eq!(decoded.exp, claims.exp);
+            assert_eq!(decoded.org_roles, claims.org_roles);
+            assert_eq!(decoded.iat_ms, claims.iat_ms);
+        }
+    }
+
+    #[test]
+    async fn test_asymmetric_token_survives_rotation_until_retired() {
+        for mode in [TokenMode::PasetoV4Public, TokenMode::JwtEdDsa] {
+            let mut cfg = make_test_config(mode);
+            let token = make_token(&cfg, &make_test_claims()).expect("make token");
+            let old_kid = cfg.security.keyring.active().unwrap().kid.clone();
+
+            cfg.security.keyring.rotate(std::time::Duration::from_secs(600)).unwrap();
+            assert!(validate_token(&cfg, &token).is_some(), "overlapping key must still verify");
+
+            cfg.security.keyring.retire(&old_kid).unwrap();
+            assert!(validate_token(&cfg, &token).is_none(), "retired key must be rejected");
+        }
+    }
+
+    #[test]
+    async fn test_asymmetric_token_rejects_foreign_key() {
+        let cfg = make_test_config(TokenMode::JwtEdDsa);
+        let other = make_test_config(TokenMode::JwtEdDsa);
+        let token = make_token(&other, &make_test_claims()).expect("make token");
+        assert!(validate_token(&cfg, &token).is_none());
     }
 
     #[test]
@@ -613,6 +1040,13 @@ mod tests {
         assert_eq!(decoded.roles, vec!["admin"]);
     }
 
+    #[test]
+    async fn test_static_access_token_requires_opt_in() {
+        let mut cfg = make_test_config(TokenMode::JwtHmac);
+        cfg.security.access_token_admin_bypass = false;
+        assert!(validate_token(&cfg, &cfg.security.access_token).is_none());
+    }
+
     #[test]
     async fn test_hmac_token_rejects_tampered() {
         let cfg = make_test_config(TokenMode::JwtHmac);
@@ -696,4 +1130,112 @@ mod tests {
         assert_eq!(resp["sub"], claims.sub);
     }
 
+    #[actix_web::test]
+    async fn reconfirm_checks_the_password_before_the_account_state() {
+        let dir = tempdir().unwrap();
+        let cfg = make_test_config(TokenMode::JwtHmac);
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default().hash_password(b"secret123", &salt).unwrap().to_string();
+        let mut user = UserRecord::new_user("off@test.dev", hash);
+        user.disabled = true;
+        db.insert("users", &user.id, &user).unwrap();
+        let token = make_token(&cfg, &Claims { sub: user.id.clone(), ..make_test_claims() }).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(cfg.clone()))
+                .service(reconfirm)
+        ).await;
+        let attempt = |password: &str| {
+            test::TestRequest::post()
+                .uri("/reconfirm")
+                .insert_header(("authorization", format!("Bearer {}", token)))
+                .set_json(json!({"password": password}))
+                .to_request()
+        };
+
+        // A wrong password learns nothing about the account
+        let resp = test::call_service(&app, attempt("guess")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        let resp = test::call_service(&app, attempt("secret123")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+    }
+
+    /// Login must not get slower as the user table grows. Run with
+    /// `cargo test --release -- --ignored --nocapture bench_login`
+    #[actix_web::test]
+    #[ignore]
+    async fn bench_login_with_100k_users() {
+        use std::time::{Duration as StdDuration, Instant};
+
+        const PASSWORD: &str = "Bench-Pass-2024!";
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default().hash_password(PASSWORD.as_bytes(), &salt).unwrap().to_string();
+        let cfg = make_test_config(TokenMode::JwtHmac);
+
+        let mut lookup_means = vec![];
+        let mut login_means = vec![];
+        for users in [1_000usize, 100_000] {
+            let dir = tempdir().unwrap();
+            let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+
+            // Bulk-load behind the index, then backfill it the way startup does
+            let tree = db.db.open_tree("users").unwrap();
+            for i in 0..users {
+                let user = UserRecord::new_user(&format!("user{}@bench.dev", i), hash.clone());
+                tree.insert(user.id.as_bytes(), serde_json::to_vec(&user).unwrap()).unwrap();
+            }
+            for def in crate::db::INDEXES.iter().filter(|d| d.collection == "users") {
+                db.rebuild_index(def).unwrap();
+            }
+
+            let lookups = 10_000;
+            let start = Instant::now();
+            for i in 0..lookups {
+                let email = format!("user{}@bench.dev", (i * 7919) % users);
+                assert!(db.find_unique::<UserRecord>("users", "email", &email).unwrap().is_some());
+            }
+            let lookup = start.elapsed() / lookups as u32;
+
+            let start = Instant::now();
+            let scans: u32 = 3;
+            for _ in 0..scans {
+                let all: Vec<UserRecord> = db.list("users").unwrap();
+                assert!(all.iter().any(|u| u.email == "user1@bench.dev"));
+            }
+            let scan = start.elapsed() / scans;
+
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(db.clone()))
+                    .app_data(web::Data::new(cfg.clone()))
+                    .service(login)
+            ).await;
+            let logins: u32 = 5;
+            let mut login_total = StdDuration::ZERO;
+            for i in 0..logins {
+                let body = LoginRequest { email: format!("user{}@bench.dev", (i as usize * 131) % users), password: PASSWORD.into() };
+                let req = test::TestRequest::post().uri("/login").set_json(&body).to_request();
+                let start = Instant::now();
+                let resp = test::call_service(&app, req).await;
+                login_total += start.elapsed();
+                assert!(resp.status().is_success());
+            }
+            let login_mean = login_total / logins;
+
+            println!(
+                "{:>7} users: index lookup {:>9.2?}  full scan {:>10.2?}  login {:>9.2?}",
+                users, lookup, scan, login_mean
+            );
+            lookup_means.push(lookup);
+            login_means.push(login_mean);
+        }
+
+        // A B-tree lookup grows logarithmically; 100x the users must stay well under 10x the cost
+        assert!(lookup_means[1] < lookup_means[0] * 10, "index lookup scales with user count");
+        // Login is dominated by Argon2, so it should stay flat
+        assert!(login_means[1] < login_means[0] * 2, "login time scales with user count");
+    }
 }
@@ -1,47 +1,39 @@
 // Cookie management helpers
-use actix_web::cookie::{Cookie, SameSite};
+use actix_web::cookie::Cookie;
 use actix_web::HttpResponse;
 use time::Duration;
 
+use crate::config::CookieConfig;
+
 pub const ACCESS_COOKIE_NAME: &str = "access_token";
 pub const REFRESH_COOKIE_NAME: &str = "refresh_token";
 
-/// Create an HttpOnly, Secure, SameSite=Strict cookie
+/// Create an HttpOnly cookie with the configured Secure, Domain and SameSite
 pub fn create_auth_cookie<'a>(
     name: &'a str,
     value: String,
     max_age_seconds: i64,
-    secure: bool,
-    domain: Option<&'a str>,
+    cookies: &CookieConfig,
 ) -> Cookie<'a> {
     let mut cookie = Cookie::build(name, value)
         .path("/")
         .max_age(Duration::seconds(max_age_seconds))
         .http_only(true)
-        .same_site(SameSite::Strict)
+        .same_site(cookies.same_site)
+        .secure(cookies.secure)
         .finish();
 
-    if secure {
-        cookie.set_secure(true);
-    }
-
-    if let Some(d) = domain {
-        if !d.is_empty() {
-            cookie.set_domain(d.to_string());
-        }
+    if let Some(d) = &cookies.domain {
+        cookie.set_domain(d.clone());
     }
 
     cookie
 }
 
-/// Clear an auth cookie (set to expired)
-pub fn clear_auth_cookie(name: &'static str) -> Cookie<'static> {
-    Cookie::build(name, "")
-        .path("/")
-        .max_age(Duration::seconds(0))
-        .http_only(true)
-        .same_site(SameSite::Strict)
-        .finish()
+/// Clear an auth cookie (set to expired). Browsers only replace a cookie
+/// with matching attributes, so these follow the config too.
+pub fn clear_auth_cookie(name: &'static str, cookies: &CookieConfig) -> Cookie<'static> {
+    create_auth_cookie(name, String::new(), 0, cookies)
 }
 
 /// Set access and refresh cookies on a response
@@ -51,23 +43,10 @@ pub fn set_auth_cookies(
     refresh_token: String,
     access_ttl: i64,
     refresh_ttl: i64,
-    secure: bool,
-    domain: Option<&str>,
+    cookies: &CookieConfig,
 ) -> HttpResponse {
-    let access_cookie = create_auth_cookie(
-        ACCESS_COOKIE_NAME,
-        access_token,
-        access_ttl,
-        secure,
-        domain,
-    );
-    let refresh_cookie = create_auth_cookie(
-        REFRESH_COOKIE_NAME,
-        refresh_token,
-        refresh_ttl,
-        secure,
-        domain,
-    );
+    let access_cookie = create_auth_cookie(ACCESS_COOKIE_NAME, access_token, access_ttl, cookies);
+    let refresh_cookie = create_auth_cookie(REFRESH_COOKIE_NAME, refresh_token, refresh_ttl, cookies);
 
     response.add_cookie(&access_cookie).ok();
     response.add_cookie(&refresh_cookie).ok();
@@ -75,9 +54,9 @@ pub fn set_auth_cookies(
 }
 
 /// Clear both access and refresh cookies
-pub fn clear_auth_cookies(mut response: HttpResponse) -> HttpResponse {
-    response.add_cookie(&clear_auth_cookie(ACCESS_COOKIE_NAME)).ok();
-    response.add_cookie(&clear_auth_cookie(REFRESH_COOKIE_NAME)).ok();
+pub fn clear_auth_cookies(mut response: HttpResponse, cookies: &CookieConfig) -> HttpResponse {
+    response.add_cookie(&clear_auth_cookie(ACCESS_COOKIE_NAME, cookies)).ok();
+    response.add_cookie(&clear_auth_cookie(REFRESH_COOKIE_NAME, cookies)).ok();
     response
 }
 
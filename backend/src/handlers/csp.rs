@@ -0,0 +1,260 @@
+// Content-Security-Policy violation reports
+//
+// Browsers POST reports to `CSP_REPORT_URI` (default `/csp-report`), either
+// as a legacy `application/csp-report` document or as a Reporting API batch
+// (`application/reports+json`). Both are normalised, logged, counted and
+// kept in a capped sled tree so admins can see what a policy breaks.
+//
+// The endpoint is public, so each client address gets a small per-minute
+// budget (429 beyond it), and once a minute has seen a burst of violations
+// the rest are only logged at debug level.
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::audit;
+use crate::db::Database;
+use crate::handlers::users::require_admin;
+use crate::metrics;
+use crate::types::ErrorResponse;
+
+pub const REPORTS_TREE: &str = "csp_reports";
+/// Older reports are dropped beyond this many
+const MAX_STORED: usize = 1000;
+const MAX_BODY: usize = 64 * 1024;
+const DEFAULT_LIMIT: usize = 100;
+/// Reports accepted per client address per window
+const REPORTS_PER_CLIENT: u32 = 30;
+/// Violations per window logged at warn level; the rest go to debug
+const WARNINGS_PER_WINDOW: u32 = 20;
+/// Addresses tracked per window; reports from further ones are refused
+const MAX_CLIENTS: usize = 10_000;
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Report counts for the current fixed window
+struct Window {
+    started: Instant,
+    per_client: HashMap<String, u32>,
+    warned: u32,
+}
+
+static BUDGET: Mutex<Option<Window>> = Mutex::new(None);
+
+/// Admit a report with `violations` entries from `client`. `None` when the
+/// client is over its budget, otherwise whether to log the violations at warn.
+fn admit(client: &str, violations: u32) -> Option<bool> {
+    let mut budget = BUDGET.lock().unwrap_or_else(|e| e.into_inner());
+    let window = match budget.as_mut() {
+        Some(w) if w.started.elapsed() < WINDOW => w,
+        _ => budget.insert(Window { started: Instant::now(), per_client: HashMap::new(), warned: 0 }),
+    };
+    if !window.per_client.contains_key(client) && window.per_client.len() >= MAX_CLIENTS {
+        return None;
+    }
+    let count = window.per_client.entry(client.to_string()).or_default();
+    if *count >= REPORTS_PER_CLIENT {
+        return None;
+    }
+    *count += 1;
+    let warn = window.warned < WARNINGS_PER_WINDOW;
+    window.warned = window.warned.saturating_add(violations);
+    Some(warn)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CspViolation {
+    pub received_at: String,
+    pub document_uri: String,
+    pub directive: String,
+    pub blocked_uri: String,
+    pub source_file: Option<String>,
+    pub line_number: Option<u64>,
+    /// `enforce` or `report`
+    pub disposition: String,
+    pub user_agent: Option<String>,
+}
+
+fn text(body: &Value, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|k| body.get(*k).and_then(Value::as_str)).map(|s| s.chars().take(512).collect())
+}
+
+fn violation(body: &Value, user_agent: Option<String>) -> CspViolation {
+    CspViolation {
+        received_at: crate::time::now(),
+        document_uri: text(body, &["document-uri", "documentURL"]).unwrap_or_default(),
+        directive: text(body, &["effective-directive", "effectiveDirective", "violated-directive"])
+            .and_then(|d| d.split_whitespace().next().map(str::to_string))
+            .unwrap_or_default(),
+        blocked_uri: text(body, &["blocked-uri", "blockedURL"]).unwrap_or_default(),
+        source_file: text(body, &["source-file", "sourceFile"]),
+        line_number: ["line-number", "lineNumber"].iter().find_map(|k| body.get(*k).and_then(Value::as_u64)),
+        disposition: text(body, &["disposition"]).unwrap_or_else(|| "enforce".into()),
+        user_agent,
+    }
+}
+
+/// Violations in a report body, in either format
+pub fn parse_reports(body: &Value, user_agent: Option<&str>) -> Vec<CspViolation> {
+    if let Some(legacy) = body.get("csp-report") {
+        return vec![violation(legacy, user_agent.map(str::to_string))];
+    }
+    body.as_array()
+        .into_iter()
+        .flatten()
+        .filter(|r| r.get("type").and_then(Value::as_str) == Some("csp-violation"))
+        .filter_map(|r| {
+            let ua = text(r, &["user_agent"]).or_else(|| user_agent.map(str::to_string));
+            r.get("body").map(|b| violation(b, ua))
+        })
+        .collect()
+}
+
+/// Directive label for metrics; report bodies are untrusted, so anything
+/// unexpected is lumped together
+fn metric_directive(directive: &str) -> &str {
+    let ok = !directive.is_empty() && directive.len() <= 32 && directive.bytes().all(|b| b.is_ascii_lowercase() || b == b'-');
+    if ok { directive } else { "other" }
+}
+
+fn store(db: &Database, violations: &[CspViolation]) -> anyhow::Result<()> {
+    let tree = db.db.open_tree(REPORTS_TREE)?;
+    for v in violations {
+        tree.insert(db.db.generate_id()?.to_be_bytes(), serde_json::to_vec(v)?)?;
+    }
+    while tree.len() > MAX_STORED {
+        if tree.pop_min()?.is_none() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Receive violation reports from browsers (public, no authentication)
+#[post("/csp-report")]
+pub async fn report(req: HttpRequest, db: web::Data<Database>, body: web::Bytes) -> Result<HttpResponse> {
+    if body.len() > MAX_BODY {
+        return Ok(HttpResponse::PayloadTooLarge().json(ErrorResponse::new("payload_too_large", "Report too large")));
+    }
+    let Ok(value) = serde_json::from_slice::<Value>(&body) else {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new("invalid_report", "Report is not JSON")));
+    };
+    let user_agent = req.headers().get("user-agent").and_then(|v| v.to_str().ok());
+    let violations = parse_reports(&value, user_agent);
+    if violations.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new("invalid_report", "No CSP violation in report")));
+    }
+    let client = audit::client_ip(&req).unwrap_or_default();
+    let Some(warn) = admit(&client, violations.len() as u32) else {
+        return Ok(HttpResponse::TooManyRequests().json(ErrorResponse::new("rate_limited", "Too many reports")));
+    };
+    let level = if warn { log::Level::Warn } else { log::Level::Debug };
+    for v in &violations {
+        metrics::CSP_VIOLATIONS.inc(&[metric_directive(&v.directive)]);
+        log::log!(
+            level,
+            "CSP violation ({}): {} blocked {} on {}",
+            v.disposition, v.directive, v.blocked_uri, v.document_uri
+        );
+    }
+    db.run(move |db| store(&db, &violations)).await.map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct ReportsQuery {
+    pub limit: Option<usize>,
+}
+
+/// Most recent violation reports first (admin only)
+#[get("/admin/csp-reports")]
+pub async fn list_reports(
+    req: HttpRequest,
+    db: web::Data<Database>,
+    query: web::Query<ReportsQuery>,
+) -> Result<HttpResponse> {
+    require_admin(&req)?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_STORED);
+    let reports = db
+        .run(move |db| {
+            let tree = db.db.open_tree(REPORTS_TREE)?;
+            let mut out = Vec::new();
+            for item in tree.iter().rev().take(limit) {
+                let (_, value) = item?;
+                out.push(serde_json::from_slice::<CspViolation>(&value)?);
+            }
+            Ok((out, tree.len()))
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(json!({ "reports": reports.0, "total": reports.1 })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn parses_both_report_formats() {
+        let legacy = json!({"csp-report": {
+            "document-uri": "https://app.example.com/login",
+            "violated-directive": "script-src-elem 'self'",
+            "blocked-uri": "inline",
+            "line-number": 12,
+        }});
+        let v = parse_reports(&legacy, Some("Firefox"));
+        assert_eq!(v.len(), 1);
+        assert_eq!((v[0].directive.as_str(), v[0].line_number), ("script-src-elem", Some(12)));
+        assert_eq!(v[0].user_agent.as_deref(), Some("Firefox"));
+
+        let batch = json!([
+            {"type": "csp-violation", "user_agent": "Chrome", "body": {
+                "documentURL": "https://app.example.com/", "effectiveDirective": "style-src", "blockedURL": "inline",
+                "disposition": "report"}},
+            {"type": "deprecation", "body": {}},
+        ]);
+        let v = parse_reports(&batch, None);
+        assert_eq!(v.len(), 1);
+        assert_eq!((v[0].directive.as_str(), v[0].disposition.as_str()), ("style-src", "report"));
+        assert_eq!(metric_directive("script-src\"} 1"), "other");
+    }
+
+    #[actix_web::test]
+    async fn stores_reports_up_to_the_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(dir.path().join("db").to_str().unwrap()).unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).service(report)).await;
+
+        let body = json!({"csp-report": {"document-uri": "https://a/", "effective-directive": "img-src", "blocked-uri": "https://x/"}});
+        let req = test::TestRequest::post()
+            .uri("/csp-report")
+            .insert_header(("content-type", "application/csp-report"))
+            .set_payload(body.to_string())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 204);
+        let req = test::TestRequest::post().uri("/csp-report").set_payload("nope").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+        // Each client gets a budget per window
+        let from = |ip: &str| {
+            test::TestRequest::post()
+                .uri("/csp-report")
+                .peer_addr(format!("{}:4000", ip).parse().unwrap())
+                .set_payload(body.to_string())
+                .to_request()
+        };
+        for _ in 0..REPORTS_PER_CLIENT {
+            assert_eq!(test::call_service(&app, from("198.51.100.7")).await.status(), 204);
+        }
+        assert_eq!(test::call_service(&app, from("198.51.100.7")).await.status(), 429);
+        assert_eq!(test::call_service(&app, from("198.51.100.8")).await.status(), 204);
+
+        let many: Vec<_> = (0..MAX_STORED + 5).map(|_| parse_reports(&body, None).remove(0)).collect();
+        store(&db, &many).unwrap();
+        assert_eq!(db.db.open_tree(REPORTS_TREE).unwrap().len(), MAX_STORED);
+    }
+}
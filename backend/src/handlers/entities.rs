@@ -0,0 +1,656 @@
+// Generic CRUD for schema-defined entities (`/api/{collection}`)
+//
+// Every entity in the `SchemaRegistry` gets list/get/create/update/delete
+// without a handler of its own. Records are org-scoped (`TenantDb`), stored
+// as versioned documents (`Repository`) and validated against the entity's
+// JSON Schema before every write.
+//
+// Entities with soft delete (`x-softDelete` in the schema or
+// `SOFT_DELETE_COLLECTIONS`) move deleted records to a trash under
+// `/{collection}/trash`, from which they can be restored until the purge job
+// removes them.
+//
+// Writes are limited to the org roles in the schema's `x-writeRoles`
+// (everyone but viewers by default).
+//
+// Writes keep revision history (`history.rs`), readable under
+// `/{collection}/{id}/history` and revertible one revision at a time.
+use actix_web::error::InternalError;
+use actix_web::{delete, get, post, put, web, HttpMessage, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::db::Database;
+use crate::handlers::orgs::TenantDb;
+use crate::history::{self, Revision};
+use crate::models::auth_types::Claims;
+use crate::models::org_types::{ORG_ADMIN, ORG_OWNER};
+use crate::query::{ListParams, ListQuery};
+use crate::repository::{RepoError, Repository};
+use crate::schema::{EntitySchema, SchemaRegistry};
+use crate::types::{ErrorResponse, ItemResponse, ListResponse, UpsertRequest, UpsertResponse, VersionedData};
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteQuery {
+    #[serde(rename = "baseVersion")]
+    pub base_version: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryQuery {
+    /// `next_cursor` of the previous page: only revisions older than this
+    pub before: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevertQuery {
+    /// Fail with 409 unless the record is still at this version
+    #[serde(rename = "baseVersion")]
+    pub base_version: Option<u64>,
+}
+
+fn entity<'a>(registry: &'a SchemaRegistry, collection: &str) -> Result<&'a EntitySchema> {
+    registry.get(collection).ok_or_else(|| {
+        let body = ErrorResponse::new("unknown_entity", format!("No entity named '{}'", collection));
+        InternalError::from_response("unknown entity", HttpResponse::NotFound().json(body)).into()
+    })
+}
+
+/// The entity, if it has soft delete; 404 otherwise (it has no trash)
+fn trash_of<'a>(registry: &'a SchemaRegistry, collection: &str) -> Result<&'a EntitySchema> {
+    let entity = entity(registry, collection)?;
+    if !entity.soft_delete {
+        let body = ErrorResponse::new("no_trash", format!("{} records are deleted permanently", entity.name));
+        return Err(InternalError::from_response("no trash", HttpResponse::NotFound().json(body)).into());
+    }
+    Ok(entity)
+}
+
+fn caller(req: &HttpRequest) -> Result<Claims> {
+    req.extensions()
+        .get::<Claims>()
+        .cloned()
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing authentication token"))
+}
+
+/// 403 unless the caller's org role may write `entity` records; global
+/// admins always may
+fn require_writer(entity: &EntitySchema, db: &TenantDb, req: &HttpRequest) -> Result<()> {
+    let admin = caller(req)?.roles.iter().any(|r| r == "admin");
+    if admin || db.1.roles.iter().any(|r| entity.write_roles.contains(r)) {
+        return Ok(());
+    }
+    let body = ErrorResponse::new(
+        "insufficient_permissions",
+        format!("Your organization role cannot change {} records", entity.name),
+    );
+    Err(InternalError::from_response("read-only role", HttpResponse::Forbidden().json(body)).into())
+}
+
+fn validate(entity: &EntitySchema, data: &Value) -> Option<HttpResponse> {
+    entity.validate(data).err().map(|details| {
+        HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "validation_failed",
+            format!("{} failed validation", entity.name),
+            details,
+        ))
+    })
+}
+
+fn item(id: String, record: VersionedData<Value>) -> ItemResponse<Value> {
+    ItemResponse {
+        name: id,
+        data: record.data,
+        updated_at: record.updated_at,
+        version: record.version,
+        deleted_at: record.deleted_at,
+        deleted_by: record.deleted_by,
+    }
+}
+
+/// List records a page at a time; `filter` works on the schema's top-level fields
+#[get("/{collection}")]
+pub async fn list_entities(
+    path: web::Path<String>,
+    params: web::Query<ListParams>,
+    registry: web::Data<SchemaRegistry>,
+    db: TenantDb,
+) -> Result<HttpResponse> {
+    let collection = path.into_inner();
+    let entity = entity(&registry, &collection)?;
+    let query = match ListQuery::from_params(params.into_inner(), &entity.fields()) {
+        Ok(q) => q,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ErrorResponse::new("invalid_query", e.to_string()))),
+    };
+    let page = db
+        .run(move |db| Ok(Repository::<Value>::new(&db, &collection).query(&query)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)??;
+    Ok(HttpResponse::Ok().json(ListResponse {
+        items: page.items.into_iter().map(|(id, record)| item(id, record)).collect(),
+        next_cursor: page.next_cursor,
+        has_more: page.has_more,
+    }))
+}
+
+#[get("/{collection}/{id}")]
+pub async fn get_entity(
+    path: web::Path<(String, String)>,
+    registry: web::Data<SchemaRegistry>,
+    db: TenantDb,
+) -> Result<HttpResponse> {
+    let (collection, id) = path.into_inner();
+    entity(&registry, &collection)?;
+    let key = id.clone();
+    let record = db
+        .run(move |db| Ok(Repository::<Value>::new(&db, &collection).get(&key)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)??;
+    match record {
+        Some(record) => Ok(HttpResponse::Ok().json(item(id, record))),
+        None => Err(RepoError::NotFound.into()),
+    }
+}
+
+/// Create a record. The id is the body's `id` field when it is a string,
+/// otherwise one is generated.
+#[post("/{collection}")]
+pub async fn create_entity(
+    path: web::Path<String>,
+    body: web::Json<Value>,
+    registry: web::Data<SchemaRegistry>,
+    db: TenantDb,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let collection = path.into_inner();
+    let entity = entity(&registry, &collection)?;
+    require_writer(entity, &db, &req)?;
+    let data = body.into_inner();
+    if let Some(resp) = validate(entity, &data) {
+        return Ok(resp);
+    }
+    let id = data.get("id").and_then(Value::as_str).map(str::to_string).unwrap_or_else(|| nanoid::nanoid!());
+    let key = id.clone();
+    let record = db
+        .for_request(&req)
+        .run(move |db| Ok(Repository::<Value>::new(&db, &collection).create(&key, data)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)??;
+    Ok(HttpResponse::Created().json(item(id, record)))
+}
+
+/// Create or replace a record; `baseVersion` makes it a compare-and-swap
+/// write that fails with 409 if someone else wrote first
+#[put("/{collection}/{id}")]
+pub async fn upsert_entity(
+    path: web::Path<(String, String)>,
+    body: web::Json<UpsertRequest<Value>>,
+    registry: web::Data<SchemaRegistry>,
+    db: TenantDb,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let (collection, id) = path.into_inner();
+    let entity = entity(&registry, &collection)?;
+    require_writer(entity, &db, &req)?;
+    let request = body.into_inner();
+    if let Some(resp) = validate(entity, &request.data) {
+        return Ok(resp);
+    }
+    let record = db
+        .for_request(&req)
+        .run(move |db| Ok(Repository::<Value>::new(&db, &collection).upsert(&id, request)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)??;
+    Ok(HttpResponse::Ok().json(UpsertResponse::from(&record)))
+}
+
+/// Delete a record; for soft-delete entities it goes to the trash
+#[delete("/{collection}/{id}")]
+pub async fn delete_entity(
+    path: web::Path<(String, String)>,
+    query: web::Query<DeleteQuery>,
+    registry: web::Data<SchemaRegistry>,
+    db: TenantDb,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let (collection, id) = path.into_inner();
+    let entity = entity(&registry, &collection)?;
+    require_writer(entity, &db, &req)?;
+    let soft = entity.soft_delete;
+    let deleted_by = caller(&req)?.sub;
+    let base_version = query.base_version;
+    let removed = db
+        .for_request(&req)
+        .run(move |db| {
+            let repo = Repository::<Value>::new(&db, &collection);
+            Ok(if soft { repo.soft_delete(&id, base_version, &deleted_by) } else { repo.delete(&id, base_version) })
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)??;
+    if !removed {
+        return Err(RepoError::NotFound.into());
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// List the trash a page at a time; `filter` works as on the normal list
+#[get("/{collection}/trash")]
+pub async fn list_trash(
+    path: web::Path<String>,
+    params: web::Query<ListParams>,
+    registry: web::Data<SchemaRegistry>,
+    db: TenantDb,
+) -> Result<HttpResponse> {
+    let collection = path.into_inner();
+    let entity = trash_of(&registry, &collection)?;
+    let query = match ListQuery::from_params(params.into_inner(), &entity.fields()) {
+        Ok(q) => q,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ErrorResponse::new("invalid_query", e.to_string()))),
+    };
+    let page = db
+        .run(move |db| Ok(Repository::<Value>::new(&db, &collection).query_trash(&query)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)??;
+    Ok(HttpResponse::Ok().json(ListResponse {
+        items: page.items.into_iter().map(|(id, record)| item(id, record)).collect(),
+        next_cursor: page.next_cursor,
+        has_more: page.has_more,
+    }))
+}
+
+#[post("/{collection}/trash/{id}/restore")]
+pub async fn restore_entity(
+    path: web::Path<(String, String)>,
+    registry: web::Data<SchemaRegistry>,
+    db: TenantDb,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let (collection, id) = path.into_inner();
+    require_writer(trash_of(&registry, &collection)?, &db, &req)?;
+    let key = id.clone();
+    let record = db
+        .for_request(&req)
+        .run(move |db| Ok(Repository::<Value>::new(&db, &collection).restore(&key)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)??;
+    Ok(HttpResponse::Ok().json(item(id, record)))
+}
+
+/// Permanently delete a trashed record before the purge job would. Org
+/// owners and admins only.
+#[delete("/{collection}/trash/{id}")]
+pub async fn purge_entity(
+    path: web::Path<(String, String)>,
+    registry: web::Data<SchemaRegistry>,
+    db: TenantDb,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let (collection, id) = path.into_inner();
+    trash_of(&registry, &collection)?;
+    let claims = caller(&req)?;
+    let allowed = claims.roles.iter().any(|r| r == "admin") || db.1.has_any_role(&[ORG_OWNER, ORG_ADMIN]);
+    if !allowed {
+        return Ok(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "insufficient_permissions",
+            "Organization admin role required",
+        )));
+    }
+    let removed = db
+        .for_request(&req)
+        .run(move |db| Ok(Repository::<Value>::new(&db, &collection).purge(&id, u64::MAX)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)??;
+    if !removed {
+        return Err(RepoError::NotFound.into());
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Revisions of a record, newest first: author, time, changed fields and
+/// the record as it was afterwards
+#[get("/{collection}/{id}/history")]
+pub async fn entity_history(
+    path: web::Path<(String, String)>,
+    query: web::Query<HistoryQuery>,
+    registry: web::Data<SchemaRegistry>,
+    db: TenantDb,
+) -> Result<HttpResponse> {
+    let (collection, id) = path.into_inner();
+    entity(&registry, &collection)?;
+    let limit = query.limit.unwrap_or(history::DEFAULT_LIMIT).clamp(1, history::MAX_LIMIT);
+    let before = query.before;
+    let (items, has_more) = db
+        .run(move |db| history::list(&db, &collection, &id, before, limit))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let next_cursor = if has_more { items.last().map(|r: &Revision| r.revision.to_string()) } else { None };
+    Ok(HttpResponse::Ok().json(ListResponse { items, next_cursor, has_more }))
+}
+
+/// Write a revision's data back as a new version of the record. The revert
+/// itself becomes a revision, so nothing is lost. Only live records can be
+/// reverted; trashed ones have to be restored first, and deleted ones stay
+/// deleted.
+#[post("/{collection}/{id}/history/{revision}/revert")]
+pub async fn revert_entity(
+    path: web::Path<(String, String, u64)>,
+    query: web::Query<RevertQuery>,
+    registry: web::Data<SchemaRegistry>,
+    db: TenantDb,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let (collection, id, revision) = path.into_inner();
+    let entity = entity(&registry, &collection)?;
+    require_writer(entity, &db, &req)?;
+    let (history_collection, key) = (collection.clone(), id.clone());
+    let target = db
+        .run(move |db| history::get(&db, &history_collection, &key, revision))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let Some(target) = target else { return Err(RepoError::NotFound.into()) };
+    let Some(data) = target.state.as_ref().and_then(|s| s.get("data")).cloned() else {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "invalid_revision",
+            "That revision deleted the record; there is nothing to revert to",
+        )));
+    };
+    // The schema may have changed since
+    if let Some(resp) = validate(entity, &data) {
+        return Ok(resp);
+    }
+    let base_version = query.base_version;
+    let key = id.clone();
+    let record = db
+        .for_request(&req)
+        .run(move |db| {
+            let repo = Repository::<Value>::new(&db, &collection);
+            Ok(match base_version {
+                Some(base) => repo.update(&key, data, base),
+                None => repo.replace(&key, data),
+            })
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)??;
+    Ok(HttpResponse::Ok().json(item(id, record)))
+}
+
+/// Purge records trashed longer than `retention` from every soft-delete
+/// collection, across all orgs. Returns how many were removed.
+pub fn purge_expired_trash(db: &Database, registry: &SchemaRegistry, retention: std::time::Duration) -> anyhow::Result<usize> {
+    let now = chrono::Utc::now().timestamp_millis().max(0) as u64;
+    let cutoff = now.saturating_sub(retention.as_millis() as u64);
+    let mut purged = 0;
+    for collection in registry.soft_delete_collections() {
+        purged += Repository::<Value>::new(db, collection)
+            .purge_expired(cutoff)
+            .map_err(|e| anyhow::anyhow!("{}: {}", collection, e))?;
+    }
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::orgs::MEMBERSHIPS_TREE;
+    use crate::models::org_types::{Membership, ORG_MEMBER, ORG_VIEWER};
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn member_of(org: &str) -> Claims {
+        Claims {
+            sub: "u1".into(),
+            email: "u1@test.dev".into(),
+            roles: vec!["user".into()],
+            iat: 0,
+            iat_ms: None,
+            exp: i64::MAX,
+            iss: "test".into(),
+            aud: "test".into(),
+            org_id: Some(org.into()),
+            org_roles: vec!["member".into()],
+            impersonator: None,
+        }
+    }
+
+    /// Store the membership `member_of` claims, as `TenantDb` checks it
+    fn join(db: &Database, org: &str, roles: &[&str]) {
+        let membership = Membership::new(org, "u1", roles.iter().map(|r| r.to_string()).collect());
+        db.insert(MEMBERSHIPS_TREE, &Membership::key(org, "u1"), &membership).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn crud_validates_against_schema() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        join(&db, "acme", &[ORG_MEMBER]);
+        let mut registry = SchemaRegistry::default();
+        registry
+            .register(
+                "Invoice",
+                json!({
+                    "type": "object",
+                    "required": ["number", "total"],
+                    "properties": {
+                        "number": {"type": "string"},
+                        "total": {"type": "number", "minimum": 0},
+                        "status": {"type": "string", "enum": ["draft", "sent", "paid"]}
+                    }
+                }),
+            )
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(registry))
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(member_of("acme"));
+                    actix_web::dev::Service::call(srv, req)
+                })
+                .service(list_entities)
+                .service(get_entity)
+                .service(create_entity)
+                .service(upsert_entity)
+                .service(delete_entity),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/invoices")
+            .set_json(json!({"total": -1, "status": "lost"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = test::read_body_json(resp).await;
+        let details = &body["error"]["details"];
+        assert_eq!(details["number"], "is required");
+        assert!(details["total"].is_string() && details["status"].is_string());
+
+        let req = test::TestRequest::post()
+            .uri("/invoices")
+            .set_json(json!({"id": "inv-1", "number": "INV-1", "total": 120.5, "status": "draft"}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+        // Stored under the caller's org
+        assert!(db.for_tenant("acme").get::<Value>("invoices", "inv-1").unwrap().is_some());
+
+        let update = |base: u64| {
+            test::TestRequest::put()
+                .uri("/invoices/inv-1")
+                .set_json(json!({"data": {"number": "INV-1", "total": 120.5, "status": "paid"}, "baseVersion": base}))
+                .to_request()
+        };
+        assert_eq!(test::call_service(&app, update(1)).await.status(), StatusCode::OK);
+        assert_eq!(test::call_service(&app, update(1)).await.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::get().uri("/invoices?filter=status:eq:paid").to_request();
+        let page: ListResponse<ItemResponse<Value>> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].version, 2);
+
+        let req = test::TestRequest::get().uri("/widgets").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::delete().uri("/invoices/inv-1?baseVersion=2").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+
+        // A removed member's token no longer opens the org
+        db.delete(MEMBERSHIPS_TREE, &Membership::key("acme", "u1")).unwrap();
+        let req = test::TestRequest::get().uri("/invoices").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn soft_deleted_records_can_be_restored() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        join(&db, "acme", &[ORG_MEMBER]);
+        let mut registry = SchemaRegistry::default();
+        registry.register("Quote", json!({"type": "object"})).unwrap();
+        registry.register("Note", json!({"type": "object"})).unwrap();
+        registry.enable_soft_delete(&["quotes".to_string()]);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(registry))
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(member_of("acme"));
+                    actix_web::dev::Service::call(srv, req)
+                })
+                .service(list_entities)
+                .service(list_trash)
+                .service(restore_entity)
+                .service(purge_entity)
+                .service(get_entity)
+                .service(create_entity)
+                .service(delete_entity),
+        )
+        .await;
+
+        let req = test::TestRequest::post().uri("/quotes").set_json(json!({"id": "q1", "total": 5})).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+        let req = test::TestRequest::delete().uri("/quotes/q1").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get().uri("/quotes/q1").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+        let req = test::TestRequest::get().uri("/quotes/trash").to_request();
+        let trash: ListResponse<ItemResponse<Value>> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(trash.items.len(), 1);
+        assert_eq!(trash.items[0].deleted_by.as_deref(), Some("u1"));
+
+        // Members can restore but not purge
+        let req = test::TestRequest::delete().uri("/quotes/trash/q1").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+        let req = test::TestRequest::post().uri("/quotes/trash/q1/restore").to_request();
+        let restored: ItemResponse<Value> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((restored.version, restored.deleted_at), (3, None));
+
+        // Entities without soft delete have no trash
+        let req = test::TestRequest::get().uri("/notes/trash").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn history_shows_who_changed_what_and_reverts() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap())
+            .unwrap()
+            .with_history(["invoices".to_string()]);
+        join(&db, "acme", &[ORG_MEMBER]);
+        let mut registry = SchemaRegistry::default();
+        registry.register("Invoice", json!({"type": "object", "required": ["amount"]})).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(registry))
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(member_of("acme"));
+                    actix_web::dev::Service::call(srv, req)
+                })
+                .service(entity_history)
+                .service(revert_entity)
+                .service(create_entity)
+                .service(upsert_entity),
+        )
+        .await;
+
+        let req = test::TestRequest::post().uri("/invoices").set_json(json!({"id": "inv-1", "amount": 100})).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+        let req = test::TestRequest::put()
+            .uri("/invoices/inv-1")
+            .set_json(json!({"data": {"id": "inv-1", "amount": 1000}, "baseVersion": 1}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/invoices/inv-1/history").to_request();
+        let page: ListResponse<Value> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.items.len(), 2);
+        let latest = &page.items[0];
+        assert_eq!(latest["author"], "u1");
+        assert_eq!(latest["op"], "updated");
+        assert_eq!(latest["changes"], json!([{"path": "/data/amount", "from": 100, "to": 1000}]));
+
+        let first = page.items[1]["revision"].as_u64().unwrap();
+        let req = test::TestRequest::post()
+            .uri(&format!("/invoices/inv-1/history/{}/revert?baseVersion=1", first))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+        let req = test::TestRequest::post().uri(&format!("/invoices/inv-1/history/{}/revert", first)).to_request();
+        let reverted: ItemResponse<Value> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!((reverted.version, reverted.data["amount"].clone()), (3, json!(100)));
+
+        let req = test::TestRequest::get().uri("/invoices/inv-1/history?limit=1").to_request();
+        let page: ListResponse<Value> = test::call_and_read_body_json(&app, req).await;
+        assert!(page.has_more);
+        assert_eq!(page.items[0]["changes"][0]["to"], 100);
+
+        // Reverting doesn't bring a deleted record back
+        assert!(Repository::<Value>::new(&db.for_tenant("acme"), "invoices").delete("inv-1", None).unwrap());
+        let req = test::TestRequest::post().uri(&format!("/invoices/inv-1/history/{}/revert", first)).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn writes_follow_the_schema_write_roles() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        join(&db, "acme", &[ORG_VIEWER]);
+        let mut registry = SchemaRegistry::default();
+        registry.register("Note", json!({"type": "object"})).unwrap();
+        registry.register("Contract", json!({"type": "object", "x-writeRoles": ["owner", "admin"]})).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(registry))
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(member_of("acme"));
+                    actix_web::dev::Service::call(srv, req)
+                })
+                .service(list_entities)
+                .service(create_entity)
+                .service(delete_entity),
+        )
+        .await;
+
+        // Viewers read but don't write
+        let req = test::TestRequest::get().uri("/notes").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let req = test::TestRequest::post().uri("/notes").set_json(json!({"id": "n1"})).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+        // The role is read per request, not from the token
+        join(&db, "acme", &[ORG_MEMBER]);
+        let req = test::TestRequest::post().uri("/notes").set_json(json!({"id": "n1"})).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+        let req = test::TestRequest::post().uri("/contracts").set_json(json!({"id": "c1"})).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+        let req = test::TestRequest::delete().uri("/contracts/c1").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    }
+}
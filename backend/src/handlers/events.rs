@@ -0,0 +1,239 @@
+// Change feed endpoints: polling with `since` cursors and Server-Sent Events
+use actix_web::{get, web, web::Bytes, HttpMessage, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::db::Database;
+use crate::events::{self, TypeFilter};
+use crate::handlers::orgs::get_membership;
+use crate::models::auth_types::Claims;
+use crate::types::{ListResponse, RuntimeEvent};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// Comment line sent on idle streams so proxies keep the connection open
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EventsQuery {
+    /// Cursor of the last event already seen; omit to start from the beginning
+    pub since: Option<String>,
+    /// Comma-separated event types, exact (`orgs.upserted`) or `collection.*`
+    pub types: Option<String>,
+    pub limit: Option<usize>,
+}
+
+fn caller(req: &HttpRequest) -> Result<Claims> {
+    req.extensions()
+        .get::<Claims>()
+        .cloned()
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing authentication token"))
+}
+
+/// Admins see every event; everyone else only events of their active org
+fn visible_to(claims: &Claims, event: &RuntimeEvent) -> bool {
+    if claims.roles.iter().any(|r| r == "admin") {
+        return true;
+    }
+    match (event.payload.get("tenant").and_then(|t| t.as_str()), &claims.org_id) {
+        (Some(tenant), Some(org)) => tenant == org,
+        _ => false,
+    }
+}
+
+/// Whether a streaming caller may still see their org's events. Tokens
+/// outlive memberships, so a removed member's open stream must not.
+fn still_member(db: &Database, claims: &Claims) -> bool {
+    if claims.roles.iter().any(|r| r == "admin") {
+        return true;
+    }
+    match &claims.org_id {
+        Some(org) => get_membership(db, org, &claims.sub).is_some(),
+        None => true,
+    }
+}
+
+/// Poll the change feed. Pass the returned `next_cursor` as `since` next time.
+#[get("/events")]
+pub async fn list_events(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    query: web::Query<EventsQuery>,
+) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    let since = events::parse_cursor(query.since.as_deref());
+    let types = TypeFilter::parse(query.types.as_deref());
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let (items, cursor, has_more) = db
+        .run(move |db| events::read_since(&db, since, limit, |e| types.matches(&e.event_type) && visible_to(&claims, e)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(ListResponse { items, next_cursor: Some(cursor.to_string()), has_more }))
+}
+
+struct StreamState {
+    db: Database,
+    claims: Claims,
+    types: TypeFilter,
+    cursor: u64,
+    updates: tokio::sync::watch::Receiver<u64>,
+    queue: VecDeque<RuntimeEvent>,
+}
+
+fn sse_frame(event: &RuntimeEvent) -> Bytes {
+    let data = serde_json::to_string(event).unwrap_or_else(|_| "{}".into());
+    Bytes::from(format!("id: {}\nevent: {}\ndata: {}\n\n", event.cursor, event.event_type, data))
+}
+
+/// Subscribe to the change feed over Server-Sent Events. Reconnecting
+/// clients resume after `Last-Event-ID` (or `since`).
+#[get("/events/stream")]
+pub async fn stream_events(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    query: web::Query<EventsQuery>,
+) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    let last_event_id = req.headers().get("Last-Event-ID").and_then(|v| v.to_str().ok());
+    let state = StreamState {
+        db: db.get_ref().clone(),
+        claims,
+        types: TypeFilter::parse(query.types.as_deref()),
+        cursor: events::parse_cursor(last_event_id.or(query.since.as_deref())),
+        updates: db.subscribe_events(),
+        queue: VecDeque::new(),
+    };
+
+    let stream = futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.queue.pop_front() {
+                return Some((Ok::<_, actix_web::Error>(sse_frame(&event)), state));
+            }
+            // Mark the feed seen before reading, so a commit landing during
+            // the read still wakes the wait below
+            state.updates.borrow_and_update();
+            let (claims, types, since) = (state.claims.clone(), state.types.clone(), state.cursor);
+            let read = state
+                .db
+                .run(move |db| {
+                    if !still_member(&db, &claims) {
+                        return Ok(None);
+                    }
+                    events::read_since(&db, since, DEFAULT_LIMIT, |e| types.matches(&e.event_type) && visible_to(&claims, e))
+                        .map(Some)
+                })
+                .await;
+            match read {
+                Ok(None) => return None,
+                Ok(Some((items, cursor, _))) => {
+                    state.cursor = cursor;
+                    if !items.is_empty() {
+                        state.queue.extend(items);
+                        continue;
+                    }
+                }
+                Err(e) => {
+                    log::error!("Change feed read failed: {}", e);
+                    return None;
+                }
+            }
+            match tokio::time::timeout(KEEP_ALIVE_INTERVAL, state.updates.changed()).await {
+                Ok(Ok(())) => continue,
+                // Every sender is gone; nothing more can arrive
+                Ok(Err(_)) => return None,
+                Err(_) => return Some((Ok(Bytes::from_static(b": keep-alive\n\n")), state)),
+            }
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::orgs::MEMBERSHIPS_TREE;
+    use crate::models::org_types::Membership;
+    use actix_web::{test, App};
+    use actix_web::body::{BoxBody, MessageBody};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    async fn next_frame(body: &mut BoxBody) -> String {
+        let chunk = futures::future::poll_fn(|cx| std::pin::Pin::new(&mut *body).poll_next(cx));
+        let chunk = tokio::time::timeout(Duration::from_secs(5), chunk).await.unwrap().unwrap().unwrap();
+        String::from_utf8(chunk.to_vec()).unwrap()
+    }
+
+    fn member_of(org: &str) -> Claims {
+        Claims {
+            sub: "u1".into(),
+            email: "u1@test.dev".into(),
+            roles: vec!["user".into()],
+            iat: 0,
+            iat_ms: None,
+            exp: i64::MAX,
+            iss: "test".into(),
+            aud: "test".into(),
+            org_id: Some(org.into()),
+            org_roles: vec![],
+            impersonator: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn stream_resumes_after_last_event_id_and_hides_other_tenants() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        db.insert(MEMBERSHIPS_TREE, &Membership::key("acme", "u1"), &Membership::new("acme", "u1", vec![])).unwrap();
+        let acme = db.for_tenant("acme");
+        acme.insert("notes", "n1", &json!({"text": "one"})).unwrap();
+        db.for_tenant("other").insert("notes", "x", &json!({"text": "hidden"})).unwrap();
+        acme.insert("notes", "n2", &json!({"text": "two"})).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .wrap_fn(|req, srv| {
+                    req.extensions_mut().insert(member_of("acme"));
+                    actix_web::dev::Service::call(srv, req)
+                })
+                .service(list_events)
+                .service(stream_events),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/events?types=notes.*").to_request();
+        let page: ListResponse<RuntimeEvent> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.items.len(), 2);
+        let first = page.items[0].cursor.clone();
+
+        let req = test::TestRequest::get()
+            .uri("/events/stream")
+            .insert_header(("Last-Event-ID", first))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), "text/event-stream");
+        let mut body = resp.into_body();
+
+        let frame = next_frame(&mut body).await;
+        assert!(frame.contains("event: notes.upserted") && frame.contains("\"n2\""), "{}", frame);
+
+        // Live: a write after subscribing is pushed without polling
+        acme.insert("notes", "n3", &json!({"text": "three"})).unwrap();
+        assert!(next_frame(&mut body).await.contains("\"n3\""));
+
+        // Removed from the org: the next wake ends the stream
+        db.delete(MEMBERSHIPS_TREE, &Membership::key("acme", "u1")).unwrap();
+        acme.insert("notes", "n4", &json!({"text": "four"})).unwrap();
+        let end = futures::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_next(cx));
+        assert!(tokio::time::timeout(Duration::from_secs(5), end).await.unwrap().is_none());
+    }
+}
@@ -1,3 +1,10 @@
+pub mod admin;
+pub mod api_keys;
+pub mod audit;
 pub mod auth;
 pub mod cookies;
+pub mod csp;
+pub mod entities;
+pub mod events;
+pub mod orgs;
 pub mod users;
@@ -0,0 +1,610 @@
+// Organization (tenant) management endpoints
+use actix_web::{delete, dev::Payload, get, post, put, web, FromRequest, HttpMessage, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use futures_util::future::{ready, Ready};
+use serde_json::json;
+
+use crate::audit::{self, AuditEvent, AuditOutcome};
+use crate::config::AppConfig;
+use crate::db::{Database, Tx, TxResult};
+use crate::handlers::auth::make_token;
+use crate::handlers::cookies::set_auth_cookies;
+use crate::models::auth_types::{Claims, UserRecord};
+use crate::models::org_types::{
+    AddMemberRequest, CreateOrgRequest, Membership, Organization, UpdateMemberRequest, ORG_ADMIN,
+    ORG_OWNER, ORG_ROLES,
+};
+use crate::types::ErrorResponse;
+
+pub const ORGS_TREE: &str = "orgs";
+pub const MEMBERSHIPS_TREE: &str = "memberships";
+
+/// Database handle scoped to the caller's active organization.
+///
+/// Business handlers take this instead of `web::Data<Database>` so every key
+/// they touch is prefixed with the org id from the token, and writes are
+/// attributed to the caller in revision history. The membership is looked up
+/// on every request, so removed members lose access immediately and role
+/// changes apply without a new token. Requests without an active org, or
+/// whose caller is no longer a member, are rejected with 403.
+pub struct TenantDb(pub Database, pub Membership);
+
+impl std::ops::Deref for TenantDb {
+    type Target = Database;
+    fn deref(&self) -> &Database {
+        &self.0
+    }
+}
+
+impl FromRequest for TenantDb {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let db = match req.app_data::<web::Data<Database>>() {
+            Some(db) => db,
+            None => return ready(Err(actix_web::error::ErrorInternalServerError("database not configured"))),
+        };
+        let caller = req.extensions().get::<Claims>().and_then(|c| Some((c.org_id.clone()?, c.sub.clone())));
+        ready(match caller {
+            Some((org_id, sub)) => match get_membership(db, &org_id, &sub) {
+                Some(membership) => Ok(TenantDb(db.for_tenant(&org_id).as_actor(&sub), membership)),
+                None => Err(actix_web::error::ErrorForbidden("Not a member of the active organization")),
+            },
+            None => Err(actix_web::error::ErrorForbidden("No active organization")),
+        })
+    }
+}
+
+fn valid_org_roles(roles: &[String]) -> bool {
+    !roles.is_empty() && roles.iter().all(|r| ORG_ROLES.contains(&r.as_str()))
+}
+
+pub fn get_membership(db: &Database, org_id: &str, user_id: &str) -> Option<Membership> {
+    db.get(MEMBERSHIPS_TREE, &Membership::key(org_id, user_id)).ok().flatten()
+}
+
+pub fn memberships_of_user(db: &Database, user_id: &str) -> Vec<Membership> {
+    let mut memberships: Vec<Membership> = db
+        .get_by_index(MEMBERSHIPS_TREE, "user_id", user_id)
+        .unwrap_or_default();
+    memberships.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    memberships
+}
+
+/// Pick the org a fresh session starts in: the last one the user switched to
+/// if they are still a member, otherwise their oldest membership.
+pub fn resolve_active_org(db: &Database, user: &UserRecord) -> Option<Membership> {
+    if let Some(org_id) = &user.active_org_id {
+        if let Some(m) = get_membership(db, org_id, &user.id) {
+            return Some(m);
+        }
+    }
+    memberships_of_user(db, &user.id).into_iter().next()
+}
+
+fn caller(req: &HttpRequest) -> Result<Claims> {
+    req.extensions()
+        .get::<Claims>()
+        .cloned()
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing authentication token"))
+}
+
+/// Global admins may manage any org; otherwise an org owner/admin role is required
+fn require_org_role(db: &Database, claims: &Claims, org_id: &str, roles: &[&str]) -> Option<HttpResponse> {
+    if claims.roles.contains(&"admin".to_string()) {
+        return None;
+    }
+    match get_membership(db, org_id, &claims.sub) {
+        Some(m) if m.has_any_role(roles) => None,
+        Some(_) => Some(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "insufficient_permissions",
+            "Organization admin role required",
+        ))),
+        None => Some(HttpResponse::NotFound().json(ErrorResponse::new(
+            "org_not_found",
+            "Organization not found",
+        ))),
+    }
+}
+
+/// Granting or revoking `owner` is reserved to owners (and global admins),
+/// so an org admin cannot promote themselves or depose an owner
+fn require_owner(db: &Database, claims: &Claims, org_id: &str) -> Option<HttpResponse> {
+    if claims.roles.contains(&"admin".to_string()) {
+        return None;
+    }
+    match get_membership(db, org_id, &claims.sub) {
+        Some(m) if m.has_any_role(&[ORG_OWNER]) => None,
+        _ => Some(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "insufficient_permissions",
+            "Only organization owners can grant or revoke the owner role",
+        ))),
+    }
+}
+
+/// Memberships of `org_id` that hold `owner`, as listed outside any transaction
+fn listed_owners(db: &Database, org_id: &str) -> anyhow::Result<Vec<Membership>> {
+    Ok(db
+        .list_prefix::<Membership>(MEMBERSHIPS_TREE, &format!("{}:", org_id))?
+        .into_iter()
+        .filter(|m| m.has_any_role(&[ORG_OWNER]))
+        .collect())
+}
+
+/// How many of `listed` are still owners inside `tx`. Transactions can't
+/// scan, so the owners are listed beforehand and re-read here; one promoted
+/// in between is missed, which only errs towards refusing the change.
+pub(crate) fn owners_left(tx: &Tx<'_>, listed: &[Membership]) -> TxResult<usize> {
+    let mut owners = 0;
+    for m in listed {
+        let current: Option<Membership> = tx.get(MEMBERSHIPS_TREE, &Membership::key(&m.org_id, &m.user_id))?;
+        if current.is_some_and(|c| c.has_any_role(&[ORG_OWNER])) {
+            owners += 1;
+        }
+    }
+    Ok(owners)
+}
+
+/// Outcome of a membership change made together with its last-owner check
+enum MemberChange {
+    /// Carries the membership as it was before the change
+    Done(Membership),
+    NotFound,
+    LastOwner,
+}
+
+/// Create an organization; the caller becomes its owner
+#[post("/orgs")]
+pub async fn create_org(
+    db: web::Data<Database>,
+    req: HttpRequest,
+    body: web::Json<CreateOrgRequest>,
+) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    let name = body.name.trim();
+    if name.is_empty() || name.len() > 128 {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "invalid_name",
+            "Organization name must be 1-128 characters",
+        )));
+    }
+
+    let org = Organization::new(name, &claims.sub);
+    let membership = Membership::new(&org.id, &claims.sub, vec![ORG_OWNER.into()]);
+    let stored = org.clone();
+    db.for_request(&req)
+        .run(move |db| {
+            let mut batch = db.batch();
+            batch
+                .insert(ORGS_TREE, &stored.id, &stored)?
+                .insert(MEMBERSHIPS_TREE, &Membership::key(&stored.id, &membership.user_id), &membership)?;
+            batch.commit()
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(org))
+}
+
+/// List the organizations the caller belongs to
+#[get("/orgs")]
+pub async fn list_my_orgs(db: web::Data<Database>, req: HttpRequest) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    let orgs: Vec<serde_json::Value> = memberships_of_user(&db, &claims.sub)
+        .into_iter()
+        .filter_map(|m| {
+            let org: Organization = db.get(ORGS_TREE, &m.org_id).ok().flatten()?;
+            Some(json!({
+                "id": org.id,
+                "name": org.name,
+                "roles": m.roles,
+                "active": claims.org_id.as_deref() == Some(org.id.as_str()),
+            }))
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "orgs": orgs })))
+}
+
+/// List members of an organization (any member may look)
+#[get("/orgs/{org_id}/members")]
+pub async fn list_members(
+    path: web::Path<String>,
+    db: web::Data<Database>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    let org_id = path.into_inner();
+    if let Some(resp) = require_org_role(&db, &claims, &org_id, ORG_ROLES) {
+        return Ok(resp);
+    }
+
+    let members: Vec<serde_json::Value> = db
+        .list_prefix::<Membership>(MEMBERSHIPS_TREE, &format!("{}:", org_id))
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .into_iter()
+        .map(|m| {
+            let email = db
+                .get::<UserRecord>("users", &m.user_id)
+                .ok()
+                .flatten()
+                .map(|u| u.email)
+                .unwrap_or_default();
+            json!({ "user_id": m.user_id, "email": email, "roles": m.roles, "created_at": m.created_at })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "members": members })))
+}
+
+/// Add an existing user to an organization (org owner/admin; only owners
+/// may add owners)
+#[post("/orgs/{org_id}/members")]
+pub async fn add_member(
+    path: web::Path<String>,
+    db: web::Data<Database>,
+    req: HttpRequest,
+    body: web::Json<AddMemberRequest>,
+) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    let org_id = path.into_inner();
+    if let Some(resp) = require_org_role(&db, &claims, &org_id, &[ORG_OWNER, ORG_ADMIN]) {
+        return Ok(resp);
+    }
+    if !valid_org_roles(&body.roles) {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "invalid_roles",
+            "Roles must be a non-empty subset of owner, admin, member, viewer",
+        )));
+    }
+    if body.roles.iter().any(|r| r == ORG_OWNER) {
+        if let Some(resp) = require_owner(&db, &claims, &org_id) {
+            return Ok(resp);
+        }
+    }
+
+    let email = body.email.trim().to_lowercase();
+    let user = match db.find_unique::<UserRecord>("users", "email", &email).ok().flatten() {
+        Some(u) => u,
+        None => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse::new("user_not_found", "User not found")));
+        }
+    };
+    if get_membership(&db, &org_id, &user.id).is_some() {
+        return Ok(HttpResponse::Conflict().json(ErrorResponse::new(
+            "already_member",
+            "User is already a member of this organization",
+        )));
+    }
+
+    let membership = Membership::new(&org_id, &user.id, body.roles.clone());
+    let stored = membership.clone();
+    db.for_request(&req)
+        .run(move |db| db.insert(MEMBERSHIPS_TREE, &Membership::key(&stored.org_id, &stored.user_id), &stored))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    audit::record(&db, AuditEvent::from_request(&req, "org.member_added", AuditOutcome::Success)
+        .actor(claims.sub.clone())
+        .target(user.id.clone())
+        .details(json!({"org_id": org_id, "roles": membership.roles}))).await;
+    Ok(HttpResponse::Created().json(membership))
+}
+
+/// Change a member's org roles (org owner/admin; only owners may grant or
+/// revoke `owner`, and the last owner cannot be demoted)
+#[put("/orgs/{org_id}/members/{user_id}")]
+pub async fn update_member(
+    path: web::Path<(String, String)>,
+    db: web::Data<Database>,
+    req: HttpRequest,
+    body: web::Json<UpdateMemberRequest>,
+) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    let (org_id, user_id) = path.into_inner();
+    if let Some(resp) = require_org_role(&db, &claims, &org_id, &[ORG_OWNER, ORG_ADMIN]) {
+        return Ok(resp);
+    }
+    if !valid_org_roles(&body.roles) {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "invalid_roles",
+            "Roles must be a non-empty subset of owner, admin, member, viewer",
+        )));
+    }
+
+    let membership = get_membership(&db, &org_id, &user_id)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Member not found"))?;
+    let was_owner = membership.has_any_role(&[ORG_OWNER]);
+    if was_owner != body.roles.iter().any(|r| r == ORG_OWNER) {
+        if let Some(resp) = require_owner(&db, &claims, &org_id) {
+            return Ok(resp);
+        }
+    }
+    let listed = listed_owners(&db, &org_id).map_err(actix_web::error::ErrorInternalServerError)?;
+    let key = Membership::key(&org_id, &user_id);
+    let roles = body.roles.clone();
+    // The owner count and the write share one transaction, so two demotions
+    // racing each other can't both see another owner left
+    let change = db
+        .for_request(&req)
+        .run(move |db| {
+            db.transaction(&[MEMBERSHIPS_TREE], |tx| {
+                let Some(previous) = tx.get::<Membership>(MEMBERSHIPS_TREE, &key)? else {
+                    return Ok(MemberChange::NotFound);
+                };
+                if previous.has_any_role(&[ORG_OWNER])
+                    && !roles.iter().any(|r| r == ORG_OWNER)
+                    && owners_left(tx, &listed)? <= 1
+                {
+                    return Ok(MemberChange::LastOwner);
+                }
+                tx.insert(MEMBERSHIPS_TREE, &key, &Membership { roles: roles.clone(), ..previous.clone() })?;
+                Ok(MemberChange::Done(previous))
+            })
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let previous = match change {
+        MemberChange::Done(previous) => previous,
+        MemberChange::NotFound => return Err(actix_web::error::ErrorNotFound("Member not found")),
+        MemberChange::LastOwner => {
+            return Ok(HttpResponse::Conflict().json(ErrorResponse::new(
+                "last_owner",
+                "Cannot demote the last owner of an organization",
+            )));
+        }
+    };
+    audit::record(&db, AuditEvent::from_request(&req, "org.member_roles_updated", AuditOutcome::Success)
+        .actor(claims.sub.clone())
+        .target(user_id.clone())
+        .details(json!({"org_id": org_id, "from": previous.roles, "to": body.roles}))).await;
+    Ok(HttpResponse::Ok().json(Membership { roles: body.roles.clone(), ..previous }))
+}
+
+/// Remove a member from an organization (org owner/admin; only owners may
+/// remove an owner, and never the last one)
+#[delete("/orgs/{org_id}/members/{user_id}")]
+pub async fn remove_member(
+    path: web::Path<(String, String)>,
+    db: web::Data<Database>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    let (org_id, user_id) = path.into_inner();
+    if let Some(resp) = require_org_role(&db, &claims, &org_id, &[ORG_OWNER, ORG_ADMIN]) {
+        return Ok(resp);
+    }
+
+    let listed = listed_owners(&db, &org_id).map_err(actix_web::error::ErrorInternalServerError)?;
+    if listed.iter().any(|m| m.user_id == user_id) {
+        if let Some(resp) = require_owner(&db, &claims, &org_id) {
+            return Ok(resp);
+        }
+    }
+
+    let key = Membership::key(&org_id, &user_id);
+    let change = db
+        .run(move |db| {
+            db.transaction(&[MEMBERSHIPS_TREE], |tx| {
+                let Some(current) = tx.get::<Membership>(MEMBERSHIPS_TREE, &key)? else {
+                    return Ok(MemberChange::NotFound);
+                };
+                if current.has_any_role(&[ORG_OWNER]) && owners_left(tx, &listed)? <= 1 {
+                    return Ok(MemberChange::LastOwner);
+                }
+                tx.delete(MEMBERSHIPS_TREE, &key)?;
+                Ok(MemberChange::Done(current))
+            })
+        })
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    match change {
+        MemberChange::Done(_) => {}
+        MemberChange::NotFound => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse::new("member_not_found", "Member not found")));
+        }
+        MemberChange::LastOwner => {
+            return Ok(HttpResponse::Conflict().json(ErrorResponse::new(
+                "last_owner",
+                "Cannot remove the last owner of an organization",
+            )));
+        }
+    }
+    audit::record(&db, AuditEvent::from_request(&req, "org.member_removed", AuditOutcome::Success)
+        .actor(claims.sub.clone())
+        .target(user_id.clone())
+        .details(json!({"org_id": org_id}))).await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Switch the active organization: re-issues access and refresh tokens
+/// carrying the new org and remembers it for the next login
+#[post("/orgs/{org_id}/switch")]
+pub async fn switch_org(
+    path: web::Path<String>,
+    db: web::Data<Database>,
+    cfg: web::Data<AppConfig>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let claims = caller(&req)?;
+    // Switching issues fresh session cookies, which would outlive an impersonation token
+    if claims.impersonator.is_some() {
+        return Ok(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "impersonation_not_allowed",
+            "Cannot switch organizations while impersonating",
+        )));
+    }
+    let org_id = path.into_inner();
+    let membership = match get_membership(&db, &org_id, &claims.sub) {
+        Some(m) => m,
+        None => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse::new("org_not_found", "Organization not found")));
+        }
+    };
+
+    let mut user: UserRecord = db
+        .get("users", &claims.sub)
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User not found"))?;
+    user.active_org_id = Some(org_id.clone());
+    let stored = user.clone();
+    db.run(move |db| db.update("users", &stored.id, &stored))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let now = Utc::now();
+    let access_claims = Claims {
+        sub: user.id.clone(),
+        email: user.email.clone(),
+        roles: user.roles.clone(),
+        iss: cfg.security.token_iss.clone(),
+        aud: cfg.security.token_aud.clone(),
+        iat: now.timestamp(),
+        iat_ms: Some(now.timestamp_millis()),
+        exp: (now + Duration::seconds(cfg.security.token_ttl_seconds as i64)).timestamp(),
+        org_id: Some(org_id.clone()),
+        org_roles: membership.roles.clone(),
+        impersonator: None,
+    };
+    let access_token = make_token(&cfg, &access_claims)
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("token error"))?;
+
+    let refresh_claims = Claims {
+        exp: (now + Duration::days(7)).timestamp(),
+        ..access_claims
+    };
+    let refresh_token = make_token(&cfg, &refresh_claims)
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("token error"))?;
+
+    let access_ttl = cfg.security.token_ttl_seconds as i64;
+    let refresh_ttl = 7 * 24 * 60 * 60; // 7 days in seconds
+
+    let response = HttpResponse::Ok().json(json!({
+        "org_id": org_id,
+        "org_roles": membership.roles,
+    }));
+    Ok(set_auth_cookies(
+        response,
+        access_token,
+        refresh_token,
+        access_ttl,
+        refresh_ttl,
+        &cfg.cookies,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use tempfile::tempdir;
+
+    async fn with_claims(
+        req: actix_web::dev::ServiceRequest,
+        next: actix_web::middleware::Next<actix_web::body::BoxBody>,
+    ) -> Result<actix_web::dev::ServiceResponse<actix_web::body::BoxBody>, actix_web::Error> {
+        let sub = req.headers().get("x-test-user").and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+        req.extensions_mut().insert(Claims {
+            sub,
+            email: String::new(),
+            roles: vec!["user".into()],
+            iss: String::new(),
+            aud: String::new(),
+            iat: 0,
+            iat_ms: None,
+            exp: 0,
+            org_id: None,
+            org_roles: vec![],
+            impersonator: None,
+        });
+        next.call(req).await
+    }
+
+    #[actix_web::test]
+    async fn create_org_and_manage_members() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        let owner = UserRecord::new_user("owner@test.dev", String::new());
+        let other = UserRecord::new_user("other@test.dev", String::new());
+        db.insert("users", &owner.id, &owner).unwrap();
+        db.insert("users", &other.id, &other).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .wrap(actix_web::middleware::from_fn(with_claims))
+                .service(create_org)
+                .service(add_member)
+                .service(update_member)
+                .service(remove_member)
+                .service(list_members),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/orgs")
+            .insert_header(("x-test-user", owner.id.clone()))
+            .set_json(json!({"name": "Acme"}))
+            .to_request();
+        let org: Organization = test::call_and_read_body_json(&app, req).await;
+
+        // Non-members cannot add people
+        let req = test::TestRequest::post()
+            .uri(&format!("/orgs/{}/members", org.id))
+            .insert_header(("x-test-user", other.id.clone()))
+            .set_json(json!({"email": "other@test.dev", "roles": ["admin"]}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/orgs/{}/members", org.id))
+            .insert_header(("x-test-user", owner.id.clone()))
+            .set_json(json!({"email": "other@test.dev", "roles": ["admin"]}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+        // Org admins manage members but not ownership
+        let member_uri = |user: &str| format!("/orgs/{}/members/{}", org.id, user);
+        let req = test::TestRequest::put()
+            .uri(&member_uri(&other.id))
+            .insert_header(("x-test-user", other.id.clone()))
+            .set_json(json!({"roles": ["owner"]}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 403);
+        let req = test::TestRequest::delete()
+            .uri(&member_uri(&owner.id))
+            .insert_header(("x-test-user", other.id.clone()))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+        // The last owner cannot step down until there is another
+        let req = test::TestRequest::put()
+            .uri(&member_uri(&owner.id))
+            .insert_header(("x-test-user", owner.id.clone()))
+            .set_json(json!({"roles": ["admin"]}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 409);
+        let req = test::TestRequest::put()
+            .uri(&member_uri(&other.id))
+            .insert_header(("x-test-user", owner.id.clone()))
+            .set_json(json!({"roles": ["owner"]}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::put()
+            .uri(&member_uri(&owner.id))
+            .insert_header(("x-test-user", owner.id.clone()))
+            .set_json(json!({"roles": ["admin"]}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/orgs/{}/members", org.id))
+            .insert_header(("x-test-user", other.id.clone()))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp["members"].as_array().unwrap().len(), 2);
+
+        let mut stored: UserRecord = db.get("users", &other.id).unwrap().unwrap();
+        assert_eq!(resolve_active_org(&db, &stored).unwrap().org_id, org.id);
+        stored.active_org_id = Some("gone".into());
+        assert_eq!(resolve_active_org(&db, &stored).unwrap().org_id, org.id);
+    }
+}
@@ -1,45 +1,88 @@
 // User management endpoints (admin only)
-use actix_web::{get, put, web, HttpRequest, HttpResponse, Result};
+use actix_web::{delete, get, post, put, web, HttpMessage, HttpRequest, HttpResponse, Result};
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHasher};
+use chrono::{DateTime, Duration, Utc};
+use rand_core::OsRng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use crate::audit::{self, AuditEvent, AuditOutcome};
 use crate::config::AppConfig;
-use crate::db::Database;
-use crate::handlers::cookies::{extract_token, ACCESS_COOKIE_NAME};
-use crate::handlers::auth::validate_token;
-use crate::models::auth_types::UserRecord;
+use crate::db::{Database, UniqueViolation};
+use crate::handlers::api_keys::API_KEYS_TREE;
+use crate::history;
+use crate::handlers::auth::make_token;
+use crate::handlers::orgs::{memberships_of_user, owners_left, resolve_active_org, MEMBERSHIPS_TREE};
+use crate::models::api_key_types::ApiKeyRecord;
+use crate::models::auth_types::{Claims, PasswordReset, UserRecord};
+use crate::models::org_types::{Membership, ORG_OWNER};
+use crate::query::{ListParams, ListQuery};
 use crate::types::ErrorResponse;
 
+/// How long an admin-issued password reset token stays usable
+const PASSWORD_RESET_TTL_HOURS: i64 = 24;
+
+/// Impersonation tokens never outlive this, whatever `TOKEN_TTL_SECONDS` says
+const IMPERSONATION_MAX_TTL_SECS: i64 = 15 * 60;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserInfo {
     pub id: String,
     pub email: String,
     pub roles: Vec<String>,
     pub created_at: String,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub password_reset_required: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anonymized_at: Option<String>,
+}
+
+impl From<UserRecord> for UserInfo {
+    fn from(u: UserRecord) -> Self {
+        Self {
+            id: u.id,
+            email: u.email,
+            roles: u.roles,
+            created_at: u.created_at,
+            disabled: u.disabled,
+            password_reset_required: u.password_reset.is_some(),
+            anonymized_at: u.anonymized_at,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UsersListResponse {
     pub users: Vec<UserInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(default)]
+    pub has_more: bool,
 }
 
+/// Fields `GET /users` may filter on
+const USER_FILTER_FIELDS: &[&str] = &["email", "roles", "disabled", "created_at", "active_org_id", "anonymized_at"];
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateUserRolesRequest {
     pub roles: Vec<String>,
 }
 
-/// List all users (admin only)
+/// List users a page at a time (admin only).
+/// Supports `limit`, `cursor`, `reverse` and `filter=field:op:value,...`.
 #[get("/users")]
 pub async fn list_users(
     db: web::Data<Database>,
-    cfg: web::Data<AppConfig>,
+    params: web::Query<ListParams>,
     req: HttpRequest,
 ) -> Result<HttpResponse> {
-    // Verify admin role
-    let token = extract_token(&req, ACCESS_COOKIE_NAME)
+    // Verify admin role (claims are set by guard_api from the session token or API key)
+    let claims = req.extensions().get::<Claims>().cloned()
         .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing authentication token"))?;
 
-    let claims = validate_token(&cfg, &token)
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid token"))?;
-
     if !claims.roles.contains(&"admin".to_string()) {
         return Ok(HttpResponse::Forbidden().json(ErrorResponse::new(
             "insufficient_permissions",
@@ -47,19 +90,20 @@ pub async fn list_users(
         )));
     }
 
-    // List all users
-    let users: Vec<UserRecord> = db.list("users")
+    let query = match ListQuery::from_params(params.into_inner(), USER_FILTER_FIELDS) {
+        Ok(q) => q,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ErrorResponse::new("invalid_query", e.to_string()))),
+    };
+    let page = db
+        .run(move |db| db.query::<UserRecord>("users", &query))
+        .await
         .map_err(actix_web::error::ErrorInternalServerError)?;
-
-    let user_infos: Vec<UserInfo> = users.into_iter().map(|u| UserInfo {
-        id: u.id,
-        email: u.email,
-        roles: u.roles,
-        created_at: u.created_at,
-    }).collect();
+    let (users, next_cursor, has_more) = page.into_values();
 
     Ok(HttpResponse::Ok().json(UsersListResponse {
-        users: user_infos,
+        users: users.into_iter().map(UserInfo::from).collect(),
+        next_cursor,
+        has_more,
     }))
 }
 
@@ -68,16 +112,12 @@ pub async fn list_users(
 pub async fn get_user(
     path: web::Path<String>,
     db: web::Data<Database>,
-    cfg: web::Data<AppConfig>,
     req: HttpRequest,
 ) -> Result<HttpResponse> {
-    // Verify admin role
-    let token = extract_token(&req, ACCESS_COOKIE_NAME)
+    // Verify admin role (claims are set by guard_api from the session token or API key)
+    let claims = req.extensions().get::<Claims>().cloned()
         .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing authentication token"))?;
 
-    let claims = validate_token(&cfg, &token)
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid token"))?;
-
     if !claims.roles.contains(&"admin".to_string()) {
         return Ok(HttpResponse::Forbidden().json(ErrorResponse::new(
             "insufficient_permissions",
@@ -92,14 +132,7 @@ pub async fn get_user(
         .map_err(actix_web::error::ErrorInternalServerError)?
         .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
 
-    let user_info = UserInfo {
-        id: user.id,
-        email: user.email,
-        roles: user.roles,
-        created_at: user.created_at,
-    };
-
-    Ok(HttpResponse::Ok().json(user_info))
+    Ok(HttpResponse::Ok().json(UserInfo::from(user)))
 }
 
 /// Update user roles (admin only)
@@ -108,43 +141,570 @@ pub async fn update_user_roles(
     path: web::Path<String>,
     payload: web::Json<UpdateUserRolesRequest>,
     db: web::Data<Database>,
-    cfg: web::Data<AppConfig>,
     req: HttpRequest,
 ) -> Result<HttpResponse> {
-    // Verify admin role
-    let token = extract_token(&req, ACCESS_COOKIE_NAME)
+    // Verify admin role (claims are set by guard_api from the session token or API key)
+    let claims = req.extensions().get::<Claims>().cloned()
         .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing authentication token"))?;
 
-    let claims = validate_token(&cfg, &token)
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid token"))?;
+    let user_id = path.into_inner();
 
     if !claims.roles.contains(&"admin".to_string()) {
+        audit::record(&db, AuditEvent::from_request(&req, "user.roles_updated", AuditOutcome::Denied)
+            .actor(claims.sub.clone())
+            .target(user_id)).await;
         return Ok(HttpResponse::Forbidden().json(ErrorResponse::new(
             "insufficient_permissions",
             "Admin role required"
         )));
     }
 
-    let user_id = path.into_inner();
-
     // Get existing user
     let mut user: UserRecord = db.get("users", &user_id)
         .map_err(actix_web::error::ErrorInternalServerError)?
         .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
 
-    // Update roles
-    user.roles = payload.roles.clone();
+    // Update roles; sessions carry roles in their claims, so end them
+    let previous_roles = std::mem::replace(&mut user.roles, payload.roles.clone());
+    user.invalidate_sessions();
 
     // Save updated user
-    db.update("users", &user_id, &user)
+    let saved = user.clone();
+    let key = user_id.clone();
+    db.run(move |db| db.durable().update("users", &key, &saved))
+        .await
         .map_err(actix_web::error::ErrorInternalServerError)?;
+    audit::record(&db, AuditEvent::from_request(&req, "user.roles_updated", AuditOutcome::Success)
+        .actor(claims.sub.clone())
+        .target(user_id.clone())
+        .details(serde_json::json!({"from": previous_roles, "to": user.roles}))).await;
+
+    Ok(HttpResponse::Ok().json(UserInfo::from(user)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUserRequest {
+    pub email: String,
+    pub password: String,
+    #[serde(default)]
+    pub roles: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteUserQuery {
+    /// Scrub personal data but keep the record (and its id) instead of deleting it
+    #[serde(default)]
+    pub anonymize: bool,
+}
+
+/// Failure of a lifecycle operation, shared by the HTTP handlers and the CLI
+#[derive(Debug)]
+pub enum LifecycleError {
+    NotFound,
+    Invalid(String),
+    Conflict(String),
+    Internal(anyhow::Error),
+}
+
+impl fmt::Display for LifecycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LifecycleError::NotFound => write!(f, "user not found"),
+            LifecycleError::Invalid(msg) | LifecycleError::Conflict(msg) => write!(f, "{}", msg),
+            LifecycleError::Internal(e) => write!(f, "internal error: {}", e),
+        }
+    }
+}
+
+impl From<anyhow::Error> for LifecycleError {
+    fn from(e: anyhow::Error) -> Self {
+        LifecycleError::Internal(e)
+    }
+}
+
+impl LifecycleError {
+    fn into_response(self) -> HttpResponse {
+        match self {
+            LifecycleError::NotFound => {
+                HttpResponse::NotFound().json(ErrorResponse::new("user_not_found", "User not found"))
+            }
+            LifecycleError::Invalid(msg) => HttpResponse::BadRequest().json(ErrorResponse::new("invalid_request", msg)),
+            LifecycleError::Conflict(msg) => HttpResponse::Conflict().json(ErrorResponse::new("conflict", msg)),
+            LifecycleError::Internal(e) => {
+                log::error!("User lifecycle operation failed: {}", e);
+                HttpResponse::InternalServerError().json(ErrorResponse::new("internal_error", "Internal server error"))
+            }
+        }
+    }
+}
+
+fn load_user(db: &Database, user_id: &str) -> Result<UserRecord, LifecycleError> {
+    db.get("users", user_id)?.ok_or(LifecycleError::NotFound)
+}
+
+pub fn find_user_by_email(db: &Database, email: &str) -> Option<UserRecord> {
+    let email = email.trim().to_lowercase();
+    db.find_unique("users", "email", &email).ok().flatten()
+}
+
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| anyhow::anyhow!("password hashing failed: {}", e))
+}
+
+fn hash_reset_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Whether a session token is still honoured: its user exists, is not
+/// disabled and the token was issued after the last session invalidation.
+/// Impersonation tokens also die with the impersonating admin's account.
+pub fn session_is_current(db: &Database, claims: &Claims) -> bool {
+    let user: Option<UserRecord> = db.get("users", &claims.sub).ok().flatten();
+    if !user.is_some_and(|u| u.accepts_session(claims.issued_at_ms())) {
+        return false;
+    }
+    match &claims.impersonator {
+        Some(admin_id) => db
+            .get::<UserRecord>("users", admin_id)
+            .ok()
+            .flatten()
+            .is_none_or(|admin| !admin.disabled),
+        None => true,
+    }
+}
+
+/// Create a user with a validated email and password
+pub fn create_user(
+    db: &Database,
+    email: &str,
+    password: &str,
+    roles: Option<Vec<String>>,
+) -> Result<UserRecord, LifecycleError> {
+    use crate::validation as v;
+
+    let email = email.trim().to_lowercase();
+    v::validate_email_strict(&email).map_err(LifecycleError::Invalid)?;
+    v::password_strength(password).map_err(LifecycleError::Invalid)?;
+    if find_user_by_email(db, &email).is_some() {
+        return Err(LifecycleError::Conflict("Email already registered".into()));
+    }
+    let mut user = UserRecord::new_user(&email, hash_password(password)?);
+    if let Some(roles) = roles.filter(|r| !r.is_empty()) {
+        user.roles = roles;
+    }
+    db.insert("users", &user.id, &user).map_err(|e| match e.downcast_ref::<UniqueViolation>() {
+        Some(_) => LifecycleError::Conflict("Email already registered".into()),
+        None => LifecycleError::Internal(e),
+    })?;
+    Ok(user)
+}
+
+/// Disable or re-enable a user. Either way existing sessions end.
+pub fn set_disabled(db: &Database, user_id: &str, disabled: bool) -> Result<UserRecord, LifecycleError> {
+    let mut user = load_user(db, user_id)?;
+    if user.anonymized_at.is_some() {
+        return Err(LifecycleError::Conflict("User has been anonymized".into()));
+    }
+    user.disabled = disabled;
+    user.invalidate_sessions();
+    // A disable that's lost in a crash would silently re-admit the user
+    db.durable().update("users", &user.id, &user)?;
+    Ok(user)
+}
+
+/// Require a new password before the user can log in again. Returns the
+/// one-time reset token (`<user_id>.<secret>`); only its hash is stored.
+pub fn force_password_reset(db: &Database, user_id: &str) -> Result<(UserRecord, String), LifecycleError> {
+    let mut user = load_user(db, user_id)?;
+    if user.anonymized_at.is_some() {
+        return Err(LifecycleError::Conflict("User has been anonymized".into()));
+    }
+    let token = format!("{}.{}", user.id, nanoid::nanoid!(40));
+    user.password_reset = Some(PasswordReset {
+        token_hash: hash_reset_token(&token),
+        expires_at: (Utc::now() + Duration::hours(PASSWORD_RESET_TTL_HOURS)).to_rfc3339(),
+    });
+    user.invalidate_sessions();
+    db.durable().update("users", &user.id, &user)?;
+    Ok((user, token))
+}
+
+/// Redeem a reset token issued by [`force_password_reset`]
+pub fn complete_password_reset(db: &Database, token: &str, new_password: &str) -> Result<UserRecord, LifecycleError> {
+    let invalid = || LifecycleError::Invalid("Invalid or expired reset token".into());
+    let (user_id, _) = token.split_once('.').ok_or_else(invalid)?;
+    let mut user: UserRecord = db.get("users", user_id)?.ok_or_else(invalid)?;
+    let reset = user.password_reset.as_ref().ok_or_else(invalid)?;
+    let expired = DateTime::parse_from_rfc3339(&reset.expires_at)
+        .map(|t| t.with_timezone(&Utc) <= Utc::now())
+        .unwrap_or(true);
+    let expected = hash_reset_token(token);
+    let matches = expected.len() == reset.token_hash.len()
+        && expected.bytes().zip(reset.token_hash.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0;
+    if expired || !matches {
+        return Err(invalid());
+    }
+    crate::validation::password_strength(new_password).map_err(LifecycleError::Invalid)?;
+    user.password_hash = hash_password(new_password)?;
+    user.password_reset = None;
+    user.invalidate_sessions();
+    db.durable().update("users", &user.id, &user)?;
+    Ok(user)
+}
+
+/// Remove a user's memberships and API keys, then delete the record or
+/// scrub it (`anonymize`). Refuses while the user is an org's only owner.
+pub fn erase_user(db: &Database, user_id: &str, anonymize: bool) -> Result<(), LifecycleError> {
+    let mut user = load_user(db, user_id)?;
+    let memberships = memberships_of_user(db, &user.id);
+
+    // Owners of every org the user owns, re-read in the transaction below
+    let all_memberships: Vec<Membership> = db.list(MEMBERSHIPS_TREE)?;
+    let owned: Vec<(&Membership, Vec<Membership>)> = memberships
+        .iter()
+        .filter(|m| m.has_any_role(&[ORG_OWNER]))
+        .map(|m| {
+            let owners = all_memberships
+                .iter()
+                .filter(|o| o.org_id == m.org_id && o.has_any_role(&[ORG_OWNER]))
+                .cloned()
+                .collect();
+            (m, owners)
+        })
+        .collect();
+
+    let keys: Vec<ApiKeyRecord> = db.get_by_index(API_KEYS_TREE, "user_id", &user.id)?;
+    if anonymize {
+        user.email = format!("deleted-{}@anonymized.invalid", user.id);
+        user.password_hash = String::new();
+        user.roles = vec![];
+        user.active_org_id = None;
+        user.password_reset = None;
+        user.disabled = true;
+        user.anonymized_at = Some(Utc::now().to_rfc3339());
+        user.invalidate_sessions();
+    }
+
+    // All or nothing: a half-erased user would keep memberships or keys around.
+    // The last-owner check runs in the same transaction as the deletes.
+    let last_owned = db.durable().transaction(&[MEMBERSHIPS_TREE, API_KEYS_TREE, "users"], |tx| {
+        for (m, owners) in &owned {
+            if owners_left(tx, owners)? <= 1 {
+                return Ok(Some(m.org_id.clone()));
+            }
+        }
+        for m in &memberships {
+            tx.delete(MEMBERSHIPS_TREE, &Membership::key(&m.org_id, &user.id))?;
+        }
+        for key in &keys {
+            tx.delete(API_KEYS_TREE, &key.prefix)?;
+        }
+        if anonymize {
+            tx.insert("users", &user.id, &user)?;
+        } else {
+            tx.delete("users", &user.id)?;
+        }
+        Ok(None)
+    })?;
+    if let Some(org_id) = last_owned {
+        return Err(LifecycleError::Conflict(format!(
+            "User is the last owner of organization {}; transfer ownership first",
+            org_id
+        )));
+    }
+
+    // Earlier revisions would still hold the email, memberships and keys
+    history::forget(db, "users", &user.id)?;
+    for m in &memberships {
+        history::forget(db, MEMBERSHIPS_TREE, &Membership::key(&m.org_id, &user.id))?;
+    }
+    for key in &keys {
+        history::forget(db, API_KEYS_TREE, &key.prefix)?;
+    }
+    Ok(())
+}
 
-    let user_info = UserInfo {
-        id: user.id,
+/// Short-lived access token for `target_id`, marked with the impersonator.
+/// Admins and inactive accounts can't be impersonated.
+pub fn impersonation_claims(
+    db: &Database,
+    cfg: &AppConfig,
+    impersonator: &str,
+    target_id: &str,
+) -> Result<Claims, LifecycleError> {
+    let user = load_user(db, target_id)?;
+    if user.disabled || user.anonymized_at.is_some() {
+        return Err(LifecycleError::Conflict("Cannot impersonate a disabled user".into()));
+    }
+    if user.roles.iter().any(|r| r == "admin") {
+        return Err(LifecycleError::Conflict("Cannot impersonate an admin".into()));
+    }
+    let now = Utc::now();
+    let ttl = (cfg.security.token_ttl_seconds as i64).min(IMPERSONATION_MAX_TTL_SECS);
+    let active_org = resolve_active_org(db, &user);
+    Ok(Claims {
+        sub: user.id,
         email: user.email,
         roles: user.roles,
-        created_at: user.created_at,
+        iss: cfg.security.token_iss.clone(),
+        aud: cfg.security.token_aud.clone(),
+        iat: now.timestamp(),
+        iat_ms: Some(now.timestamp_millis()),
+        exp: (now + Duration::seconds(ttl)).timestamp(),
+        org_id: active_org.as_ref().map(|m| m.org_id.clone()),
+        org_roles: active_org.map(|m| m.roles).unwrap_or_default(),
+        impersonator: Some(impersonator.to_string()),
+    })
+}
+
+/// Admin caller from the request, or a ready-made 401/403 error
+pub(crate) fn require_admin(req: &HttpRequest) -> Result<Claims> {
+    let claims = req.extensions().get::<Claims>().cloned()
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing authentication token"))?;
+    if !claims.roles.contains(&"admin".to_string()) || claims.impersonator.is_some() {
+        let resp = HttpResponse::Forbidden().json(ErrorResponse::new(
+            "insufficient_permissions",
+            "Admin role required"
+        ));
+        return Err(actix_web::error::InternalError::from_response("forbidden", resp).into());
+    }
+    Ok(claims)
+}
+
+fn refuse_self(claims: &Claims, user_id: &str, what: &str) -> Option<HttpResponse> {
+    (claims.sub == user_id).then(|| {
+        HttpResponse::BadRequest().json(ErrorResponse::new("invalid_request", format!("You cannot {} your own account", what)))
+    })
+}
+
+/// Create a user (admin only)
+#[post("/users")]
+pub async fn create_user_handler(
+    payload: web::Json<CreateUserRequest>,
+    db: web::Data<Database>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let claims = require_admin(&req)?;
+    let payload = payload.into_inner();
+    let created = db
+        .run(move |db| Ok(create_user(&db, &payload.email, &payload.password, payload.roles)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    match created {
+        Ok(user) => {
+            audit::record(&db, AuditEvent::from_request(&req, "user.created", AuditOutcome::Success)
+                .actor(claims.sub)
+                .target(user.id.clone())
+                .details(serde_json::json!({"roles": user.roles}))).await;
+            Ok(HttpResponse::Created().json(UserInfo::from(user)))
+        }
+        Err(e) => Ok(e.into_response()),
+    }
+}
+
+/// Disable a user: blocks login, refresh and API keys (admin only)
+#[post("/users/{user_id}/disable")]
+pub async fn disable_user(
+    path: web::Path<String>,
+    db: web::Data<Database>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let claims = require_admin(&req)?;
+    let user_id = path.into_inner();
+    if let Some(resp) = refuse_self(&claims, &user_id, "disable") {
+        return Ok(resp);
+    }
+    let id = user_id.clone();
+    let outcome = db
+        .run(move |db| Ok(set_disabled(&db, &id, true)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    match outcome {
+        Ok(user) => {
+            audit::record(&db, AuditEvent::from_request(&req, "user.disabled", AuditOutcome::Success)
+                .actor(claims.sub)
+                .target(user_id)).await;
+            Ok(HttpResponse::Ok().json(UserInfo::from(user)))
+        }
+        Err(e) => Ok(e.into_response()),
+    }
+}
+
+/// Re-enable a disabled user (admin only)
+#[post("/users/{user_id}/enable")]
+pub async fn enable_user(
+    path: web::Path<String>,
+    db: web::Data<Database>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let claims = require_admin(&req)?;
+    let user_id = path.into_inner();
+    let id = user_id.clone();
+    let outcome = db
+        .run(move |db| Ok(set_disabled(&db, &id, false)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    match outcome {
+        Ok(user) => {
+            audit::record(&db, AuditEvent::from_request(&req, "user.enabled", AuditOutcome::Success)
+                .actor(claims.sub)
+                .target(user_id)).await;
+            Ok(HttpResponse::Ok().json(UserInfo::from(user)))
+        }
+        Err(e) => Ok(e.into_response()),
+    }
+}
+
+/// Force a password reset; returns the one-time token to hand to the user (admin only)
+#[post("/users/{user_id}/password-reset")]
+pub async fn force_password_reset_handler(
+    path: web::Path<String>,
+    db: web::Data<Database>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let claims = require_admin(&req)?;
+    let user_id = path.into_inner();
+    let id = user_id.clone();
+    let outcome = db
+        .run(move |db| Ok(force_password_reset(&db, &id)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    match outcome {
+        Ok((user, token)) => {
+            audit::record(&db, AuditEvent::from_request(&req, "user.password_reset_forced", AuditOutcome::Success)
+                .actor(claims.sub)
+                .target(user_id)).await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "reset_token": token,
+                "expires_at": user.password_reset.map(|r| r.expires_at),
+            })))
+        }
+        Err(e) => Ok(e.into_response()),
+    }
+}
+
+/// Delete a user, or anonymize with `?anonymize=true` (admin only)
+#[delete("/users/{user_id}")]
+pub async fn delete_user(
+    path: web::Path<String>,
+    query: web::Query<DeleteUserQuery>,
+    db: web::Data<Database>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let claims = require_admin(&req)?;
+    let user_id = path.into_inner();
+    if let Some(resp) = refuse_self(&claims, &user_id, "delete") {
+        return Ok(resp);
+    }
+    let (id, anonymize) = (user_id.clone(), query.anonymize);
+    let outcome = db
+        .run(move |db| Ok(erase_user(&db, &id, anonymize)))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    match outcome {
+        Ok(()) => {
+            let action = if query.anonymize { "user.anonymized" } else { "user.deleted" };
+            audit::record(&db, AuditEvent::from_request(&req, action, AuditOutcome::Success)
+                .actor(claims.sub)
+                .target(user_id)).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(e.into_response()),
+    }
+}
+
+/// Issue a short-lived access token acting as the user (admin only).
+/// The token carries an `impersonator` claim and is not refreshable.
+#[post("/users/{user_id}/impersonate")]
+pub async fn impersonate_user(
+    path: web::Path<String>,
+    db: web::Data<Database>,
+    cfg: web::Data<AppConfig>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let claims = require_admin(&req)?;
+    let user_id = path.into_inner();
+    let imp_claims = match impersonation_claims(&db, &cfg, &claims.sub, &user_id) {
+        Ok(c) => c,
+        Err(e) => {
+            audit::record(&db, AuditEvent::from_request(&req, "user.impersonated", AuditOutcome::Denied)
+                .actor(claims.sub)
+                .target(user_id)).await;
+            return Ok(e.into_response());
+        }
     };
+    let token = make_token(&cfg, &imp_claims)
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("token error"))?;
+    audit::record(&db, AuditEvent::from_request(&req, "user.impersonated", AuditOutcome::Success)
+        .actor(claims.sub.clone())
+        .target(user_id)
+        .details(serde_json::json!({"exp": imp_claims.exp}))).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "token": token,
+        "impersonator": claims.sub,
+        "expires_at": imp_claims.exp,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn lifecycle_invalidates_sessions() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        let user = create_user(&db, "life@test.dev", "Lifecycle-Pass-77!", None).unwrap();
+        let claims = Claims {
+            sub: user.id.clone(),
+            email: user.email.clone(),
+            roles: user.roles.clone(),
+            iss: String::new(),
+            aud: String::new(),
+            iat: Utc::now().timestamp(),
+            iat_ms: Some(Utc::now().timestamp_millis()),
+            exp: 0,
+            org_id: None,
+            org_roles: vec![],
+            impersonator: None,
+        };
+        assert!(session_is_current(&db, &claims));
+        assert!(matches!(create_user(&db, "LIFE@test.dev", "Lifecycle-Pass-77!", None), Err(LifecycleError::Conflict(_))));
 
-    Ok(HttpResponse::Ok().json(user_info))
+        set_disabled(&db, &user.id, true).unwrap();
+        assert!(!session_is_current(&db, &claims));
+        set_disabled(&db, &user.id, false).unwrap();
+        // Re-enabling doesn't resurrect sessions issued before the change
+        assert!(!session_is_current(&db, &claims));
+
+        let (_, token) = force_password_reset(&db, &user.id).unwrap();
+        assert!(complete_password_reset(&db, &format!("{}x", token), "Rotated-Pass-88!").is_err());
+        let user = complete_password_reset(&db, &token, "Rotated-Pass-88!").unwrap();
+        assert!(user.password_reset.is_none());
+        assert!(complete_password_reset(&db, &token, "Rotated-Pass-88!").is_err());
+        // A login straight after the reset, usually within the same second, is honoured
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let now = Utc::now();
+        let fresh = Claims { iat: now.timestamp(), iat_ms: Some(now.timestamp_millis()), ..claims.clone() };
+        assert!(session_is_current(&db, &fresh));
+        assert!(!session_is_current(&db, &claims));
+
+        // Cut-offs stored in whole seconds by earlier versions still apply
+        let legacy = UserRecord { sessions_valid_after_ms: None, sessions_valid_after: Some(now.timestamp()), ..user.clone() };
+        assert!(!legacy.accepts_session(now.timestamp() * 1000 + 999));
+        assert!(legacy.accepts_session((now.timestamp() + 1) * 1000));
+
+        erase_user(&db, &user.id, true).unwrap();
+        let scrubbed: UserRecord = db.get("users", &user.id).unwrap().unwrap();
+        assert!(scrubbed.email.ends_with("@anonymized.invalid"));
+        assert!(find_user_by_email(&db, "life@test.dev").is_none());
+        erase_user(&db, &user.id, false).unwrap();
+        assert!(db.get::<UserRecord>("users", &user.id).unwrap().is_none());
+    }
 }
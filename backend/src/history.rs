@@ -0,0 +1,276 @@
+// Per-record revision history
+//
+// Writes made through `Database` to collections with history enabled append
+// a `Revision` to the `__history` tree in the same transaction: who made the
+// write (`Database::as_actor`), when, the changed fields, and the record as
+// it stood afterwards so any revision can be reverted to. Entries are keyed
+// `{collection} 0x00 {raw key} 0x00 {sequence}` so one record's history is a
+// single prefix scan, oldest first.
+//
+// Each record keeps its newest `HISTORY_MAX_REVISIONS` revisions; older ones
+// are trimmed after every write. Purging a record from the trash, or erasing
+// a user, drops its history entirely.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sled::transaction::{ConflictableTransactionError, TransactionalTree};
+
+use crate::db::{Database, TxResult};
+
+pub const HISTORY_TREE: &str = "__history";
+
+pub const DEFAULT_LIMIT: usize = 50;
+pub const MAX_LIMIT: usize = 500;
+
+/// Top-level fields that change on every write and would only add noise to diffs
+const BOOKKEEPING: &[&str] = &["version", "updated_at"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionOp {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// One changed field; `path` is a JSON pointer into the record (`/data/total`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub path: String,
+    /// Absent when the field was added
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<Value>,
+    /// Absent when the field was removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+    /// Increasing across all records; use it to revert or page with `before`
+    pub revision: u64,
+    /// ms since epoch
+    pub at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    pub op: RevisionOp,
+    /// Record version after the write, for versioned records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    pub changes: Vec<Change>,
+    /// The record after the write; `None` for deletes
+    pub state: Option<Value>,
+}
+
+fn escape(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+fn diff_into(path: &str, from: Option<&Value>, to: Option<&Value>, changes: &mut Vec<Change>) {
+    match (from, to) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            for (key, old) in a {
+                diff_into(&format!("{}/{}", path, escape(key)), Some(old), b.get(key), changes);
+            }
+            for (key, new) in b.iter().filter(|(k, _)| !a.contains_key(*k)) {
+                diff_into(&format!("{}/{}", path, escape(key)), None, Some(new), changes);
+            }
+        }
+        (a, b) if a == b => {}
+        (a, b) => changes.push(Change { path: path.to_string(), from: a.cloned(), to: b.cloned() }),
+    }
+}
+
+/// Field-level changes from `from` to `to`. Objects are compared key by
+/// key; arrays and scalars are replaced whole.
+pub fn diff(from: Option<&Value>, to: Option<&Value>) -> Vec<Change> {
+    let strip = |v: Option<&Value>| -> Option<Value> {
+        v.map(|v| match v {
+            Value::Object(map) => Value::Object(
+                map.iter().filter(|(k, _)| !BOOKKEEPING.contains(&k.as_str())).map(|(k, v)| (k.clone(), v.clone())).collect::<Map<_, _>>(),
+            ),
+            other => other.clone(),
+        })
+    };
+    let (from, to) = (strip(from), strip(to));
+    let mut changes = Vec::new();
+    diff_into("", from.as_ref(), to.as_ref(), &mut changes);
+    changes
+}
+
+fn prefix(collection: &str, key: &str) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(collection.len() + key.len() + 2);
+    prefix.extend_from_slice(collection.as_bytes());
+    prefix.push(0);
+    prefix.extend_from_slice(key.as_bytes());
+    prefix.push(0);
+    prefix
+}
+
+/// The revision for one write of `key` (raw, tenant scoped), if it changed anything.
+/// Returns the history-tree key and the serialized revision, still to be
+/// stored by the caller (which may seal it).
+pub(crate) fn build(
+    history: &TransactionalTree,
+    collection: &str,
+    key: &str,
+    previous: Option<&[u8]>,
+    current: Option<&[u8]>,
+    author: Option<&str>,
+) -> TxResult<Option<(Vec<u8>, Vec<u8>)>> {
+    if previous.is_none() && current.is_none() {
+        return Ok(None);
+    }
+    let parse = |b: Option<&[u8]>| b.and_then(|b| serde_json::from_slice::<Value>(b).ok());
+    let (before, after) = (parse(previous), parse(current));
+    let op = match (&before, &after) {
+        (None, _) => RevisionOp::Created,
+        (_, None) => RevisionOp::Deleted,
+        _ => RevisionOp::Updated,
+    };
+    let changes = diff(before.as_ref(), after.as_ref());
+    if op == RevisionOp::Updated && changes.is_empty() {
+        return Ok(None);
+    }
+    let revision = history.generate_id()? + 1;
+    let entry = Revision {
+        revision,
+        at: chrono::Utc::now().timestamp_millis().max(0) as u64,
+        author: author.map(str::to_string),
+        op,
+        version: after.as_ref().and_then(|v| v.get("version")).and_then(Value::as_u64),
+        changes,
+        state: after,
+    };
+    let bytes = serde_json::to_vec(&entry).map_err(|e| ConflictableTransactionError::Abort(e.into()))?;
+    let mut history_key = prefix(collection, key);
+    history_key.extend_from_slice(&revision.to_be_bytes());
+    Ok(Some((history_key, bytes)))
+}
+
+/// Revisions of one record (key relative to `db`'s tenant), newest first,
+/// optionally only those before revision `before`
+pub fn list(db: &Database, collection: &str, key: &str, before: Option<u64>, limit: usize) -> Result<(Vec<Revision>, bool)> {
+    let tree = db.db.open_tree(HISTORY_TREE)?;
+    let prefix = prefix(collection, &db.scoped_key(key));
+    let mut upper = prefix.clone();
+    upper.extend_from_slice(&before.unwrap_or(u64::MAX).to_be_bytes());
+
+    let mut items = Vec::new();
+    for entry in tree.range(prefix.clone()..upper).rev() {
+        let (history_key, stored) = entry?;
+        if items.len() == limit {
+            return Ok((items, true));
+        }
+        let plain = db.decode(collection, &history_key, &stored)?;
+        items.push(serde_json::from_slice(&plain)?);
+    }
+    Ok((items, false))
+}
+
+/// Drop every revision of one record (key relative to `db`'s tenant), e.g.
+/// once it is purged for good. Returns how many went.
+pub fn forget(db: &Database, collection: &str, key: &str) -> Result<usize> {
+    let tree = db.db.open_tree(HISTORY_TREE)?;
+    let mut removed = 0;
+    for history_key in tree.scan_prefix(prefix(collection, &db.scoped_key(key))).keys() {
+        tree.remove(history_key?)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Keep only the newest `keep` revisions of one record (`raw_key` tenant
+/// scoped). Returns how many were removed.
+pub(crate) fn trim(db: &Database, collection: &str, raw_key: &str, keep: usize) -> Result<usize> {
+    let tree = db.db.open_tree(HISTORY_TREE)?;
+    let mut removed = 0;
+    for history_key in tree.scan_prefix(prefix(collection, raw_key)).keys().rev().skip(keep) {
+        tree.remove(history_key?)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// One revision of a record
+pub fn get(db: &Database, collection: &str, key: &str, revision: u64) -> Result<Option<Revision>> {
+    let tree = db.db.open_tree(HISTORY_TREE)?;
+    let mut history_key = prefix(collection, &db.scoped_key(key));
+    history_key.extend_from_slice(&revision.to_be_bytes());
+    match tree.get(&history_key)? {
+        Some(stored) => Ok(Some(serde_json::from_slice(&db.decode(collection, &history_key, &stored)?)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::Repository;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn diff_reports_nested_field_changes() {
+        let before = json!({"data": {"total": 100, "lines": [1], "note": "x"}, "version": 1});
+        let after = json!({"data": {"total": 120, "lines": [1, 2], "paid": true}, "version": 2});
+        let changes = diff(Some(&before), Some(&after));
+        let paths: Vec<_> = changes.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["/data/lines", "/data/note", "/data/total", "/data/paid"]);
+        let total = changes.iter().find(|c| c.path == "/data/total").unwrap();
+        assert_eq!((total.from.clone(), total.to.clone()), (Some(json!(100)), Some(json!(120))));
+        assert!(changes.iter().find(|c| c.path == "/data/note").unwrap().to.is_none());
+    }
+
+    #[test]
+    fn writes_record_author_and_state() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap())
+            .unwrap()
+            .with_history(["invoices".to_string()]);
+        let acme = db.for_tenant("acme");
+        let repo: Repository<Value> = Repository::new(&acme.as_actor("alice"), "invoices");
+        repo.create("inv-1", json!({"amount": 100})).unwrap();
+        let repo: Repository<Value> = Repository::new(&acme.as_actor("bob"), "invoices");
+        repo.replace("inv-1", json!({"amount": 90})).unwrap();
+        db.for_tenant("acme").insert("notes", "n1", &json!({"text": "no history"})).unwrap();
+
+        let (revisions, more) = list(&acme, "invoices", "inv-1", None, 10).unwrap();
+        assert!(!more);
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].author.as_deref(), Some("bob"));
+        assert_eq!(revisions[0].op, RevisionOp::Updated);
+        assert_eq!(revisions[0].changes, vec![Change { path: "/data/amount".into(), from: Some(json!(100)), to: Some(json!(90)) }]);
+        assert_eq!(revisions[1].state.as_ref().unwrap()["data"]["amount"], 100);
+
+        let (older, _) = list(&acme, "invoices", "inv-1", Some(revisions[0].revision), 10).unwrap();
+        assert_eq!(older.len(), 1);
+        assert!(get(&acme, "invoices", "inv-1", revisions[1].revision).unwrap().is_some());
+        // Other tenants and untracked collections see nothing
+        assert!(list(&db.for_tenant("other"), "invoices", "inv-1", None, 10).unwrap().0.is_empty());
+        assert!(list(&acme, "notes", "n1", None, 10).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn history_is_capped_and_dropped_on_purge() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap())
+            .unwrap()
+            .with_history(["quotes".to_string()])
+            .with_history_limit(2);
+        let acme = db.for_tenant("acme");
+        let repo: Repository<Value> = Repository::new(&acme, "quotes");
+        repo.create("q1", json!({"total": 1})).unwrap();
+        repo.replace("q1", json!({"total": 2})).unwrap();
+        repo.replace("q1", json!({"total": 3})).unwrap();
+
+        let (revisions, _) = list(&acme, "quotes", "q1", None, 10).unwrap();
+        let totals: Vec<_> = revisions.iter().map(|r| r.state.as_ref().unwrap()["data"]["total"].clone()).collect();
+        assert_eq!(totals, [json!(3), json!(2)]);
+
+        assert!(repo.soft_delete("q1", None, "alice").unwrap());
+        assert!(repo.purge("q1", u64::MAX).unwrap());
+        assert!(list(&acme, "quotes", "q1", None, 10).unwrap().0.is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+// Signing key ring for the asymmetric token modes (PASETO v4.public and EdDSA JWT)
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Utc};
+use pasetors::{
+    keys::{AsymmetricKeyPair, AsymmetricPublicKey, AsymmetricSecretKey, Generate},
+    paserk::{FormatAsPaserk, Id},
+    version4::V4,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io::Write, path::Path, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    /// Used to sign new tokens. At most one key is active at a time.
+    Active,
+    /// No longer signs, but still verifies tokens until `retire_at`.
+    VerifyOnly,
+    /// Kept for the record only; tokens signed with it are rejected.
+    Retired,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SigningKey {
+    /// PASERK id of the public key (`k4.pid.…`), used as `kid`
+    pub kid: String,
+    pub secret_key_hex: String,
+    pub public_key_hex: String,
+    pub status: KeyStatus,
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retire_at: Option<String>,
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("kid", &self.kid)
+            .field("secret_key_hex", &"<redacted>")
+            .field("public_key_hex", &self.public_key_hex)
+            .field("status", &self.status)
+            .field("created_at", &self.created_at)
+            .field("retire_at", &self.retire_at)
+            .finish()
+    }
+}
+
+impl SigningKey {
+    /// Generate a fresh Ed25519 key pair tagged with its PASERK id
+    pub fn generate() -> Result<Self> {
+        let pair = AsymmetricKeyPair::<V4>::generate()
+            .map_err(|e| anyhow!("key generation failed: {:?}", e))?;
+        Ok(Self {
+            kid: kid_for(&pair.public)?,
+            secret_key_hex: hex::encode(pair.secret.as_bytes()),
+            public_key_hex: hex::encode(pair.public.as_bytes()),
+            status: KeyStatus::Active,
+            created_at: Utc::now().to_rfc3339(),
+            retire_at: None,
+        })
+    }
+
+    pub fn secret_key(&self) -> Option<AsymmetricSecretKey<V4>> {
+        let bytes = hex::decode(self.secret_key_hex.trim()).ok()?;
+        AsymmetricSecretKey::<V4>::from(&bytes).ok()
+    }
+
+    pub fn public_key(&self) -> Option<AsymmetricPublicKey<V4>> {
+        let bytes = hex::decode(self.public_key_hex.trim()).ok()?;
+        AsymmetricPublicKey::<V4>::from(&bytes).ok()
+    }
+
+    /// Whether tokens signed with this key are still accepted at `now`
+    pub fn can_verify_at(&self, now: DateTime<Utc>) -> bool {
+        match self.status {
+            KeyStatus::Active => true,
+            KeyStatus::Retired => false,
+            KeyStatus::VerifyOnly => match &self.retire_at {
+                Some(ts) => DateTime::parse_from_rfc3339(ts)
+                    .map(|t| t.with_timezone(&Utc) > now)
+                    .unwrap_or(false),
+                None => true,
+            },
+        }
+    }
+
+    /// Public half as a JSON Web Key (RFC 8037, OKP / Ed25519)
+    pub fn to_jwk(&self) -> Option<serde_json::Value> {
+        let public = hex::decode(self.public_key_hex.trim()).ok()?;
+        Some(serde_json::json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "use": "sig",
+            "alg": "EdDSA",
+            "kid": self.kid,
+            "x": URL_SAFE_NO_PAD.encode(public),
+        }))
+    }
+}
+
+fn kid_for(public: &AsymmetricPublicKey<V4>) -> Result<String> {
+    let mut kid = String::new();
+    Id::from(public)
+        .fmt(&mut kid)
+        .map_err(|_| anyhow!("failed to format key id"))?;
+    Ok(kid)
+}
+
+/// Write `value` as pretty JSON readable by the owner only (temp file, then rename)
+pub(crate) fn write_private_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let tmp = path.with_extension("json.tmp");
+    // Left over from an interrupted write; create_new below must not follow it
+    match fs::remove_file(&tmp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // Created owner-only, so the key material is never readable by others
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&tmp)?;
+    file.write_all(&serde_json::to_vec_pretty(value)?)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyRing {
+    pub keys: Vec<SigningKey>,
+}
+
+impl KeyRing {
+    /// Load the key ring from a JSON file. A missing file yields an empty ring.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read key ring {}", path.display()))?;
+        let ring: KeyRing = serde_json::from_str(&content)
+            .with_context(|| format!("invalid key ring {}", path.display()))?;
+        if ring.keys.iter().filter(|k| k.status == KeyStatus::Active).count() > 1 {
+            return Err(anyhow!("key ring {} has more than one active key", path.display()));
+        }
+        Ok(ring)
+    }
+
+    /// Persist the key ring atomically (write to a temp file, then rename)
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_private_json(path.as_ref(), self)
+    }
+
+    pub fn active(&self) -> Option<&SigningKey> {
+        self.keys.iter().find(|k| k.status == KeyStatus::Active)
+    }
+
+    /// Look up a key by `kid`, only if it may still verify tokens
+    pub fn verifying_key(&self, kid: &str) -> Option<&SigningKey> {
+        let now = Utc::now();
+        self.keys.iter().find(|k| k.kid == kid && k.can_verify_at(now))
+    }
+
+    /// All keys that still verify tokens (active + overlapping)
+    pub fn verifying_keys(&self) -> impl Iterator<Item = &SigningKey> {
+        let now = Utc::now();
+        self.keys.iter().filter(move |k| k.can_verify_at(now))
+    }
+
+    /// Mark overlapping keys whose grace period has passed as retired
+    pub fn retire_expired(&mut self) -> usize {
+        let now = Utc::now();
+        let mut retired = 0;
+        for key in self.keys.iter_mut() {
+            if key.status == KeyStatus::VerifyOnly && !key.can_verify_at(now) {
+                key.status = KeyStatus::Retired;
+                retired += 1;
+            }
+        }
+        retired
+    }
+
+    /// Generate a new active key. The previous active key keeps verifying
+    /// tokens for `overlap` so sessions signed with it survive the rotation.
+    pub fn rotate(&mut self, overlap: Duration) -> Result<&SigningKey> {
+        self.retire_expired();
+        let overlap = chrono::Duration::from_std(overlap)?;
+        let retire_at = (Utc::now() + overlap).to_rfc3339();
+        for key in self.keys.iter_mut() {
+            if key.status == KeyStatus::Active {
+                key.status = KeyStatus::VerifyOnly;
+                key.retire_at = Some(retire_at.clone());
+            }
+        }
+        self.keys.push(SigningKey::generate()?);
+        Ok(self.keys.last().expect("key just pushed"))
+    }
+
+    /// Immediately stop accepting tokens signed with `kid`
+    pub fn retire(&mut self, kid: &str) -> Result<()> {
+        let key = self
+            .keys
+            .iter_mut()
+            .find(|k| k.kid == kid)
+            .ok_or_else(|| anyhow!("unknown key id: {}", kid))?;
+        if key.status == KeyStatus::Active {
+            return Err(anyhow!("refusing to retire the active key; rotate first"));
+        }
+        key.status = KeyStatus::Retired;
+        key.retire_at = Some(Utc::now().to_rfc3339());
+        Ok(())
+    }
+
+    /// JWKS document with every key that still verifies tokens
+    pub fn jwks(&self) -> serde_json::Value {
+        let keys: Vec<serde_json::Value> = self.verifying_keys().filter_map(|k| k.to_jwk()).collect();
+        serde_json::json!({ "keys": keys })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn rotate_keeps_previous_key_for_overlap() {
+        let mut ring = KeyRing::default();
+        let first = ring.rotate(Duration::from_secs(3600)).unwrap().kid.clone();
+        let second = ring.rotate(Duration::from_secs(3600)).unwrap().kid.clone();
+
+        assert_ne!(first, second);
+        assert!(first.starts_with("k4.pid."));
+        assert_eq!(ring.active().unwrap().kid, second);
+        assert!(ring.verifying_key(&first).is_some());
+        assert_eq!(ring.jwks()["keys"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn zero_overlap_retires_previous_key() {
+        let mut ring = KeyRing::default();
+        let first = ring.rotate(Duration::from_secs(0)).unwrap().kid.clone();
+        ring.rotate(Duration::from_secs(0)).unwrap();
+
+        assert!(ring.verifying_key(&first).is_none());
+        assert_eq!(ring.retire_expired(), 1);
+        assert!(ring.retire(&ring.active().unwrap().kid.clone()).is_err());
+    }
+
+    #[test]
+    fn save_and_load_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("keys").join("ring.json");
+        let mut ring = KeyRing::default();
+        ring.rotate(Duration::from_secs(60)).unwrap();
+        ring.save(&path).unwrap();
+
+        let loaded = KeyRing::load(&path).unwrap();
+        assert_eq!(loaded.keys.len(), 1);
+        assert!(loaded.active().unwrap().secret_key().is_some());
+        assert!(KeyRing::load(dir.path().join("missing.json")).unwrap().keys.is_empty());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        // A stale temp file from an interrupted save doesn't block the next one
+        fs::write(path.with_extension("json.tmp"), "partial").unwrap();
+        ring.save(&path).unwrap();
+        fs::write(&path, "{ not json").unwrap();
+        assert!(KeyRing::load(&path).is_err());
+    }
+}
@@ -1,47 +1,251 @@
-use std::io;
+use chrono::{DateTime, Utc};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use tracing_subscriber::{
     fmt::{self, time::ChronoUtc},
+    layer::Layered,
     prelude::*,
-    EnvFilter,
+    EnvFilter, Layer, Registry,
 };
 
-/// Initialize the logging system with colors and build information
-pub fn init_logging(verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
-    let filter = if verbose {
-        "debug,quoteflow_backend=trace,actix_web=debug"
-    } else {
-        "info,quoteflow_backend=info,actix_web=info"
-    };
+use crate::config::{LogFormat, LogRotation, LoggingConfig};
 
-    let env_filter = EnvFilter::try_from_default_env()
-        .or_else(|_| EnvFilter::try_new(filter))
-        .unwrap();
+type BoxedLayer = Box<dyn Layer<Layered<EnvFilter, Registry>> + Send + Sync>;
 
-    let use_ansi = atty::is(atty::Stream::Stdout);
-    let fmt_layer = fmt::layer()
+fn fmt_layer<W>(format: LogFormat, ansi: bool, writer: W) -> BoxedLayer
+where
+    W: for<'w> fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer()
         .with_target(true)
         .with_thread_ids(true)
         .with_thread_names(true)
         .with_timer(ChronoUtc::rfc_3339())
-        .with_ansi(use_ansi)
         .with_file(true)
         .with_line_number(true)
-        .with_writer(io::stdout);
+        .with_writer(writer);
+    match format {
+        LogFormat::Text => layer.with_ansi(ansi).boxed(),
+        LogFormat::Json => layer.json().with_current_span(true).with_span_list(false).boxed(),
+    }
+}
+
+/// Initialize the logging system with colors and build information.
+/// `--verbose` overrides the configured level.
+pub fn init_logging(cfg: &LoggingConfig, verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let (env_filter, bad_level) = if verbose {
+        (EnvFilter::new("debug,quoteflow_backend=trace,actix_web=debug"), None)
+    } else {
+        match EnvFilter::try_new(&cfg.level) {
+            Ok(filter) => (filter, None),
+            Err(e) => (EnvFilter::new("info"), Some(e)),
+        }
+    };
+
+    let use_ansi = atty::is(atty::Stream::Stdout);
+    let mut layers = vec![fmt_layer(cfg.format, use_ansi, io::stdout)];
+    if cfg.file_enabled {
+        let path = cfg.file_path.clone().unwrap_or_else(|| "backend.log".to_string());
+        let file = RollingFile::open(&path, cfg.file_max_size, cfg.file_rotation, cfg.file_retention)
+            .map_err(|e| format!("cannot open log file {}: {}", path, e))?;
+        layers.push(fmt_layer(cfg.format, false, Mutex::new(file)));
+    }
+
+    let otlp_error = match &cfg.otlp_endpoint {
+        Some(endpoint) => match otlp_layer(endpoint) {
+            Ok(layer) => {
+                layers.push(layer);
+                None
+            }
+            Err(e) => Some(e),
+        },
+        None => None,
+    };
 
     // Forward log crate records to tracing
     let _ = tracing_log::LogTracer::init();
 
     let _ = tracing_subscriber::registry()
         .with(env_filter)
-        .with(fmt_layer)
+        .with(layers)
         .try_init();
 
     // First log line: PID
     tracing::info!("PID={} starting up", std::process::id());
+    if let Some(e) = bad_level {
+        tracing::warn!("Invalid log level '{}' ({}), using info", cfg.level, e);
+    }
+    match (&cfg.otlp_endpoint, otlp_error) {
+        (Some(endpoint), None) => tracing::info!("Exporting spans over OTLP to {}", endpoint),
+        (Some(endpoint), Some(e)) => tracing::warn!("Not exporting spans to {}: {}", endpoint, e),
+        _ => {}
+    }
+    if cfg.file_enabled {
+        tracing::info!(
+            "Logging to file {} (rotation: {:?}, max size: {:?}, keeping {})",
+            cfg.file_path.as_deref().unwrap_or("backend.log"),
+            cfg.file_rotation,
+            cfg.file_max_size,
+            cfg.file_retention
+        );
+    }
 
     Ok(())
 }
 
+#[cfg(feature = "otlp")]
+static TRACER_PROVIDER: once_cell::sync::OnceCell<opentelemetry_sdk::trace::SdkTracerProvider> =
+    once_cell::sync::OnceCell::new();
+
+/// Span exporter to an OTLP/HTTP collector (e.g. `http://localhost:4318/v1/traces`)
+#[cfg(feature = "otlp")]
+fn otlp_layer(endpoint: &str) -> Result<BoxedLayer, String> {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let service = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string());
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(opentelemetry_sdk::Resource::builder().with_service_name(service).build())
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    let _ = TRACER_PROVIDER.set(provider);
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer).boxed())
+}
+
+#[cfg(not(feature = "otlp"))]
+fn otlp_layer(_endpoint: &str) -> Result<BoxedLayer, String> {
+    Err("built without the `otlp` feature".into())
+}
+
+/// Flush spans still waiting for export; call before exiting
+pub fn shutdown_tracing() {
+    #[cfg(feature = "otlp")]
+    if let Some(provider) = TRACER_PROVIDER.get() {
+        if let Err(e) = provider.shutdown() {
+            eprintln!("Failed to flush OTLP spans: {}", e);
+        }
+    }
+}
+
+/// Log file that rolls over when it would outgrow `max_size` or when the
+/// hour/day changes. The active file keeps its configured name; rolled
+/// files get a `.YYYYMMDD-HHMMSS` suffix and only the newest `keep` stay.
+pub struct RollingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    period: String,
+    max_size: Option<u64>,
+    rotation: LogRotation,
+    keep: usize,
+}
+
+fn period_key(rotation: LogRotation, at: DateTime<Utc>) -> String {
+    match rotation {
+        LogRotation::Never => String::new(),
+        LogRotation::Hourly => at.format("%Y%m%d%H").to_string(),
+        LogRotation::Daily => at.format("%Y%m%d").to_string(),
+    }
+}
+
+impl RollingFile {
+    pub fn open<P: AsRef<Path>>(path: P, max_size: Option<u64>, rotation: LogRotation, keep: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        // A file left over from an earlier period rolls on the first write
+        let modified: DateTime<Utc> = metadata.modified().map(Into::into).unwrap_or_else(|_| Utc::now());
+        Ok(Self {
+            period: period_key(rotation, modified),
+            size: metadata.len(),
+            path,
+            file,
+            max_size,
+            rotation,
+            keep,
+        })
+    }
+
+    fn should_roll(&self, incoming: usize) -> bool {
+        let too_big = self.max_size.is_some_and(|max| self.size > 0 && self.size + incoming as u64 > max);
+        too_big || period_key(self.rotation, Utc::now()) != self.period
+    }
+
+    fn roll(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let stamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut target = self.rolled_name(&stamp);
+        let mut n = 1;
+        while target.exists() {
+            target = self.rolled_name(&format!("{}.{}", stamp, n));
+            n += 1;
+        }
+        fs::rename(&self.path, &target)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        self.period = period_key(self.rotation, Utc::now());
+        self.prune()
+    }
+
+    fn rolled_name(&self, suffix: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(suffix);
+        self.path.with_file_name(name)
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let dir = match self.path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let prefix = format!("{}.", self.path.file_name().unwrap_or_default().to_string_lossy());
+        let mut rolled: Vec<PathBuf> = fs::read_dir(&dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|e| e.path())
+            .collect();
+        // Suffixes are timestamps, so name order is age order
+        rolled.sort();
+        let excess = rolled.len().saturating_sub(self.keep);
+        for old in rolled.iter().take(excess) {
+            let _ = fs::remove_file(old);
+        }
+        Ok(())
+    }
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_roll(buf.len()) {
+            // Keep logging into the current file if rolling fails (e.g. permissions)
+            if let Err(e) = self.roll() {
+                eprintln!("Failed to rotate log file {}: {}", self.path.display(), e);
+                self.size = 0;
+                self.period = period_key(self.rotation, Utc::now());
+            }
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
 /// Print build and version information with colors
 pub fn print_build_info() {
     let name = env!("CARGO_PKG_NAME");
@@ -173,14 +377,38 @@ pub fn log_success(message: &str) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config_source::{ConfigLayers, ConfigSource};
 
     #[test]
     fn test_logging_initialization() {
         // Test that logging initialization doesn't panic
-        let result = init_logging(false);
+        let src = ConfigSource::load(&ConfigLayers::default());
+        let result = init_logging(&LoggingConfig::from_source(&src), false);
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn rolling_file_rotates_by_size_and_prunes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("app.log");
+        let mut file = RollingFile::open(&path, Some(32), LogRotation::Never, 2).unwrap();
+        for i in 0..5 {
+            file.write_all(format!("line {:02} padding-padding\n", i).as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        // The active file plus the two newest rolled files
+        assert_eq!(names.len(), 3, "{:?}", names);
+        assert_eq!(names[0], "app.log");
+        assert!(names[1..].iter().all(|n| n.starts_with("app.log.")));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line 04 padding-padding\n");
+    }
+
     #[test]
     fn test_build_info_display() {
         // This test just ensures the function doesn't panic
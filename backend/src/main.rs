@@ -1,6 +1,4 @@
 // Clean minimal main.rs - Auth-focused backend
-use actix_cors::Cors;
-use actix_files::Files;
 use actix_web::{
     middleware::Logger,
     web::{self, JsonConfig},
@@ -10,24 +8,38 @@ use actix_web::{
 use std::sync::Arc;
 
 // Module declarations
+mod audit;
 mod backup;
 mod cli;
 mod config;
+mod config_source;
 mod db;
 mod db_manager;
+mod durability;
+mod encryption;
+mod events;
 mod handlers;
+mod history;
+mod keyring;
 mod logging;
+mod metrics;
 mod middleware;
 mod models;
+mod query;
+mod reload;
 mod replicate;
+mod repository;
 mod routes;
+mod schema;
 mod time;
+mod trash;
 mod types;
 mod validation;
 
 // Imports from our modules
 use backup::BackupManager;
 use cli::Cli;
+use config_source::{ConfigLayers, ConfigSource};
 // use config::AppConfig;
 use db::Database;
 use replicate::Replicator;
@@ -57,12 +69,49 @@ async fn main() -> std::io::Result<()> {
     // Parse CLI arguments
     let cli = Cli::parse_args();
 
-    // Initialize logging
-    logging::init_logging(cli.verbose).expect("Failed to initialize logging");
+    // Configuration layers: defaults, config.toml, environment (and .env), command line
+    config::snapshot_process_env();
+    let layers = ConfigLayers::from_cli(&cli);
+    let source = ConfigSource::load(&layers);
+
+    // Config commands print to stdout, so they run before logging is set up
+    if let Some(cli::Commands::Config { action }) = &cli.command {
+        let result = config::try_load_config(&layers);
+        if let cli::ConfigCommands::Print { redacted } = action {
+            print!("{}", source.render(*redacted));
+        }
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        if let cli::ConfigCommands::Check = action {
+            let file = source.toml_file().map(|p| format!("{} + ", p.display())).unwrap_or_default();
+            println!("✓ Configuration is valid ({}environment)", file);
+        }
+        return Ok(());
+    }
+
+    // Initialize logging first so its settings apply from the first line
+    logging::init_logging(&config::LoggingConfig::from_source(&source), cli.verbose).expect("Failed to initialize logging");
     logging::print_build_info();
 
     // Load configuration
-    let cfg = config::load_config_from_file(&cli.config);
+    let mut cfg = config::load_config(&layers);
+
+    // Encryption at rest needs a data key; bootstrap one on first start like the token ring
+    let enc = &mut cfg.encryption;
+    if !enc.collections.is_empty() && enc.keyring.active.is_none() && enc.key_hex.trim().is_empty() {
+        log::warn!("No data key in {}, generating one", enc.keyring_path);
+        enc.keyring.rotate();
+        enc.keyring.save(&enc.keyring_path).expect("Failed to save data key ring");
+    }
+    let cipher = match encryption::Cipher::from_config(&cfg.encryption) {
+        Ok(cipher) => cipher.map(Arc::new),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     // Handle CLI commands (admin user creation, backup, etc.)
     if let Some(command) = &cli.command {
@@ -71,15 +120,17 @@ async fn main() -> std::io::Result<()> {
                 use cli::UserCommands;
                 use argon2::{Argon2, password_hash::SaltString, PasswordHasher};
                 use rand_core::OsRng;
+                use handlers::users;
+
+                // Audit actor for CLI-initiated changes
+                let cli_actor = format!("cli:{}", std::env::var("USER").unwrap_or_else(|_| "unknown".into()));
 
                 match action {
                     UserCommands::AddAdmin(args) => {
-                        let db = Database::new(&cfg.sled_path).expect("Failed to open database");
+                        let db = Database::open(&cfg.sled_path, cipher.clone()).expect("Failed to open database");
 
                         let email = args.email.trim().to_lowercase();
-                        let users: Vec<models::auth_types::UserRecord> = db.list("users").unwrap_or_default();
-
-                        if users.iter().any(|u| u.email == email) {
+                        if users::find_user_by_email(&db, &email).is_some() {
                             eprintln!("Error: User with email '{}' already exists", email);
                             std::process::exit(2);
                         }
@@ -92,12 +143,92 @@ async fn main() -> std::io::Result<()> {
 
                         let admin = models::auth_types::UserRecord::new_admin(&email, hash);
                         db.insert("users", &admin.id, &admin).expect("Failed to insert admin user");
+                        audit::record_blocking(&db, audit::AuditEvent::new("user.admin_created", audit::AuditOutcome::Success)
+                            .actor(cli_actor.clone())
+                            .target(admin.id.clone())
+                            .details(serde_json::json!({"roles": admin.roles})));
 
                         println!("✓ Admin user created: {}", email);
                         println!("  ID: {}", admin.id);
                         println!("  Roles: {:?}", admin.roles);
                         return Ok(());
                     }
+                    UserCommands::Create(args) => {
+                        let db = Database::open(&cfg.sled_path, cipher.clone()).expect("Failed to open database");
+                        let roles = (!args.roles.is_empty()).then(|| args.roles.clone());
+                        let user = match users::create_user(&db, &args.email, &args.password, roles) {
+                            Ok(u) => u,
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                std::process::exit(2);
+                            }
+                        };
+                        audit::record_blocking(&db, audit::AuditEvent::new("user.created", audit::AuditOutcome::Success)
+                            .actor(cli_actor.clone())
+                            .target(user.id.clone())
+                            .details(serde_json::json!({"roles": user.roles})));
+                        println!("✓ User created: {}", user.email);
+                        println!("  ID: {}", user.id);
+                        println!("  Roles: {:?}", user.roles);
+                        return Ok(());
+                    }
+                    UserCommands::Disable(args)
+                    | UserCommands::Enable(args)
+                    | UserCommands::ResetPassword(args)
+                    | UserCommands::Impersonate(args) => {
+                        let db = Database::open(&cfg.sled_path, cipher.clone()).expect("Failed to open database");
+                        let user = users::find_user_by_email(&db, &args.email).unwrap_or_else(|| {
+                            eprintln!("Error: No user with email '{}'", args.email);
+                            std::process::exit(2);
+                        });
+                        let outcome = match action {
+                            UserCommands::Disable(_) => users::set_disabled(&db, &user.id, true)
+                                .map(|_| ("user.disabled", format!("✓ User disabled: {}", user.email))),
+                            UserCommands::Enable(_) => users::set_disabled(&db, &user.id, false)
+                                .map(|_| ("user.enabled", format!("✓ User enabled: {}", user.email))),
+                            UserCommands::ResetPassword(_) => users::force_password_reset(&db, &user.id)
+                                .map(|(_, token)| ("user.password_reset_forced", format!(
+                                    "✓ Password reset required for {}\n  Reset token (POST /api/auth/password-reset): {}",
+                                    user.email, token
+                                ))),
+                            _ => users::impersonation_claims(&db, &cfg, &cli_actor, &user.id).and_then(|claims| {
+                                let token = handlers::auth::make_token(&cfg, &claims).ok_or_else(|| {
+                                    users::LifecycleError::Internal(anyhow::anyhow!("token signing failed"))
+                                })?;
+                                Ok(("user.impersonated", format!("Impersonation token for {} (expires {}):\n{}", user.email, claims.exp, token)))
+                            }),
+                        };
+                        match outcome {
+                            Ok((event, message)) => {
+                                audit::record_blocking(&db, audit::AuditEvent::new(event, audit::AuditOutcome::Success)
+                                    .actor(cli_actor.clone())
+                                    .target(user.id.clone()));
+                                println!("{}", message);
+                                return Ok(());
+                            }
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                std::process::exit(2);
+                            }
+                        }
+                    }
+                    UserCommands::Delete(args) => {
+                        let db = Database::open(&cfg.sled_path, cipher.clone()).expect("Failed to open database");
+                        let user = users::find_user_by_email(&db, &args.email).unwrap_or_else(|| {
+                            eprintln!("Error: No user with email '{}'", args.email);
+                            std::process::exit(2);
+                        });
+                        if let Err(e) = users::erase_user(&db, &user.id, args.anonymize) {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(2);
+                        }
+                        let event = if args.anonymize { "user.anonymized" } else { "user.deleted" };
+                        audit::record_blocking(&db, audit::AuditEvent::new(event, audit::AuditOutcome::Success)
+                            .actor(cli_actor.clone())
+                            .target(user.id.clone()));
+                        println!("✓ User {}: {}", if args.anonymize { "anonymized" } else { "deleted" }, user.email);
+                        return Ok(());
+                    }
                 }
             }
             cli::Commands::Db { action } => {
@@ -110,7 +241,7 @@ async fn main() -> std::io::Result<()> {
                     }
                     DbCommands::Test => {
                         println!("Testing database connection...");
-                        let _db = Database::new(&cfg.sled_path).expect("Failed to open database");
+                        let _db = Database::open(&cfg.sled_path, cipher.clone()).expect("Failed to open database");
                         println!("✓ Database connection successful");
                         return Ok(());
                     }
@@ -120,6 +251,120 @@ async fn main() -> std::io::Result<()> {
                     }
                 }
             }
+            cli::Commands::Keys { action } => {
+                use cli::KeyCommands;
+
+                let path = cfg.security.token_keyring_path.clone();
+                let mut ring = cfg.security.keyring.clone();
+                match action {
+                    KeyCommands::List => {
+                        let now = chrono::Utc::now();
+                        for key in &ring.keys {
+                            println!(
+                                "{}  {:?}  created={}  retire_at={}  verifies={}",
+                                key.kid,
+                                key.status,
+                                key.created_at,
+                                key.retire_at.as_deref().unwrap_or("-"),
+                                key.can_verify_at(now)
+                            );
+                        }
+                        if ring.keys.is_empty() {
+                            println!("Key ring {} is empty", path);
+                        }
+                        return Ok(());
+                    }
+                    KeyCommands::Rotate { overlap } => {
+                        let overlap = config::parse_duration(overlap).unwrap_or_else(|e| {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(2);
+                        });
+                        let kid = ring.rotate(overlap).expect("Failed to generate signing key").kid.clone();
+                        ring.save(&path).expect("Failed to save key ring");
+                        println!("✓ New active signing key: {}", kid);
+                        println!("  Previous key verifies for another {:?}", overlap);
+                        return Ok(());
+                    }
+                    KeyCommands::Retire { kid } => {
+                        if let Err(e) = ring.retire(kid) {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(2);
+                        }
+                        ring.save(&path).expect("Failed to save key ring");
+                        println!("✓ Key retired: {}", kid);
+                        return Ok(());
+                    }
+                }
+            }
+            cli::Commands::Encryption { action } => {
+                use cli::EncryptionCommands;
+
+                let path = cfg.encryption.keyring_path.clone();
+                let mut ring = encryption::effective_ring(&cfg.encryption).unwrap_or_else(|e| {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                });
+                match action {
+                    EncryptionCommands::Status => {
+                        for key in &ring.keys {
+                            let active = if ring.active.as_ref() == Some(&key.id) { "  (active)" } else { "" };
+                            println!("{}  created={}{}", key.id, key.created_at, active);
+                        }
+                        if ring.keys.is_empty() {
+                            println!("No data keys in {}", path);
+                        }
+                        println!("Encrypted collections: {}", cfg.encryption.collections.join(", "));
+                        let db = Database::open(&cfg.sled_path, cipher.clone()).expect("Failed to open database");
+                        for (collection, per_key) in encryption::census(&db).expect("Failed to scan database") {
+                            let counts: Vec<String> = per_key.iter().map(|(k, n)| format!("{}={}", k, n)).collect();
+                            println!("  {}: {}", collection, counts.join(" "));
+                        }
+                        return Ok(());
+                    }
+                    EncryptionCommands::Rotate => {
+                        // Save the new key before anything is sealed with it
+                        let key_id = ring.rotate().id.clone();
+                        ring.save(&path).expect("Failed to save data key ring");
+                        let collections = encryption::Collections::parse(&cfg.encryption.collections);
+                        let cipher = encryption::Cipher::new(&ring, collections).expect("Invalid data key ring");
+                        let db = Database::open(&cfg.sled_path, Some(Arc::new(cipher))).expect("Failed to open database");
+                        let rewritten = encryption::reencrypt_all(&db).unwrap_or_else(|e| {
+                            eprintln!("Error: {} (rerun `encryption rotate` to finish)", e);
+                            std::process::exit(2);
+                        });
+                        let actor = format!("cli:{}", std::env::var("USER").unwrap_or_else(|_| "unknown".into()));
+                        audit::record_blocking(&db, audit::AuditEvent::new("encryption.key_rotated", audit::AuditOutcome::Success)
+                            .actor(actor)
+                            .details(serde_json::json!({"key_id": key_id, "reencrypted": rewritten})));
+                        println!("✓ New active data key: {}", key_id);
+                        println!("  Re-encrypted {} record(s); older keys stay in {} for existing backups", rewritten, path);
+                        return Ok(());
+                    }
+                }
+            }
+            cli::Commands::Cors { action: cli::CorsCommands::Test { origin, method, path, headers } } => {
+                let headers: Vec<String> = headers.iter().map(|h| h.trim().to_lowercase()).collect();
+                println!("{} rule(s) from {}", cfg.cors_rules.len(), config::CORS_RULES_FILE);
+                for (label, preflight) in [("Preflight", true), ("Request", false)] {
+                    let decision = middleware::cors::evaluate(&cfg.cors_rules, origin, method, path, &headers, preflight);
+                    if preflight {
+                        match decision.rule {
+                            Some(rule) => println!("Matched line {}: {}", rule.line, rule),
+                            None => println!("No rule matches {} on {}", origin, path),
+                        }
+                    }
+                    match &decision.refused {
+                        Some(reason) => println!("{}: refused ({})", label, reason),
+                        None => {
+                            println!("{}: allowed", label);
+                            for (name, value) in &decision.headers {
+                                println!("  {}: {}", name, value);
+                            }
+                        }
+                    }
+                }
+                return Ok(());
+            }
             _ => {
                 eprintln!("Unknown command");
                 std::process::exit(1);
@@ -127,8 +372,39 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
+    if cfg.security.access_token_admin_bypass {
+        logging::log_warning(
+            "ACCESS_TOKEN_ADMIN_BYPASS is enabled: the global ACCESS_TOKEN grants full admin access. \
+             Use personal API keys (/api/keys) instead and disable this in production.",
+        );
+    }
+
+    // Asymmetric token modes need an active signing key; bootstrap one on
+    // first start, but never write over an existing ring
+    if cfg.security.token_mode.is_asymmetric() && cfg.security.keyring.active().is_none() {
+        if std::path::Path::new(&cfg.security.token_keyring_path).exists() {
+            log::error!(
+                "Key ring {} has no active signing key; run `keys rotate` to add one",
+                cfg.security.token_keyring_path
+            );
+            std::process::exit(1);
+        }
+        log::warn!(
+            "No active signing key in {}, generating one",
+            cfg.security.token_keyring_path
+        );
+        cfg.security
+            .keyring
+            .rotate(std::time::Duration::from_secs(0))
+            .expect("Failed to generate signing key");
+        cfg.security
+            .keyring
+            .save(&cfg.security.token_keyring_path)
+            .expect("Failed to save key ring");
+    }
+
     // Initialize database
-    let database = Database::new(&cfg.sled_path).expect("Failed to open database");
+    let database = Database::open(&cfg.sled_path, cipher.clone()).expect("Failed to open database");
 
     // Setup PostgreSQL replication (optional)
     let replicator = if cfg.database_sync_on && !cfg.pg_conns.is_empty() {
@@ -149,7 +425,15 @@ async fn main() -> std::io::Result<()> {
         None
     };
 
-    let database = database.with_replicator(replicator);
+    let database = database.with_replicator(replicator).with_durability(cfg.durability.clone());
+    log::info!("Durability: {} (overrides: {:?})", cfg.durability.default, cfg.durability.overrides);
+    if let Some(cipher) = &cipher {
+        log::info!(
+            "Encryption at rest: collections [{}], active data key {}",
+            cfg.encryption.collections.join(", "),
+            cipher.active_key_id()
+        );
+    }
 
     // Setup backup manager
     let backup_manager = Arc::new(BackupManager::new(
@@ -160,6 +444,7 @@ async fn main() -> std::io::Result<()> {
 
     // Start periodic backup task
     if let Some(interval) = cfg.backup_interval {
+        backup_manager.load_last_success().await;
         let backup_mgr = backup_manager.clone();
         let retention = cfg.backup_retention;
         tokio::spawn(async move {
@@ -168,33 +453,119 @@ async fn main() -> std::io::Result<()> {
         log::info!("Periodic backups enabled: interval={:?}, retention={}", interval, retention);
     }
 
+    // Keep the change feed bounded
+    {
+        let db = database.clone();
+        let keep = cfg.event_log_max_events;
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(60));
+            loop {
+                ticker.tick().await;
+                match db.run(move |db| events::prune(&db, keep)).await {
+                    Ok(0) => {}
+                    Ok(n) => log::debug!("Pruned {} change-feed events", n),
+                    Err(e) => log::warn!("Failed to prune change feed: {}", e),
+                }
+            }
+        });
+    }
+
     // Prepare server address
     let bind_address = format!("{}:{}", cfg.server.host, cfg.server.port);
     log::info!("Starting server on {}", bind_address);
     log::info!("Database path: {}", cfg.sled_path);
     log::info!("Backup path: {}", cfg.backup_dir);
 
+    // Entity schemas for the generic CRUD router
+    let mut schemas = schema::SchemaRegistry::load_dir(std::path::Path::new(&cfg.entities_dir)).unwrap_or_else(|e| {
+        log::error!("Failed to load entity schemas from {}: {}", cfg.entities_dir, e);
+        std::process::exit(1);
+    });
+    schemas.enable_soft_delete(&cfg.soft_delete_collections);
+    log::info!("Entity collections from {}: {:?}", cfg.entities_dir, schemas.collections());
+    // Entity writes keep revision history
+    let database = database
+        .with_history(schemas.collections().into_iter().map(str::to_string))
+        .with_history_limit(cfg.history_max_revisions);
+
+    // Empty the trash of records past their retention
+    if !schemas.soft_delete_collections().is_empty() {
+        log::info!(
+            "Soft delete: {:?}, trash kept for {:?}",
+            schemas.soft_delete_collections(),
+            cfg.trash_retention
+        );
+        let db = database.clone();
+        let registry = schemas.clone();
+        let (interval, retention) = (cfg.trash_purge_interval, cfg.trash_retention);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let registry = registry.clone();
+                match db.run(move |db| handlers::entities::purge_expired_trash(&db, &registry, retention)).await {
+                    Ok(0) => {}
+                    Ok(n) => log::info!("Purged {} expired record(s) from the trash", n),
+                    Err(e) => log::warn!("Failed to purge the trash: {}", e),
+                }
+            }
+        });
+    }
+
     // Wrap shared state
     let db_data = web::Data::new(database);
-    let cfg_data = web::Data::new(cfg.clone());
+    let schema_data = web::Data::new(schemas);
+    let backup_data = web::Data::from(backup_manager.clone());
+
+    // Prometheus metrics
+    let metrics_enabled = cfg.security.metrics_enabled;
+    if metrics_enabled {
+        if cfg.security.metrics_token.is_empty() {
+            log::warn!("METRICS_ENABLED is set but METRICS_TOKEN is empty; /metrics stays closed");
+        } else {
+            log::info!("Prometheus metrics enabled on /metrics");
+        }
+    }
 
-    // Clone CORS rules for use in the HttpServer closure
-    let cors_rules = cfg.cors_rules.clone();
+    // Live configuration: requests and CORS read the current one, reloads swap it
+    let live_config = Arc::new(reload::LiveConfig::new(cfg.clone(), layers));
+    let live_data = web::Data::from(live_config.clone());
+    #[cfg(unix)]
+    {
+        let live = live_config.clone();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(s) => s,
+                Err(e) => {
+                    log::warn!("Cannot listen for SIGHUP, config reload by signal disabled: {}", e);
+                    return;
+                }
+            };
+            while hangup.recv().await.is_some() {
+                let live = live.clone();
+                let _ = tokio::task::spawn_blocking(move || live.reload_logged("SIGHUP")).await;
+            }
+        });
+    }
+    // Dropping the watcher stops it, so it lives until the server exits
+    let _config_watcher = if cfg.config_watch {
+        match reload::watch(live_config.clone()) {
+            Ok(watcher) => {
+                log::info!("Watching {:?} for config changes", live_config.watched_files());
+                Some(watcher)
+            }
+            Err(e) => {
+                log::warn!("Cannot watch config files, reload with SIGHUP or the admin endpoint instead: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
 
     // Start HTTP server
-    HttpServer::new(move || {
-        // Configure CORS
-        let rules_clone = cors_rules.clone();
-        let cors = Cors::default()
-            .allowed_origin_fn(move |origin, _req| {
-                let origin_str = origin.to_str().unwrap_or("");
-                config::is_origin_allowed(&rules_clone, origin_str)
-            })
-            .allow_any_method()
-            .allow_any_header()
-            .supports_credentials()
-            .max_age(3600);
-
+    let server = HttpServer::new(move || {
         App::new()
             // Configure JSON limits and error handling
             .app_data(JsonConfig::default()
@@ -203,19 +574,32 @@ async fn main() -> std::io::Result<()> {
 
             // Shared application state
             .app_data(db_data.clone())
-            .app_data(cfg_data.clone())
+            .app_data(live_data.clone())
+            .app_data(schema_data.clone())
+            .app_data(backup_data.clone())
 
             // Middleware
-            .wrap(middleware::security::SecurityHeaders)
-            .wrap(cors)
-            .wrap(Logger::default())
+            .wrap(middleware::live_config::LiveConfigData(live_config.clone()))
+            .wrap(middleware::security::SecurityHeaders(live_config.clone()))
+            .wrap(middleware::cors::CorsMiddleware(live_config.clone()))
+            .wrap(middleware::request_id::RequestIdMiddleware)
+            .wrap(Logger::new(r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T request_id=%{x-request-id}o"#))
+            .wrap(actix_web::middleware::Condition::new(metrics_enabled, middleware::metrics::RequestMetrics))
 
             // Health check endpoints
             .service(routes::health::healthz)
             .service(routes::health::health)
+            .service(routes::health::livez)
+            .service(routes::health::readyz)
+
+            // Prometheus scrape endpoint (separate scrape token)
+            .service(routes::metrics::scrape)
 
-            // WASM file serving
-            .service(routes::static_files::serve_wasm)
+            // Public signing keys (JWKS) for asymmetric token modes
+            .service(routes::jwks::jwks)
+
+            // Content-Security-Policy violation reports from browsers
+            .service(handlers::csp::report)
 
             // API routes
             .service(
@@ -230,6 +614,7 @@ async fn main() -> std::io::Result<()> {
                             .service(handlers::auth::logout)
                             .service(handlers::auth::refresh)
                             .service(handlers::auth::reconfirm)
+                            .service(handlers::auth::reset_password)
                             .service(handlers::auth::me)
                     )
                     // Protected routes (require authentication)
@@ -240,16 +625,63 @@ async fn main() -> std::io::Result<()> {
                             .service(handlers::users::list_users)
                             .service(handlers::users::get_user)
                             .service(handlers::users::update_user_roles)
-                            // Add your business routes here
+                            .service(handlers::users::create_user_handler)
+                            .service(handlers::users::disable_user)
+                            .service(handlers::users::enable_user)
+                            .service(handlers::users::force_password_reset_handler)
+                            .service(handlers::users::delete_user)
+                            .service(handlers::users::impersonate_user)
+                            // Personal API keys
+                            .service(handlers::api_keys::create_api_key)
+                            .service(handlers::api_keys::list_api_keys)
+                            .service(handlers::api_keys::revoke_api_key)
+                            // Security audit log (admin only)
+                            .service(handlers::audit::query_audit)
+                            .service(handlers::audit::export_audit)
+                            .service(handlers::audit::verify_audit)
+                            // Change feed
+                            .service(handlers::events::list_events)
+                            .service(handlers::events::stream_events)
+                            // Organizations (tenants) and membership
+                            .service(handlers::orgs::create_org)
+                            .service(handlers::orgs::list_my_orgs)
+                            .service(handlers::orgs::list_members)
+                            .service(handlers::orgs::add_member)
+                            .service(handlers::orgs::update_member)
+                            .service(handlers::orgs::remove_member)
+                            .service(handlers::orgs::switch_org)
+                            // Server administration
+                            .service(handlers::admin::reload_config)
+                            .service(handlers::csp::list_reports)
+                            // Add your business routes here; take `handlers::orgs::TenantDb`
+                            // instead of `web::Data<Database>` for org-scoped data
+
+                            // Schema-defined entities; last so explicit routes win
+                            .service(handlers::entities::list_entities)
+                            // Before `/{collection}/{id}`, which would take `trash` as an id
+                            .service(handlers::entities::list_trash)
+                            .service(handlers::entities::restore_entity)
+                            .service(handlers::entities::purge_entity)
+                            .service(handlers::entities::entity_history)
+                            .service(handlers::entities::revert_entity)
+                            .service(handlers::entities::get_entity)
+                            .service(handlers::entities::create_entity)
+                            .service(handlers::entities::upsert_entity)
+                            .service(handlers::entities::delete_entity)
                     )
             )
 
-            // Static files and SPA fallback (must be last)
-            .service(Files::new("/", "./static")
-                .index_file("index.html")
-                .default_handler(web::to(routes::static_files::spa_fallback)))
+            // Static files and SPA fallback (must be last); responses without
+            // a precompressed variant are compressed on the fly
+            .service(web::resource("/{tail:.*}")
+                .wrap(actix_web::middleware::Compress::default())
+                .route(web::get().to(routes::static_files::serve_static))
+                .route(web::head().to(routes::static_files::serve_static)))
     })
     .bind(&bind_address)?
     .run()
-    .await
+    .await;
+
+    logging::shutdown_tracing();
+    server
 }
@@ -0,0 +1,265 @@
+// Prometheus metrics (text exposition format 0.0.4)
+//
+// A handful of fixed metric families, updated in place by the code they
+// describe and rendered on scrape by `/metrics`. Label values are kept
+// bounded: routes are recorded by their pattern (`/api/users/{id}`), never
+// by the raw path.
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::db::Database;
+
+/// Latency buckets in seconds, from fast cache hits to slow exports
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+/// Backups copy the whole sled directory and can take minutes
+const BACKUP_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0];
+
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, f64>>,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self { name, help, labels, values: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Add one; `labels` are the values for the family's label names, in order
+    pub fn inc(&self, labels: &[&str]) {
+        let key = labels.iter().map(|l| l.to_string()).collect();
+        if let Ok(mut values) = self.values.lock() {
+            *values.entry(key).or_default() += 1.0;
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "counter");
+        if let Ok(values) = self.values.lock() {
+            for (labels, value) in values.iter() {
+                let _ = writeln!(out, "{}{} {}", self.name, label_set(self.labels, labels, None), value);
+            }
+        }
+    }
+}
+
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: Mutex<Option<f64>>,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Self { name, help, value: Mutex::new(None) }
+    }
+
+    pub fn set(&self, value: f64) {
+        if let Ok(mut v) = self.value.lock() {
+            *v = Some(value);
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        // Unset gauges (nothing happened yet) are left out rather than reported as 0
+        if let Some(value) = self.value.lock().ok().and_then(|v| *v) {
+            header(out, self.name, self.help, "gauge");
+            let _ = writeln!(out, "{} {}", self.name, value);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    bounds: &'static [f64],
+    values: Mutex<BTreeMap<Vec<String>, Buckets>>,
+}
+
+impl Histogram {
+    pub const fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+        bounds: &'static [f64],
+    ) -> Self {
+        Self { name, help, labels, bounds, values: Mutex::new(BTreeMap::new()) }
+    }
+
+    pub fn observe(&self, labels: &[&str], value: f64) {
+        let key = labels.iter().map(|l| l.to_string()).collect();
+        if let Ok(mut values) = self.values.lock() {
+            let entry = values.entry(key).or_default();
+            if entry.counts.is_empty() {
+                entry.counts = vec![0; self.bounds.len()];
+            }
+            for (count, bound) in entry.counts.iter_mut().zip(self.bounds) {
+                if value <= *bound {
+                    *count += 1;
+                }
+            }
+            entry.sum += value;
+            entry.count += 1;
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "histogram");
+        if let Ok(values) = self.values.lock() {
+            for (labels, b) in values.iter() {
+                for (bound, count) in self.bounds.iter().zip(&b.counts) {
+                    let le = bound.to_string();
+                    let _ = writeln!(out, "{}_bucket{} {}", self.name, label_set(self.labels, labels, Some(&le)), count);
+                }
+                let _ = writeln!(out, "{}_bucket{} {}", self.name, label_set(self.labels, labels, Some("+Inf")), b.count);
+                let _ = writeln!(out, "{}_sum{} {}", self.name, label_set(self.labels, labels, None), b.sum);
+                let _ = writeln!(out, "{}_count{} {}", self.name, label_set(self.labels, labels, None), b.count);
+            }
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn label_set(names: &[&str], values: &[String], le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = names.iter().zip(values).map(|(n, v)| format!("{}=\"{}\"", n, escape(v))).collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+pub static HTTP_REQUESTS: Counter =
+    Counter::new("http_requests_total", "HTTP requests handled", &["method", "route", "status"]);
+pub static HTTP_DURATION: Histogram = Histogram::new(
+    "http_request_duration_seconds",
+    "Time to produce a response",
+    &["method", "route", "status"],
+    LATENCY_BUCKETS,
+);
+pub static AUTH_EVENTS: Counter =
+    Counter::new("auth_events_total", "Login, logout, refresh and registration attempts", &["event", "outcome"]);
+pub static AUTH_CHECKS: Counter =
+    Counter::new("auth_checks_total", "Credential checks on protected API routes", &["credential", "outcome"]);
+pub static BACKUP_DURATION: Histogram =
+    Histogram::new("backup_duration_seconds", "Time per backup attempt", &["outcome"], BACKUP_BUCKETS);
+pub static BACKUP_FAILURES: Counter =
+    Counter::new("backup_failures_total", "Backups that failed after all retries", &[]);
+pub static BACKUP_LAST_SUCCESS: Gauge =
+    Gauge::new("backup_last_success_timestamp_seconds", "Unix time of the last successful backup");
+pub static REPLICATION_LAG: Histogram = Histogram::new(
+    "replication_lag_seconds",
+    "Time from commit until the write was applied in Postgres",
+    &["op"],
+    LATENCY_BUCKETS,
+);
+pub static REPLICATION_ERRORS: Counter =
+    Counter::new("replication_errors_total", "Writes that failed to replicate to Postgres", &["table", "op"]);
+pub static CSP_VIOLATIONS: Counter =
+    Counter::new("csp_violations_total", "Content-Security-Policy violation reports received", &["directive"]);
+
+/// Counting keys walks every tree, so scrapes reuse the counts for this long
+const TREE_KEYS_TTL: Duration = Duration::from_secs(300);
+
+/// Keys per tree, with when they were counted
+type TreeKeyCache = Option<(Instant, Vec<(String, usize)>)>;
+
+static TREE_KEYS: Mutex<TreeKeyCache> = Mutex::new(None);
+
+/// Key counts per tree, recounted at most once per `TREE_KEYS_TTL`
+fn tree_keys(db: &Database) -> Result<Vec<(String, usize)>> {
+    let mut cached = TREE_KEYS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((counted, keys)) = cached.as_ref() {
+        if counted.elapsed() < TREE_KEYS_TTL {
+            return Ok(keys.clone());
+        }
+    }
+    let mut names: Vec<String> =
+        db.db.tree_names().into_iter().map(|n| String::from_utf8_lossy(&n).into_owned()).collect();
+    names.sort();
+    let mut keys = Vec::with_capacity(names.len());
+    for name in names {
+        let len = db.db.open_tree(&name)?.len();
+        keys.push((name, len));
+    }
+    *cached = Some((Instant::now(), keys.clone()));
+    Ok(keys)
+}
+
+/// Sled sizes, read at scrape time; key counts may be a few minutes old
+fn render_sled(db: &Database, out: &mut String) -> Result<()> {
+    header(out, "sled_size_on_disk_bytes", "Size of the sled database on disk", "gauge");
+    let _ = writeln!(out, "sled_size_on_disk_bytes {}", db.db.size_on_disk()?);
+    header(out, "sled_tree_keys", "Keys per sled tree (recounted every few minutes)", "gauge");
+    for (name, len) in tree_keys(db)? {
+        let _ = writeln!(out, "sled_tree_keys{{tree=\"{}\"}} {}", escape(&name), len);
+    }
+    Ok(())
+}
+
+/// Every metric, in the Prometheus text format
+pub fn render(db: &Database) -> Result<String> {
+    let mut out = String::new();
+    HTTP_REQUESTS.render(&mut out);
+    HTTP_DURATION.render(&mut out);
+    AUTH_EVENTS.render(&mut out);
+    AUTH_CHECKS.render(&mut out);
+    BACKUP_DURATION.render(&mut out);
+    BACKUP_FAILURES.render(&mut out);
+    BACKUP_LAST_SUCCESS.render(&mut out);
+    REPLICATION_LAG.render(&mut out);
+    REPLICATION_ERRORS.render(&mut out);
+    CSP_VIOLATIONS.render(&mut out);
+    render_sled(db, &mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_text_exposition_format() {
+        static REQUESTS: Counter = Counter::new("test_requests_total", "Requests", &["route"]);
+        static LATENCY: Histogram = Histogram::new("test_latency_seconds", "Latency", &["route"], &[0.1, 1.0]);
+        REQUESTS.inc(&["/a"]);
+        REQUESTS.inc(&["/a"]);
+        REQUESTS.inc(&["/b\"x"]);
+        LATENCY.observe(&["/a"], 0.05);
+        LATENCY.observe(&["/a"], 0.5);
+
+        let mut out = String::new();
+        REQUESTS.render(&mut out);
+        LATENCY.render(&mut out);
+        assert!(out.contains("# TYPE test_requests_total counter\n"));
+        assert!(out.contains("test_requests_total{route=\"/a\"} 2\n"));
+        assert!(out.contains("test_requests_total{route=\"/b\\\"x\"} 1\n"));
+        assert!(out.contains("test_latency_seconds_bucket{route=\"/a\",le=\"0.1\"} 1\n"));
+        assert!(out.contains("test_latency_seconds_bucket{route=\"/a\",le=\"1\"} 2\n"));
+        assert!(out.contains("test_latency_seconds_bucket{route=\"/a\",le=\"+Inf\"} 2\n"));
+        assert!(out.contains("test_latency_seconds_count{route=\"/a\"} 2\n"));
+    }
+}
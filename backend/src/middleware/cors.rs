@@ -0,0 +1,254 @@
+// CORS middleware
+//
+// Enforces the rules from `.env_cors` (see `config::parse_cors_rules`): the
+// first rule matching the origin and path decides. Preflights are answered
+// here with the rule's methods, headers, credentials and max-age. Cross-
+// origin requests no rule allows are refused with 403 before reaching a
+// handler, since browsers don't preflight simple requests. Rules are read
+// from the live configuration, so edits apply on reload.
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{self, HeaderName, HeaderValue},
+        Method,
+    },
+    Error, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+use std::sync::Arc;
+
+use crate::config::{self, CorsAction, CorsRule};
+use crate::middleware::request_id::REQUEST_ID_HEADER;
+use crate::reload::LiveConfig;
+use crate::types::ErrorResponse;
+
+/// What the rules say about one cross-origin request
+pub struct Decision<'a> {
+    pub rule: Option<&'a CorsRule>,
+    /// Why the request is refused, if it is
+    pub refused: Option<String>,
+    /// CORS response headers to send when allowed
+    pub headers: Vec<(&'static str, String)>,
+}
+
+/// Apply the rules to a request from `origin`. For a preflight, `method` and
+/// `request_headers` are what the browser asks permission for.
+pub fn evaluate<'a>(
+    rules: &'a [CorsRule],
+    origin: &str,
+    method: &str,
+    path: &str,
+    request_headers: &[String],
+    preflight: bool,
+) -> Decision<'a> {
+    let refuse = |rule, reason: String| Decision { rule, refused: Some(reason), headers: Vec::new() };
+    let Some(rule) = config::find_cors_rule(rules, origin, path) else {
+        return refuse(None, format!("no rule allows origin {} for {}", origin, path));
+    };
+    if rule.action == CorsAction::Deny {
+        return refuse(Some(rule), format!("origin {} is denied", origin));
+    }
+    if !rule.allows_method(method) {
+        return refuse(Some(rule), format!("method {} is not allowed", method));
+    }
+    // Every header the browser names must be allowed, safelisted ones included:
+    // it only names them when their values fall outside the safelist
+    // (e.g. `Content-Type: application/json`)
+    if preflight {
+        if let Some(h) = request_headers.iter().find(|h| !rule.allows_header(h)) {
+            return refuse(Some(rule), format!("header {} is not allowed", h));
+        }
+    }
+
+    let mut headers = vec![("access-control-allow-origin", origin.to_string())];
+    if rule.credentials {
+        headers.push(("access-control-allow-credentials", "true".to_string()));
+    }
+    if preflight {
+        let methods = if rule.allows_method("ALL") { method.to_uppercase() } else { rule.methods.join(", ") };
+        headers.push(("access-control-allow-methods", methods));
+        if !request_headers.is_empty() {
+            headers.push(("access-control-allow-headers", request_headers.join(", ")));
+        }
+        headers.push(("access-control-max-age", rule.max_age.to_string()));
+    } else {
+        let mut expose = vec![REQUEST_ID_HEADER.to_string()];
+        expose.extend(rule.expose_headers.iter().filter(|h| h.as_str() != REQUEST_ID_HEADER).cloned());
+        headers.push(("access-control-expose-headers", expose.join(", ")));
+    }
+    Decision { rule: Some(rule), refused: None, headers }
+}
+
+/// Browsers send `Origin` on same-origin POSTs too; those need no rule
+fn is_same_origin(req: &ServiceRequest, origin: &str) -> bool {
+    origin.split_once("://").is_some_and(|(_, authority)| authority.eq_ignore_ascii_case(req.connection_info().host()))
+}
+
+fn apply_headers(out: &mut header::HeaderMap, headers: &[(&'static str, String)]) {
+    for (name, value) in headers {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            out.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+pub struct CorsMiddleware(pub Arc<LiveConfig>);
+
+impl<S, B> Transform<S, ServiceRequest> for CorsMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CorsService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CorsService { service, live: self.0.clone() }))
+    }
+}
+
+pub struct CorsService<S> {
+    service: S,
+    live: Arc<LiveConfig>,
+}
+
+impl<S, B> Service<ServiceRequest> for CorsService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let origin = req.headers().get(header::ORIGIN).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let Some(origin) = origin.filter(|o| !is_same_origin(&req, o)) else {
+            let fut = self.service.call(req);
+            return Box::pin(async move { Ok(fut.await?.map_into_left_body()) });
+        };
+
+        let cfg = self.live.load();
+        let requested_method = req.headers().get(header::ACCESS_CONTROL_REQUEST_METHOD).and_then(|v| v.to_str().ok());
+        let preflight = req.method() == Method::OPTIONS && requested_method.is_some();
+        let method = requested_method.unwrap_or(req.method().as_str()).to_string();
+        let request_headers: Vec<String> = req
+            .headers()
+            .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(',').map(|h| h.trim().to_lowercase()).filter(|h| !h.is_empty()).collect())
+            .unwrap_or_default();
+        let decision = evaluate(&cfg.cors_rules, &origin, &method, req.path(), &request_headers, preflight);
+
+        if let Some(reason) = &decision.refused {
+            log::debug!("CORS: refused {} {} from {}: {}", method, req.path(), origin, reason);
+            let res = HttpResponse::Forbidden()
+                .insert_header((header::VARY, "Origin"))
+                .json(ErrorResponse::new("cors_rejected", reason.clone()));
+            return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+        }
+        if preflight {
+            let mut res = HttpResponse::NoContent()
+                .insert_header((header::VARY, "Origin, Access-Control-Request-Method, Access-Control-Request-Headers"))
+                .finish();
+            apply_headers(res.headers_mut(), &decision.headers);
+            return Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) });
+        }
+
+        let headers = decision.headers;
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            apply_headers(res.headers_mut(), &headers);
+            res.headers_mut().append(header::VARY, HeaderValue::from_static("Origin"));
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App};
+
+    const RULES: &str = "\
+https://app.example.com ALLOW GET,POST content-type,authorization expose=etag max-age=600
+https://partner.example.com ALLOW GET ALL path=/api/public/* credentials=false
+https://*.example.com DENY
+";
+
+    fn live() -> Arc<LiveConfig> {
+        let mut cfg = config::load_config_from_file("/nonexistent/.env");
+        cfg.cors_rules = config::parse_cors_rules(RULES).0;
+        Arc::new(LiveConfig::new(cfg, Default::default()))
+    }
+
+    #[actix_web::test]
+    async fn evaluate_applies_the_first_matching_rule() {
+        let (rules, errors) = config::parse_cors_rules(RULES);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let ok = evaluate(&rules, "https://app.example.com", "POST", "/api/x", &["authorization".into()], true);
+        assert!(ok.refused.is_none());
+        assert!(ok.headers.contains(&("access-control-allow-methods", "GET, POST".into())));
+        assert!(ok.headers.contains(&("access-control-max-age", "600".into())));
+
+        let put = evaluate(&rules, "https://app.example.com", "PUT", "/api/x", &[], true);
+        assert!(put.refused.unwrap().contains("PUT"));
+        let header = evaluate(&rules, "https://app.example.com", "GET", "/api/x", &["x-custom".into()], true);
+        assert!(header.refused.unwrap().contains("x-custom"));
+        let named = evaluate(&rules, "https://app.example.com", "GET", "/api/x", &["content-language".into()], true);
+        assert!(named.refused.unwrap().contains("content-language"));
+
+        // Path-scoped rule: outside its paths the wildcard DENY applies
+        let public = evaluate(&rules, "https://partner.example.com", "GET", "/api/public/menu", &[], false);
+        assert!(public.refused.is_none());
+        assert!(!public.headers.iter().any(|(n, _)| *n == "access-control-allow-credentials"));
+        let private = evaluate(&rules, "https://partner.example.com", "GET", "/api/users", &[], false);
+        assert_eq!(private.rule.unwrap().line, 3);
+        assert!(private.refused.is_some());
+    }
+
+    #[actix_web::test]
+    async fn middleware_answers_preflights_and_refuses_unknown_origins() {
+        let app = test::init_service(
+            App::new().wrap(CorsMiddleware(live())).route("/api/x", web::to(|| async { HttpResponse::Ok().body("hi") })),
+        )
+        .await;
+
+        let req = test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/api/x")
+            .insert_header(("Origin", "https://app.example.com"))
+            .insert_header(("Access-Control-Request-Method", "POST"))
+            .insert_header(("Access-Control-Request-Headers", "Content-Type, Authorization"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 204);
+        assert_eq!(resp.headers().get("access-control-allow-origin").unwrap(), "https://app.example.com");
+        assert_eq!(resp.headers().get("access-control-allow-credentials").unwrap(), "true");
+
+        let req = test::TestRequest::get().uri("/api/x").insert_header(("Origin", "https://app.example.com")).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("access-control-expose-headers").unwrap(), "x-request-id, etag");
+
+        let req = test::TestRequest::post().uri("/api/x").insert_header(("Origin", "https://evil.test")).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+        // Same-origin and non-browser requests pass untouched
+        let req = test::TestRequest::post().uri("/api/x").insert_header(("Origin", "http://localhost:8080")).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        let req = test::TestRequest::post().uri("/api/x").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+    }
+}
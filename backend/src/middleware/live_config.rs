@@ -0,0 +1,59 @@
+// Live configuration middleware
+use actix_web::{
+    dev::{forward_ready, Extensions, Service, ServiceRequest, ServiceResponse, Transform},
+    web, Error,
+};
+use futures_util::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::reload::LiveConfig;
+
+/// Hands each request the configuration in effect when it arrived, as
+/// `web::Data<AppConfig>`, so handlers pick up reloads without a restart
+/// and keep one consistent view for the whole request.
+pub struct LiveConfigData(pub Arc<LiveConfig>);
+
+impl<S, B> Transform<S, ServiceRequest> for LiveConfigData
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = LiveConfigMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(LiveConfigMiddleware { service, live: self.0.clone() }))
+    }
+}
+
+pub struct LiveConfigMiddleware<S> {
+    service: S,
+    live: Arc<LiveConfig>,
+}
+
+impl<S, B> Service<ServiceRequest> for LiveConfigMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let mut data = Extensions::new();
+        data.insert(web::Data::from(self.live.load()));
+        // Request-level data shadows anything registered on the App
+        req.add_data_container(Rc::new(data));
+        Box::pin(self.service.call(req))
+    }
+}
@@ -1 +1,5 @@
 pub mod security;
+pub mod metrics;
+pub mod request_id;
+pub mod live_config;
+pub mod cors;
@@ -0,0 +1,130 @@
+// Request correlation middleware
+//
+// Every request gets an id: the caller's `X-Request-Id` when it looks sane,
+// otherwise a fresh one. The handler runs inside a `request` tracing span
+// carrying the id, the id is echoed in the `X-Request-Id` response header,
+// and `ErrorResponse::new` picks it up (via `current()`) so clients can
+// quote it when reporting a failure.
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: String;
+}
+
+/// The id of the request being handled, when called from inside one
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.clone()).ok()
+}
+
+/// Ids we accept from callers: short, printable and safe to log
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LEN
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+pub struct RequestIdMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdService { service }))
+    }
+}
+
+pub struct RequestIdService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| is_valid(v))
+            .map(str::to_string)
+            .unwrap_or_else(|| nanoid::nanoid!(21));
+
+        let span = tracing::info_span!(
+            "request",
+            request_id = %id,
+            method = %req.method(),
+            path = %req.path(),
+            status = tracing::field::Empty,
+        );
+        let fut = span.in_scope(|| self.service.call(req));
+
+        Box::pin(CURRENT.scope(id.clone(), async move {
+            let mut res = fut.await?;
+            tracing::Span::current().record("status", res.status().as_u16());
+            if let Ok(value) = HeaderValue::from_str(&id) {
+                res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(res)
+        }.instrument(span)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ErrorResponse;
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[actix_web::test]
+    async fn echoes_or_generates_request_ids() {
+        let app = test::init_service(
+            App::new().wrap(RequestIdMiddleware).route(
+                "/fail",
+                web::get().to(|| async { HttpResponse::BadRequest().json(ErrorResponse::new("bad", "nope")) }),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/fail").insert_header(("X-Request-Id", "abc-123")).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "abc-123");
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"]["requestId"], "abc-123");
+
+        // Unusable ids are replaced
+        let req = test::TestRequest::get().uri("/fail").insert_header(("X-Request-Id", "bad id\"")).to_request();
+        let resp = test::call_service(&app, req).await;
+        let generated = resp.headers().get(REQUEST_ID_HEADER).unwrap().to_str().unwrap().to_string();
+        assert_eq!(generated.len(), 21);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"]["requestId"], generated);
+    }
+}
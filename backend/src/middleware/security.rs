@@ -1,13 +1,39 @@
 // Security headers middleware
+//
+// Headers come from `HeadersConfig` in the live configuration. Each request
+// gets a fresh CSP nonce, stored in the request extensions so the SPA
+// fallback can stamp it on index.html's inline scripts and styles.
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    Error,
-    // HttpResponse,
+    http::header::{HeaderName, HeaderValue},
+    Error, HttpMessage, HttpRequest,
 };
+use base64::Engine;
 use futures_util::future::LocalBoxFuture;
+use rand::RngCore;
 use std::future::{ready, Ready};
+use std::sync::Arc;
 
-pub struct SecurityHeaders;
+use crate::reload::LiveConfig;
+
+/// The nonce allowed by this response's Content-Security-Policy
+#[derive(Clone)]
+pub struct CspNonce(pub String);
+
+impl CspNonce {
+    fn generate() -> Self {
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(base64::engine::general_purpose::STANDARD.encode(bytes))
+    }
+}
+
+/// The CSP nonce of the request being handled
+pub fn nonce(req: &HttpRequest) -> Option<String> {
+    req.extensions().get::<CspNonce>().map(|n| n.0.clone())
+}
+
+pub struct SecurityHeaders(pub Arc<LiveConfig>);
 
 impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
 where
@@ -22,12 +48,13 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(SecurityHeadersMiddleware { service }))
+        ready(Ok(SecurityHeadersMiddleware { service, live: self.0.clone() }))
     }
 }
 
 pub struct SecurityHeadersMiddleware<S> {
     service: S,
+    live: Arc<LiveConfig>,
 }
 
 impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
@@ -43,58 +70,73 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
+        let cfg = self.live.load();
+        let nonce = CspNonce::generate();
+        req.extensions_mut().insert(nonce.clone());
         let fut = self.service.call(req);
 
         Box::pin(async move {
             let mut res = fut.await?;
+            let conf = &cfg.headers;
+            let mut set = |name: &'static str, value: &str| {
+                if let Ok(value) = HeaderValue::from_str(value) {
+                    res.headers_mut().insert(HeaderName::from_static(name), value);
+                }
+            };
 
-            let headers = res.headers_mut();
-
-            // Security headers
-            headers.insert(
-                actix_web::http::header::HeaderName::from_static("x-content-type-options"),
-                actix_web::http::header::HeaderValue::from_static("nosniff"),
-            );
-            headers.insert(
-                actix_web::http::header::HeaderName::from_static("referrer-policy"),
-                actix_web::http::header::HeaderValue::from_static("same-origin"),
-            );
-            headers.insert(
-                actix_web::http::header::HeaderName::from_static("x-frame-options"),
-                actix_web::http::header::HeaderValue::from_static("DENY"),
-            );
-            headers.insert(
-                actix_web::http::header::HeaderName::from_static("permissions-policy"),
-                actix_web::http::header::HeaderValue::from_static(
-                    "geolocation=(), microphone=(), camera=()",
-                ),
-            );
-
-            // CSP - adjust for your needs
-            // Note: 'unsafe-inline' for styles is common with Tailwind, consider using nonces in production
-            headers.insert(
-                actix_web::http::header::HeaderName::from_static("content-security-policy"),
-                actix_web::http::header::HeaderValue::from_static(
-                    "default-src 'self'; \
-                     script-src 'self' 'wasm-unsafe-eval' 'sha256-ZswfTY7H35rbv8WC7NXBoiC7WNu86vSzCDChNWwZZDM='; \
-                     style-src 'self' 'unsafe-inline'; \
-                     img-src 'self' data:; \
-                     font-src 'self' data:; \
-                     connect-src 'self' https:; \
-                     frame-ancestors 'none'; \
-                     base-uri 'self';",
-                ),
-            );
-
-            // HSTS (uncomment for production with HTTPS)
-            // headers.insert(
-            //     actix_web::http::header::HeaderName::from_static("strict-transport-security"),
-            //     actix_web::http::header::HeaderValue::from_static(
-            //         "max-age=31536000; includeSubDomains; preload",
-            //     ),
-            // );
+            set("x-content-type-options", "nosniff");
+            set("referrer-policy", "same-origin");
+            if let Some(frame_options) = &conf.frame_options {
+                set("x-frame-options", frame_options);
+            }
+            if !conf.permissions_policy.is_empty() {
+                set("permissions-policy", &conf.permissions_policy);
+            }
+            if let Some(hsts) = conf.hsts_header() {
+                set("strict-transport-security", &hsts);
+            }
+            if let Some(policy) = conf.csp_header(&nonce.0) {
+                let name = if conf.csp_report_only {
+                    "content-security-policy-report-only"
+                } else {
+                    "content-security-policy"
+                };
+                set(name, &policy);
+            }
 
             Ok(res)
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpResponse};
+
+    #[actix_web::test]
+    async fn headers_follow_config_with_a_fresh_nonce_per_response() {
+        let mut cfg = crate::config::load_config_from_file("/nonexistent/.env");
+        cfg.headers.hsts_max_age = Some(std::time::Duration::from_secs(600));
+        cfg.headers.frame_options = None;
+        let live = Arc::new(LiveConfig::new(cfg, Default::default()));
+        let app = test::init_service(
+            App::new()
+                .wrap(SecurityHeaders(live))
+                .route("/", web::get().to(|req: HttpRequest| async move { HttpResponse::Ok().body(nonce(&req).unwrap()) })),
+        )
+        .await;
+
+        let mut seen = Vec::new();
+        for _ in 0..2 {
+            let resp = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+            let csp = resp.headers().get("content-security-policy").unwrap().to_str().unwrap().to_string();
+            assert_eq!(resp.headers().get("strict-transport-security").unwrap(), "max-age=600");
+            assert!(resp.headers().get("x-frame-options").is_none());
+            let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+            assert!(csp.contains(&format!("'nonce-{}'", body)) && csp.ends_with("report-uri /csp-report"), "{}", csp);
+            seen.push(body);
+        }
+        assert_ne!(seen[0], seen[1]);
+    }
+}
@@ -0,0 +1,76 @@
+// src/models/api_key_types.rs
+use serde::{Deserialize, Serialize};
+
+/// Scopes an API key can carry. `read` allows safe methods only, `write`
+/// allows mutations, `admin` keeps the owner's global admin role.
+pub const SCOPE_READ: &str = "read";
+pub const SCOPE_WRITE: &str = "write";
+pub const SCOPE_ADMIN: &str = "admin";
+pub const ALL_SCOPES: [&str; 3] = [SCOPE_READ, SCOPE_WRITE, SCOPE_ADMIN];
+
+/// Stored form of a personal API key. Only the SHA-256 of the full key is
+/// kept; the plaintext is shown once at creation. Keyed by `prefix`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKeyRecord {
+    pub prefix: String,
+    pub user_id: String,
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<String>,
+}
+
+impl ApiKeyRecord {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub expires_in_days: Option<u32>,
+}
+
+/// API key as shown to its owner (never includes the hash)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKeyInfo {
+    pub prefix: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,
+    pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<String>,
+}
+
+impl From<ApiKeyRecord> for ApiKeyInfo {
+    fn from(r: ApiKeyRecord) -> Self {
+        Self {
+            prefix: r.prefix,
+            name: r.name,
+            scopes: r.scopes,
+            org_id: r.org_id,
+            created_at: r.created_at,
+            expires_at: r.expires_at,
+            last_used_at: r.last_used_at,
+            revoked_at: r.revoked_at,
+        }
+    }
+}
@@ -11,7 +11,25 @@ pub struct Claims {
     pub iss: String,
     pub aud: String,
     pub iat: i64,
+    /// Issue time in milliseconds, so session invalidation can tell apart
+    /// tokens issued within the same second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<i64>,
     pub exp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,     // active organization (tenant)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub org_roles: Vec<String>,     // roles within the active organization
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonator: Option<String>, // admin user id when this is an impersonation token
+}
+
+impl Claims {
+    /// Issue time in milliseconds; tokens without `iat_ms` count from the
+    /// start of their `iat` second
+    pub fn issued_at_ms(&self) -> i64 {
+        self.iat_ms.unwrap_or(self.iat * 1000)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -33,6 +51,32 @@ pub struct UserRecord {
     pub password_hash: String,
     pub roles: Vec<String>,
     pub created_at: String,
+    /// Last organization the user switched to; used as the active org on login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_org_id: Option<String>,
+    /// Disabled accounts can't log in, refresh or use API keys
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// Session tokens issued at or before this unix time in milliseconds are rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sessions_valid_after_ms: Option<i64>,
+    /// Whole-second cut-off written by earlier versions (tokens issued at
+    /// or before it are rejected); replaced on the next invalidation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sessions_valid_after: Option<i64>,
+    /// Pending admin-forced password reset; blocks login until completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_reset: Option<PasswordReset>,
+    /// Set when personal data was scrubbed (GDPR erasure)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anonymized_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasswordReset {
+    /// SHA-256 hex of the one-time reset token
+    pub token_hash: String,
+    pub expires_at: String,
 }
 
 impl UserRecord {
@@ -43,6 +87,12 @@ impl UserRecord {
             password_hash,
             roles: vec!["admin".into()],
             created_at: Utc::now().to_rfc3339(),
+            active_org_id: None,
+            disabled: false,
+            sessions_valid_after_ms: None,
+            sessions_valid_after: None,
+            password_reset: None,
+            anonymized_at: None,
         }
     }
 
@@ -53,6 +103,26 @@ impl UserRecord {
             password_hash,
             roles: vec!["user".into()],
             created_at: Utc::now().to_rfc3339(),
+            active_org_id: None,
+            disabled: false,
+            sessions_valid_after_ms: None,
+            sessions_valid_after: None,
+            password_reset: None,
+            anonymized_at: None,
         }
     }
+
+    /// Reject every session token issued up to now, including within the
+    /// current millisecond
+    pub fn invalidate_sessions(&mut self) {
+        self.sessions_valid_after_ms = Some(Utc::now().timestamp_millis());
+        self.sessions_valid_after = None;
+    }
+
+    /// Whether a session token issued at `issued_ms` (see
+    /// `Claims::issued_at_ms`) is still honoured for this user
+    pub fn accepts_session(&self, issued_ms: i64) -> bool {
+        let after = self.sessions_valid_after_ms.or(self.sessions_valid_after.map(|secs| secs * 1000 + 999));
+        !self.disabled && self.anonymized_at.is_none() && after.is_none_or(|after| issued_ms > after)
+    }
 }
@@ -1 +1,3 @@
 pub mod auth_types;
+pub mod org_types;
+pub mod api_key_types;
@@ -0,0 +1,75 @@
+// src/models/org_types.rs
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use chrono::Utc;
+
+/// Org-level roles, from most to least privileged
+pub const ORG_OWNER: &str = "owner";
+pub const ORG_ADMIN: &str = "admin";
+pub const ORG_MEMBER: &str = "member";
+/// Read-only access to org data
+pub const ORG_VIEWER: &str = "viewer";
+pub const ORG_ROLES: &[&str] = &[ORG_OWNER, ORG_ADMIN, ORG_MEMBER, ORG_VIEWER];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+impl Organization {
+    pub fn new(name: &str, created_by: &str) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name: name.trim().to_string(),
+            created_by: created_by.to_string(),
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// A user's membership in an organization, stored under `{org_id}:{user_id}`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Membership {
+    pub org_id: String,
+    pub user_id: String,
+    pub roles: Vec<String>,
+    pub created_at: String,
+}
+
+impl Membership {
+    pub fn new(org_id: &str, user_id: &str, roles: Vec<String>) -> Self {
+        Self {
+            org_id: org_id.to_string(),
+            user_id: user_id.to_string(),
+            roles,
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+
+    pub fn key(org_id: &str, user_id: &str) -> String {
+        format!("{}:{}", org_id, user_id)
+    }
+
+    pub fn has_any_role(&self, roles: &[&str]) -> bool {
+        self.roles.iter().any(|r| roles.contains(&r.as_str()))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateOrgRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AddMemberRequest {
+    pub email: String,
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpdateMemberRequest {
+    pub roles: Vec<String>,
+}
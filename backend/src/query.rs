@@ -0,0 +1,206 @@
+// Paged, filtered scans over a collection (see `Database::query`)
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde::Deserialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+pub const DEFAULT_LIMIT: usize = 50;
+pub const MAX_LIMIT: usize = 500;
+
+/// Cursors are versioned so their encoding can change without old ones
+/// being misread
+const CURSOR_VERSION: &str = "k1.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    /// Substring of a string field, or element of an array field
+    Contains,
+}
+
+/// One condition on a (dotted) field of the stored JSON record
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub field: String,
+    pub op: FilterOp,
+    pub value: Value,
+}
+
+impl Filter {
+    /// `field:op:value`, e.g. `disabled:eq:true` or `email:contains:@acme.com`.
+    /// `value` is read as JSON when it parses, otherwise as a string.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, ':');
+        let (Some(field), Some(op), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(anyhow!("Invalid filter '{}': expected field:op:value", s));
+        };
+        let op = match op {
+            "eq" => FilterOp::Eq,
+            "ne" => FilterOp::Ne,
+            "gt" => FilterOp::Gt,
+            "gte" => FilterOp::Gte,
+            "lt" => FilterOp::Lt,
+            "lte" => FilterOp::Lte,
+            "contains" => FilterOp::Contains,
+            other => return Err(anyhow!("Unknown filter operator '{}'", other)),
+        };
+        if field.is_empty() {
+            return Err(anyhow!("Invalid filter '{}': empty field", s));
+        }
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+        Ok(Self { field: field.to_string(), op, value })
+    }
+
+    /// A missing field compares as `null`
+    pub fn matches(&self, record: &Value) -> bool {
+        let pointer = format!("/{}", self.field.replace('.', "/"));
+        let actual = record.pointer(&pointer).unwrap_or(&Value::Null);
+        match self.op {
+            FilterOp::Eq => actual == &self.value,
+            FilterOp::Ne => actual != &self.value,
+            FilterOp::Gt => compare(actual, &self.value) == Some(Ordering::Greater),
+            FilterOp::Gte => matches!(compare(actual, &self.value), Some(Ordering::Greater | Ordering::Equal)),
+            FilterOp::Lt => compare(actual, &self.value) == Some(Ordering::Less),
+            FilterOp::Lte => matches!(compare(actual, &self.value), Some(Ordering::Less | Ordering::Equal)),
+            FilterOp::Contains => match (actual, &self.value) {
+                (Value::String(a), Value::String(v)) => a.contains(v.as_str()),
+                (Value::Array(items), v) => items.contains(v),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Numbers compare numerically, strings lexicographically; anything else
+/// (or mixed types) doesn't compare
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// What to read from a collection. Keys are tenant-relative; results come
+/// back in key order (or reverse), `limit` at a time.
+#[derive(Debug, Clone, Default)]
+pub struct ListQuery {
+    /// Only keys starting with this
+    pub prefix: String,
+    /// Inclusive lower key bound
+    pub start: Option<String>,
+    /// Exclusive upper key bound
+    pub end: Option<String>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+    pub filters: Vec<Filter>,
+    pub reverse: bool,
+}
+
+impl ListQuery {
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    /// Build from query-string parameters, rejecting filters on fields
+    /// outside `filterable`
+    pub fn from_params(params: ListParams, filterable: &[&str]) -> Result<Self> {
+        let mut filters = Vec::new();
+        for raw in params.filter.iter().flat_map(|f| f.split(',')).map(str::trim).filter(|f| !f.is_empty()) {
+            let filter = Filter::parse(raw)?;
+            if !filterable.contains(&filter.field.as_str()) {
+                return Err(anyhow!("Filtering on '{}' is not supported", filter.field));
+            }
+            filters.push(filter);
+        }
+        if let Some(cursor) = &params.cursor {
+            decode_cursor(cursor)?;
+        }
+        Ok(Self {
+            prefix: params.prefix.unwrap_or_default(),
+            start: params.start,
+            end: params.end,
+            cursor: params.cursor,
+            limit: params.limit,
+            filters,
+            reverse: params.reverse.unwrap_or(false),
+        })
+    }
+
+    pub fn matches(&self, record: &Value) -> bool {
+        self.filters.iter().all(|f| f.matches(record))
+    }
+}
+
+/// Query-string form of [`ListQuery`]:
+/// `?limit=50&cursor=..&reverse=true&prefix=..&start=..&end=..&filter=field:op:value,..`
+#[derive(Debug, Default, Deserialize)]
+pub struct ListParams {
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+    pub reverse: Option<bool>,
+    pub prefix: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub filter: Option<String>,
+}
+
+/// One page of results with the (tenant-relative) key of each record
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<(String, T)>,
+    /// Pass back as `cursor` to continue after the last item
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+impl<T> Page<T> {
+    /// Drop the keys, e.g. when records carry their own id
+    pub fn into_values(self) -> (Vec<T>, Option<String>, bool) {
+        (self.items.into_iter().map(|(_, v)| v).collect(), self.next_cursor, self.has_more)
+    }
+}
+
+pub fn encode_cursor(key: &str) -> String {
+    format!("{}{}", CURSOR_VERSION, URL_SAFE_NO_PAD.encode(key))
+}
+
+pub fn decode_cursor(cursor: &str) -> Result<String> {
+    let encoded = cursor.strip_prefix(CURSOR_VERSION).ok_or_else(|| anyhow!("Invalid cursor"))?;
+    let bytes = URL_SAFE_NO_PAD.decode(encoded).map_err(|_| anyhow!("Invalid cursor"))?;
+    String::from_utf8(bytes).map_err(|_| anyhow!("Invalid cursor"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn filters_parse_and_match() {
+        let user = json!({"email": "a@acme.com", "roles": ["user", "admin"], "age": 41, "disabled": false});
+        let matches = |f: &str| Filter::parse(f).unwrap().matches(&user);
+        assert!(matches("email:contains:@acme.com"));
+        assert!(matches("roles:contains:admin"));
+        assert!(matches("disabled:eq:false"));
+        assert!(matches("age:gte:41") && !matches("age:gt:41"));
+        assert!(matches("missing:ne:1") && !matches("missing:eq:1"));
+        assert!(matches("missing:eq:null") && !matches("missing:ne:null"));
+        assert!(!matches("email:lt:5"));
+
+        assert!(Filter::parse("email:like:x").is_err());
+        assert!(Filter::parse("email").is_err());
+
+        let params = ListParams { filter: Some("password_hash:contains:$".into()), ..Default::default() };
+        assert!(ListQuery::from_params(params, &["email"]).is_err());
+        assert_eq!(decode_cursor(&encode_cursor("user:42")).unwrap(), "user:42");
+        assert!(decode_cursor("not-a-cursor").is_err());
+    }
+}
@@ -0,0 +1,264 @@
+// Hot configuration reload
+//
+// The running configuration lives in a `LiveConfig` and is replaced as a
+// whole, so a request sees either the old or the new settings, never a mix.
+// Reloads re-read the .env, TOML and `.env_cors` files, validate the result, log
+// what changed and swap it in. Settings that were consumed at startup
+// (listen address, storage paths, background task intervals, ...) are
+// reported but keep their running value until the next restart.
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::{self, AppConfig};
+use crate::config_source::ConfigLayers;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigChange {
+    pub field: String,
+    pub from: String,
+    pub to: String,
+    /// False for settings that only take effect after a restart
+    pub applied: bool,
+}
+
+pub struct LiveConfig {
+    current: ArcSwap<AppConfig>,
+    layers: ConfigLayers,
+    /// One reload at a time; the watcher and a signal may fire together
+    reloading: Mutex<()>,
+}
+
+impl LiveConfig {
+    pub fn new(cfg: AppConfig, layers: ConfigLayers) -> Self {
+        Self { current: ArcSwap::from_pointee(cfg), layers, reloading: Mutex::new(()) }
+    }
+
+    /// The configuration in effect right now
+    pub fn load(&self) -> Arc<AppConfig> {
+        self.current.load_full()
+    }
+
+    /// Re-read, validate and apply the configuration. Nothing changes when
+    /// the new configuration is invalid.
+    pub fn reload(&self) -> Result<Vec<ConfigChange>> {
+        let _guard = self.reloading.lock().map_err(|_| anyhow::anyhow!("reload lock poisoned"))?;
+        let mut next = config::reload_config(&self.layers)?;
+        config::validate(&next)?;
+        let current = self.current.load_full();
+        let changes = diff(&current, &mut next);
+        if !changes.is_empty() {
+            self.current.store(Arc::new(next));
+        }
+        Ok(changes)
+    }
+
+    /// `reload`, logging the outcome; for triggers without a caller to report to
+    pub fn reload_logged(&self, trigger: &str) {
+        match self.reload() {
+            Ok(changes) if changes.is_empty() => log::info!("Config reload ({}): no changes", trigger),
+            Ok(changes) => log_changes(trigger, &changes),
+            Err(e) => log::error!("Config reload ({}) rejected, keeping the running configuration: {}", trigger, e),
+        }
+    }
+
+    /// Files whose changes should trigger a reload
+    pub fn watched_files(&self) -> Vec<String> {
+        let mut files = vec![self.layers.env_file.clone(), config::CORS_RULES_FILE.to_string()];
+        files.extend(self.layers.toml_path().map(|p| p.to_string_lossy().into_owned()));
+        files
+    }
+}
+
+pub fn log_changes(trigger: &str, changes: &[ConfigChange]) {
+    for c in changes {
+        if c.applied {
+            log::info!("Config reload ({}): {}: {} -> {}", trigger, c.field, c.from, c.to);
+        } else {
+            log::warn!("Config reload ({}): {}: {} -> {} (restart required)", trigger, c.field, c.from, c.to);
+        }
+    }
+}
+
+const REDACTED: &str = "<redacted>";
+
+struct Differ<'a> {
+    changes: &'a mut Vec<ConfigChange>,
+}
+
+impl Differ<'_> {
+    fn check<T: std::fmt::Debug>(&mut self, field: &str, old: &T, new: &T, secret: bool, applied: bool) -> bool {
+        let (from, to) = (format!("{:?}", old), format!("{:?}", new));
+        if from == to {
+            return false;
+        }
+        let (from, to) = if secret { (REDACTED.to_string(), REDACTED.to_string()) } else { (from, to) };
+        self.changes.push(ConfigChange { field: field.to_string(), from, to, applied });
+        true
+    }
+}
+
+/// Changes from `old` to `new`. Restart-only settings are reset in `new`
+/// to their running value, so `new` can be swapped in as is.
+pub fn diff(old: &AppConfig, new: &mut AppConfig) -> Vec<ConfigChange> {
+    let mut changes = Vec::new();
+    let mut d = Differ { changes: &mut changes };
+
+    // Applied immediately
+    d.check("cors_rules", &old.cors_rules, &new.cors_rules, false, true);
+    d.check("backup_max_age", &old.backup_max_age, &new.backup_max_age, false, true);
+    d.check("headers", &old.headers, &new.headers, false, true);
+    d.check("cookies", &old.cookies, &new.cookies, false, true);
+    d.check("health_min_free_disk_mb", &old.health_min_free_disk_mb, &new.health_min_free_disk_mb, false, true);
+    let (o, n) = (&old.security, &new.security);
+    d.check("security.access_token", &o.access_token, &n.access_token, true, true);
+    d.check("security.access_token_admin_bypass", &o.access_token_admin_bypass, &n.access_token_admin_bypass, false, true);
+    d.check("security.trusted_proxies", &o.trusted_proxies, &n.trusted_proxies, false, true);
+    d.check("security.auth_token_expiry_hours", &o.auth_token_expiry_hours, &n.auth_token_expiry_hours, false, true);
+    d.check("security.token_iss", &o.token_iss, &n.token_iss, false, true);
+    d.check("security.token_aud", &o.token_aud, &n.token_aud, false, true);
+    d.check("security.token_ttl_seconds", &o.token_ttl_seconds, &n.token_ttl_seconds, false, true);
+    d.check("security.paseto_v4_local_key_hex", &o.paseto_v4_local_key_hex, &n.paseto_v4_local_key_hex, true, true);
+    d.check("security.token_mode", &o.token_mode, &n.token_mode, false, true);
+    d.check("security.token_keyring_path", &o.token_keyring_path, &n.token_keyring_path, false, true);
+    d.check("security.keyring", &o.keyring, &n.keyring, false, true);
+    d.check("security.debug_mode", &o.debug_mode, &n.debug_mode, false, true);
+    d.check("security.health_check_enabled", &o.health_check_enabled, &n.health_check_enabled, false, true);
+    d.check("security.metrics_token", &o.metrics_token, &n.metrics_token, true, true);
+
+    // Consumed at startup: report, keep the running value
+    macro_rules! restart_only {
+        ($($field:ident).+, $secret:expr) => {
+            if d.check(stringify!($($field).+), &old.$($field).+, &new.$($field).+, $secret, false) {
+                new.$($field).+ = old.$($field).+.clone();
+            }
+        };
+    }
+    restart_only!(server, false);
+    restart_only!(database, true);
+    restart_only!(sled_path, false);
+    restart_only!(backup_dir, false);
+    restart_only!(backup_name_template, false);
+    restart_only!(backup_interval, false);
+    restart_only!(backup_retention, false);
+    restart_only!(pg_conns, true);
+    restart_only!(logging, false);
+    restart_only!(security.metrics_enabled, false);
+    restart_only!(security.rate_limit_enabled, false);
+    restart_only!(security.rate_limit_rpm, false);
+    restart_only!(database_sync_on, false);
+    restart_only!(durability, false);
+    restart_only!(event_log_max_events, false);
+    restart_only!(history_max_revisions, false);
+    restart_only!(entities_dir, false);
+    restart_only!(encryption, false);
+    restart_only!(soft_delete_collections, false);
+    restart_only!(trash_retention, false);
+    restart_only!(trash_purge_interval, false);
+    restart_only!(config_watch, false);
+
+    changes
+}
+
+/// Reload when the .env, TOML or CORS file changes. Editors often write a file in
+/// several steps, so events are debounced before reloading.
+pub fn watch(live: Arc<LiveConfig>) -> Result<notify::RecommendedWatcher> {
+    use notify::{RecursiveMode, Watcher};
+
+    let files = live.watched_files();
+    let names: Vec<std::ffi::OsString> =
+        files.iter().filter_map(|f| Path::new(f).file_name().map(|n| n.to_os_string())).collect();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<()>();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let relevant = event.paths.iter().any(|p| p.file_name().is_some_and(|n| names.iter().any(|w| w == n)));
+            if relevant && (event.kind.is_modify() || event.kind.is_create() || event.kind.is_remove()) {
+                let _ = tx.send(());
+            }
+        }
+    })?;
+    // Watch the directories: files replaced by rename would drop a file watch
+    let mut dirs: Vec<std::path::PathBuf> = files
+        .iter()
+        .map(|f| match Path::new(f).parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => std::path::PathBuf::from("."),
+        })
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    for dir in &dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    tokio::spawn(async move {
+        while rx.recv().await.is_some() {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            while rx.try_recv().is_ok() {}
+            let live = live.clone();
+            let _ = tokio::task::spawn_blocking(move || live.reload_logged("file change")).await;
+        }
+    });
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CorsAction, CorsRule};
+
+    #[test]
+    fn diff_applies_hot_settings_and_defers_the_rest() {
+        let old = config::load_config_from_file("/nonexistent/.env");
+        let mut new = old.clone();
+        new.cors_rules.push(CorsRule {
+            origin: "https://app.example.com".into(),
+            action: CorsAction::Allow,
+            methods: vec![],
+            headers: vec![],
+            ..CorsRule::default()
+        });
+        new.security.access_token = "rotated".into();
+        new.server.port = old.server.port + 1;
+        new.security.rate_limit_rpm = old.security.rate_limit_rpm + 1;
+
+        let changes = diff(&old, &mut new);
+        let fields: Vec<_> = changes.iter().map(|c| (c.field.as_str(), c.applied)).collect();
+        assert_eq!(fields, [("cors_rules", true), ("security.access_token", true), ("server", false), ("security.rate_limit_rpm", false)]);
+        // Secrets never reach the log
+        assert!(changes.iter().find(|c| c.field == "security.access_token").unwrap().to == REDACTED);
+        // The port keeps its running value; the CORS rule goes live
+        assert_eq!(new.server.port, old.server.port);
+        assert_eq!(new.security.rate_limit_rpm, old.security.rate_limit_rpm);
+        assert_eq!(new.cors_rules.len(), old.cors_rules.len() + 1);
+        assert!(diff(&old.clone(), &mut old.clone()).is_empty());
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        let mut cfg = config::load_config_from_file("/nonexistent/.env");
+        cfg.server.port = 0;
+        cfg.cors_rules.push(CorsRule { origin: String::new(), action: CorsAction::Allow, ..CorsRule::default() });
+        let err = config::validate(&cfg).unwrap_err().to_string();
+        assert!(err.contains("PORT") && err.contains("CORS origin"), "{}", err);
+    }
+
+    #[test]
+    fn reload_reads_the_env_file_without_touching_the_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let env_file = dir.path().join(".env");
+        std::fs::write(&env_file, "PERMISSIONS_POLICY=camera=()\nRELOAD_TEST_ONLY=1\n").unwrap();
+        let env = config::reload_env(env_file.to_str().unwrap()).unwrap();
+        assert_eq!(env.get("RELOAD_TEST_ONLY").map(String::as_str), Some("1"));
+        assert!(std::env::var("RELOAD_TEST_ONLY").is_err());
+
+        let live = LiveConfig::new(config::load_config_from_file("/nonexistent/.env"), ConfigLayers::env_file(env_file.to_str().unwrap()));
+        live.reload().unwrap();
+        assert_eq!(live.load().headers.permissions_policy, "camera=()");
+        assert!(std::env::var("PERMISSIONS_POLICY").is_err());
+    }
+}
@@ -57,6 +57,29 @@ impl Replicator {
         Ok(())
     }
 
+    /// Check out a connection from every pool and run a trivial query, all
+    /// pools at once, giving up on each after `timeout`. One entry per pool,
+    /// in config order.
+    pub async fn ping(&self, timeout: std::time::Duration) -> Vec<Result<()>> {
+        let probes = self.pools.iter().map(|pool| async move {
+            let probe = async {
+                let client = pool.get().await?;
+                client.simple_query("SELECT 1").await?;
+                Ok(())
+            };
+            match tokio::time::timeout(timeout, probe).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow!("timed out after {:?}", timeout)),
+            }
+        });
+        futures::future::join_all(probes).await
+    }
+
+    /// Whether writes to `table` are sent anywhere
+    pub fn replicates(&self, table: &str) -> bool {
+        !self.pools.is_empty() && self.routes.contains_key(table)
+    }
+
     pub async fn upsert(
         &self,
         table: &str,
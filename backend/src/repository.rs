@@ -0,0 +1,404 @@
+// Typed, versioned records over `Database` with optimistic concurrency
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::db::Database;
+use crate::history;
+use crate::query::{Filter, FilterOp, ListQuery, Page};
+use crate::trash;
+use crate::types::{ConflictResponse, ErrorResponse, UpsertRequest, UpsertResponse, VersionedData};
+
+fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[derive(Debug)]
+pub enum RepoError {
+    NotFound,
+    AlreadyExists,
+    /// `base_version` didn't match the stored record
+    Conflict(ConflictResponse),
+    /// Unconditional write over a record in the trash; restore it first
+    Trashed,
+    Storage(anyhow::Error),
+}
+
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepoError::NotFound => write!(f, "record not found"),
+            RepoError::AlreadyExists => write!(f, "record already exists"),
+            RepoError::Conflict(c) => write!(f, "version conflict (server version {})", c.server_version),
+            RepoError::Trashed => write!(f, "record is in the trash"),
+            RepoError::Storage(e) => write!(f, "storage error: {}", e),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RepoError {
+    fn from(e: anyhow::Error) -> Self {
+        RepoError::Storage(e)
+    }
+}
+
+impl From<serde_json::Error> for RepoError {
+    fn from(e: serde_json::Error) -> Self {
+        RepoError::Storage(e.into())
+    }
+}
+
+impl ResponseError for RepoError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RepoError::NotFound => StatusCode::NOT_FOUND,
+            RepoError::AlreadyExists | RepoError::Conflict(_) | RepoError::Trashed => StatusCode::CONFLICT,
+            RepoError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            RepoError::NotFound => HttpResponse::NotFound().json(ErrorResponse::new("not_found", "Record not found")),
+            RepoError::AlreadyExists => {
+                HttpResponse::Conflict().json(ErrorResponse::new("already_exists", "Record already exists"))
+            }
+            RepoError::Conflict(c) => HttpResponse::Conflict().json(c),
+            RepoError::Trashed => HttpResponse::Conflict()
+                .json(ErrorResponse::new("record_trashed", "Record is in the trash; restore it first")),
+            RepoError::Storage(e) => {
+                log::error!("Repository storage error: {}", e);
+                HttpResponse::InternalServerError().json(ErrorResponse::new("internal_error", "Internal server error"))
+            }
+        }
+    }
+}
+
+impl<T> From<&VersionedData<T>> for UpsertResponse {
+    fn from(v: &VersionedData<T>) -> Self {
+        UpsertResponse { version: v.version, updated_at: v.updated_at }
+    }
+}
+
+/// What a write expects the stored version to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    /// Overwrite whatever is there
+    Any,
+    /// Record must not exist yet
+    Absent,
+    /// Record must exist (any version)
+    Present,
+    /// Record must be at exactly this version; 0 means "must not exist yet"
+    Version(u64),
+}
+
+/// Collection of `VersionedData<T>` records. Every write bumps `version` and
+/// `updated_at` (ms since epoch); conditional writes are compare-and-swap on
+/// `version` inside a single sled transaction.
+///
+/// Build it over a tenant-scoped handle (`TenantDb`) for org data.
+pub struct Repository<T> {
+    db: Database,
+    collection: String,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Repository<T> {
+    fn clone(&self) -> Self {
+        Self { db: self.db.clone(), collection: self.collection.clone(), _marker: PhantomData }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Clone> Repository<T> {
+    pub fn new(db: &Database, collection: &str) -> Self {
+        Self { db: db.clone(), collection: collection.to_string(), _marker: PhantomData }
+    }
+
+    /// A live record; records in the trash read as missing
+    pub fn get(&self, id: &str) -> Result<Option<VersionedData<T>>, RepoError> {
+        let record: Option<VersionedData<T>> = self.db.get(&self.collection, id)?;
+        Ok(record.filter(|r| !r.is_deleted()))
+    }
+
+    /// One page of live records. Filter fields are relative to the record's
+    /// `data`, so `status:eq:"paid"` matches `data.status`.
+    pub fn query(&self, q: &ListQuery) -> Result<Page<VersionedData<T>>, RepoError> {
+        self.query_where(q, FilterOp::Eq)
+    }
+
+    /// One page of the trash, filtered like [`Repository::query`]
+    pub fn query_trash(&self, q: &ListQuery) -> Result<Page<VersionedData<T>>, RepoError> {
+        self.query_where(q, FilterOp::Ne)
+    }
+
+    /// `deleted`: `Eq` for live records (`deleted_at` null), `Ne` for the trash
+    fn query_where(&self, q: &ListQuery, deleted: FilterOp) -> Result<Page<VersionedData<T>>, RepoError> {
+        let mut q = q.clone();
+        for filter in &mut q.filters {
+            filter.field = format!("data.{}", filter.field);
+        }
+        q.filters.push(Filter { field: "deleted_at".into(), op: deleted, value: serde_json::Value::Null });
+        Ok(self.db.query(&self.collection, &q)?)
+    }
+
+    /// Insert a new record at version 1; fails if the id is taken
+    pub fn create(&self, id: &str, data: T) -> Result<VersionedData<T>, RepoError> {
+        self.write(id, data, Expect::Absent)
+    }
+
+    /// Compare-and-swap update: succeeds only if the stored version is `base_version`
+    pub fn update(&self, id: &str, data: T, base_version: u64) -> Result<VersionedData<T>, RepoError> {
+        self.write(id, data, Expect::Version(base_version))
+    }
+
+    /// Create or replace. With `base_version` set this is a CAS write
+    /// (`0` = create only); without it the write is unconditional, but
+    /// still won't replace a record in the trash.
+    pub fn upsert(&self, id: &str, request: UpsertRequest<T>) -> Result<VersionedData<T>, RepoError> {
+        let expect = request.base_version.map(Expect::Version).unwrap_or(Expect::Any);
+        self.write(id, request.data, expect)
+    }
+
+    /// Replace an existing record regardless of its version
+    pub fn replace(&self, id: &str, data: T) -> Result<VersionedData<T>, RepoError> {
+        self.write(id, data, Expect::Present)
+    }
+
+    /// Move a live record to the trash, optionally only if the stored
+    /// version is `base_version`. Counts as a write (bumps the version) and
+    /// replicates as an update, not a delete. Returns whether a record was trashed.
+    pub fn soft_delete(&self, id: &str, base_version: Option<u64>, deleted_by: &str) -> Result<bool, RepoError> {
+        let now = current_timestamp();
+        let outcome = self.db.modify(&self.collection, id, |current| {
+            let Some(mut record) = parse_meta(current)?.filter(|r| !r.is_deleted()) else {
+                return Err(RepoError::NotFound);
+            };
+            if base_version.is_some_and(|base| base != record.version) {
+                return Err(conflict(&record));
+            }
+            record.version += 1;
+            record.updated_at = now;
+            record.deleted_at = Some(now);
+            record.deleted_by = Some(deleted_by.to_string());
+            serde_json::to_vec(&record).map(Some).map_err(|e| RepoError::Storage(e.into()))
+        })?;
+        match outcome {
+            Ok(_) => Ok(true),
+            Err(RepoError::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Take a record out of the trash
+    pub fn restore(&self, id: &str) -> Result<VersionedData<T>, RepoError> {
+        let now = current_timestamp();
+        let outcome = self.db.modify(&self.collection, id, |current| {
+            let Some(mut record) = parse_meta(current)?.filter(|r| r.is_deleted()) else {
+                return Err(RepoError::NotFound);
+            };
+            record.version += 1;
+            record.updated_at = now;
+            record.deleted_at = None;
+            record.deleted_by = None;
+            serde_json::to_vec(&record).map(Some).map_err(|e| RepoError::Storage(e.into()))
+        })??;
+        let bytes = outcome.current.expect("restore stores a record");
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Permanently delete a record from the trash, along with its revision
+    /// history, if it was trashed at or before `trashed_before` (ms since
+    /// epoch). Returns whether it was removed.
+    pub fn purge(&self, id: &str, trashed_before: u64) -> Result<bool, RepoError> {
+        let outcome = self.db.modify(&self.collection, id, |current| {
+            match parse_meta(current)?.and_then(|r| r.deleted_at) {
+                Some(at) if at <= trashed_before => Ok(None),
+                _ => Err(RepoError::NotFound),
+            }
+        })?;
+        match outcome {
+            Ok(_) => {
+                // Gone for good: its revisions would otherwise outlive it
+                history::forget(&self.db, &self.collection, id)?;
+                Ok(true)
+            }
+            Err(RepoError::NotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Purge everything trashed at or before `trashed_before`. On a global
+    /// (non-tenant) handle this covers every org. Returns how many went.
+    pub fn purge_expired(&self, trashed_before: u64) -> Result<usize, RepoError> {
+        let mut purged = 0;
+        for id in trash::expired(&self.db, &self.collection, trashed_before)? {
+            if self.purge(&id, trashed_before)? {
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
+    /// Delete, optionally only if the stored version is `base_version`.
+    /// Returns whether a record was removed.
+    pub fn delete(&self, id: &str, base_version: Option<u64>) -> Result<bool, RepoError> {
+        let outcome = self.db.modify(&self.collection, id, |current| {
+            let Some(bytes) = current else { return Ok(None) };
+            if let Some(base) = base_version {
+                let existing: VersionedData<serde_json::Value> = serde_json::from_slice(bytes).map_err(|e| RepoError::Storage(e.into()))?;
+                if existing.version != base {
+                    return Err(conflict(&existing));
+                }
+            }
+            Ok(None)
+        })??;
+        Ok(outcome.previous.is_some())
+    }
+
+    fn write(&self, id: &str, data: T, expect: Expect) -> Result<VersionedData<T>, RepoError> {
+        let now = current_timestamp();
+        let outcome = self.db.modify(&self.collection, id, |current| {
+            // Only the metadata is needed to decide; leave `data` untyped
+            let existing = parse_meta(current)?;
+            let current_version = existing.as_ref().map(|e| e.version).unwrap_or(0);
+            match (expect, &existing) {
+                (Expect::Absent, Some(_)) => return Err(RepoError::AlreadyExists),
+                (Expect::Present, None) => return Err(RepoError::NotFound),
+                (Expect::Version(base), Some(e)) if base != current_version => return Err(conflict(e)),
+                (Expect::Version(base), None) if base != 0 => return Err(RepoError::NotFound),
+                _ => {}
+            }
+            // Conditional writes don't see the trash, and an unconditional
+            // one must not quietly bring a record back: that is `restore`
+            if existing.as_ref().is_some_and(|e| e.is_deleted()) {
+                return Err(if expect == Expect::Any { RepoError::Trashed } else { RepoError::NotFound });
+            }
+            let record = VersionedData {
+                data: data.clone(),
+                version: current_version + 1,
+                updated_at: now,
+                created_at: existing.as_ref().map(|e| e.created_at).unwrap_or(now),
+                deleted_at: None,
+                deleted_by: None,
+            };
+            serde_json::to_vec(&record).map(Some).map_err(|e| RepoError::Storage(e.into()))
+        })??;
+
+        let bytes = outcome.current.expect("write stores a record");
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+/// Stored record with `data` left untyped
+fn parse_meta(current: Option<&[u8]>) -> Result<Option<VersionedData<serde_json::Value>>, RepoError> {
+    current.map(serde_json::from_slice).transpose().map_err(|e| RepoError::Storage(e.into()))
+}
+
+fn conflict(existing: &VersionedData<serde_json::Value>) -> RepoError {
+    RepoError::Conflict(ConflictResponse {
+        server_version: existing.version,
+        server_updated_at: existing.updated_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use tempfile::tempdir;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Note {
+        text: String,
+    }
+
+    fn note(text: &str) -> Note {
+        Note { text: text.into() }
+    }
+
+    #[test]
+    fn cas_updates_detect_conflicts() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        let repo: Repository<Note> = Repository::new(&db, "notes");
+
+        let v1 = repo.create("n1", note("first")).unwrap();
+        assert_eq!(v1.version, 1);
+        assert!(matches!(repo.create("n1", note("again")), Err(RepoError::AlreadyExists)));
+
+        let v2 = repo.update("n1", note("second"), 1).unwrap();
+        assert_eq!(v2.version, 2);
+        assert_eq!(v2.created_at, v1.created_at);
+
+        // A writer still holding version 1 loses
+        match repo.update("n1", note("stale"), 1) {
+            Err(RepoError::Conflict(c)) => assert_eq!(c.server_version, 2),
+            other => panic!("expected conflict, got {:?}", other),
+        }
+        assert_eq!(repo.get("n1").unwrap().unwrap().data, note("second"));
+
+        let upsert = |base| UpsertRequest { data: note("upserted"), base_version: base, updated_at_client: None };
+        assert!(matches!(repo.upsert("n2", upsert(Some(3))), Err(RepoError::NotFound)));
+        assert_eq!(repo.upsert("n2", upsert(Some(0))).unwrap().version, 1);
+        assert_eq!(repo.upsert("n2", upsert(None)).unwrap().version, 2);
+
+        assert!(matches!(repo.delete("n1", Some(1)), Err(RepoError::Conflict(_))));
+        assert!(repo.delete("n1", Some(2)).unwrap());
+        assert!(!repo.delete("n1", None).unwrap());
+        assert_eq!(repo.query(&ListQuery::default()).unwrap().items.len(), 1);
+    }
+
+    #[test]
+    fn soft_delete_moves_records_to_the_trash() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        let acme: Repository<Note> = Repository::new(&db.for_tenant("acme"), "quotes");
+        acme.create("q1", note("kept")).unwrap();
+        acme.create("q2", note("trashed")).unwrap();
+
+        assert!(matches!(acme.soft_delete("q2", Some(7), "u1"), Err(RepoError::Conflict(_))));
+        assert!(acme.soft_delete("q2", Some(1), "u1").unwrap());
+        assert!(!acme.soft_delete("q2", None, "u1").unwrap());
+        assert!(acme.get("q2").unwrap().is_none());
+        assert_eq!(acme.query(&ListQuery::default()).unwrap().items.len(), 1);
+        let trash = acme.query_trash(&ListQuery::default()).unwrap();
+        assert_eq!(trash.items.len(), 1);
+        assert_eq!(trash.items[0].1.deleted_by.as_deref(), Some("u1"));
+        // The id stays taken and CAS writes don't reach into the trash
+        assert!(matches!(acme.create("q2", note("new")), Err(RepoError::AlreadyExists)));
+        assert!(matches!(acme.update("q2", note("new"), 2), Err(RepoError::NotFound)));
+        let blind = UpsertRequest { data: note("new"), base_version: None, updated_at_client: None };
+        assert!(matches!(acme.upsert("q2", blind), Err(RepoError::Trashed)));
+
+        let restored = acme.restore("q2").unwrap();
+        assert_eq!((restored.version, restored.deleted_at), (3, None));
+        assert!(matches!(acme.restore("q2"), Err(RepoError::NotFound)));
+
+        // The purge job runs on the global handle and only takes expired records
+        acme.soft_delete("q1", None, "u1").unwrap();
+        let trashed_at = acme.query_trash(&ListQuery::default()).unwrap().items[0].1.deleted_at.unwrap();
+        let global: Repository<Note> = Repository::new(&db, "quotes");
+        assert_eq!(global.purge_expired(trashed_at - 1).unwrap(), 0);
+        assert_eq!(global.purge_expired(trashed_at).unwrap(), 1);
+        assert!(acme.query_trash(&ListQuery::default()).unwrap().items.is_empty());
+        assert!(acme.get("q2").unwrap().is_some());
+    }
+
+    #[test]
+    fn conflict_maps_to_409_with_server_version() {
+        let err = RepoError::Conflict(ConflictResponse { server_version: 7, server_updated_at: 42 });
+        let resp = err.error_response();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body = actix_web::body::to_bytes(resp.into_body());
+        let body = futures::executor::block_on(body).ok().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["serverVersion"], 7);
+        assert_eq!(json["serverUpdatedAt"], 42);
+    }
+}
@@ -0,0 +1,154 @@
+// Frontend bundle compiled into the binary (`embed-static` feature)
+//
+// The `static/` tree (the built frontend) is embedded at compile time, so a
+// release binary serves the app from any working directory. Responses follow
+// the on-disk serving in `static_files`: precompressed `.br`/`.gz` siblings,
+// the same Cache-Control rules, strong ETags (from the content hash) and
+// single byte ranges. Debug builds read the files from disk at runtime.
+// Without a frontend build the bundle is empty and only the API is served;
+// tests embed the small fixture bundle in `tests/fixtures/static/` instead.
+use actix_files::HttpRange;
+use actix_web::http::header::{self, EntityTag, IfNoneMatch};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use rust_embed::{EmbeddedFile, Embed};
+use std::borrow::Cow;
+
+use super::static_files::{accepts, accepts_any, cache_control, is_compressible, ON_THE_FLY, PRECOMPRESSED};
+
+#[derive(Embed)]
+#[cfg_attr(not(test), folder = "static/", allow_missing = true)]
+#[cfg_attr(test, folder = "tests/fixtures/static/")]
+struct Bundle;
+
+/// index.html of the bundle
+pub fn index() -> Option<String> {
+    Bundle::get("index.html").map(|f| String::from_utf8_lossy(&f.data).into_owned())
+}
+
+fn etag(file: &EmbeddedFile) -> EntityTag {
+    let hash = file.metadata.sha256_hash();
+    EntityTag::new_strong(hash[..16].iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn not_modified(req: &HttpRequest, etag: &EntityTag) -> bool {
+    match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(items)) => items.iter().any(|item| item.weak_eq(etag)),
+        None => false,
+    }
+}
+
+fn bytes(data: Cow<'static, [u8]>) -> Bytes {
+    match data {
+        Cow::Borrowed(data) => Bytes::from_static(data),
+        Cow::Owned(data) => Bytes::from(data),
+    }
+}
+
+/// Serve `path` from the bundle, or `None` if it has no such file
+pub fn serve(path: &str, req: &HttpRequest) -> Option<HttpResponse> {
+    let plain = Bundle::get(path)?;
+    let ext = path.rsplit_once('.').map_or("", |(_, ext)| ext);
+    let mime = actix_files::file_extension_to_mime(ext);
+
+    // As on disk, ranges always address the plain file
+    let ranged = req.headers().contains_key(header::RANGE);
+    let mut has_variant = false;
+    let mut chosen = None;
+    for (ext, coding, _) in PRECOMPRESSED {
+        let Some(variant) = Bundle::get(&format!("{}.{}", path, ext)) else {
+            continue;
+        };
+        has_variant = true;
+        if !ranged && accepts(req, coding) {
+            chosen = Some((variant, *coding));
+            break;
+        }
+    }
+    let compressed_later = chosen.is_none() && !ranged && is_compressible(&mime) && accepts_any(req, ON_THE_FLY);
+    let (file, coding) = match chosen {
+        Some((variant, coding)) => (variant, Some(coding)),
+        None => (plain, None),
+    };
+
+    let etag = etag(&file);
+    let mut res = HttpResponse::Ok();
+    res.insert_header((header::CONTENT_TYPE, mime.to_string()))
+        .insert_header((header::ETAG, etag.to_string()))
+        .insert_header((header::CACHE_CONTROL, cache_control(path)))
+        .insert_header((header::ACCEPT_RANGES, "bytes"));
+    if let Some(coding) = coding {
+        res.insert_header((header::CONTENT_ENCODING, coding));
+    }
+    if has_variant && !compressed_later {
+        res.append_header((header::VARY, "Accept-Encoding"));
+    }
+    if not_modified(req, &etag) {
+        return Some(res.status(StatusCode::NOT_MODIFIED).finish());
+    }
+
+    let data = bytes(file.data);
+    if let Some(range) = req.headers().get(header::RANGE) {
+        let total = data.len() as u64;
+        let range = range.to_str().ok().and_then(|r| HttpRange::parse(r, total).ok()).and_then(|r| r.first().copied());
+        let Some(range) = range else {
+            res.insert_header((header::CONTENT_RANGE, format!("bytes */{}", total)));
+            return Some(res.status(StatusCode::RANGE_NOT_SATISFIABLE).finish());
+        };
+        let end = range.start + range.length;
+        // Identity keeps `Compress` off partial content
+        res.insert_header((header::CONTENT_ENCODING, "identity"))
+            .insert_header((header::CONTENT_RANGE, format!("bytes {}-{}/{}", range.start, end - 1, total)))
+            .status(StatusCode::PARTIAL_CONTENT);
+        return Some(res.body(data.slice(range.start as usize..end as usize)));
+    }
+    Some(res.body(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[actix_web::test]
+    async fn bundle_files_get_etags_ranges_and_revalidation() {
+        let get = TestRequest::default;
+        assert!(serve("no/such/file.js", &get().to_http_request()).is_none());
+        assert!(index().unwrap().contains("/assets/app-1a2b3c4d.js"));
+
+        let path = "assets/app-1a2b3c4d.js";
+        let len = Bundle::get(path).unwrap().data.len();
+        let res = serve(path, &get().to_http_request()).unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers().get("content-type").unwrap(), "text/javascript");
+        assert_eq!(res.headers().get("cache-control").unwrap(), "public, max-age=31536000, immutable");
+        assert!(res.headers().get("content-encoding").is_none());
+        let etag = res.headers().get("etag").unwrap().clone();
+        assert!(!etag.to_str().unwrap().starts_with("W/"));
+
+        let req = get().insert_header(("if-none-match", etag.clone())).to_http_request();
+        assert_eq!(serve(path, &req).unwrap().status(), 304);
+
+        // The precompressed sibling is its own representation
+        let req = get().insert_header(("accept-encoding", "gzip, br")).to_http_request();
+        let res = serve(path, &req).unwrap();
+        assert_eq!(res.headers().get("content-encoding").unwrap(), "br");
+        assert_eq!(res.headers().get("vary").unwrap(), "Accept-Encoding");
+        assert_ne!(res.headers().get("etag").unwrap(), &etag);
+
+        // Ranges address the plain file, even when a sibling is acceptable
+        let req = get().insert_header(("range", "bytes=1-")).insert_header(("accept-encoding", "br")).to_http_request();
+        let res = serve(path, &req).unwrap();
+        assert_eq!(res.status(), 206);
+        assert_eq!(res.headers().get("content-encoding").unwrap(), "identity");
+        assert_eq!(res.headers().get("content-range").unwrap().to_str().unwrap(), format!("bytes 1-{}/{}", len - 1, len));
+        let req = get().insert_header(("range", format!("bytes={}-", len + 10))).to_http_request();
+        assert_eq!(serve(path, &req).unwrap().status(), 416);
+
+        // Outside `assets/` names aren't trusted to change with the content
+        let res = serve("index.html", &get().to_http_request()).unwrap();
+        assert_eq!(res.headers().get("cache-control").unwrap(), "no-cache");
+    }
+}
@@ -1,25 +1,303 @@
-// Health check endpoint
-use actix_web::{get, HttpResponse, Result};
-use crate::types::HealthResponse;
+// Health check endpoints
+//
+// `/livez` only says the process is serving requests; orchestrators restart
+// on failure, so it must not depend on anything outside the process.
+// `/readyz` checks the components the service relies on and reports each
+// with an overall verdict: `ok`, `degraded` (serving, but something needs
+// attention) or `unhealthy` (503, take it out of rotation). Paths, figures
+// and error text are only included for callers presenting the metrics token
+// (`Authorization: Bearer <METRICS_TOKEN>`); everyone else gets the verdicts.
+// `HEALTH_CHECK_ENABLED=false` turns all of them off (404).
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
 use chrono::Utc;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
 
-#[get("/healthz")]
-pub async fn healthz() -> Result<HttpResponse> {
-    let response = HealthResponse {
+use crate::backup::BackupManager;
+use crate::config::{AppConfig, TokenMode};
+use crate::db::Database;
+use crate::routes::metrics::presents_scrape_token;
+use crate::types::{ComponentHealth, HealthResponse, HealthStatus, ReadinessResponse};
+
+const PROBE_TREE: &str = "__health";
+/// Per replica; all replicas are pinged at once, so this bounds the whole check
+const REPLICA_TIMEOUT: Duration = Duration::from_millis(500);
+
+fn liveness(cfg: &AppConfig) -> HttpResponse {
+    if !cfg.security.health_check_enabled {
+        return HttpResponse::NotFound().finish();
+    }
+    HttpResponse::Ok().json(HealthResponse {
         status: "ok".to_string(),
         time: Utc::now().to_rfc3339(),
         version: option_env!("CARGO_PKG_VERSION").map(|s| s.to_string()),
-    };
-    Ok(HttpResponse::Ok().json(response))
+    })
+}
+
+#[get("/healthz")]
+pub async fn healthz(cfg: web::Data<AppConfig>) -> Result<HttpResponse> {
+    Ok(liveness(&cfg))
 }
 
 #[get("/health")]
-pub async fn health() -> Result<HttpResponse> {
+pub async fn health(cfg: web::Data<AppConfig>) -> Result<HttpResponse> {
     // Alias for compatibility
-    let response = HealthResponse {
-        status: "ok".to_string(),
+    Ok(liveness(&cfg))
+}
+
+#[get("/livez")]
+pub async fn livez(cfg: web::Data<AppConfig>) -> Result<HttpResponse> {
+    Ok(liveness(&cfg))
+}
+
+#[get("/readyz")]
+pub async fn readyz(
+    req: HttpRequest,
+    cfg: web::Data<AppConfig>,
+    db: web::Data<Database>,
+    backups: web::Data<BackupManager>,
+) -> Result<HttpResponse> {
+    if !cfg.security.health_check_enabled {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let mut checks = BTreeMap::new();
+    checks.insert("sled".to_string(), check_sled(&db).await);
+    let min_free = cfg.health_min_free_disk_mb * 1024 * 1024;
+    checks.insert("disk_data".to_string(), check_disk(Path::new(&cfg.sled_path), min_free));
+    checks.insert("disk_backups".to_string(), check_disk(Path::new(&cfg.backup_dir), min_free));
+    checks.insert("backups".to_string(), check_backups(&cfg, &backups));
+    checks.insert("replication".to_string(), check_replication(&cfg, &db).await);
+    checks.insert("signing_key".to_string(), check_signing_key(&cfg));
+
+    let status = checks.values().map(|c| c.status).max().unwrap_or(HealthStatus::Ok);
+    if !presents_scrape_token(&req, &cfg) {
+        for check in checks.values_mut() {
+            check.details = None;
+        }
+    }
+    let response = ReadinessResponse {
+        status,
         time: Utc::now().to_rfc3339(),
         version: option_env!("CARGO_PKG_VERSION").map(|s| s.to_string()),
+        checks,
     };
-    Ok(HttpResponse::Ok().json(response))
+    let mut builder = match status {
+        HealthStatus::Unhealthy => HttpResponse::ServiceUnavailable(),
+        _ => HttpResponse::Ok(),
+    };
+    Ok(builder.insert_header(("Cache-Control", "no-store")).json(response))
+}
+
+fn component(status: HealthStatus, message: Option<String>, details: Option<serde_json::Value>) -> ComponentHealth {
+    ComponentHealth { status, message, details }
+}
+
+/// Write, read back and remove a probe key
+async fn check_sled(db: &Database) -> ComponentHealth {
+    let started = std::time::Instant::now();
+    let probe = db
+        .run(|db| {
+            let tree = db.db.open_tree(PROBE_TREE)?;
+            let value = Utc::now().timestamp_nanos_opt().unwrap_or_default().to_be_bytes();
+            tree.insert("probe", &value)?;
+            let read = tree.get("probe")?;
+            tree.remove("probe")?;
+            if read.as_deref() != Some(&value[..]) {
+                anyhow::bail!("probe read back a different value");
+            }
+            Ok(())
+        })
+        .await;
+    let latency = started.elapsed().as_millis() as u64;
+    match probe {
+        Ok(()) => component(HealthStatus::Ok, None, Some(json!({"latencyMs": latency}))),
+        Err(e) => component(
+            HealthStatus::Unhealthy,
+            Some("storage probe failed".into()),
+            Some(json!({"latencyMs": latency, "error": e.to_string()})),
+        ),
+    }
+}
+
+/// Free bytes on the filesystem holding `path` (or its nearest existing parent)
+#[cfg(unix)]
+fn free_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let existing = path.ancestors().find(|p| p.exists()).filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let c_path = std::ffi::CString::new(existing.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a valid out pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_space(_path: &Path) -> std::io::Result<u64> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "not supported on this platform"))
+}
+
+fn check_disk(path: &Path, min_free: u64) -> ComponentHealth {
+    match free_space(path) {
+        Ok(free) => {
+            let details = Some(json!({"path": path.display().to_string(), "freeBytes": free, "minFreeBytes": min_free}));
+            if free < min_free {
+                component(HealthStatus::Degraded, Some("low free disk space".into()), details)
+            } else {
+                component(HealthStatus::Ok, None, details)
+            }
+        }
+        Err(e) => component(
+            HealthStatus::Degraded,
+            Some("cannot read free space".into()),
+            Some(json!({"path": path.display().to_string(), "error": e.to_string()})),
+        ),
+    }
+}
+
+fn check_backups(cfg: &AppConfig, backups: &BackupManager) -> ComponentHealth {
+    let (Some(interval), Some(max_age)) = (cfg.backup_interval, cfg.backup_max_age) else {
+        return component(HealthStatus::Ok, Some("periodic backups disabled".into()), None);
+    };
+    let now = Utc::now();
+    let age = |since: chrono::DateTime<Utc>| (now - since).to_std().unwrap_or_default();
+    let details = json!({
+        "intervalSeconds": interval.as_secs(),
+        "maxAgeSeconds": max_age.as_secs(),
+        "lastSuccess": backups.last_success().map(|t| t.to_rfc3339()),
+        "ageSeconds": backups.last_success().map(|t| age(t).as_secs()),
+    });
+    match backups.last_success() {
+        Some(last) if age(last) > max_age => {
+            component(HealthStatus::Degraded, Some("last successful backup is too old".into()), Some(details))
+        }
+        Some(_) => component(HealthStatus::Ok, None, Some(details)),
+        // Give a fresh process one full window before complaining
+        None if age(backups.started_at()) > max_age => {
+            component(HealthStatus::Degraded, Some("no successful backup yet".into()), Some(details))
+        }
+        None => component(HealthStatus::Ok, Some("no backup yet".into()), Some(details)),
+    }
+}
+
+/// Replication is asynchronous, so failing replicas degrade but never fail readiness
+async fn check_replication(cfg: &AppConfig, db: &Database) -> ComponentHealth {
+    let Some(replicator) = db.replicator() else {
+        return if cfg.database_sync_on && !cfg.pg_conns.is_empty() {
+            component(HealthStatus::Degraded, Some("replicator failed to initialize".into()), None)
+        } else {
+            component(HealthStatus::Ok, Some("replication disabled".into()), None)
+        };
+    };
+    let results = replicator.ping(REPLICA_TIMEOUT).await;
+    let pools: Vec<_> = results
+        .iter()
+        .enumerate()
+        .map(|(i, r)| match r {
+            Ok(()) => json!({"pool": i, "status": "ok"}),
+            Err(e) => json!({"pool": i, "status": "error", "error": e.to_string()}),
+        })
+        .collect();
+    let failed = results.iter().filter(|r| r.is_err()).count();
+    if failed == 0 {
+        component(HealthStatus::Ok, None, Some(json!({"pools": pools})))
+    } else {
+        component(
+            HealthStatus::Degraded,
+            Some(format!("{} of {} replica(s) unreachable", failed, results.len())),
+            Some(json!({"pools": pools})),
+        )
+    }
+}
+
+/// Without a usable signing key no one can log in
+fn check_signing_key(cfg: &AppConfig) -> ComponentHealth {
+    let mode = Some(json!({"mode": format!("{:?}", cfg.security.token_mode)}));
+    let missing = match cfg.security.token_mode {
+        TokenMode::PasetoV4Local => crate::handlers::auth::paseto_key(cfg).is_none(),
+        TokenMode::PasetoV4Public | TokenMode::JwtEdDsa => cfg.security.keyring.active().is_none(),
+        TokenMode::JwtHmac => {
+            if cfg.security.paseto_v4_local_key_hex.is_empty() && cfg.security.access_token.is_empty() {
+                return component(HealthStatus::Degraded, Some("signing with the built-in fallback secret".into()), mode);
+            }
+            false
+        }
+    };
+    if missing {
+        component(HealthStatus::Unhealthy, Some("no valid signing key configured".into()), mode)
+    } else {
+        component(HealthStatus::Ok, None, mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use tempfile::tempdir;
+
+    #[actix_web::test]
+    async fn readyz_reports_components_and_verdict() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        let mut cfg = crate::config::load_config_from_file("/nonexistent/.env");
+        cfg.sled_path = dir.path().join("sled").to_string_lossy().into_owned();
+        cfg.backup_dir = dir.path().join("backups").to_string_lossy().into_owned();
+        cfg.health_min_free_disk_mb = 0;
+        cfg.backup_interval = None;
+        cfg.backup_max_age = None;
+        cfg.database_sync_on = false;
+        cfg.security.token_mode = TokenMode::PasetoV4Local;
+        cfg.security.paseto_v4_local_key_hex = "142f46b1b4acb0946e0d9413f29b331db345cf664b9307165eab7531fa32d8bd".into();
+        cfg.security.metrics_token = "scrape-secret".into();
+        let backups = BackupManager::new(&cfg.sled_path, &cfg.backup_dir, "backup_{{timestamp}}");
+
+        let cfg_data = web::Data::new(cfg.clone());
+        let app = test::init_service(
+            App::new()
+                .app_data(cfg_data.clone())
+                .app_data(web::Data::new(db.clone()))
+                .app_data(web::Data::new(backups))
+                .service(readyz)
+                .service(livez),
+        )
+        .await;
+
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/readyz").to_request()).await;
+        assert_eq!(resp.status(), 200);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "ok");
+        for name in ["sled", "disk_data", "disk_backups", "backups", "replication", "signing_key"] {
+            assert_eq!(body["checks"][name]["status"], "ok", "{}: {}", name, body["checks"][name]);
+            // Anonymous callers only get the verdicts
+            assert!(body["checks"][name].get("details").is_none(), "{}: {}", name, body["checks"][name]);
+        }
+        let req = test::TestRequest::get()
+            .uri("/readyz")
+            .insert_header(("Authorization", "Bearer scrape-secret"))
+            .to_request();
+        let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+        assert!(body["checks"]["disk_data"]["details"]["freeBytes"].is_u64());
+
+        // A missing signing key makes the instance unready
+        cfg.security.paseto_v4_local_key_hex = String::new();
+        assert_eq!(check_signing_key(&cfg).status, HealthStatus::Unhealthy);
+        // A stale backup only degrades it
+        cfg.backup_interval = Some(Duration::from_secs(60));
+        cfg.backup_max_age = Some(Duration::from_secs(120));
+        let stale = BackupManager::new(&cfg.sled_path, &cfg.backup_dir, "backup_{{timestamp}}");
+        assert_eq!(check_backups(&cfg, &stale).status, HealthStatus::Ok);
+        stale.mark_success(Utc::now() - chrono::Duration::minutes(10));
+        assert_eq!(check_backups(&cfg, &stale).status, HealthStatus::Degraded);
+
+        // Disabled health checks are hidden
+        let mut disabled = cfg.clone();
+        disabled.security.health_check_enabled = false;
+        assert_eq!(liveness(&disabled).status(), 404);
+    }
 }
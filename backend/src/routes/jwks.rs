@@ -0,0 +1,12 @@
+// Public key discovery for the asymmetric token modes
+use actix_web::{get, web, HttpResponse, Result};
+use crate::config::AppConfig;
+
+/// JWKS document listing every non-retired signing key, so other services
+/// can verify our tokens without holding the signing key
+#[get("/.well-known/jwks.json")]
+pub async fn jwks(cfg: web::Data<AppConfig>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=300"))
+        .json(cfg.security.keyring.jwks()))
+}
@@ -0,0 +1,104 @@
+// Prometheus scrape endpoint
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse, Result};
+use crate::config::AppConfig;
+use crate::db::Database;
+use crate::handlers::api_keys::constant_time_eq;
+use crate::metrics;
+use crate::types::ErrorResponse;
+
+/// Whether `req` carries `Authorization: Bearer <METRICS_TOKEN>` (never
+/// true while no token is configured)
+pub(crate) fn presents_scrape_token(req: &HttpRequest, cfg: &AppConfig) -> bool {
+    let token = &cfg.security.metrics_token;
+    let presented = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+    !token.is_empty() && constant_time_eq(presented.as_bytes(), token.as_bytes())
+}
+
+/// Metrics in the Prometheus text format. Scrapers authenticate with
+/// `Authorization: Bearer <METRICS_TOKEN>`, a credential separate from user
+/// sessions and API keys; without a configured token the endpoint stays hidden.
+#[get("/metrics")]
+pub async fn scrape(req: HttpRequest, cfg: web::Data<AppConfig>, db: web::Data<Database>) -> Result<HttpResponse> {
+    if !cfg.security.metrics_enabled || cfg.security.metrics_token.is_empty() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    if !presents_scrape_token(&req, &cfg) {
+        return Ok(HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+            .json(ErrorResponse::new("unauthorized", "Invalid scrape token")));
+    }
+
+    match db.run(|db| metrics::render(&db)).await {
+        Ok(body) => Ok(HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .content_type("text/plain; version=0.0.4; charset=utf-8")
+            .body(body)),
+        Err(e) => {
+            log::error!("Failed to render metrics: {}", e);
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse::new("internal_error", "Internal server error")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{dev::Service, test, App};
+    use tempfile::tempdir;
+
+    #[actix_web::test]
+    async fn requires_the_scrape_token() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        let mut cfg = crate::config::load_config_from_file("/nonexistent/.env");
+        cfg.security.metrics_enabled = true;
+        cfg.security.metrics_token = "scrape-secret".into();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(db))
+                .app_data(web::Data::new(cfg))
+                // Stands in for a middleware that rejects with an `Err`
+                .wrap_fn(|req, srv| {
+                    let rejected = req.path() == "/denied";
+                    let fut = srv.call(req);
+                    async move {
+                        if rejected {
+                            return Err(actix_web::error::ErrorForbidden("denied"));
+                        }
+                        fut.await
+                    }
+                })
+                .wrap(crate::middleware::metrics::RequestMetrics)
+                .service(scrape)
+                .route("/denied", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/denied").to_request();
+        assert!(test::try_call_service(&app, req).await.is_err());
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+        let req = test::TestRequest::get()
+            .uri("/metrics")
+            .insert_header(("Authorization", "Bearer wrong"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+        let req = test::TestRequest::get()
+            .uri("/metrics")
+            .insert_header(("Authorization", "Bearer scrape-secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("http_requests_total{method=\"GET\",route=\"/metrics\",status=\"401\"} 2"));
+        assert!(body.contains("http_requests_total{method=\"GET\",route=\"/denied\",status=\"403\"} 1"));
+        assert!(body.contains("# TYPE sled_tree_keys gauge"));
+    }
+}
@@ -1,2 +1,6 @@
 pub mod health;
+pub mod jwks;
 pub mod static_files;
+#[cfg(feature = "embed-static")]
+pub mod embedded_static;
+pub mod metrics;
@@ -1,26 +1,242 @@
 // Static file serving with WASM support and SPA fallback
+//
+// Files under ./static are served with strong ETags, Last-Modified and byte
+// ranges (via `NamedFile`). A `.br` or `.gz` sibling written at build time is
+// sent instead of the file when the client accepts that encoding; everything
+// else is compressed on the fly by the `Compress` middleware on the route.
+// Filenames carrying a content hash are cached for a year, the rest must be
+// revalidated on every use. With the `embed-static` feature the same tree is
+// compiled into the binary and served from there (see `embedded_static`).
 use actix_files::NamedFile;
-use actix_web::{get, web, HttpRequest, HttpResponse, Result};
-use actix_web::http::header::{self, HeaderValue};
-use std::path::PathBuf;
-
-/// Serve WASM files with correct MIME type
-#[get("/assets/{file:.+\\.wasm}")]
-pub async fn serve_wasm(path: web::Path<String>, req: HttpRequest) -> Result<HttpResponse> {
-    let file_rel = path.into_inner();
-    let full_path = format!("./static/assets/{}", file_rel);
-    let file = NamedFile::open_async(full_path).await?;
-    let mut res = file.into_response(&req);
-    res.headers_mut().insert(
-        header::CONTENT_TYPE,
-        HeaderValue::from_static("application/wasm"),
-    );
-    Ok(res)
+use actix_web::{mime, HttpRequest, HttpResponse, Result};
+use actix_web::http::header::{self, ContentEncoding, HeaderValue};
+use std::path::{Component, Path, PathBuf};
+
+use crate::middleware::security;
+
+#[cfg_attr(feature = "embed-static", allow(dead_code))]
+const STATIC_ROOT: &str = "./static";
+const CACHE_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+const CACHE_REVALIDATE: &str = "no-cache";
+/// Encodings the `Compress` middleware can apply
+pub(crate) const ON_THE_FLY: &[&str] = &["br", "gzip", "zstd", "deflate"];
+/// Precompressed siblings, in order of preference
+pub(crate) const PRECOMPRESSED: &[(&str, &str, ContentEncoding)] =
+    &[("br", "br", ContentEncoding::Brotli), ("gz", "gzip", ContentEncoding::Gzip)];
+
+/// Where Vite writes content-hashed build output; files elsewhere (`public/`
+/// copies like `logo-20240101.png`) keep their names across builds
+const HASHED_DIR: &str = "assets/";
+
+/// Whether a file (path relative to the static root, `/`-separated) is build
+/// output named by its content hash, like `assets/index-BQ3x9aZ1.js`,
+/// `assets/main.3f2a9c1b.css` or `assets/frontend-a1b2c3d4e5f6_bg.wasm`: it
+/// lives under `assets/` and some segment of its name after the first is at
+/// least 8 word characters and contains a digit.
+pub fn is_hashed(path: &str) -> bool {
+    let Some(rest) = path.strip_prefix(HASHED_DIR) else { return false };
+    let file_name = rest.rsplit('/').next().unwrap_or(rest);
+    let segments: Vec<&str> = file_name.split(['.', '-']).collect();
+    segments.len() > 2
+        && segments[1..segments.len() - 1].iter().any(|s| {
+            s.len() >= 8
+                && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+                && s.bytes().any(|b| b.is_ascii_digit())
+        })
+}
+
+/// Cache-Control for a file (path relative to the static root): immutable
+/// when it is content-hashed build output
+pub(crate) fn cache_control(path: &str) -> &'static str {
+    if is_hashed(path) { CACHE_IMMUTABLE } else { CACHE_REVALIDATE }
+}
+
+/// Whether `Accept-Encoding` allows `coding`; an explicit entry wins over `*`
+pub(crate) fn accepts(req: &HttpRequest, coding: &str) -> bool {
+    let Some(value) = req.headers().get(header::ACCEPT_ENCODING).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let entries: Vec<(&str, bool)> = value
+        .split(',')
+        .map(|item| {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or("").trim();
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (name, q > 0.0)
+        })
+        .collect();
+    entries
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(coding))
+        .or_else(|| entries.iter().find(|(name, _)| *name == "*"))
+        .is_some_and(|(_, ok)| *ok)
+}
+
+pub(crate) fn accepts_any(req: &HttpRequest, codings: &[&str]) -> bool {
+    codings.iter().any(|c| accepts(req, c))
+}
+
+/// Mirrors what the `Compress` middleware leaves alone: images other than SVG, and video
+pub(crate) fn is_compressible(mime: &mime::Mime) -> bool {
+    match mime.type_() {
+        mime::IMAGE => mime.subtype() == mime::SVG,
+        mime::VIDEO => false,
+        _ => true,
+    }
+}
+
+/// Relative path of a request tail, refusing `..`, absolute paths and dotfiles
+fn relative_path(tail: &str) -> Option<PathBuf> {
+    let path = Path::new(tail);
+    path.components()
+        .all(|c| matches!(c, Component::Normal(s) if !s.to_string_lossy().starts_with('.')))
+        .then(|| path.to_path_buf())
+}
+
+fn with_suffix(path: &Path, ext: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(ext);
+    PathBuf::from(name)
+}
+
+/// Serve `rel` from `root`, or `None` if it is not a file there
+#[cfg_attr(feature = "embed-static", allow(dead_code))]
+pub async fn serve_file(root: &Path, rel: &Path, req: &HttpRequest) -> Result<Option<HttpResponse>> {
+    let path = root.join(rel);
+    if !tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_file()) {
+        return Ok(None);
+    }
+    let mime = actix_files::file_extension_to_mime(path.extension().and_then(|e| e.to_str()).unwrap_or(""));
+
+    // Ranges must address the plain file: NamedFile marks partial responses
+    // as identity-encoded, which would mislabel a compressed sibling
+    let ranged = req.headers().contains_key(header::RANGE);
+    let mut has_variant = false;
+    let mut served_variant = false;
+    let mut file = None;
+    for (ext, coding, encoding) in PRECOMPRESSED {
+        let variant = with_suffix(&path, ext);
+        if !tokio::fs::metadata(&variant).await.is_ok_and(|m| m.is_file()) {
+            continue;
+        }
+        has_variant = true;
+        if !ranged && accepts(req, coding) {
+            file = Some(
+                NamedFile::open_async(&variant)
+                    .await?
+                    .set_content_type(mime.clone())
+                    .set_content_encoding(*encoding)
+                    .disable_content_disposition(),
+            );
+            served_variant = true;
+            break;
+        }
+    }
+    // `Compress` adds its own Vary whenever it encodes the plain file
+    let compressed_later = !served_variant && !ranged && is_compressible(&mime) && accepts_any(req, ON_THE_FLY);
+    let file = match file {
+        Some(file) => file,
+        None => NamedFile::open_async(&path).await?.set_content_type(mime),
+    };
+
+    let mut res = file.into_response(req);
+    let rel_path: Vec<_> = rel.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+    res.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control(&rel_path.join("/"))));
+    if has_variant && !compressed_later {
+        res.headers_mut().append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+    Ok(Some(res))
+}
+
+/// Everything not matched by another route: files from ./static, index.html
+/// for the root and for client-side routes
+pub async fn serve_static(req: HttpRequest) -> Result<HttpResponse> {
+    let tail = req.match_info().query("tail");
+    if tail.is_empty() || tail == "index.html" {
+        return serve_index(req).await;
+    }
+    let Some(rel) = relative_path(tail) else {
+        return Err(actix_web::error::ErrorNotFound("Not found"));
+    };
+    match find_file(&rel, &req).await? {
+        Some(res) => Ok(res),
+        None => spa_fallback(req).await,
+    }
+}
+
+#[cfg(not(feature = "embed-static"))]
+async fn find_file(rel: &Path, req: &HttpRequest) -> Result<Option<HttpResponse>> {
+    serve_file(Path::new(STATIC_ROOT), rel, req).await
+}
+
+#[cfg(feature = "embed-static")]
+async fn find_file(rel: &Path, req: &HttpRequest) -> Result<Option<HttpResponse>> {
+    Ok(rel.to_str().and_then(|path| super::embedded_static::serve(path, req)))
+}
+
+#[cfg(not(feature = "embed-static"))]
+async fn read_index() -> Result<String> {
+    tokio::fs::read_to_string(Path::new(STATIC_ROOT).join("index.html"))
+        .await
+        .map_err(actix_web::error::ErrorNotFound)
+}
+
+#[cfg(feature = "embed-static")]
+async fn read_index() -> Result<String> {
+    super::embedded_static::index().ok_or_else(|| actix_web::error::ErrorNotFound("index.html is not embedded"))
+}
+
+/// Add `nonce="..."` to every `<script>` and `<style>` tag that has none, so
+/// inline code the build generated passes a nonce-based CSP
+pub fn inject_nonce(html: &str, nonce: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let mut out = String::with_capacity(html.len() + 64);
+    let mut copied = 0;
+    let mut pos = 0;
+    while let Some(found) = lower[pos..].find('<') {
+        let start = pos + found;
+        pos = start + 1;
+        let Some(tag) = ["<script", "<style"].into_iter().find(|t| lower[start..].starts_with(t)) else {
+            continue;
+        };
+        let name_end = start + tag.len();
+        if !lower[name_end..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+            continue;
+        }
+        let tag_end = lower[name_end..].find('>').map_or(lower.len(), |i| name_end + i);
+        if lower[name_end..tag_end].contains("nonce=") {
+            continue;
+        }
+        out.push_str(&html[copied..name_end]);
+        out.push_str(&format!(" nonce=\"{}\"", nonce));
+        copied = name_end;
+    }
+    out.push_str(&html[copied..]);
+    out
+}
+
+/// index.html carrying this request's CSP nonce. Sent without validators and
+/// with `no-cache`, so browsers fetch it on every load; the body differs per
+/// response because the nonce must match that response's policy header.
+pub async fn serve_index(req: HttpRequest) -> Result<HttpResponse> {
+    let html = read_index().await?;
+    let html = match security::nonce(&req) {
+        Some(nonce) => inject_nonce(&html, &nonce),
+        None => html,
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, CACHE_REVALIDATE))
+        .body(html))
 }
 
 /// SPA fallback - serve index.html for non-API routes
 /// This enables HTML5 history mode routing in the frontend
-pub async fn spa_fallback(req: HttpRequest) -> Result<NamedFile> {
+pub async fn spa_fallback(req: HttpRequest) -> Result<HttpResponse> {
     let path: PathBuf = req.match_info().query("tail").parse()?;
 
     // Don't fallback for API routes or static assets
@@ -30,8 +246,93 @@ pub async fn spa_fallback(req: HttpRequest) -> Result<NamedFile> {
     }
 
     // Serve index.html for all other routes (SPA routing)
-    let index_path = PathBuf::from("./static/index.html");
-    NamedFile::open_async(index_path)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))
+    serve_index(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nonce_goes_on_script_and_style_tags_only() {
+        let html = r#"<head><STYLE>a{}</STYLE><script type="module" src="/a.js"></script>
+<script nonce="keep">x()</script><scripts></scripts><link rel="stylesheet" href="/a.css"></head>"#;
+        let out = inject_nonce(html, "abc");
+        assert_eq!(
+            out,
+            r#"<head><STYLE nonce="abc">a{}</STYLE><script nonce="abc" type="module" src="/a.js"></script>
+<script nonce="keep">x()</script><scripts></scripts><link rel="stylesheet" href="/a.css"></head>"#
+        );
+    }
+
+    #[test]
+    fn hashed_names_and_accepted_encodings() {
+        for name in ["assets/index-BQ3x9aZ1.js", "assets/main.3f2a9c1b.css", "assets/wasm/frontend-a1b2c3d4e5f6_bg.wasm"] {
+            assert!(is_hashed(name), "{}", name);
+        }
+        for name in [
+            "index.html",
+            "assets/parallax-background.png",
+            "assets/logo-2024.png",
+            "assets/a1b2c3d4e5.js",
+            // Only Vite's output directory is hashed
+            "index-BQ3x9aZ1.js",
+            "img/logo-20240101.png",
+        ] {
+            assert!(!is_hashed(name), "{}", name);
+        }
+
+        let req = |v: &str| actix_web::test::TestRequest::default().insert_header(("accept-encoding", v)).to_http_request();
+        assert!(accepts(&req("gzip, deflate, br"), "br"));
+        assert!(!accepts(&req("gzip, br;q=0"), "br"));
+        assert!(!accepts(&req("br;q=0, *"), "br"));
+        assert!(accepts(&req("*;q=0.5"), "gzip"));
+        assert!(!accepts(&actix_web::test::TestRequest::default().to_http_request(), "gzip"));
+        assert!(relative_path("../secret").is_none() && relative_path("assets/.env").is_none());
+    }
+
+    #[actix_web::test]
+    async fn serves_precompressed_variants_with_cache_headers_and_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("assets")).unwrap();
+        std::fs::write(dir.path().join("assets/app-1a2b3c4d.wasm"), b"plain wasm bytes").unwrap();
+        std::fs::write(dir.path().join("assets/app-1a2b3c4d.wasm.br"), b"brotli").unwrap();
+        std::fs::write(dir.path().join("hero.png"), b"0123456789").unwrap();
+        let serve = |rel: &'static str, req: actix_web::test::TestRequest| {
+            let root = dir.path().to_path_buf();
+            async move { serve_file(&root, Path::new(rel), &req.to_http_request()).await.unwrap().unwrap() }
+        };
+        let get = actix_web::test::TestRequest::default;
+
+        let res = serve("assets/app-1a2b3c4d.wasm", get().insert_header(("accept-encoding", "gzip, br"))).await;
+        assert_eq!(res.headers().get("content-encoding").unwrap(), "br");
+        assert_eq!(res.headers().get("content-type").unwrap(), "application/wasm");
+        assert_eq!(res.headers().get("cache-control").unwrap(), CACHE_IMMUTABLE);
+        assert_eq!(res.headers().get("vary").unwrap(), "Accept-Encoding");
+        let br_etag = res.headers().get("etag").unwrap().clone();
+        assert!(!br_etag.to_str().unwrap().starts_with("W/"));
+
+        // Left to `Compress`, which sets its own Vary
+        let res = serve("assets/app-1a2b3c4d.wasm", get().insert_header(("accept-encoding", "gzip"))).await;
+        assert!(res.headers().get("vary").is_none());
+        let res = serve("assets/app-1a2b3c4d.wasm", get()).await;
+        assert!(res.headers().get("content-encoding").is_none());
+        assert_eq!(res.headers().get("vary").unwrap(), "Accept-Encoding");
+        assert_ne!(res.headers().get("etag").unwrap(), br_etag);
+
+        let res = serve("hero.png", get()).await;
+        assert_eq!(res.headers().get("cache-control").unwrap(), CACHE_REVALIDATE);
+        let etag = res.headers().get("etag").unwrap().clone();
+        let res = serve("hero.png", get().insert_header(("if-none-match", etag))).await;
+        assert_eq!(res.status(), 304);
+        let res = serve("hero.png", get().insert_header(("range", "bytes=2-5"))).await;
+        assert_eq!(res.status(), 206);
+        assert_eq!(res.headers().get("content-range").unwrap(), "bytes 2-5/10");
+        let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"2345");
+
+        let root = dir.path().to_path_buf();
+        let missing = serve_file(&root, Path::new("nope.js"), &get().to_http_request()).await.unwrap();
+        assert!(missing.is_none());
+    }
 }
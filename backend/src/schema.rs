@@ -0,0 +1,359 @@
+// JSON Schema validation for entity documents (`../src/entities/*.json`)
+//
+// Covers the subset of JSON Schema the entity files use: `type` (single or
+// list), `required`, `properties`, `additionalProperties: false`, `items`,
+// `enum`, `format`, `minLength`/`maxLength`, `minimum`/`maximum` and
+// `minItems`/`maxItems`. Unknown keywords and formats are ignored.
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::audit::AUDIT_TREE;
+use crate::db_manager::DbManager;
+use crate::handlers::api_keys::API_KEYS_TREE;
+use crate::handlers::csp::REPORTS_TREE;
+use crate::handlers::orgs::{MEMBERSHIPS_TREE, ORGS_TREE};
+use crate::models::org_types::{ORG_ADMIN, ORG_MEMBER, ORG_OWNER, ORG_ROLES};
+use crate::validation;
+
+/// Collections the generic entity router must never shadow: every tree the
+/// backend itself opens (internal `__*` trees and `{collection}__idx_*`
+/// indexes are excluded by their separator) and the `/api/*` paths that
+/// already have handlers. New built-in trees belong here too.
+const RESERVED: &[&str] = &[
+    "users",
+    API_KEYS_TREE,
+    MEMBERSHIPS_TREE,
+    ORGS_TREE,
+    AUDIT_TREE,
+    REPORTS_TREE,
+    "admin",
+    "audit",
+    "auth",
+    "events",
+    "keys",
+];
+
+/// Schema keyword that turns on soft delete for an entity
+const SOFT_DELETE_KEYWORD: &str = "x-softDelete";
+
+/// Schema keyword listing the org roles that may write an entity's records
+const WRITE_ROLES_KEYWORD: &str = "x-writeRoles";
+
+/// Who may write when a schema doesn't say: everyone but viewers
+const DEFAULT_WRITE_ROLES: &[&str] = &[ORG_OWNER, ORG_ADMIN, ORG_MEMBER];
+
+/// One entity's schema; `name` is the file stem (`Invoice`)
+#[derive(Debug, Clone)]
+pub struct EntitySchema {
+    pub name: String,
+    pub schema: Value,
+    /// Deletes move records to the trash instead of removing them
+    pub soft_delete: bool,
+    /// Org roles that may create, change, delete and restore records
+    pub write_roles: Vec<String>,
+}
+
+impl EntitySchema {
+    /// Top-level property names (what list endpoints may filter on)
+    pub fn fields(&self) -> Vec<&str> {
+        self.schema
+            .get("properties")
+            .and_then(Value::as_object)
+            .map(|p| p.keys().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Check `doc` against the schema; on failure returns one message per
+    /// offending field path (`address.city`, `items[2].price`)
+    pub fn validate(&self, doc: &Value) -> std::result::Result<(), HashMap<String, String>> {
+        let mut errors = BTreeMap::new();
+        check(&self.schema, doc, "", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.into_iter().collect())
+        }
+    }
+}
+
+/// All entity schemas, by collection
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    entities: HashMap<String, EntitySchema>,
+}
+
+impl SchemaRegistry {
+    /// Load every `*.json` in `dir`. A missing directory yields an empty registry.
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let mut registry = Self::default();
+        if !dir.exists() {
+            return Ok(registry);
+        }
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+            let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+            let schema: Value = serde_json::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+            registry.register(&name, schema)?;
+        }
+        Ok(registry)
+    }
+
+    /// Add an entity; its collection is the plural snake_case of `name`
+    /// (`QuoteComment` -> `quote_comments`), as in the generated SQL tables
+    pub fn register(&mut self, name: &str, schema: Value) -> Result<()> {
+        let collection = DbManager::pluralize_snake(name);
+        if collection.contains("__") || RESERVED.contains(&collection.as_str()) {
+            return Err(anyhow!("entity '{}' would use reserved collection '{}'", name, collection));
+        }
+        if !schema.is_object() {
+            return Err(anyhow!("schema for entity '{}' must be a JSON object", name));
+        }
+        let soft_delete = schema.get(SOFT_DELETE_KEYWORD) == Some(&Value::Bool(true));
+        let write_roles = match schema.get(WRITE_ROLES_KEYWORD) {
+            None => DEFAULT_WRITE_ROLES.iter().map(|r| r.to_string()).collect(),
+            Some(roles) => roles
+                .as_array()
+                .filter(|roles| !roles.is_empty())
+                .and_then(|roles| {
+                    roles.iter().map(|r| r.as_str().filter(|r| ORG_ROLES.contains(r)).map(str::to_string)).collect()
+                })
+                .ok_or_else(|| {
+                    anyhow!("{} of entity '{}' must be a non-empty list of {}", WRITE_ROLES_KEYWORD, name, ORG_ROLES.join(", "))
+                })?,
+        };
+        self.entities.insert(collection, EntitySchema { name: name.to_string(), schema, soft_delete, write_roles });
+        Ok(())
+    }
+
+    /// Turn on soft delete for the listed collections (`SOFT_DELETE_COLLECTIONS`),
+    /// in addition to schemas that set `x-softDelete`
+    pub fn enable_soft_delete(&mut self, collections: &[String]) {
+        for collection in collections {
+            if let Some(entity) = self.entities.get_mut(collection) {
+                entity.soft_delete = true;
+            }
+        }
+    }
+
+    /// Collections whose deletes go to the trash, sorted
+    pub fn soft_delete_collections(&self) -> Vec<&str> {
+        self.collections().into_iter().filter(|c| self.entities[*c].soft_delete).collect()
+    }
+
+    pub fn get(&self, collection: &str) -> Option<&EntitySchema> {
+        self.entities.get(collection)
+    }
+
+    /// Registered collection names, sorted
+    pub fn collections(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.entities.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+fn type_matches(ty: &str, value: &Value) -> bool {
+    match ty {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn format_matches(format: &str, s: &str) -> bool {
+    match format {
+        "email" => validation::email(s),
+        "uri" | "url" => validation::url(s),
+        "date" => chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok(),
+        "date-time" => chrono::DateTime::parse_from_rfc3339(s).is_ok(),
+        "uuid" => uuid::Uuid::parse_str(s).is_ok(),
+        _ => true,
+    }
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut BTreeMap<String, String>) {
+    let key = if path.is_empty() { "$".to_string() } else { path.to_string() };
+
+    if let Some(ty) = schema.get("type") {
+        let allowed: Vec<&str> = match ty {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| type_matches(t, value)) {
+            errors.insert(key, format!("must be of type {}", allowed.join(" or ")));
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            let names: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+            errors.insert(key, format!("must be one of {}", names.join(", ")));
+            return;
+        }
+    }
+
+    match value {
+        Value::String(s) => {
+            if let Some(format) = schema.get("format").and_then(Value::as_str) {
+                if !format_matches(format, s) {
+                    errors.insert(key, format!("must be a valid {}", format));
+                    return;
+                }
+            }
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64).filter(|m| len < *m) {
+                errors.insert(key, format!("must be at least {} characters", min));
+            } else if let Some(max) = schema.get("maxLength").and_then(Value::as_u64).filter(|m| len > *m) {
+                errors.insert(key, format!("must be at most {} characters", max));
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64).filter(|m| n < *m) {
+                errors.insert(key, format!("must be at least {}", min));
+            } else if let Some(max) = schema.get("maximum").and_then(Value::as_f64).filter(|m| n > *m) {
+                errors.insert(key, format!("must be at most {}", max));
+            }
+        }
+        Value::Array(items) => {
+            let len = items.len() as u64;
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64).filter(|m| len < *m) {
+                errors.insert(key.clone(), format!("must have at least {} items", min));
+            } else if let Some(max) = schema.get("maxItems").and_then(Value::as_u64).filter(|m| len > *m) {
+                errors.insert(key.clone(), format!("must have at most {} items", max));
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::Object(fields) => {
+            for required in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+                if let Some(name) = required.as_str() {
+                    if fields.get(name).is_none_or(Value::is_null) {
+                        errors.insert(join(path, name), "is required".into());
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+            for (name, field) in fields {
+                match properties.and_then(|p| p.get(name)) {
+                    // Optional fields may be sent as null to clear them
+                    Some(_) if field.is_null() => {}
+                    Some(field_schema) => check(field_schema, field, &join(path, name), errors),
+                    None if closed => {
+                        errors.insert(join(path, name), "is not allowed".into());
+                    }
+                    None => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn customer() -> EntitySchema {
+        let mut registry = SchemaRegistry::default();
+        registry
+            .register(
+                "Customer",
+                json!({
+                    "type": "object",
+                    "required": ["name", "email"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "name": {"type": "string", "minLength": 1},
+                        "email": {"type": "string", "format": "email"},
+                        "tier": {"type": "string", "enum": ["basic", "pro"]},
+                        "credit": {"type": "number", "minimum": 0},
+                        "since": {"type": "string", "format": "date"},
+                        "address": {
+                            "type": "object",
+                            "required": ["city"],
+                            "properties": {"city": {"type": "string"}}
+                        },
+                        "tags": {"type": "array", "items": {"type": "string"}}
+                    }
+                }),
+            )
+            .unwrap();
+        registry.get("customers").unwrap().clone()
+    }
+
+    #[test]
+    fn reports_each_invalid_field() {
+        let schema = customer();
+        assert!(schema
+            .validate(&json!({"name": "Globex", "email": "ops@globex.test", "tier": "pro", "since": "2024-02-29"}))
+            .is_ok());
+
+        let errors = schema
+            .validate(&json!({
+                "email": "not-an-email",
+                "tier": "gold",
+                "credit": -5,
+                "since": "2024-02-30",
+                "address": {},
+                "tags": ["ok", 7],
+                "extra": true
+            }))
+            .unwrap_err();
+        for field in ["name", "email", "tier", "credit", "since", "address.city", "tags[1]", "extra"] {
+            assert!(errors.contains_key(field), "missing error for {}: {:?}", field, errors);
+        }
+        assert_eq!(errors["name"], "is required");
+
+        let errors = schema.validate(&json!([1, 2])).unwrap_err();
+        assert!(errors.contains_key("$"));
+    }
+
+    #[test]
+    fn refuses_reserved_collections() {
+        let mut registry = SchemaRegistry::default();
+        assert!(registry.register("User", json!({"type": "object"})).is_err());
+        assert!(registry.register("CspReport", json!({"type": "object"})).is_err());
+        assert!(registry.register("Users__idx_email", json!({"type": "object"})).is_err());
+        assert!(registry.register("BlogCategory", json!({"type": "object"})).is_ok());
+        assert!(registry.get("blog_categories").is_some());
+    }
+
+    #[test]
+    fn write_roles_default_to_everyone_but_viewers() {
+        let mut registry = SchemaRegistry::default();
+        registry.register("Note", json!({"type": "object"})).unwrap();
+        assert_eq!(registry.get("notes").unwrap().write_roles, ["owner", "admin", "member"]);
+        registry.register("Contract", json!({"type": "object", "x-writeRoles": ["owner"]})).unwrap();
+        assert_eq!(registry.get("contracts").unwrap().write_roles, ["owner"]);
+        assert!(registry.register("Bad", json!({"type": "object", "x-writeRoles": ["root"]})).is_err());
+        assert!(registry.register("Bad", json!({"type": "object", "x-writeRoles": []})).is_err());
+    }
+}
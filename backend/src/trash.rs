@@ -0,0 +1,126 @@
+// Index of trashed records
+//
+// Soft-deleted records stay in their collection with `deleted_at` set, so
+// finding the expired ones would mean reading every record. Writes made
+// through `Database` mirror `deleted_at` into the `__trash` tree in the same
+// transaction instead, keyed `{collection} 0x00 {raw key}` with the
+// big-endian timestamp as value; the purge job only scans that.
+use anyhow::Result;
+use serde_json::Value;
+use sled::transaction::TransactionalTree;
+
+use crate::db::{Database, TxResult};
+
+pub const TRASH_TREE: &str = "__trash";
+
+fn entry_key(collection: &str, raw_key: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(collection.len() + raw_key.len() + 1);
+    entry.extend_from_slice(collection.as_bytes());
+    entry.push(0);
+    entry.extend_from_slice(raw_key);
+    entry
+}
+
+/// `deleted_at` of a stored (plaintext) record, if it is in the trash
+fn deleted_at(record: &[u8]) -> Option<u64> {
+    // Cheap pre-check; most records never went near the trash
+    if !record.windows(12).any(|w| w == b"\"deleted_at\"") {
+        return None;
+    }
+    serde_json::from_slice::<Value>(record).ok()?.get("deleted_at")?.as_u64()
+}
+
+/// Mirror one write into the index. Called by `Tx` for every record write.
+pub(crate) fn track(
+    trash: &TransactionalTree,
+    collection: &str,
+    key: &str,
+    current: Option<&[u8]>,
+    existed: bool,
+) -> TxResult<()> {
+    let entry = entry_key(collection, key.as_bytes());
+    match current.and_then(deleted_at) {
+        Some(at) => {
+            trash.insert(entry, &at.to_be_bytes())?;
+        }
+        None if existed => {
+            trash.remove(entry)?;
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// Ids (relative to `db`'s tenant) of records in `collection` trashed at or
+/// before `trashed_before`. On a global handle this covers every org.
+pub fn expired(db: &Database, collection: &str, trashed_before: u64) -> Result<Vec<String>> {
+    let scope = db.scoped_key("");
+    let prefix = entry_key(collection, scope.as_bytes());
+    let mut ids = Vec::new();
+    for item in db.db.open_tree(TRASH_TREE)?.scan_prefix(&prefix) {
+        let (entry, at) = item?;
+        let at = u64::from_be_bytes(at.as_ref().try_into()?);
+        if at > trashed_before {
+            continue;
+        }
+        ids.push(String::from_utf8(entry[prefix.len()..].to_vec())?);
+    }
+    Ok(ids)
+}
+
+/// Rebuild the index from every collection, for data written before it existed
+pub(crate) fn rebuild(db: &Database) -> Result<()> {
+    let trash = db.db.open_tree(TRASH_TREE)?;
+    trash.clear()?;
+    for name in db.db.tree_names() {
+        let collection = String::from_utf8_lossy(&name).into_owned();
+        // Internal and index trees never hold records
+        if collection.contains("__") {
+            continue;
+        }
+        for item in db.db.open_tree(&name)?.iter() {
+            let (key, stored) = item?;
+            let Ok(plain) = db.decode(&collection, &key, &stored) else { continue };
+            if let Some(at) = deleted_at(&plain) {
+                trash.insert(entry_key(&collection, &key), &at.to_be_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::Repository;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn trash_index_follows_soft_delete_restore_and_purge() {
+        let dir = tempdir().unwrap();
+        let db = Database::new(dir.path().join("sled").to_str().unwrap()).unwrap();
+        let acme: Repository<Value> = Repository::new(&db.for_tenant("acme"), "quotes");
+        let globex: Repository<Value> = Repository::new(&db.for_tenant("globex"), "quotes");
+        acme.create("q1", json!({"total": 1})).unwrap();
+        acme.create("q2", json!({"total": 2})).unwrap();
+        globex.create("q1", json!({"total": 3})).unwrap();
+
+        acme.soft_delete("q1", None, "u1").unwrap();
+        acme.soft_delete("q2", None, "u1").unwrap();
+        globex.soft_delete("q1", None, "u1").unwrap();
+        acme.restore("q2").unwrap();
+
+        let now = u64::MAX;
+        assert_eq!(expired(&db.for_tenant("acme"), "quotes", now).unwrap(), vec!["q1".to_string()]);
+        assert_eq!(expired(&db, "quotes", now).unwrap().len(), 2);
+        assert!(expired(&db, "quotes", 0).unwrap().is_empty());
+
+        // Dropping the index and rebuilding it finds the same records
+        rebuild(&db).unwrap();
+        assert_eq!(expired(&db, "quotes", now).unwrap().len(), 2);
+
+        assert!(acme.purge("q1", now).unwrap());
+        assert_eq!(expired(&db, "quotes", now).unwrap().len(), 1);
+    }
+}
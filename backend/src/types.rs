@@ -20,6 +20,11 @@ pub struct ItemMetadata {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ListResponse<T> {
     pub items: Vec<T>,
+    /// Opaque cursor for the next page; absent on the last page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(default)]
+    pub has_more: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -29,6 +34,11 @@ pub struct ItemResponse<T> {
     #[serde(rename = "updatedAt")]
     pub updated_at: u64,
     pub version: u64,
+    /// Set on records in the trash
+    #[serde(rename = "deletedAt", default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<u64>,
+    #[serde(rename = "deletedBy", default, skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -61,6 +71,18 @@ pub struct VersionedData<T> {
     pub version: u64,
     pub updated_at: u64,
     pub created_at: u64,
+    /// Soft delete: when (ms since epoch) and by whom the record was moved
+    /// to the trash. Trashed records are hidden from normal reads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
+}
+
+impl<T> VersionedData<T> {
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
 }
 
 // ============================================================================
@@ -166,6 +188,35 @@ pub struct HealthResponse {
     pub version: Option<String>,
 }
 
+/// Worst-of verdict for readiness; ordered so `max` picks the worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Degraded,
+    Unhealthy,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Component-specific figures (free bytes, backup age, ...)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    pub status: HealthStatus,
+    pub time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub checks: std::collections::BTreeMap<String, ComponentHealth>,
+}
+
 // ============================================================================
 // Error response (per-field errors)
 // ============================================================================
@@ -181,6 +232,9 @@ pub struct ErrorDetail {
     pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub details: Option<HashMap<String, String>>,
+    /// Id of the failed request (`X-Request-Id`), for support and log lookups
+    #[serde(rename = "requestId", default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl ErrorResponse {
@@ -190,6 +244,7 @@ impl ErrorResponse {
                 code: code.into(),
                 message: message.into(),
                 details: None,
+                request_id: crate::middleware::request_id::current(),
             },
         }
     }
@@ -204,6 +259,7 @@ impl ErrorResponse {
                 code: code.into(),
                 message: message.into(),
                 details: Some(details),
+                request_id: crate::middleware::request_id::current(),
             },
         }
     }